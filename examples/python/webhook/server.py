@@ -1,4 +1,7 @@
 #!/usr/bin/env python3
+import hashlib
+import hmac
+import time
 from http.server import BaseHTTPRequestHandler, HTTPServer
 
 """
@@ -13,7 +16,8 @@ We can register this webhook to the netspot_control using the JSON below:
   "type": "both"
 }
 
-We may need to edit the configuration to match our setup.
+We may need to edit the configuration to match our setup. When the webhook has a "secret", set the same
+secret below to verify the signatures of the received messages.
 
 Use the variables below to configure the server.
 """
@@ -21,6 +25,18 @@ Use the variables below to configure the server.
 SERVER_ADDRESS = ''
 SERVER_PORT = 9001
 ACCEPTED_PATH = '/webhook'
+SECRET = None  # For example: b'a long random string'
+MAX_TIME_DIFFERENCE = 5 * 60  # Seconds
+
+
+# Checks the signature headers, see "Webhook signatures" in the README
+def signature_is_valid(headers, body):
+    timestamp = headers.get('X-Netspot-Timestamp', '')
+    signature = headers.get('X-Netspot-Signature', '')
+    if not timestamp.isdigit() or abs(time.time() - int(timestamp)) > MAX_TIME_DIFFERENCE:
+        return False
+    digest = hmac.new(SECRET, timestamp.encode('utf-8') + b'.' + body, hashlib.sha256).hexdigest()
+    return hmac.compare_digest(signature, 'sha256=' + digest)
 
 
 # Simple server that prints received messages
@@ -31,7 +47,13 @@ class Webhook(BaseHTTPRequestHandler):
         if self.path == ACCEPTED_PATH:
             if 'Content-Length' in self.headers:
                 content_length = int(self.headers['Content-Length'])
-                message = self.rfile.read(content_length).decode('utf-8')
+                body = self.rfile.read(content_length)
+                if SECRET is not None and not signature_is_valid(self.headers, body):
+                    print("Responding with 401 to a message with an invalid signature")
+                    self.send_response(401)
+                    self.end_headers()
+                    return
+                message = body.decode('utf-8')
                 print(f'{method}: {message}')
             else:
                 print(f'{method}: No message')
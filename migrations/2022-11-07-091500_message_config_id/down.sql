@@ -0,0 +1,21 @@
+-- Removing config_id from alarms
+CREATE TABLE alarms_new
+(
+    id INTEGER NOT NULL PRIMARY KEY,
+    time BIGINT NOT NULL,
+    message TEXT NOT NULL
+);
+INSERT INTO alarms_new(time, message) SELECT time, message FROM alarms;
+DROP TABLE alarms;
+ALTER TABLE alarms_new RENAME TO alarms;
+
+-- Removing config_id from data
+CREATE TABLE data_new
+(
+    id INTEGER NOT NULL PRIMARY KEY,
+    time BIGINT NOT NULL,
+    message TEXT NOT NULL
+);
+INSERT INTO data_new(time, message) SELECT time, message FROM data;
+DROP TABLE data;
+ALTER TABLE data_new RENAME TO data;
@@ -0,0 +1,3 @@
+-- Storing the configuration id of the netspot that produced the message
+ALTER TABLE alarms ADD COLUMN config_id INTEGER;
+ALTER TABLE data ADD COLUMN config_id INTEGER;
@@ -0,0 +1,6 @@
+-- Server settings are stored as a single JSON row
+CREATE TABLE settings
+(
+    id INTEGER NOT NULL PRIMARY KEY,
+    config TEXT NOT NULL
+);
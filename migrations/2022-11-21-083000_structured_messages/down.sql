@@ -0,0 +1,64 @@
+-- Storing alarm messages as JSON again
+CREATE TABLE alarms_new
+(
+    id INTEGER NOT NULL PRIMARY KEY,
+    time BIGINT NOT NULL,
+    message TEXT NOT NULL,
+    config_id INTEGER
+);
+INSERT INTO alarms_new(time, message, config_id)
+SELECT time,
+       json_object('time', time, 'name', name, 'series', series, 'stat', stat,
+                   'status', status, 'value', value, 'probability', probability,
+                   'code', code, 'type', 'alarm'),
+       config_id
+FROM alarms;
+DROP TABLE alarms;
+ALTER TABLE alarms_new RENAME TO alarms;
+
+-- Storing data messages as JSON again
+CREATE TABLE data_new
+(
+    id INTEGER NOT NULL PRIMARY KEY,
+    time BIGINT NOT NULL,
+    message TEXT NOT NULL,
+    config_id INTEGER
+);
+INSERT INTO data_new(time, message, config_id)
+SELECT time,
+       json_object('time', time, 'name', name, 'series', series,
+                   'AVG_PKT_SIZE', avg_pkt_size,
+                   'AVG_PKT_SIZE_DOWN', avg_pkt_size_down,
+                   'AVG_PKT_SIZE_UP', avg_pkt_size_up,
+                   'PERF', perf,
+                   'PERF_DOWN', perf_down,
+                   'PERF_UP', perf_up,
+                   'R_ACK', r_ack,
+                   'R_ACK_DOWN', r_ack_down,
+                   'R_ACK_UP', r_ack_up,
+                   'R_ARP', r_arp,
+                   'R_ARP_DOWN', r_arp_down,
+                   'R_ARP_UP', r_arp_up,
+                   'R_DST_SRC', r_dst_src,
+                   'R_DST_SRC_DOWN', r_dst_src_down,
+                   'R_DST_SRC_UP', r_dst_src_up,
+                   'R_DST_SRC_PORT', r_dst_src_port,
+                   'R_DST_SRC_PORT_DOWN', r_dst_src_port_down,
+                   'R_DST_SRC_PORT_UP', r_dst_src_port_up,
+                   'R_ICMP', r_icmp,
+                   'R_ICMP_DOWN', r_icmp_down,
+                   'R_ICMP_UP', r_icmp_up,
+                   'R_IP', r_ip,
+                   'R_IP_DOWN', r_ip_down,
+                   'R_IP_UP', r_ip_up,
+                   'R_SYN', r_syn,
+                   'R_SYN_DOWN', r_syn_down,
+                   'R_SYN_UP', r_syn_up,
+                   'TRAFFIC', traffic,
+                   'TRAFFIC_DOWN', traffic_down,
+                   'TRAFFIC_UP', traffic_up,
+                   'type', 'data'),
+       config_id
+FROM data;
+DROP TABLE data;
+ALTER TABLE data_new RENAME TO data;
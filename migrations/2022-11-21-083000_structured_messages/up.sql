@@ -0,0 +1,142 @@
+-- Storing alarm messages in columns
+CREATE TABLE alarms_new
+(
+    id INTEGER NOT NULL PRIMARY KEY,
+    time BIGINT NOT NULL,
+    config_id INTEGER,
+    name TEXT NOT NULL,
+    series TEXT NOT NULL,
+    stat TEXT NOT NULL,
+    status TEXT NOT NULL,
+    value DOUBLE NOT NULL,
+    probability DOUBLE NOT NULL,
+    code INTEGER NOT NULL
+);
+INSERT INTO alarms_new(time, config_id, name, series, stat, status, value, probability, code)
+SELECT time,
+       config_id,
+       json_extract(message, '$.name'),
+       json_extract(message, '$.series'),
+       json_extract(message, '$.stat'),
+       json_extract(message, '$.status'),
+       json_extract(message, '$.value'),
+       json_extract(message, '$.probability'),
+       json_extract(message, '$.code')
+FROM alarms;
+DROP TABLE alarms;
+ALTER TABLE alarms_new RENAME TO alarms;
+CREATE INDEX alarms_time ON alarms (time);
+CREATE INDEX alarms_config_id ON alarms (config_id);
+CREATE INDEX alarms_name ON alarms (name);
+CREATE INDEX alarms_stat ON alarms (stat);
+
+-- Storing data messages in columns
+CREATE TABLE data_new
+(
+    id INTEGER NOT NULL PRIMARY KEY,
+    time BIGINT NOT NULL,
+    config_id INTEGER,
+    name TEXT NOT NULL,
+    series TEXT NOT NULL,
+    avg_pkt_size DOUBLE,
+    avg_pkt_size_down DOUBLE,
+    avg_pkt_size_up DOUBLE,
+    perf DOUBLE,
+    perf_down DOUBLE,
+    perf_up DOUBLE,
+    r_ack DOUBLE,
+    r_ack_down DOUBLE,
+    r_ack_up DOUBLE,
+    r_arp DOUBLE,
+    r_arp_down DOUBLE,
+    r_arp_up DOUBLE,
+    r_dst_src DOUBLE,
+    r_dst_src_down DOUBLE,
+    r_dst_src_up DOUBLE,
+    r_dst_src_port DOUBLE,
+    r_dst_src_port_down DOUBLE,
+    r_dst_src_port_up DOUBLE,
+    r_icmp DOUBLE,
+    r_icmp_down DOUBLE,
+    r_icmp_up DOUBLE,
+    r_ip DOUBLE,
+    r_ip_down DOUBLE,
+    r_ip_up DOUBLE,
+    r_syn DOUBLE,
+    r_syn_down DOUBLE,
+    r_syn_up DOUBLE,
+    traffic DOUBLE,
+    traffic_down DOUBLE,
+    traffic_up DOUBLE
+);
+INSERT INTO data_new(time, config_id, name, series,
+                     avg_pkt_size,
+                     avg_pkt_size_down,
+                     avg_pkt_size_up,
+                     perf,
+                     perf_down,
+                     perf_up,
+                     r_ack,
+                     r_ack_down,
+                     r_ack_up,
+                     r_arp,
+                     r_arp_down,
+                     r_arp_up,
+                     r_dst_src,
+                     r_dst_src_down,
+                     r_dst_src_up,
+                     r_dst_src_port,
+                     r_dst_src_port_down,
+                     r_dst_src_port_up,
+                     r_icmp,
+                     r_icmp_down,
+                     r_icmp_up,
+                     r_ip,
+                     r_ip_down,
+                     r_ip_up,
+                     r_syn,
+                     r_syn_down,
+                     r_syn_up,
+                     traffic,
+                     traffic_down,
+                     traffic_up)
+SELECT time,
+       config_id,
+       json_extract(message, '$.name'),
+       json_extract(message, '$.series'),
+       json_extract(message, '$.AVG_PKT_SIZE'),
+       json_extract(message, '$.AVG_PKT_SIZE_DOWN'),
+       json_extract(message, '$.AVG_PKT_SIZE_UP'),
+       json_extract(message, '$.PERF'),
+       json_extract(message, '$.PERF_DOWN'),
+       json_extract(message, '$.PERF_UP'),
+       json_extract(message, '$.R_ACK'),
+       json_extract(message, '$.R_ACK_DOWN'),
+       json_extract(message, '$.R_ACK_UP'),
+       json_extract(message, '$.R_ARP'),
+       json_extract(message, '$.R_ARP_DOWN'),
+       json_extract(message, '$.R_ARP_UP'),
+       json_extract(message, '$.R_DST_SRC'),
+       json_extract(message, '$.R_DST_SRC_DOWN'),
+       json_extract(message, '$.R_DST_SRC_UP'),
+       json_extract(message, '$.R_DST_SRC_PORT'),
+       json_extract(message, '$.R_DST_SRC_PORT_DOWN'),
+       json_extract(message, '$.R_DST_SRC_PORT_UP'),
+       json_extract(message, '$.R_ICMP'),
+       json_extract(message, '$.R_ICMP_DOWN'),
+       json_extract(message, '$.R_ICMP_UP'),
+       json_extract(message, '$.R_IP'),
+       json_extract(message, '$.R_IP_DOWN'),
+       json_extract(message, '$.R_IP_UP'),
+       json_extract(message, '$.R_SYN'),
+       json_extract(message, '$.R_SYN_DOWN'),
+       json_extract(message, '$.R_SYN_UP'),
+       json_extract(message, '$.TRAFFIC'),
+       json_extract(message, '$.TRAFFIC_DOWN'),
+       json_extract(message, '$.TRAFFIC_UP')
+FROM data;
+DROP TABLE data;
+ALTER TABLE data_new RENAME TO data;
+CREATE INDEX data_time ON data (time);
+CREATE INDEX data_config_id ON data (config_id);
+CREATE INDEX data_name ON data (name);
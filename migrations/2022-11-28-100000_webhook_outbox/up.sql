@@ -0,0 +1,16 @@
+-- Messages waiting for webhook delivery. Messages that could not be delivered within the retry
+-- policy are kept as dead letters.
+CREATE TABLE webhook_outbox
+(
+    id           INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    webhook_id   INTEGER NOT NULL,
+    created      BIGINT  NOT NULL,
+    payload      TEXT    NOT NULL,
+    attempts     INTEGER NOT NULL DEFAULT 0,
+    next_attempt BIGINT  NOT NULL,
+    last_error   TEXT,
+    dead         BOOLEAN NOT NULL DEFAULT 0
+);
+
+CREATE INDEX webhook_outbox_due ON webhook_outbox (dead, next_attempt);
+CREATE INDEX webhook_outbox_webhook_id ON webhook_outbox (webhook_id);
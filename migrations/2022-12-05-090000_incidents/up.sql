@@ -0,0 +1,18 @@
+-- Alarms grouped by configuration, stat and status. Open incidents have no closing time.
+CREATE TABLE incidents
+(
+    id              INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    config_id       INTEGER,
+    name            TEXT    NOT NULL,
+    stat            TEXT    NOT NULL,
+    status          TEXT    NOT NULL,
+    first_seen      BIGINT  NOT NULL,
+    last_seen       BIGINT  NOT NULL,
+    closed          BIGINT,
+    count           INTEGER NOT NULL,
+    peak_value      DOUBLE  NOT NULL,
+    min_probability DOUBLE  NOT NULL
+);
+
+CREATE INDEX incidents_first_seen ON incidents (first_seen);
+CREATE INDEX incidents_closed ON incidents (closed);
@@ -0,0 +1,5 @@
+DROP INDEX alarms_label;
+ALTER TABLE alarms DROP COLUMN note;
+ALTER TABLE alarms DROP COLUMN review_time;
+ALTER TABLE alarms DROP COLUMN review_user;
+ALTER TABLE alarms DROP COLUMN label;
@@ -0,0 +1,6 @@
+-- Review of alarms by operators. Alarms without a review have no review time.
+ALTER TABLE alarms ADD COLUMN label TEXT;
+ALTER TABLE alarms ADD COLUMN review_user TEXT;
+ALTER TABLE alarms ADD COLUMN review_time BIGINT;
+ALTER TABLE alarms ADD COLUMN note TEXT;
+CREATE INDEX alarms_label ON alarms (label);
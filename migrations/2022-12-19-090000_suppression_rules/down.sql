@@ -0,0 +1,3 @@
+ALTER TABLE alarms DROP COLUMN suppression_rule_id;
+ALTER TABLE alarms DROP COLUMN suppressed;
+DROP TABLE suppression_rules;
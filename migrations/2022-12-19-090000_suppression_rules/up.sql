@@ -0,0 +1,10 @@
+-- Rules for suppressing alarms during maintenance windows
+CREATE TABLE suppression_rules
+(
+    id INTEGER NOT NULL PRIMARY KEY,
+    config TEXT NOT NULL
+);
+
+-- Suppressed alarms are stored with the matching rule
+ALTER TABLE alarms ADD COLUMN suppressed BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE alarms ADD COLUMN suppression_rule_id INTEGER;
@@ -0,0 +1 @@
+DROP TABLE threshold_rules;
@@ -0,0 +1,6 @@
+-- Rules for sending alarms when data values cross thresholds
+CREATE TABLE threshold_rules
+(
+    id INTEGER NOT NULL PRIMARY KEY,
+    config TEXT NOT NULL
+);
@@ -0,0 +1,2 @@
+DROP TABLE detections;
+DROP TABLE correlation_rules;
@@ -0,0 +1,22 @@
+-- Rules for combining alarms of several stats into detections
+CREATE TABLE correlation_rules
+(
+    id INTEGER NOT NULL PRIMARY KEY,
+    config TEXT NOT NULL
+);
+
+-- Stats and configurations are stored as JSON arrays
+CREATE TABLE detections
+(
+    id             INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    time           BIGINT  NOT NULL,
+    first_seen     BIGINT  NOT NULL,
+    name           TEXT    NOT NULL,
+    rule_id        INTEGER NOT NULL,
+    severity       TEXT    NOT NULL,
+    config_id      INTEGER,
+    configurations TEXT    NOT NULL,
+    stats          TEXT    NOT NULL
+);
+
+CREATE INDEX detections_time ON detections (time);
@@ -0,0 +1,2 @@
+DROP INDEX alarms_severity;
+ALTER TABLE alarms DROP COLUMN severity;
@@ -0,0 +1,4 @@
+-- Severity given to the alarm when it was received, missing for older alarms
+ALTER TABLE alarms ADD COLUMN severity TEXT;
+
+CREATE INDEX alarms_severity ON alarms (severity);
@@ -0,0 +1,6 @@
+DROP INDEX data_job_id;
+DROP INDEX alarms_job_id;
+ALTER TABLE data DROP COLUMN job_id;
+ALTER TABLE alarms DROP COLUMN job_id;
+DROP TABLE replays;
+DROP TABLE pcaps;
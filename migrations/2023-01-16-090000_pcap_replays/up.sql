@@ -0,0 +1,31 @@
+-- Uploaded capture files, the files themselves are kept next to the database
+CREATE TABLE pcaps
+(
+    id INTEGER NOT NULL PRIMARY KEY,
+    name TEXT NOT NULL,
+    uploaded BIGINT NOT NULL,
+    size BIGINT NOT NULL,
+    packets BIGINT NOT NULL,
+    first_packet BIGINT,
+    last_packet BIGINT
+);
+
+-- Jobs replaying the capture files through netspot
+CREATE TABLE replays
+(
+    id INTEGER NOT NULL PRIMARY KEY,
+    config_id INTEGER NOT NULL,
+    pcap_id INTEGER NOT NULL,
+    status TEXT NOT NULL,
+    started BIGINT NOT NULL,
+    finished BIGINT,
+    progress DOUBLE NOT NULL,
+    error TEXT
+);
+
+-- Messages from the replays are kept apart from the live messages
+ALTER TABLE alarms ADD COLUMN job_id INTEGER;
+ALTER TABLE data ADD COLUMN job_id INTEGER;
+
+CREATE INDEX alarms_job_id ON alarms (job_id);
+CREATE INDEX data_job_id ON data (job_id);
@@ -0,0 +1,2 @@
+-- Highest severity of the alarms in the incident. Alarms without one count as info.
+ALTER TABLE incidents ADD COLUMN severity TEXT NOT NULL DEFAULT 'info';
@@ -0,0 +1,2 @@
+-- Alarms of different series are grouped into different incidents
+ALTER TABLE incidents ADD COLUMN series TEXT NOT NULL DEFAULT '';
@@ -1,15 +1,27 @@
 pub mod configuration;
+pub mod correlations;
+pub mod incidents;
+pub mod logs;
+pub mod metrics;
 pub mod network;
+pub mod replays;
+pub mod rules;
+pub mod settings;
 pub mod statistics;
 pub mod status;
+pub mod stream;
+pub mod suppressions;
 pub mod testing;
+pub mod thresholds;
 pub mod webhooks;
+pub mod ws;
 
 use rocket_okapi::openapi_get_routes;
 
 pub fn routes() -> Vec<rocket::Route> {
     openapi_get_routes![
         status::status_all,
+        status::listeners,
         status::start_all,
         status::stop_all,
         status::restart_all,
@@ -17,18 +29,56 @@ pub fn routes() -> Vec<rocket::Route> {
         status::start_by_id,
         status::stop_by_id,
         status::restart_by_id,
+        logs::logs_by_id,
         statistics::get_alarms,
+        statistics::review_alarm,
         statistics::get_data,
+        correlations::correlations_list,
+        correlations::correlation_add,
+        correlations::correlation_get,
+        correlations::correlation_put,
+        correlations::correlation_delete,
+        correlations::get_detections,
+        correlations::get_detection,
+        incidents::get_incidents,
+        incidents::get_incident,
+        stream::message_stream,
+        ws::websocket,
         configuration::netspot_add,
         configuration::netspot_get,
         configuration::netspot_put,
         configuration::netspot_delete,
         network::interfaces,
+        replays::pcap_upload,
+        replays::pcaps_list,
+        replays::pcap_get,
+        replays::pcap_delete,
+        replays::replay_start,
+        replays::replays_list,
+        replays::replay_get,
+        replays::replay_stop,
+        replays::replay_delete,
+        settings::settings_get,
+        settings::settings_put,
+        settings::retention_status,
+        suppressions::suppressions_list,
+        suppressions::suppression_add,
+        suppressions::suppression_get,
+        suppressions::suppression_put,
+        suppressions::suppression_delete,
+        thresholds::thresholds_list,
+        thresholds::threshold_add,
+        thresholds::threshold_get,
+        thresholds::threshold_put,
+        thresholds::threshold_delete,
         webhooks::webhooks_list,
         webhooks::webhook_add,
         webhooks::webhook_get,
         webhooks::webhook_put,
         webhooks::webhook_delete,
+        webhooks::webhook_status,
+        webhooks::webhook_deliveries,
+        webhooks::webhook_test,
         testing::send_test_alarm,
     ]
 }
@@ -1,20 +1,23 @@
 use crate::state::database::DatabaseError;
 use crate::state::NetspotControlState;
 use crate::structures::configuration::NetspotConfig;
+use crate::structures::status::ProcessUpdates;
 use rocket::http::Status;
 use rocket::log::private::warn;
 use rocket::serde::json::Json;
 use rocket::{delete, get, post, put, State};
 use rocket_okapi::openapi;
 
-async fn update_all_netspots(state: &State<NetspotControlState>) {
+async fn update_all_netspots(state: &State<NetspotControlState>) -> ProcessUpdates {
     if let Ok(configurations) = state.database.get_configurations() {
-        if state.netspots.update_all(configurations).await.is_err() {
-            warn!("Unexpected: updating process configurations failed");
+        match state.netspots.update_all(configurations).await {
+            Ok(updates) => return updates,
+            Err(_) => warn!("Unexpected: updating process configurations failed"),
         }
     } else {
         warn!("Unexpected: reading configurations failed");
     }
+    ProcessUpdates::new()
 }
 
 /// # Create a new netspot configuration
@@ -55,20 +58,19 @@ pub async fn netspot_get(
 
 /// # Update an existing netspot configuration
 ///
-/// Update netspot configuration by ID
+/// Update netspot configuration by ID. Running processes are restarted when their configuration
+/// changes, disabled configurations are stopped and enabled configurations are started. The
+/// response tells what was done for each configuration.
 #[openapi(tag = "Configuration")]
 #[put("/netspot/<id>", data = "<config>")]
 pub async fn netspot_put(
     state: &State<NetspotControlState>,
     id: Result<i32, &str>,
     config: Json<NetspotConfig>,
-) -> Result<(), Status> {
+) -> Result<Json<ProcessUpdates>, Status> {
     if let Ok(id) = id {
         return match state.database.set_configuration(id, &config) {
-            Ok(_) => {
-                update_all_netspots(state).await;
-                Ok(())
-            }
+            Ok(_) => Ok(Json(update_all_netspots(state).await)),
             Err(DatabaseError::NotFound) => Err(Status::NotFound),
             Err(_) => Err(Status::BadRequest),
         };
@@ -90,6 +92,7 @@ pub async fn netspot_delete(
         return match state.database.delete_configuration(id) {
             Ok(_) => {
                 update_all_netspots(state).await;
+                state.metrics.remove_configuration(id);
                 Ok(())
             }
             Err(DatabaseError::NotFound) => Err(Status::NotFound),
@@ -102,7 +105,7 @@ pub async fn netspot_delete(
 #[cfg(test)]
 mod tests {
     use crate::structures::configuration::NetspotConfig;
-    use crate::structures::status::Statuses;
+    use crate::structures::status::{ProcessAction, ProcessStatus, ProcessUpdates, Statuses};
     use crate::tests_common::TestSetup;
     use rocket::http::Status;
 
@@ -183,6 +186,14 @@ mod tests {
             .dispatch()
             .await;
         assert_eq!(response.status(), Status::Ok);
+        let updates = response
+            .into_json::<ProcessUpdates>()
+            .await
+            .expect("Valid JSON");
+        assert_eq!(updates.len(), 2);
+        assert_eq!(updates[0].action, ProcessAction::Unchanged);
+        assert_eq!(updates[1].id, 2);
+        assert_eq!(updates[1].action, ProcessAction::Updated);
 
         // 5. GET     /netspot/2   : Checks that test configuration changed
         let response = client.get("/v1/netspot/2").dispatch().await;
@@ -208,6 +219,77 @@ mod tests {
         setup.cleanup().await;
     }
 
+    // Sends the configuration to /netspot/2 and returns the action taken for it
+    async fn put_test_config(setup: &TestSetup, name: &str, enabled: bool) -> ProcessAction {
+        let response = setup
+            .client
+            .put("/v1/netspot/2")
+            .body(format!(
+                r#"{{"configuration": {{"name": "{name}", "device": "any", "enabled": {enabled}}}}}"#
+            ))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let updates = response
+            .into_json::<ProcessUpdates>()
+            .await
+            .expect("Valid JSON");
+        let update = updates.into_iter().find(|update| update.id == 2);
+        update.expect("Update for the test configuration").action
+    }
+
+    async fn test_config_status(setup: &TestSetup) -> ProcessStatus {
+        let response = setup.client.get("/v1/netspot/2/status").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let status = response
+            .into_json::<crate::structures::status::Status>()
+            .await
+            .expect("Valid JSON");
+        status.status
+    }
+
+    // This test does the following:
+    //
+    // 1. POST    /netspot     : Adds disabled test configuration
+    // 2. PUT     /netspot/2   : Same configuration is unchanged
+    // 3. PUT     /netspot/2   : Enabling starts the process
+    // 4. PUT     /netspot/2   : Changing running configuration restarts the process
+    // 5. PUT     /netspot/2   : Disabling stops the process
+    #[tokio::test]
+    async fn test_configuration_reconcile() {
+        let setup = TestSetup::new().await;
+        let client = &setup.client;
+
+        // 1. POST    /netspot     : Adds disabled test configuration
+        let response = client
+            .post("/v1/netspot")
+            .body(r#"{"configuration": {"name": "Test", "device": "any", "enabled": false}}"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Created);
+
+        // 2. PUT     /netspot/2   : Same configuration is unchanged
+        let action = put_test_config(&setup, "Test", false).await;
+        assert_eq!(action, ProcessAction::Unchanged);
+
+        // 3. PUT     /netspot/2   : Enabling starts the process
+        let action = put_test_config(&setup, "Test", true).await;
+        assert_eq!(action, ProcessAction::Started);
+        assert_eq!(test_config_status(&setup).await, ProcessStatus::Running);
+
+        // 4. PUT     /netspot/2   : Changing running configuration restarts the process
+        let action = put_test_config(&setup, "Test Changed", true).await;
+        assert_eq!(action, ProcessAction::Restarted);
+        assert_eq!(test_config_status(&setup).await, ProcessStatus::Running);
+
+        // 5. PUT     /netspot/2   : Disabling stops the process
+        let action = put_test_config(&setup, "Test Changed", false).await;
+        assert_eq!(action, ProcessAction::Stopped);
+        assert_eq!(test_config_status(&setup).await, ProcessStatus::Disabled);
+
+        setup.cleanup().await;
+    }
+
     // This test does the following:
     //
     // 1. POST    /netspot     : Invalid JSON
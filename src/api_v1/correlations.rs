@@ -0,0 +1,252 @@
+use crate::api_v1::rules::{self, ErrorResponse, ManagedRule};
+use crate::state::correlations::validate_rule;
+use crate::state::NetspotControlState;
+use crate::structures::correlations::{
+    CorrelationRule, CorrelationRules, Detection, DetectionQuery, Detections,
+};
+use crate::structures::rules::RuleList;
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::{delete, get, post, put, State};
+use rocket_okapi::openapi;
+
+// Number of detections returned when no time limits are given
+const DEFAULT_LAST: i32 = 100;
+
+impl ManagedRule for CorrelationRule {
+    fn validate(&self) -> Result<(), String> {
+        validate_rule(self)
+    }
+
+    fn use_rules(state: &NetspotControlState, rules: CorrelationRules) {
+        state.correlations.update(rules);
+    }
+}
+
+/// # List correlation rules
+///
+/// Correlation rules combine alarms for several stats into a named detection with a severity. A
+/// detection is sent when every stat of the rule has had an alarm within the window. Suppressed
+/// alarms are not combined.
+#[openapi(tag = "Correlations")]
+#[get("/correlations")]
+pub async fn correlations_list(
+    state: &State<NetspotControlState>,
+) -> Result<Json<RuleList>, Status> {
+    rules::list::<CorrelationRule>(state)
+}
+
+/// # Create a new correlation rule
+///
+/// The rule combines alarms received after it is created. A rule needs at least two different
+/// stats and a window of at least one second, and invalid rules are rejected with 422
+/// Unprocessable Entity and the reason.
+#[openapi(tag = "Correlations")]
+#[post("/correlations", data = "<new_rule>")]
+pub async fn correlation_add(
+    state: &State<NetspotControlState>,
+    new_rule: Json<CorrelationRule>,
+) -> Result<Status, ErrorResponse> {
+    rules::add(state, &*new_rule)
+}
+
+/// # Get correlation rule
+///
+/// Get correlation rule by ID
+#[openapi(tag = "Correlations")]
+#[get("/correlations/<id>")]
+pub async fn correlation_get(
+    state: &State<NetspotControlState>,
+    id: Result<i32, &str>,
+) -> Result<Option<Json<CorrelationRule>>, Status> {
+    rules::get(state, id)
+}
+
+/// # Update correlation rule
+///
+/// Update correlation rule by ID. The rule is checked the same way as when it is created, and
+/// changing any rule forgets the alarms waiting to be combined.
+#[openapi(tag = "Correlations")]
+#[put("/correlations/<id>", data = "<rule>")]
+pub async fn correlation_put(
+    state: &State<NetspotControlState>,
+    id: Result<i32, &str>,
+    rule: Json<CorrelationRule>,
+) -> Result<(), ErrorResponse> {
+    rules::put(state, id, &*rule)
+}
+
+/// # Delete correlation rule
+///
+/// Delete correlation rule by ID. Detections of the rule are kept.
+#[openapi(tag = "Correlations")]
+#[delete("/correlations/<id>")]
+pub async fn correlation_delete(
+    state: &State<NetspotControlState>,
+    id: Result<i32, &str>,
+) -> Result<(), Status> {
+    rules::delete::<CorrelationRule>(state, id)
+}
+
+/// # Read detections
+///
+/// Detections are stored when the correlation rules send them, and they are removed with the
+/// alarms of the same age unless the retention settings have a policy for the detections. The
+/// `from` time is inclusive and the `to` time is exclusive. Without time limits, only 100 last
+/// detections are returned. Live detections can be received from the webhooks, the stream and
+/// the WebSocket with the detection message type.
+#[openapi(tag = "Correlations")]
+#[get("/detections?<query..>")]
+pub async fn get_detections(
+    state: &State<NetspotControlState>,
+    mut query: DetectionQuery,
+) -> Result<Json<Detections>, Status> {
+    if query.last.is_none() && query.from.is_none() && query.to.is_none() {
+        query.last = Some(DEFAULT_LAST);
+    }
+    match state.database.get_detections(&query) {
+        Ok(detections) => Ok(Json(detections)),
+        Err(err) => {
+            eprintln!("Could not read detections: {err}");
+            Err(Status::InternalServerError)
+        }
+    }
+}
+
+/// # Get detection
+///
+/// Get detection by ID
+#[openapi(tag = "Correlations")]
+#[get("/detections/<id>")]
+pub async fn get_detection(
+    state: &State<NetspotControlState>,
+    id: Result<i32, &str>,
+) -> Result<Option<Json<Detection>>, Status> {
+    match id {
+        Ok(id) => Ok(state.database.get_detection(id).map(Json)),
+        Err(_) => Err(Status::BadRequest),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::structures::correlations::{CorrelationRule, Detections};
+    use crate::structures::rules::RuleList;
+    use crate::structures::statistics::{Severity, Stat};
+    use crate::tests_common::TestSetup;
+    use rocket::http::Status;
+    use rocket::local::asynchronous::Client;
+
+    async fn send_alarm(client: &Client, stat: &str) {
+        let response = client
+            .post("/v1/netspots/test/alarm")
+            .body(format!(r#"{{"stat": "{stat}"}}"#))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Created);
+    }
+
+    async fn get_detections(client: &Client, uri: &str) -> Detections {
+        let response = client.get(uri).dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        response.into_json::<Detections>().await.unwrap()
+    }
+
+    // This test does the following:
+    //
+    // 1. POST   /v1/correlations        : Expecting 422 for a rule with one stat
+    // 2. POST   /v1/correlations        : Adding a rule for R_SYN and TRAFFIC alarms
+    // 3. GET    /v1/correlations        : Checking that the rule was added
+    // 4. POST   /v1/netspots/test/alarm : Sending R_SYN and TRAFFIC alarms
+    // 5. GET    /v1/detections          : Checking that the detection was stored
+    // 6. GET    /v1/detections/1        : Checking the detection by id
+    // 7. PUT    /v1/correlations/1      : Updating the rule
+    // 8. GET    /v1/correlations/1      : Checking that the rule changed
+    // 9. DELETE /v1/correlations/1      : Deleting the rule, and again expecting 404 Not Found
+    #[tokio::test]
+    async fn test_correlations() {
+        let setup = TestSetup::new().await;
+        let client = &setup.client;
+
+        // 1. POST   /v1/correlations        : Expecting 422 for a rule with one stat
+        let response = client
+            .post("/v1/correlations")
+            .body(r#"{"name": "Scan", "stats": ["R_SYN"]}"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        assert_eq!(
+            response.into_string().await.unwrap(),
+            "Rule requires at least two stats"
+        );
+
+        // 2. POST   /v1/correlations        : Adding a rule for R_SYN and TRAFFIC alarms
+        let response = client
+            .post("/v1/correlations")
+            .body(r#"{"name": "Possible SYN scan", "stats": ["R_SYN", "TRAFFIC"], "severity": "high"}"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Created);
+
+        // 3. GET    /v1/correlations        : Checking that the rule was added
+        let response = client.get("/v1/correlations").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let rules = response.into_json::<RuleList>().await.unwrap();
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].id, 1);
+        assert_eq!(rules[0].name, "Possible SYN scan");
+
+        // 4. POST   /v1/netspots/test/alarm : Sending R_SYN and TRAFFIC alarms
+        send_alarm(client, "R_SYN").await;
+        assert!(get_detections(client, "/v1/detections").await.is_empty());
+        send_alarm(client, "TRAFFIC").await;
+        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+
+        // 5. GET    /v1/detections          : Checking that the detection was stored
+        let detections = get_detections(client, "/v1/detections").await;
+        assert_eq!(detections.len(), 1);
+        assert_eq!(detections[0].id, 1);
+        assert_eq!(detections[0].rule_id, 1);
+        assert_eq!(detections[0].name, "Possible SYN scan");
+        assert_eq!(detections[0].severity, Severity::High);
+        assert_eq!(detections[0].stats, vec![Stat::RSyn, Stat::Traffic]);
+        let uri = "/v1/detections?min_severity=critical";
+        assert!(get_detections(client, uri).await.is_empty());
+        let uri = "/v1/detections?min_severity=medium&rule_id=1";
+        assert_eq!(get_detections(client, uri).await.len(), 1);
+
+        // 6. GET    /v1/detections/1        : Checking the detection by id
+        let response = client.get("/v1/detections/1").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let response = client.get("/v1/detections/2").dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+
+        // 7. PUT    /v1/correlations/1      : Updating the rule
+        let response = client
+            .put("/v1/correlations/1")
+            .body(r#"{"name": "Scan", "stats": ["R_SYN", "R_DST_SRC_PORT"], "window": 30}"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+
+        // 8. GET    /v1/correlations/1      : Checking that the rule changed
+        let response = client.get("/v1/correlations/1").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let rule = response.into_json::<CorrelationRule>().await.unwrap();
+        assert_eq!(rule.stats, vec![Stat::RSyn, Stat::RDstSrcPort]);
+        assert_eq!(rule.window, 30);
+        assert_eq!(rule.severity, Severity::Info);
+
+        // 9. DELETE /v1/correlations/1      : Deleting the rule, and again expecting 404 Not Found
+        let response = client.delete("/v1/correlations/1").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let response = client.delete("/v1/correlations/1").dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+        let response = client.get("/v1/correlations/1").dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+        let response = client.get("/v1/correlations/foo").dispatch().await;
+        assert_eq!(response.status(), Status::BadRequest);
+
+        setup.cleanup().await;
+    }
+}
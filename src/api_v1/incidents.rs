@@ -0,0 +1,171 @@
+use crate::state::NetspotControlState;
+use crate::structures::incidents::{Incident, IncidentQuery, Incidents};
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::{get, State};
+use rocket_okapi::openapi;
+
+// Number of incidents returned when no time limits are given
+const DEFAULT_LAST: i32 = 100;
+
+/// # Read incidents
+///
+/// Alarms of the same configuration, name, series, stat and status are grouped into an incident
+/// while they keep coming within the incident window of the settings. The incident is closed
+/// after the window passes without new alarms.
+///
+/// Incidents are sent in addition to the alarms, which still go to every receiver. To get fewer
+/// messages, webhooks can receive only incidents with the incident message type, and the DHT with
+/// the `--dht-incidents` option.
+///
+/// The `from` time is inclusive and compared to the latest alarm, while the `to` time is
+/// exclusive and compared to the first alarm. Without time limits, only 100 last incidents are
+/// returned. Live incidents can be received from the webhooks, the stream and the WebSocket with
+/// the incident message type.
+#[openapi(tag = "Incidents")]
+#[get("/incidents?<query..>")]
+pub async fn get_incidents(
+    state: &State<NetspotControlState>,
+    mut query: IncidentQuery,
+) -> Result<Json<Incidents>, Status> {
+    if query.last.is_none() && query.from.is_none() && query.to.is_none() {
+        query.last = Some(DEFAULT_LAST);
+    }
+    match state.database.get_incidents(&query) {
+        Ok(incidents) => Ok(Json(incidents)),
+        Err(err) => {
+            eprintln!("Could not read incidents: {err}");
+            Err(Status::InternalServerError)
+        }
+    }
+}
+
+/// # Get incident
+///
+/// Get incident by ID
+#[openapi(tag = "Incidents")]
+#[get("/incidents/<id>")]
+pub async fn get_incident(
+    state: &State<NetspotControlState>,
+    id: Result<i32, &str>,
+) -> Result<Option<Json<Incident>>, Status> {
+    match id {
+        Ok(id) => Ok(state.database.get_incident(id).map(Json)),
+        Err(_) => Err(Status::BadRequest),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::structures::incidents::{Incident, IncidentState, Incidents};
+    use crate::structures::statistics::{AlertStatus, Stat};
+    use crate::tests_common::TestSetup;
+    use rocket::http::Status;
+    use rocket::local::asynchronous::Client;
+    use std::time::Duration;
+
+    // Polls the incident until the condition holds
+    async fn wait_incident(client: &Client, uri: &str, done: fn(&Incident) -> bool) -> Incident {
+        for _ in 0..100 {
+            let response = client.get(uri).dispatch().await;
+            if response.status() == Status::Ok {
+                let incident = response.into_json::<Incident>().await.unwrap();
+                if done(&incident) {
+                    return incident;
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("Incident {uri} did not reach the expected state");
+    }
+
+    // This test does the following:
+    //
+    // 1. POST /v1/netspots/test/alarm : Sending three alarms of the same stat and status
+    // 2. POST /v1/netspots/test/alarm : Sending an alarm of another stat
+    // 3. GET  /v1/incidents/1         : Three alarms should be in one open incident
+    // 4. GET  /v1/incidents           : Both incidents should be listed
+    // 5. PUT  /v1/settings            : Using a one second incident window
+    // 6. GET  /v1/incidents/1         : Incident should be closed after the window
+    // 7. GET  /v1/incidents?state=closed&stat=R_SYN : Only the first incident should be listed
+    // 8. GET  /v1/incidents/3         : Expecting 404 Not Found
+    // 9. GET  /v1/incidents/foo       : Expecting 400 Bad Request
+    #[tokio::test]
+    async fn test_incidents() {
+        let setup = TestSetup::new().await;
+        let client = &setup.client;
+
+        // 1. POST /v1/netspots/test/alarm : Sending three alarms of the same stat and status
+        for (value, probability) in [(10.0, 0.5), (30.0, 0.01), (20.0, 0.1)] {
+            let response = client
+                .post("/v1/netspots/test/alarm")
+                .body(format!(
+                    r#"{{"stat": "R_SYN", "value": {value}, "probability": {probability}}}"#
+                ))
+                .dispatch()
+                .await;
+            assert_eq!(response.status(), Status::Created);
+        }
+
+        // 2. POST /v1/netspots/test/alarm : Sending an alarm of another stat
+        let response = client
+            .post("/v1/netspots/test/alarm")
+            .body(r#"{"stat": "TRAFFIC"}"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Created);
+
+        // 3. GET  /v1/incidents/1         : Three alarms should be in one open incident
+        let incident =
+            wait_incident(client, "/v1/incidents/1", |incident| incident.count == 3).await;
+        assert_eq!(incident.stat, Stat::RSyn);
+        assert_eq!(incident.status, AlertStatus::UpAlert);
+        assert_eq!(incident.state, IncidentState::Open);
+        assert_eq!(incident.peak_value, 30.0);
+        assert_eq!(incident.min_probability, 0.01);
+        assert!(incident.first_seen <= incident.last_seen);
+
+        // 4. GET  /v1/incidents           : Both incidents should be listed
+        let response = client.get("/v1/incidents").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let incidents = response.into_json::<Incidents>().await.unwrap();
+        assert_eq!(incidents.len(), 2);
+        assert_eq!(incidents[1].stat, Stat::Traffic);
+
+        // 5. PUT  /v1/settings            : Using a one second incident window
+        let response = client
+            .put("/v1/settings")
+            .body(r#"{"incidents": {"window": 1}}"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+
+        // 6. GET  /v1/incidents/1         : Incident should be closed after the window
+        let incident = wait_incident(client, "/v1/incidents/1", |incident| {
+            incident.state == IncidentState::Closed
+        })
+        .await;
+        assert!(incident.closed.is_some());
+        assert_eq!(incident.count, 3);
+
+        // 7. GET  /v1/incidents?state=closed&stat=R_SYN : Only the first incident should be listed
+        let response = client
+            .get("/v1/incidents?state=closed&stat=R_SYN")
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let incidents = response.into_json::<Incidents>().await.unwrap();
+        assert_eq!(incidents.len(), 1);
+        assert_eq!(incidents[0].id, 1);
+
+        // 8. GET  /v1/incidents/3         : Expecting 404 Not Found
+        let response = client.get("/v1/incidents/3").dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+
+        // 9. GET  /v1/incidents/foo       : Expecting 400 Bad Request
+        let response = client.get("/v1/incidents/foo").dispatch().await;
+        assert_eq!(response.status(), Status::BadRequest);
+
+        setup.cleanup().await;
+    }
+}
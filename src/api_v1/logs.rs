@@ -0,0 +1,113 @@
+use crate::state::NetspotControlState;
+use crate::structures::logs::LogLines;
+use rocket::futures::stream::{BoxStream, StreamExt};
+use rocket::request::Request;
+use rocket::response::stream::{stream, TextStream};
+use rocket::response::{self, Responder};
+use rocket::serde::json::Json;
+use rocket::{get, Shutdown, State};
+use rocket_okapi::gen::OpenApiGenerator;
+use rocket_okapi::okapi::openapi3::Responses;
+use rocket_okapi::openapi;
+use rocket_okapi::response::OpenApiResponderInner;
+use tokio::sync::broadcast::error::RecvError;
+
+// Number of lines returned when the lines parameter is not given
+const DEFAULT_LINES: usize = 100;
+
+/// Netspot output is either a JSON array of lines, or a newline delimited JSON stream when the
+/// output is followed.
+pub enum NetspotLogs {
+    Lines(Json<LogLines>),
+    Follow(TextStream<BoxStream<'static, String>>),
+}
+
+impl<'r> Responder<'r, 'r> for NetspotLogs {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'r> {
+        match self {
+            NetspotLogs::Lines(lines) => lines.respond_to(request),
+            NetspotLogs::Follow(stream) => stream.respond_to(request),
+        }
+    }
+}
+
+impl OpenApiResponderInner for NetspotLogs {
+    fn responses(gen: &mut OpenApiGenerator) -> rocket_okapi::Result<Responses> {
+        Json::<LogLines>::responses(gen)
+    }
+}
+
+/// # Netspot service output
+///
+/// Returns the last lines netspot printed to its stdout and stderr for the configuration by ID.
+///
+/// Without parameters, only 100 last lines are returned. When `follow` is true, the response
+/// is kept open and new lines are streamed as newline delimited JSON.
+#[openapi(tag = "Status")]
+#[get("/netspot/<id>/logs?<lines>&<follow>")]
+pub async fn logs_by_id(
+    state: &State<NetspotControlState>,
+    id: i32,
+    lines: Option<usize>,
+    follow: Option<bool>,
+    mut shutdown: Shutdown,
+) -> Option<NetspotLogs> {
+    let lines = lines.unwrap_or(DEFAULT_LINES);
+    if !follow.unwrap_or(false) {
+        return match state.netspots.logs_by_id(id, lines).await {
+            Ok(lines) => Some(NetspotLogs::Lines(Json(lines))),
+            Err(_) => None,
+        };
+    }
+
+    let (lines, mut lines_rx) = state.netspots.follow_logs_by_id(id, lines).await.ok()?;
+    let stream = stream! {
+        for line in lines {
+            if let Ok(json) = serde_json::to_string(&line) {
+                yield format!("{json}\n");
+            }
+        }
+        loop {
+            tokio::select! {
+                result = lines_rx.recv() => match result {
+                    Ok(line) => {
+                        if let Ok(json) = serde_json::to_string(&line) {
+                            yield format!("{json}\n");
+                        }
+                    }
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                },
+                _ = &mut shutdown => break,
+            }
+        }
+    };
+    Some(NetspotLogs::Follow(TextStream(stream.boxed())))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::structures::logs::LogLines;
+    use crate::tests_common::TestSetup;
+    use rocket::http::Status;
+
+    #[tokio::test]
+    async fn test_logs() {
+        let setup = TestSetup::new().await;
+        let client = &setup.client;
+
+        // Default configuration should have logs available, even when empty
+        let response = client.get("/v1/netspot/1/logs?lines=10").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let lines = response.into_json::<LogLines>().await.expect("Valid JSON");
+        assert!(lines.len() <= 10);
+
+        // Unknown configurations should not have logs
+        let response = client.get("/v1/netspot/2/logs").dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+        let response = client.get("/v1/netspot/foo/logs").dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+
+        setup.cleanup().await;
+    }
+}
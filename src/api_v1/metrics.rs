@@ -0,0 +1,141 @@
+use crate::state::metrics::MetricsText;
+use crate::state::NetspotControlState;
+use crate::structures::statistics::MessageType;
+use crate::structures::status::ProcessStatus;
+use rocket::http::{ContentType, Status};
+use rocket::{get, State};
+
+/// # Prometheus metrics
+///
+/// Latest data message stats, alarm counts, netspot process statuses and internal counters in
+/// the Prometheus text format. Mounted at `/metrics`, outside of the versioned API.
+#[get("/metrics")]
+pub async fn metrics(state: &State<NetspotControlState>) -> Result<(ContentType, String), Status> {
+    let mut text = MetricsText::default();
+    state.metrics.write(&mut text);
+
+    let statuses = state.netspots.status_all().await;
+    text.family(
+        "netspot_process_up",
+        "gauge",
+        "Whether the netspot process is running",
+    );
+    for status in &statuses {
+        let up = matches!(
+            status.status,
+            ProcessStatus::Running | ProcessStatus::Restarting
+        );
+        text.sample(
+            "netspot_process_up",
+            &[
+                ("config_id", &status.id.to_string()),
+                ("name", &status.name),
+            ],
+            if up { 1.0 } else { 0.0 },
+        );
+    }
+    text.family(
+        "netspot_process_restarts_total",
+        "counter",
+        "Automatic restarts of the netspot process after a crash",
+    );
+    for status in &statuses {
+        text.sample(
+            "netspot_process_restarts_total",
+            &[
+                ("config_id", &status.id.to_string()),
+                ("name", &status.name),
+            ],
+            status.restarts as f64,
+        );
+    }
+
+    text.family(
+        "netspot_control_listener_messages_total",
+        "counter",
+        "Messages received from netspot by parse result",
+    );
+    for listener in state.netspots.listener_statuses() {
+        let socket = match listener.socket {
+            MessageType::Alarm => "alarm",
+            MessageType::Data => "data",
+            MessageType::Incident => "incident",
+            MessageType::Detection => "detection",
+        };
+        let counters = [
+            ("parsed", listener.messages.parsed),
+            ("malformed", listener.messages.malformed),
+            ("oversized", listener.messages.oversized),
+        ];
+        for (result, count) in counters {
+            text.sample(
+                "netspot_control_listener_messages_total",
+                &[("socket", socket), ("result", result)],
+                count as f64,
+            );
+        }
+    }
+
+    let (alarms, data) = state.database.count_messages().map_err(|err| {
+        eprintln!("Could not count stored messages: {err}");
+        Status::InternalServerError
+    })?;
+    text.family(
+        "netspot_control_database_rows",
+        "gauge",
+        "Messages stored in the database",
+    );
+    text.sample(
+        "netspot_control_database_rows",
+        &[("table", "alarms")],
+        alarms as f64,
+    );
+    text.sample(
+        "netspot_control_database_rows",
+        &[("table", "data")],
+        data as f64,
+    );
+
+    let content_type =
+        ContentType::new("text", "plain").with_params([("version", "0.0.4"), ("charset", "utf-8")]);
+    Ok((content_type, text.into_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests_common::TestSetup;
+    use rocket::http::Status;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_metrics() {
+        let setup = TestSetup::new().await;
+        let client = &setup.client;
+
+        let response = client
+            .post("/v1/netspots/test/alarm")
+            .body(r#"{"name": "Test", "stat": "R_SYN", "status": "UP_ALERT"}"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Created);
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        let response = client.get("/metrics").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let content_type = response.content_type().expect("Content type");
+        assert_eq!(
+            content_type.to_string(),
+            "text/plain; version=0.0.4; charset=utf-8"
+        );
+        let text = response.into_string().await.expect("Metrics text");
+        assert!(text.contains("netspot_alarms_total{stat=\"R_SYN\",status=\"UP_ALERT\"} 1\n"));
+        assert!(text.contains("netspot_process_up{config_id=\"1\",name=\"Default configuration\"}"));
+        assert!(text.contains("# TYPE netspot_stat gauge\n"));
+        assert!(text.contains(
+            "netspot_control_listener_messages_total{socket=\"alarm\",result=\"malformed\"} 0\n"
+        ));
+        assert!(text.contains("netspot_control_database_rows{table=\"alarms\"} 1\n"));
+
+        setup.cleanup().await;
+    }
+}
@@ -0,0 +1,563 @@
+use crate::state::database::DatabaseError;
+use crate::state::pcaps::read_capture;
+use crate::state::NetspotControlState;
+use crate::structures::replays::{Pcap, Pcaps, Replay, ReplayRequest, ReplayStatus, Replays};
+use rocket::data::{ByteUnit, Data};
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::serde::json::Json;
+use rocket::{delete, get, post, State};
+use rocket_okapi::openapi;
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Largest accepted capture file
+const MAX_PCAP_SIZE: ByteUnit = ByteUnit::Gibibyte(1);
+
+// Name given to the uploads without one
+const DEFAULT_PCAP_NAME: &str = "capture.pcap";
+
+// Errors have the reason as plain text when the request itself is invalid
+type ErrorResponse = status::Custom<String>;
+
+fn error_status(status: Status) -> ErrorResponse {
+    status::Custom(status, String::new())
+}
+
+fn unprocessable(reason: &str) -> ErrorResponse {
+    status::Custom(Status::UnprocessableEntity, reason.to_string())
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos() as i64
+}
+
+/// # Upload a capture file
+///
+/// The request body is the capture file as is, for example with `curl --data-binary @file.pcap`.
+/// Files that libpcap cannot read are rejected with 422 Unprocessable Entity and the reason, and
+/// files larger than 1 GiB with 413 Payload Too Large. Configurations replay the file when they
+/// have its id as `pcap`.
+#[openapi(tag = "Replays")]
+#[post("/pcaps?<name>", data = "<data>")]
+pub async fn pcap_upload(
+    state: &State<NetspotControlState>,
+    name: Option<String>,
+    data: Data<'_>,
+) -> Result<status::Created<Json<Pcap>>, ErrorResponse> {
+    let upload_path = state.pcaps.upload_path();
+    let file = match data.open(MAX_PCAP_SIZE).into_file(&upload_path).await {
+        Ok(file) => file,
+        Err(err) => {
+            eprintln!("Could not receive capture file: {err}");
+            return Err(error_status(Status::InternalServerError));
+        }
+    };
+    if !file.is_complete() {
+        let _ = fs::remove_file(&upload_path);
+        return Err(status::Custom(
+            Status::PayloadTooLarge,
+            "Capture file is larger than 1 GiB".to_string(),
+        ));
+    }
+
+    // Reading through the packets may take a while with large files
+    let path = upload_path.clone();
+    let pcap = match tokio::task::spawn_blocking(move || read_capture(&path)).await {
+        Ok(Ok(pcap)) => pcap,
+        Ok(Err(err)) => {
+            let _ = fs::remove_file(&upload_path);
+            return Err(status::Custom(Status::UnprocessableEntity, err));
+        }
+        Err(err) => {
+            let _ = fs::remove_file(&upload_path);
+            eprintln!("Could not read capture file: {err}");
+            return Err(error_status(Status::InternalServerError));
+        }
+    };
+    let mut pcap = Pcap {
+        name: name.unwrap_or_else(|| DEFAULT_PCAP_NAME.to_string()),
+        uploaded: now(),
+        ..pcap
+    };
+    pcap.id = match state.database.add_pcap(&pcap) {
+        Ok(id) => id,
+        Err(err) => {
+            let _ = fs::remove_file(&upload_path);
+            eprintln!("Could not store capture file: {err}");
+            return Err(error_status(Status::InternalServerError));
+        }
+    };
+    if let Err(err) = state.pcaps.keep(&upload_path, pcap.id) {
+        let _ = fs::remove_file(&upload_path);
+        let _ = state.database.delete_pcap(pcap.id);
+        eprintln!("Could not store capture file: {err}");
+        return Err(error_status(Status::InternalServerError));
+    }
+    Ok(status::Created::new(format!("/v1/pcaps/{}", pcap.id)).body(Json(pcap)))
+}
+
+/// # List capture files
+#[openapi(tag = "Replays")]
+#[get("/pcaps")]
+pub async fn pcaps_list(state: &State<NetspotControlState>) -> Result<Json<Pcaps>, Status> {
+    match state.database.get_pcaps() {
+        Ok(pcaps) => Ok(Json(pcaps)),
+        Err(err) => {
+            eprintln!("Could not list capture files: {err}");
+            Err(Status::InternalServerError)
+        }
+    }
+}
+
+/// # Get capture file
+///
+/// Get the details of the capture file by ID
+#[openapi(tag = "Replays")]
+#[get("/pcaps/<id>")]
+pub async fn pcap_get(
+    state: &State<NetspotControlState>,
+    id: Result<i32, &str>,
+) -> Result<Option<Json<Pcap>>, Status> {
+    match id {
+        Ok(id) => Ok(state.database.get_pcap(id).map(Json)),
+        Err(_) => Err(Status::BadRequest),
+    }
+}
+
+/// # Delete capture file
+///
+/// Delete capture file by ID. Replays of the file are kept. Capture files that a configuration
+/// has as `pcap`, or that are being replayed, give 409 Conflict with the reason.
+#[openapi(tag = "Replays")]
+#[delete("/pcaps/<id>")]
+pub async fn pcap_delete(
+    state: &State<NetspotControlState>,
+    id: Result<i32, &str>,
+) -> Result<(), ErrorResponse> {
+    if let Ok(id) = id {
+        return match state.database.delete_pcap(id) {
+            Ok(_) => {
+                if let Err(err) = state.pcaps.remove(id) {
+                    eprintln!("Could not remove capture file {id}: {err}");
+                }
+                Ok(())
+            }
+            Err(DatabaseError::NotFound) => Err(error_status(Status::NotFound)),
+            Err(DatabaseError::Conflict(reason)) => Err(status::Custom(Status::Conflict, reason)),
+            Err(DatabaseError::Unexpected(err)) => {
+                eprintln!("Could not delete capture file {id}: {err}");
+                Err(error_status(Status::InternalServerError))
+            }
+        };
+    }
+    Err(error_status(Status::BadRequest))
+}
+
+/// # Start a replay
+///
+/// Runs netspot over the capture file of the configuration, using the settings of the
+/// configuration. Configurations with a capture file are not run live, so they are typically made
+/// only for the replays. The replay is returned with the `running` status, or with the `failed`
+/// status when netspot could not be started. The progress of a running replay is updated every
+/// second. Alarms and data messages of the replay are stored with its id as `job_id`, and they
+/// are not sent to the webhooks, the stream, the WebSocket, the DHT or the rules, which handle
+/// live messages only.
+#[openapi(tag = "Replays")]
+#[post("/replays", data = "<request>")]
+pub async fn replay_start(
+    state: &State<NetspotControlState>,
+    request: Json<ReplayRequest>,
+) -> Result<status::Created<Json<Replay>>, ErrorResponse> {
+    let config = state
+        .database
+        .get_configuration(request.config_id)
+        .ok_or_else(|| unprocessable("Configuration not found"))?;
+    let pcap_id = config
+        .configuration
+        .pcap
+        .ok_or_else(|| unprocessable("Configuration has no capture file"))?;
+    let pcap = state
+        .database
+        .get_pcap(pcap_id)
+        .ok_or_else(|| unprocessable("Capture file not found"))?;
+    let mut replay = Replay {
+        id: 0,
+        config_id: request.config_id,
+        pcap_id,
+        status: ReplayStatus::Running,
+        started: now(),
+        finished: None,
+        progress: 0.0,
+        error: None,
+    };
+    replay.id = match state.database.add_replay(&replay) {
+        Ok(id) => id,
+        Err(err) => {
+            eprintln!("Could not store replay: {err}");
+            return Err(error_status(Status::InternalServerError));
+        }
+    };
+    let pcap_path = state.pcaps.file_path(pcap_id);
+    if let Err(err) = state
+        .netspots
+        .start_replay(replay.clone(), &config, pcap, &pcap_path)
+        .await
+    {
+        replay.status = ReplayStatus::Failed;
+        replay.finished = Some(now());
+        replay.error = Some(format!("Could not start netspot: {err}"));
+        if let Err(err) = state.database.set_replay(&replay) {
+            eprintln!("Could not store replay: {err}");
+        }
+    }
+    Ok(status::Created::new(format!("/v1/replays/{}", replay.id)).body(Json(replay)))
+}
+
+/// # List replays
+#[openapi(tag = "Replays")]
+#[get("/replays")]
+pub async fn replays_list(state: &State<NetspotControlState>) -> Result<Json<Replays>, Status> {
+    match state.database.get_replays() {
+        Ok(replays) => Ok(Json(replays)),
+        Err(err) => {
+            eprintln!("Could not list replays: {err}");
+            Err(Status::InternalServerError)
+        }
+    }
+}
+
+/// # Get replay
+///
+/// Get replay by ID with its status and progress
+#[openapi(tag = "Replays")]
+#[get("/replays/<id>")]
+pub async fn replay_get(
+    state: &State<NetspotControlState>,
+    id: Result<i32, &str>,
+) -> Result<Option<Json<Replay>>, Status> {
+    match id {
+        Ok(id) => Ok(state.database.get_replay(id).map(Json)),
+        Err(_) => Err(Status::BadRequest),
+    }
+}
+
+/// # Stop replay
+///
+/// Stops the running replay by ID, after which it has the `cancelled` status. Messages of the
+/// replay received so far are kept. Replays that are not running give 409 Conflict.
+#[openapi(tag = "Replays")]
+#[post("/replays/<id>/stop")]
+pub async fn replay_stop(
+    state: &State<NetspotControlState>,
+    id: Result<i32, &str>,
+) -> Result<(), Status> {
+    if let Ok(id) = id {
+        if state.netspots.stop_replay(id).await {
+            return Ok(());
+        }
+        return match state.database.get_replay(id) {
+            Some(_) => Err(Status::Conflict),
+            None => Err(Status::NotFound),
+        };
+    }
+    Err(Status::BadRequest)
+}
+
+/// # Delete replay
+///
+/// Delete replay by ID together with its alarms and data messages. Running replays must be
+/// stopped first, and they give 409 Conflict.
+#[openapi(tag = "Replays")]
+#[delete("/replays/<id>")]
+pub async fn replay_delete(
+    state: &State<NetspotControlState>,
+    id: Result<i32, &str>,
+) -> Result<(), Status> {
+    if let Ok(id) = id {
+        return match state.database.delete_replay(id) {
+            Ok(_) => Ok(()),
+            Err(DatabaseError::NotFound) => Err(Status::NotFound),
+            Err(DatabaseError::Conflict(_)) => Err(Status::Conflict),
+            Err(_) => Err(Status::InternalServerError),
+        };
+    }
+    Err(Status::BadRequest)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::state::NetspotControlState;
+    use crate::structures::replays::{Pcap, Pcaps, Replay, ReplayStatus, Replays};
+    use crate::structures::statistics::{AlarmMessage, AlarmMessages, Message};
+    use crate::structures::status::{ProcessStatus, Status as ProcessState};
+    use crate::tests_common::{wait_alarms, TestSetup};
+    use rocket::http::Status;
+    use rocket::local::asynchronous::Client;
+    use std::fs;
+    use std::time::Duration;
+    use tokio::process::Command;
+
+    // Capture file with two Ethernet frames one and a half seconds apart
+    fn capture_file() -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for value in [0xa1b2c3d4u32, 0x00040002, 0, 0, 65535, 1] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        for (seconds, microseconds) in [(1_600_000_000u32, 0u32), (1_600_000_001, 500_000)] {
+            for value in [seconds, microseconds, 14, 14] {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            bytes.extend_from_slice(&[0; 14]);
+        }
+        bytes
+    }
+
+    async fn get_replay(client: &Client, uri: &str) -> Replay {
+        let response = client.get(uri).dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        response.into_json::<Replay>().await.unwrap()
+    }
+
+    async fn get_alarms(client: &Client, uri: &str) -> AlarmMessages {
+        let response = client.get(uri).dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        response.into_json::<AlarmMessages>().await.unwrap()
+    }
+
+    // Polls the replay until the condition holds
+    async fn wait_replay(client: &Client, uri: &str, condition: fn(&Replay) -> bool) -> Replay {
+        for _ in 0..100 {
+            let replay = get_replay(client, uri).await;
+            if condition(&replay) {
+                return replay;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("Replay {uri} did not reach the expected state");
+    }
+
+    // Stores a running replay of the capture file, and runs the program in place of netspot
+    async fn start_replay(setup: &TestSetup, pcap: Pcap, program: &str, args: &[&str]) {
+        let state = setup
+            .client
+            .rocket()
+            .state::<NetspotControlState>()
+            .unwrap();
+        let mut replay = Replay {
+            id: 0,
+            config_id: 2,
+            pcap_id: pcap.id,
+            status: ReplayStatus::Running,
+            started: 0,
+            finished: None,
+            progress: 0.0,
+            error: None,
+        };
+        replay.id = state.database.add_replay(&replay).unwrap();
+        let toml_file_path = setup
+            .test_dir
+            .path()
+            .join(format!("replay_{}.toml", replay.id));
+        fs::write(&toml_file_path, "").unwrap();
+        let process = Command::new(program)
+            .args(args)
+            .kill_on_drop(true)
+            .spawn()
+            .unwrap();
+        state
+            .netspots
+            .run_replay(replay, pcap, toml_file_path, process)
+            .await;
+    }
+
+    // This test does the following:
+    //
+    // 1.  POST   /v1/pcaps           : Expecting 422 for a file that is not a capture file
+    // 2.  POST   /v1/pcaps           : Uploading a capture file with two packets
+    // 3.  GET    /v1/pcaps           : Checking that the capture file was added
+    // 4.  POST   /v1/replays         : Expecting 422 for a configuration without a capture file
+    // 5.  POST   /v1/netspot         : Adding a configuration for the capture file
+    // 6.  POST   /v1/replays         : Starting a replay of the configuration, and stopping it
+    // 7.  DELETE /v1/pcaps/1         : Expecting 409 while the configuration uses the capture file
+    // 8.  GET    /v1/replays/2       : Checking that a replay running a test process is running
+    // 9.  GET    /v1/netspots/alarms : Checking that replayed alarms are read by job id
+    // 10. DELETE /v1/pcaps/1         : Expecting 409 while the capture file is being replayed
+    // 11. DELETE /v1/replays/2       : Expecting 409 while the replay is running
+    // 12. POST   /v1/replays/2/stop  : Stopping the replay, and expecting 409 after that
+    // 13. GET    /v1/replays/3       : Checking that a replay whose process succeeds is completed
+    // 14. DELETE /v1/replays/2       : Deleting the replay and its alarms
+    // 15. DELETE /v1/pcaps/1         : Deleting the capture file, and expecting 404 after that
+    #[tokio::test]
+    async fn test_replays() {
+        let setup = TestSetup::new().await;
+        let client = &setup.client;
+        let state = client.rocket().state::<NetspotControlState>().unwrap();
+
+        // 1.  POST   /v1/pcaps           : Expecting 422 for a file that is not a capture file
+        let response = client
+            .post("/v1/pcaps?name=test.pcap")
+            .body("not a capture file")
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        assert!(response
+            .into_string()
+            .await
+            .unwrap()
+            .starts_with("Not a capture file"));
+
+        // 2.  POST   /v1/pcaps           : Uploading a capture file with two packets
+        let response = client
+            .post("/v1/pcaps?name=test.pcap")
+            .body(capture_file())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Created);
+        let pcap = response.into_json::<Pcap>().await.unwrap();
+        assert_eq!(pcap.id, 1);
+        assert_eq!(pcap.name, "test.pcap");
+        assert_eq!(pcap.packets, 2);
+        assert_eq!(pcap.size, capture_file().len() as i64);
+        assert_eq!(pcap.first_packet, Some(1_600_000_000_000_000_000));
+        assert_eq!(pcap.last_packet, Some(1_600_000_001_500_000_000));
+        assert!(setup.test_dir.path().join("pcaps/1.pcap").exists());
+
+        // 3.  GET    /v1/pcaps           : Checking that the capture file was added
+        let response = client.get("/v1/pcaps").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.into_json::<Pcaps>().await.unwrap(),
+            vec![pcap.clone()]
+        );
+
+        // 4.  POST   /v1/replays         : Expecting 422 for a configuration without a capture file
+        let response = client
+            .post("/v1/replays")
+            .body(r#"{"config_id": 1}"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        assert_eq!(
+            response.into_string().await.unwrap(),
+            "Configuration has no capture file"
+        );
+
+        // 5.  POST   /v1/netspot         : Adding a configuration for the capture file
+        let response = client
+            .post("/v1/netspot")
+            .body(r#"{"configuration": {"name": "Replay", "pcap": 1}}"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Created);
+        let response = client.get("/v1/netspot/2/status").dispatch().await;
+        let status = response.into_json::<ProcessState>().await.unwrap();
+        assert_eq!(status.status, ProcessStatus::Disabled);
+
+        // 6.  POST   /v1/replays         : Starting a replay of the configuration, and stopping it
+        let response = client
+            .post("/v1/replays")
+            .body(r#"{"config_id": 2}"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Created);
+        let replay = response.into_json::<Replay>().await.unwrap();
+        assert_eq!(replay.id, 1);
+        assert_eq!(replay.config_id, 2);
+        assert_eq!(replay.pcap_id, 1);
+        assert_eq!(get_replay(client, "/v1/replays/1").await.config_id, 2);
+        let response = client.get("/v1/replays").dispatch().await;
+        assert_eq!(response.into_json::<Replays>().await.unwrap().len(), 1);
+        state.netspots.stop_replay(1).await;
+        wait_replay(client, "/v1/replays/1", |replay| replay.finished.is_some()).await;
+
+        // 7.  DELETE /v1/pcaps/1         : Expecting 409 while the configuration uses the capture file
+        let response = client.delete("/v1/pcaps/1").dispatch().await;
+        assert_eq!(response.status(), Status::Conflict);
+        assert_eq!(
+            response.into_string().await.unwrap(),
+            "Capture file is used by configuration 2"
+        );
+        let response = client.delete("/v1/netspot/2").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+
+        // 8.  GET    /v1/replays/2       : Checking that a replay running a test process is running
+        start_replay(&setup, pcap.clone(), "sleep", &["30"]).await;
+        let replay = get_replay(client, "/v1/replays/2").await;
+        assert_eq!(replay.status, ReplayStatus::Running);
+        assert_eq!(replay.finished, None);
+
+        // 9.  GET    /v1/netspots/alarms : Checking that replayed alarms are read by job id
+        state
+            .database
+            .add_message(Message::Alarm(Box::new(AlarmMessage {
+                time: 1_600_000_001_000_000_000,
+                config_id: Some(2),
+                job_id: Some(2),
+                ..AlarmMessage::default()
+            })));
+        let alarms = wait_alarms(client, "/v1/netspots/alarms?job_id=2", 1).await;
+        assert_eq!(alarms[0].job_id, Some(2));
+        assert!(get_alarms(client, "/v1/netspots/alarms").await.is_empty());
+
+        // 10. DELETE /v1/pcaps/1         : Expecting 409 while the capture file is being replayed
+        let response = client.delete("/v1/pcaps/1").dispatch().await;
+        assert_eq!(response.status(), Status::Conflict);
+        assert_eq!(
+            response.into_string().await.unwrap(),
+            "Capture file is being replayed"
+        );
+
+        // 11. DELETE /v1/replays/2       : Expecting 409 while the replay is running
+        let response = client.delete("/v1/replays/2").dispatch().await;
+        assert_eq!(response.status(), Status::Conflict);
+
+        // 12. POST   /v1/replays/2/stop  : Stopping the replay, and expecting 409 after that
+        let response = client.post("/v1/replays/2/stop").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let replay = wait_replay(client, "/v1/replays/2", |replay| {
+            replay.status != ReplayStatus::Running
+        })
+        .await;
+        assert_eq!(replay.status, ReplayStatus::Cancelled);
+        assert!(replay.finished.is_some());
+        let response = client.post("/v1/replays/2/stop").dispatch().await;
+        assert_eq!(response.status(), Status::Conflict);
+        let response = client.post("/v1/replays/9/stop").dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+
+        // 13. GET    /v1/replays/3       : Checking that a replay whose process succeeds is completed
+        start_replay(&setup, pcap, "true", &[]).await;
+        let replay = wait_replay(client, "/v1/replays/3", |replay| {
+            replay.status != ReplayStatus::Running
+        })
+        .await;
+        assert_eq!(replay.status, ReplayStatus::Completed);
+        assert_eq!(replay.progress, 1.0);
+        assert_eq!(replay.error, None);
+        assert!(!setup.test_dir.path().join("replay_3.toml").exists());
+
+        // 14. DELETE /v1/replays/2       : Deleting the replay and its alarms
+        let response = client.delete("/v1/replays/2").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        assert!(get_alarms(client, "/v1/netspots/alarms?job_id=2")
+            .await
+            .is_empty());
+        let response = client.get("/v1/replays/2").dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+
+        // 15. DELETE /v1/pcaps/1         : Deleting the capture file, and expecting 404 after that
+        let response = client.delete("/v1/pcaps/1").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        assert!(!setup.test_dir.path().join("pcaps/1.pcap").exists());
+        let response = client.delete("/v1/pcaps/1").dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+        let response = client.get("/v1/pcaps/foo").dispatch().await;
+        assert_eq!(response.status(), Status::BadRequest);
+
+        setup.cleanup().await;
+    }
+}
@@ -0,0 +1,110 @@
+use crate::state::database::{DatabaseError, JsonRule};
+use crate::state::NetspotControlState;
+use crate::structures::rules::RuleList;
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::serde::json::Json;
+use std::collections::HashMap;
+
+// Correlation, suppression and threshold rules have the same endpoints, which only call these
+// functions with the type of the rule
+//--------------------------------------------------------------------------------------------------
+
+// Errors have the reason as plain text when the rule itself is invalid
+pub type ErrorResponse = status::Custom<String>;
+
+pub trait ManagedRule: JsonRule {
+    // Checks the rule before it is stored
+    fn validate(&self) -> Result<(), String>;
+
+    // Takes the stored rules into use after they have changed
+    fn use_rules(state: &NetspotControlState, rules: HashMap<i32, Self>);
+}
+
+fn error_status(status: Status) -> ErrorResponse {
+    status::Custom(status, String::new())
+}
+
+fn validate<T: ManagedRule>(rule: &T) -> Result<(), ErrorResponse> {
+    rule.validate()
+        .map_err(|err| status::Custom(Status::UnprocessableEntity, err))
+}
+
+fn update_rules<T: ManagedRule>(state: &NetspotControlState) {
+    match state.database.get_rules::<T>() {
+        Ok(rules) => {
+            T::use_rules(state, rules);
+        }
+        Err(err) => {
+            println!("Unexpected: Could not get {}s {err}", T::KIND);
+        }
+    }
+}
+
+pub fn list<T: ManagedRule>(state: &NetspotControlState) -> Result<Json<RuleList>, Status> {
+    match state.database.list_rules::<T>() {
+        Ok(rules) => Ok(Json(rules)),
+        Err(err) => {
+            eprintln!("Could not list {}s: {err}", T::KIND);
+            Err(Status::InternalServerError)
+        }
+    }
+}
+
+pub fn add<T: ManagedRule>(
+    state: &NetspotControlState,
+    new_rule: &T,
+) -> Result<Status, ErrorResponse> {
+    validate(new_rule)?;
+    if state.database.add_rule(new_rule).is_ok() {
+        update_rules::<T>(state);
+        return Ok(Status::Created);
+    }
+    Err(error_status(Status::BadRequest))
+}
+
+pub fn get<T: ManagedRule>(
+    state: &NetspotControlState,
+    id: Result<i32, &str>,
+) -> Result<Option<Json<T>>, Status> {
+    match id {
+        Ok(id) => Ok(state.database.get_rule(id).map(Json)),
+        Err(_) => Err(Status::BadRequest),
+    }
+}
+
+pub fn put<T: ManagedRule>(
+    state: &NetspotControlState,
+    id: Result<i32, &str>,
+    rule: &T,
+) -> Result<(), ErrorResponse> {
+    if let Ok(id) = id {
+        validate(rule)?;
+        return match state.database.set_rule(id, rule) {
+            Ok(_) => {
+                update_rules::<T>(state);
+                Ok(())
+            }
+            Err(DatabaseError::NotFound) => Err(error_status(Status::NotFound)),
+            Err(_) => Err(error_status(Status::InternalServerError)),
+        };
+    }
+    Err(error_status(Status::BadRequest))
+}
+
+pub fn delete<T: ManagedRule>(
+    state: &NetspotControlState,
+    id: Result<i32, &str>,
+) -> Result<(), Status> {
+    if let Ok(id) = id {
+        return match state.database.delete_rule::<T>(id) {
+            Ok(_) => {
+                update_rules::<T>(state);
+                Ok(())
+            }
+            Err(DatabaseError::NotFound) => Err(Status::NotFound),
+            Err(_) => Err(Status::InternalServerError),
+        };
+    }
+    Err(Status::BadRequest)
+}
@@ -0,0 +1,166 @@
+use crate::state::NetspotControlState;
+use crate::structures::settings::{RetentionStatus, Settings};
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::serde::json::Json;
+use rocket::{get, put, State};
+use rocket_okapi::openapi;
+
+// Errors have the reason as plain text when the settings are invalid
+type ErrorResponse = status::Custom<String>;
+
+/// # Get server settings
+///
+/// Returns settings currently in use, including any overrides given from the command line.
+#[openapi(tag = "Settings")]
+#[get("/settings")]
+pub async fn settings_get(state: &State<NetspotControlState>) -> Json<Settings> {
+    Json(state.database.get_settings())
+}
+
+/// # Update server settings
+///
+/// Stores new settings to the database and takes them into use immediately. Stored settings
+/// replace command line overrides until the server is restarted. Limits that do not fit in
+/// message times or row counts and invalid severity settings are rejected with 422 and the
+/// reason.
+#[openapi(tag = "Settings")]
+#[put("/settings", data = "<settings>")]
+pub async fn settings_put(
+    state: &State<NetspotControlState>,
+    settings: Json<Settings>,
+) -> Result<(), ErrorResponse> {
+    settings
+        .validate()
+        .map_err(|err| status::Custom(Status::UnprocessableEntity, err))?;
+    match state.database.set_settings(&settings) {
+        Ok(_) => Ok(()),
+        Err(err) => {
+            eprintln!("Could not store settings: {err}");
+            Err(status::Custom(Status::InternalServerError, String::new()))
+        }
+    }
+}
+
+/// # Message retention status
+///
+/// Returns the retention policy in use and the result of the latest database cleanup.
+#[openapi(tag = "Settings")]
+#[get("/settings/retention")]
+pub async fn retention_status(state: &State<NetspotControlState>) -> Json<RetentionStatus> {
+    Json(state.database.get_retention_status())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::structures::settings::{RetentionStatus, Settings};
+    use crate::structures::statistics::{AlarmMessages, Severity, Stat};
+    use crate::tests_common::{wait_alarms, TestSetup};
+    use rocket::http::Status;
+
+    #[tokio::test]
+    async fn test_settings() {
+        let setup = TestSetup::new().await;
+        let client = &setup.client;
+
+        // New database should use the default settings
+        let response = client.get("/v1/settings").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let settings = response.into_json::<Settings>().await.expect("Valid JSON");
+        assert_eq!(settings, Settings::default());
+
+        // Changing retention policy
+        let response = client
+            .put("/v1/settings")
+            .body(
+                r#"{
+    "retention": {
+        "alarms": {"max_age": 604800},
+        "data": {"max_age": 86400, "max_rows": 100000},
+        "max_database_size": 104857600
+    }
+}"#,
+            )
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+
+        let response = client.get("/v1/settings").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let settings = response.into_json::<Settings>().await.expect("Valid JSON");
+        assert_eq!(settings.retention.alarms.max_age, Some(604800));
+        assert_eq!(settings.retention.alarms.max_rows, None);
+        assert_eq!(settings.retention.data.max_age, Some(86400));
+        assert_eq!(settings.retention.data.max_rows, Some(100000));
+        assert_eq!(settings.retention.max_database_size, Some(104857600));
+
+        // Retention status should show the new policy
+        let response = client.get("/v1/settings/retention").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let status = response
+            .into_json::<RetentionStatus>()
+            .await
+            .expect("Valid JSON");
+        assert_eq!(status.policy, settings.retention);
+
+        // Severity settings should be used for the next alarms
+        let response = client
+            .put("/v1/settings")
+            .body(r#"{"severity": {"stats": {"TRAFFIC": "high"}}}"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        for stat in ["TRAFFIC", "R_SYN"] {
+            let response = client
+                .post("/v1/netspots/test/alarm")
+                .body(format!(r#"{{"stat": "{stat}"}}"#))
+                .dispatch()
+                .await;
+            assert_eq!(response.status(), Status::Created);
+        }
+        let alarms = wait_alarms(client, "/v1/netspots/alarms", 2).await;
+        assert_eq!(alarms[0].severity, Some(Severity::High));
+        assert_eq!(alarms[1].severity, Some(Severity::Low));
+        let uri = "/v1/netspots/alarms?min_severity=medium";
+        let response = client.get(uri).dispatch().await;
+        let alarms = response.into_json::<AlarmMessages>().await.unwrap();
+        assert_eq!(alarms.len(), 1);
+        assert_eq!(alarms[0].stat, Stat::Traffic);
+
+        // Invalid settings should be rejected
+        let response = client
+            .put("/v1/settings")
+            .body(r#"{"retention": {"alarms": {"max_age": -1}}}"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        let response = client
+            .put("/v1/settings")
+            .body(r#"{"retention": {"data": {"max_age": 18446744073709551615}}}"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        let reason = response.into_string().await.unwrap();
+        assert!(
+            reason.starts_with("Maximum age of data messages"),
+            "{reason}"
+        );
+        let response = client.get("/v1/settings").dispatch().await;
+        let settings = response.into_json::<Settings>().await.expect("Valid JSON");
+        assert_eq!(settings.retention, Settings::default().retention);
+        for severity in [
+            r#"{"bands": [{"max_probability": 2.0, "raise": 1}]}"#,
+            r#"{"escalation": {"window": 300, "repeats": 0}}"#,
+            r#"{"escalation": {"window": 18446744073709551615, "repeats": 2}}"#,
+        ] {
+            let response = client
+                .put("/v1/settings")
+                .body(format!(r#"{{"severity": {severity}}}"#))
+                .dispatch()
+                .await;
+            assert_eq!(response.status(), Status::UnprocessableEntity, "{severity}");
+        }
+
+        setup.cleanup().await;
+    }
+}
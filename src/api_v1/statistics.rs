@@ -1,57 +1,199 @@
-use crate::structures::statistics::{AlarmMessages, DataMessages};
+use crate::state::database::DatabaseError;
+use crate::structures::statistics::{
+    AlarmMessage, AlarmMessages, AlarmQuery, AlarmReviewUpdate, DataMessages, DataQuery,
+    MessageCursor,
+};
 use crate::NetspotControlState;
+use rocket::request::Request;
+use rocket::response::{self, Responder};
 use rocket::serde::json::Json;
-use rocket::{get, http, State};
+use rocket::{get, http, patch, State};
+use rocket_okapi::gen::OpenApiGenerator;
+use rocket_okapi::okapi::openapi3::Responses;
+use rocket_okapi::okapi::schemars::JsonSchema;
 use rocket_okapi::openapi;
+use rocket_okapi::response::OpenApiResponderInner;
+use serde::Serialize;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Number of items returned when no other limits are given
+const DEFAULT_LAST: i32 = 100;
+
+// Page size when paging without a limit, and the largest allowed page
+const DEFAULT_PAGE_SIZE: i32 = 100;
+const MAX_PAGE_SIZE: i32 = 10000;
+
+// Checks paging parameters and returns the cursor and the page size to use
+fn paging(
+    cursor: &Option<String>,
+    limit: Option<i32>,
+    last: Option<i32>,
+) -> Result<(Option<MessageCursor>, Option<i32>), http::Status> {
+    if matches!(last, Some(last) if last < 0) {
+        return Err(http::Status::UnprocessableEntity);
+    }
+    let cursor = match cursor {
+        Some(cursor) => Some(
+            cursor
+                .parse::<MessageCursor>()
+                .map_err(|_| http::Status::BadRequest)?,
+        ),
+        None => None,
+    };
+    let limit = match (limit, cursor) {
+        (Some(limit), _) => Some(limit.clamp(1, MAX_PAGE_SIZE)),
+        (None, Some(_)) => Some(DEFAULT_PAGE_SIZE),
+        (None, None) => None,
+    };
+    if limit.is_some() && last.is_some() {
+        // Paging goes forward in time, while last reads backwards from the newest message
+        return Err(http::Status::BadRequest);
+    }
+    Ok((cursor, limit))
+}
+
+/// Messages are always a JSON array. When more messages are available, the `Link` header has
+/// the address of the next page, which is the same request with the `cursor` parameter.
+pub struct MessagePage<T> {
+    messages: Json<T>,
+    next: Option<MessageCursor>,
+}
+
+impl<'r, T: Serialize> Responder<'r, 'static> for MessagePage<T> {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let mut response = self.messages.respond_to(request)?;
+        if let Some(next) = self.next {
+            let uri = request.uri();
+            let mut query: Vec<&str> = uri
+                .query()
+                .map(|query| query.as_str().split('&').collect())
+                .unwrap_or_default();
+            query.retain(|field| !field.is_empty() && !field.starts_with("cursor="));
+            let cursor = format!("cursor={next}");
+            query.push(&cursor);
+            let link = format!("<{}?{}>; rel=\"next\"", uri.path(), query.join("&"));
+            response.set_raw_header("Link", link);
+        }
+        Ok(response)
+    }
+}
+
+impl<T: Serialize + JsonSchema + Send> OpenApiResponderInner for MessagePage<T> {
+    fn responses(gen: &mut OpenApiGenerator) -> rocket_okapi::Result<Responses> {
+        Json::<T>::responses(gen)
+    }
+}
 
 /// # Read alarms from netspot statistics
 ///
 /// Reads recorded alarms from netspot statistics.
 ///
-/// We can use parameters to limit which results are returned.
-/// Without parameters, only 100 last items are returned.
+/// We can use parameters to limit which results are returned. The `from` time is inclusive and
+/// the `to` time is exclusive. Without parameters, only 100 last items are returned.
+///
+/// Giving `limit` returns results in pages of that size. When more results are available, the
+/// `Link` header has the address of the next page with `rel="next"`, which repeats the filters
+/// and adds the `cursor` parameter. Paging cannot be combined with `last`, and a negative `last`
+/// gives 422 Unprocessable Entity.
 #[openapi(tag = "Statistics")]
-#[get("/netspots/alarms?<time>&<last>")]
+#[get("/netspots/alarms?<query..>")]
 pub async fn get_alarms(
     state: &State<NetspotControlState>,
-    time: Option<i64>,
-    mut last: Option<i32>,
-) -> Result<Json<AlarmMessages>, http::Status> {
-    if time.is_none() && last.is_none() {
-        last = Some(100);
+    mut query: AlarmQuery,
+) -> Result<MessagePage<AlarmMessages>, http::Status> {
+    let (cursor, limit) = paging(&query.cursor, query.limit, query.last)?;
+    query.limit = limit;
+    if query.time.is_none()
+        && query.last.is_none()
+        && query.from.is_none()
+        && query.to.is_none()
+        && query.limit.is_none()
+    {
+        query.last = Some(DEFAULT_LAST);
     }
-    match state.database.get_alarms(time, last) {
-        Ok(results) => Ok(Json(results)),
+    match state.database.get_alarms(&query, cursor) {
+        Ok((messages, next)) => Ok(MessagePage {
+            messages: Json(messages),
+            next,
+        }),
         Err(_) => Err(http::Status::InternalServerError),
     }
 }
 
+/// # Review alarm
+///
+/// Marks a stored alarm as `acknowledged`, `false_positive` or `investigated`, and attaches a
+/// free-text note to it. The `user` is required and replaces the previous reviewer, while a
+/// missing `label` or `note` keeps the previous value and `null` removes it. The review time is
+/// set by the server.
+///
+/// Returns the updated alarm. Stored alarms have their `id` in the alarm listing, which can be
+/// filtered by `label`, `reviewed` and `reviewed_by`.
+#[openapi(tag = "Statistics")]
+#[patch("/netspots/alarms/<id>", data = "<review>")]
+pub async fn review_alarm(
+    state: &State<NetspotControlState>,
+    id: Result<i32, &str>,
+    review: Json<AlarmReviewUpdate>,
+) -> Result<Option<Json<AlarmMessage>>, http::Status> {
+    let id = id.map_err(|_| http::Status::BadRequest)?;
+    if review.user.trim().is_empty() {
+        return Err(http::Status::BadRequest);
+    }
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos() as i64;
+    match state.database.set_alarm_review(id, &review, now) {
+        Ok(alarm) => Ok(Some(Json(alarm))),
+        Err(DatabaseError::NotFound) => Ok(None),
+        Err(DatabaseError::Conflict(err)) | Err(DatabaseError::Unexpected(err)) => {
+            eprintln!("Could not review alarm({id}): {err}");
+            Err(http::Status::InternalServerError)
+        }
+    }
+}
+
 /// # Read netspot statistics
 ///
 /// Reads recorded netspot statistics.
 ///
-/// We can use parameters to limit which results are returned.
-/// Without parameters, only 100 last items are returned.
+/// We can use parameters to limit which results are returned. The `from` time is inclusive and
+/// the `to` time is exclusive. Without parameters, only 100 last items are returned.
+///
+/// Giving `limit` returns results in pages of that size. When more results are available, the
+/// `Link` header has the address of the next page with `rel="next"`, which repeats the filters
+/// and adds the `cursor` parameter. Paging cannot be combined with `last`, and a negative `last`
+/// gives 422 Unprocessable Entity.
 #[openapi(tag = "Statistics")]
-#[get("/netspots/data?<time>&<last>")]
+#[get("/netspots/data?<query..>")]
 pub async fn get_data(
     state: &State<NetspotControlState>,
-    time: Option<i64>,
-    mut last: Option<i32>,
-) -> Result<Json<DataMessages>, http::Status> {
-    if time.is_none() && last.is_none() {
-        last = Some(100);
+    mut query: DataQuery,
+) -> Result<MessagePage<DataMessages>, http::Status> {
+    let (cursor, limit) = paging(&query.cursor, query.limit, query.last)?;
+    query.limit = limit;
+    if query.time.is_none()
+        && query.last.is_none()
+        && query.from.is_none()
+        && query.to.is_none()
+        && query.limit.is_none()
+    {
+        query.last = Some(DEFAULT_LAST);
     }
-    match state.database.get_data(time, last) {
-        Ok(results) => Ok(Json(results)),
+    match state.database.get_data(&query, cursor) {
+        Ok((messages, next)) => Ok(MessagePage {
+            messages: Json(messages),
+            next,
+        }),
         Err(_) => Err(http::Status::InternalServerError),
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::structures::statistics::{AlarmMessages, DataMessages};
-    use crate::tests_common::TestSetup;
+    use crate::structures::statistics::{AlarmLabel, AlarmMessage, AlarmMessages, DataMessages};
+    use crate::tests_common::{wait_alarms, TestSetup};
     use rocket::http::Status;
 
     #[tokio::test]
@@ -97,6 +239,264 @@ mod tests {
         assert_eq!(messages.len(), 1);
         assert_eq!(messages[0].name, "Test");
 
+        // Test alarm does not belong to any configuration
+        let response = client
+            .get("/v1/netspots/alarms?config_id=1")
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let messages = response
+            .into_json::<AlarmMessages>()
+            .await
+            .expect("Valid JSON");
+        assert!(messages.is_empty());
+
+        // Data messages come from the default configuration
+        let response = client.get("/v1/netspots/data?config_id=1").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let messages = response
+            .into_json::<DataMessages>()
+            .await
+            .expect("Valid JSON");
+        assert!(!messages.is_empty());
+        assert!(messages.iter().all(|message| message.config_id == Some(1)));
+
+        setup.cleanup().await;
+    }
+
+    #[tokio::test]
+    async fn test_alarm_paging() {
+        let setup = TestSetup::new().await;
+        let client = &setup.client;
+
+        // Inserting alarms with different stats and probabilities
+        for (stat, probability) in [
+            ("PERF", 0.1),
+            ("R_SYN", 0.2),
+            ("PERF", 0.3),
+            ("R_SYN", 0.4),
+            ("PERF", 0.5),
+        ] {
+            let response = client
+                .post("/v1/netspots/test/alarm")
+                .body(format!(
+                    r#"{{"name": "Paging", "stat": "{stat}", "probability": {probability}}}"#
+                ))
+                .dispatch()
+                .await;
+            assert_eq!(response.status(), Status::Created);
+        }
+        wait_alarms(client, "/v1/netspots/alarms?name=Paging", 5).await;
+
+        // Reading all alarms two at a time
+        let mut probabilities = Vec::new();
+        let mut uri = "/v1/netspots/alarms?name=Paging&limit=2".to_string();
+        let mut pages = 0;
+        loop {
+            let response = client.get(uri.clone()).dispatch().await;
+            assert_eq!(response.status(), Status::Ok);
+            let next = response.headers().get_one("Link").map(|link| {
+                assert!(link.ends_with(r#">; rel="next""#));
+                link[1..link.find('>').expect("Link address")].to_string()
+            });
+            let messages = response
+                .into_json::<AlarmMessages>()
+                .await
+                .expect("Valid JSON");
+            assert!(messages.len() <= 2);
+            probabilities.extend(messages.iter().map(|message| message.probability));
+            pages += 1;
+            match next {
+                Some(next) => {
+                    assert!(next.starts_with("/v1/netspots/alarms?name=Paging&limit=2&cursor="));
+                    uri = next;
+                }
+                None => break,
+            }
+        }
+        assert_eq!(pages, 3);
+        assert_eq!(probabilities, vec![0.1, 0.2, 0.3, 0.4, 0.5]);
+
+        // Filtering by stat and probability
+        let response = client
+            .get("/v1/netspots/alarms?stat=PERF&min_probability=0.3")
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let messages = response
+            .into_json::<AlarmMessages>()
+            .await
+            .expect("Valid JSON");
+        assert_eq!(messages.len(), 2);
+        assert!(messages.iter().all(|message| message.probability >= 0.3));
+
+        // Name that no configuration uses
+        let response = client.get("/v1/netspots/alarms?name=None").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let messages = response
+            .into_json::<AlarmMessages>()
+            .await
+            .expect("Valid JSON");
+        assert!(messages.is_empty());
+
+        // Invalid cursor and conflicting parameters
+        let response = client
+            .get("/v1/netspots/alarms?cursor=foo")
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::BadRequest);
+        let response = client
+            .get("/v1/netspots/alarms?limit=2&last=2")
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::BadRequest);
+        let response = client.get("/v1/netspots/alarms?last=-1").dispatch().await;
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+
+        setup.cleanup().await;
+    }
+
+    // This test does the following:
+    //
+    // 1. POST  /v1/netspots/test/alarm : Sending two alarms
+    // 2. GET   /v1/netspots/alarms     : Stored alarms should have ids but no reviews yet
+    // 3. PATCH /v1/netspots/alarms/1   : Acknowledging the first alarm with a note
+    // 4. PATCH /v1/netspots/alarms/1   : Changing the label should keep the note
+    // 5. GET   /v1/netspots/alarms?label=false_positive : Filtering by the review
+    // 6. PATCH /v1/netspots/alarms/1   : Null should remove the label and the note
+    // 7. PATCH /v1/netspots/alarms/1   : Expecting 400 Bad Request for a blank user
+    // 8. PATCH /v1/netspots/alarms/1   : Expecting 422 Unprocessable Entity for an unknown label
+    // 9. PATCH /v1/netspots/alarms/1000 : Expecting 404 Not Found
+    // 10. PATCH /v1/netspots/alarms/foo : Expecting 400 Bad Request
+    #[tokio::test]
+    async fn test_alarm_review() {
+        let setup = TestSetup::new().await;
+        let client = &setup.client;
+
+        // 1. POST  /v1/netspots/test/alarm : Sending two alarms
+        for name in ["First", "Second"] {
+            let response = client
+                .post("/v1/netspots/test/alarm")
+                .body(format!(r#"{{"name": "{name}"}}"#))
+                .dispatch()
+                .await;
+            assert_eq!(response.status(), Status::Created);
+        }
+
+        // 2. GET   /v1/netspots/alarms     : Stored alarms should have ids but no reviews yet
+        let messages = wait_alarms(client, "/v1/netspots/alarms", 2).await;
+        assert!(messages.iter().all(|message| message.review.is_none()));
+        let id = messages[0].id.expect("Stored alarm id");
+
+        // 3. PATCH /v1/netspots/alarms/1   : Acknowledging the first alarm with a note
+        let response = client
+            .patch(format!("/v1/netspots/alarms/{id}"))
+            .body(r#"{"user": "alice", "label": "acknowledged", "note": "Looking into it"}"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let alarm = response
+            .into_json::<AlarmMessage>()
+            .await
+            .expect("Valid JSON");
+        let review = alarm.review.expect("Alarm review");
+        assert_eq!(review.user, "alice");
+        assert_eq!(review.label, Some(AlarmLabel::Acknowledged));
+        assert!(review.time > 0);
+
+        // 4. PATCH /v1/netspots/alarms/1   : Changing the label should keep the note
+        let response = client
+            .patch(format!("/v1/netspots/alarms/{id}"))
+            .body(r#"{"user": "bob", "label": "false_positive"}"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let alarm = response
+            .into_json::<AlarmMessage>()
+            .await
+            .expect("Valid JSON");
+        let review = alarm.review.expect("Alarm review");
+        assert_eq!(review.user, "bob");
+        assert_eq!(review.label, Some(AlarmLabel::FalsePositive));
+        assert_eq!(review.note.as_deref(), Some("Looking into it"));
+
+        // 5. GET   /v1/netspots/alarms?label=false_positive : Filtering by the review
+        for (query, expected) in [
+            ("label=false_positive", "First"),
+            ("label=false_positive&reviewed_by=bob", "First"),
+            ("reviewed=true", "First"),
+            ("reviewed=false", "Second"),
+        ] {
+            let response = client
+                .get(format!("/v1/netspots/alarms?{query}"))
+                .dispatch()
+                .await;
+            assert_eq!(response.status(), Status::Ok);
+            let messages = response
+                .into_json::<AlarmMessages>()
+                .await
+                .expect("Valid JSON");
+            assert_eq!(messages.len(), 1, "{query}");
+            assert_eq!(messages[0].name, expected, "{query}");
+        }
+        let response = client
+            .get("/v1/netspots/alarms?label=investigated")
+            .dispatch()
+            .await;
+        let messages = response
+            .into_json::<AlarmMessages>()
+            .await
+            .expect("Valid JSON");
+        assert!(messages.is_empty());
+
+        // 6. PATCH /v1/netspots/alarms/1   : Null should remove the label and the note
+        let response = client
+            .patch(format!("/v1/netspots/alarms/{id}"))
+            .body(r#"{"user": "carol", "label": null, "note": null}"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let alarm = response
+            .into_json::<AlarmMessage>()
+            .await
+            .expect("Valid JSON");
+        let review = alarm.review.expect("Alarm review");
+        assert_eq!(review.user, "carol");
+        assert_eq!(review.label, None);
+        assert_eq!(review.note, None);
+
+        // 7. PATCH /v1/netspots/alarms/1   : Expecting 400 Bad Request for a blank user
+        let response = client
+            .patch(format!("/v1/netspots/alarms/{id}"))
+            .body(r#"{"user": " "}"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::BadRequest);
+
+        // 8. PATCH /v1/netspots/alarms/1   : Expecting 422 Unprocessable Entity for an unknown label
+        let response = client
+            .patch(format!("/v1/netspots/alarms/{id}"))
+            .body(r#"{"user": "alice", "label": "ignored"}"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+
+        // 9. PATCH /v1/netspots/alarms/1000 : Expecting 404 Not Found
+        let response = client
+            .patch("/v1/netspots/alarms/1000")
+            .body(r#"{"user": "alice"}"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::NotFound);
+
+        // 10. PATCH /v1/netspots/alarms/foo : Expecting 400 Bad Request
+        let response = client
+            .patch("/v1/netspots/alarms/foo")
+            .body(r#"{"user": "alice"}"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::BadRequest);
+
         setup.cleanup().await;
     }
 }
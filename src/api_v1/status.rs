@@ -1,5 +1,5 @@
 use crate::state::NetspotControlState;
-use crate::structures::status::{Status, Statuses};
+use crate::structures::status::{ListenerStatuses, Status, Statuses};
 use rocket::serde::json::Json;
 use rocket::{get, State};
 use rocket_okapi::openapi;
@@ -61,6 +61,15 @@ pub async fn status_all(state: &State<NetspotControlState>) -> Json<Statuses> {
     Json(state.netspots.status_all().await)
 }
 
+/// # Netspot message listeners
+///
+/// Message counters for the sockets receiving alarm and data messages from netspot
+#[openapi(tag = "Status")]
+#[get("/netspots/listeners")]
+pub async fn listeners(state: &State<NetspotControlState>) -> Json<ListenerStatuses> {
+    Json(state.netspots.listener_statuses())
+}
+
 /// # Restart all netspot services
 ///
 /// Restart all netspot configurations
@@ -93,7 +102,8 @@ pub async fn stop_all(state: &State<NetspotControlState>) -> Json<Statuses> {
 
 #[cfg(test)]
 mod tests {
-    use crate::structures::status::{ProcessStatus, Status, Statuses};
+    use crate::structures::statistics::MessageType;
+    use crate::structures::status::{ListenerStatuses, ProcessStatus, Status, Statuses};
     use crate::tests_common::{statuses_to_hash_map, TestSetup};
     use rocket::http;
 
@@ -239,4 +249,26 @@ mod tests {
 
         setup.cleanup().await;
     }
+
+    #[tokio::test]
+    async fn test_listeners() {
+        let setup = TestSetup::new().await;
+        let client = &setup.client;
+
+        let response = client.get("/v1/netspots/listeners").dispatch().await;
+        assert_eq!(response.status(), http::Status::Ok);
+        let listeners = response
+            .into_json::<ListenerStatuses>()
+            .await
+            .expect("Valid JSON");
+        assert_eq!(listeners.len(), 2);
+        assert_eq!(listeners[0].socket, MessageType::Alarm);
+        assert_eq!(listeners[1].socket, MessageType::Data);
+        for listener in listeners {
+            assert_eq!(listener.messages.malformed, 0);
+            assert_eq!(listener.messages.oversized, 0);
+        }
+
+        setup.cleanup().await;
+    }
 }
@@ -0,0 +1,393 @@
+use crate::state::database::Database;
+use crate::state::NetspotControlState;
+use crate::structures::statistics::{
+    AlarmQuery, DataQuery, Message, MessageCursor, MessageFilter, MessageType,
+};
+use rocket::futures::Stream;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::response::stream::{stream, Event, EventStream};
+use rocket::{get, Shutdown, State};
+use rocket_okapi::gen::OpenApiGenerator;
+use rocket_okapi::openapi;
+use rocket_okapi::request::{OpenApiFromRequest, RequestHeaderInput};
+use std::collections::{HashSet, VecDeque};
+use std::pin::Pin;
+use std::str::FromStr;
+use tokio::sync::broadcast::error::RecvError;
+
+// Number of stored messages of each type read at a time when a stream is resumed
+const RESUME_PAGE_SIZE: i32 = 1000;
+
+// Events are boxed, so that the route has a named return type
+type MessageEvents = EventStream<Pin<Box<dyn Stream<Item = Event> + Send>>>;
+
+// Event ids are the type and the cursor of the stored message, such as `alarm-<time>_<id>`
+#[derive(Clone, Copy)]
+struct StreamPosition {
+    msg_type: MessageType,
+    cursor: MessageCursor,
+}
+
+impl FromStr for StreamPosition {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (msg_type, cursor) = value
+            .split_once('-')
+            .ok_or_else(|| format!("Invalid event id: {value}"))?;
+        let msg_type = match msg_type {
+            "alarm" => MessageType::Alarm,
+            "data" => MessageType::Data,
+            _ => return Err(format!("Invalid event id: {value}")),
+        };
+        Ok(StreamPosition {
+            msg_type,
+            cursor: cursor.parse()?,
+        })
+    }
+}
+
+/// Last event the client received, from the `Last-Event-ID` header
+pub struct LastEventId(Option<StreamPosition>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for LastEventId {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match request.headers().get_one("Last-Event-ID") {
+            None => Outcome::Success(LastEventId(None)),
+            Some(value) => match value.parse::<StreamPosition>() {
+                Ok(position) => Outcome::Success(LastEventId(Some(position))),
+                Err(_) => Outcome::Error((Status::BadRequest, ())),
+            },
+        }
+    }
+}
+
+impl<'r> OpenApiFromRequest<'r> for LastEventId {
+    fn from_request_input(
+        _gen: &mut OpenApiGenerator,
+        _name: String,
+        _required: bool,
+    ) -> rocket_okapi::Result<RequestHeaderInput> {
+        Ok(RequestHeaderInput::None)
+    }
+}
+
+// Stored alarms and data messages are identified by their type and id
+type MessageKey = (MessageType, i32);
+
+fn message_key(message: &Message) -> Option<MessageKey> {
+    match message {
+        Message::Alarm(alarm) => Some((MessageType::Alarm, alarm.id?)),
+        Message::Data(data) => Some((MessageType::Data, data.id?)),
+        Message::Incident(_) | Message::Detection(_) => None,
+    }
+}
+
+fn message_event(message: &Message) -> Event {
+    let event = match message {
+        Message::Alarm(alarm) => Event::json(alarm).event("alarm"),
+        Message::Data(data) => Event::json(data).event("data"),
+        Message::Incident(incident) => Event::json(incident).event("incident"),
+        Message::Detection(detection) => Event::json(detection).event("detection"),
+    };
+    match message_key(message) {
+        Some((msg_type, id)) => {
+            let cursor = MessageCursor {
+                time: message.time(),
+                id,
+            };
+            let msg_type = match msg_type {
+                MessageType::Alarm => "alarm",
+                _ => "data",
+            };
+            event.id(format!("{msg_type}-{cursor}"))
+        }
+        None => event,
+    }
+}
+
+// Stored messages of one type after the cursor, read from the database a page at a time
+struct StoredPages {
+    database: Database,
+    filter: MessageFilter,
+    msg_type: MessageType,
+    cursor: MessageCursor,
+    page: VecDeque<Message>,
+    done: bool,
+}
+
+impl StoredPages {
+    fn new(
+        database: &Database,
+        filter: &MessageFilter,
+        msg_type: MessageType,
+        position: StreamPosition,
+    ) -> StoredPages {
+        // Ids of the other type are not comparable, so its messages sharing the time of the last
+        // event are all read again. Row ids start from one.
+        let cursor = if position.msg_type == msg_type {
+            position.cursor
+        } else {
+            MessageCursor {
+                time: position.cursor.time,
+                id: 0,
+            }
+        };
+        StoredPages {
+            database: database.clone(),
+            filter: filter.clone(),
+            done: filter.msg_type.is_some_and(|filtered| filtered != msg_type),
+            msg_type,
+            cursor,
+            page: VecDeque::new(),
+        }
+    }
+
+    fn peek(&mut self) -> Result<Option<&Message>, String> {
+        while self.page.is_empty() && !self.done {
+            self.read_page()?;
+        }
+        Ok(self.page.front())
+    }
+
+    fn read_page(&mut self) -> Result<(), String> {
+        let filter = &self.filter;
+        let (messages, next) = match self.msg_type {
+            MessageType::Alarm => {
+                let query = AlarmQuery {
+                    limit: Some(RESUME_PAGE_SIZE),
+                    config_id: filter.config_id,
+                    name: filter.name.clone(),
+                    stat: filter.stat.clone(),
+                    status: filter.status.clone(),
+                    min_probability: filter.min_probability,
+                    ..AlarmQuery::default()
+                };
+                let (alarms, next) = self
+                    .database
+                    .get_alarms(&query, Some(self.cursor))
+                    .map_err(|_| "Could not read stored alarms".to_string())?;
+                let alarms = alarms
+                    .into_iter()
+                    .map(|alarm| Message::Alarm(Box::new(alarm)));
+                (alarms.collect::<Vec<_>>(), next)
+            }
+            _ => {
+                let query = DataQuery {
+                    limit: Some(RESUME_PAGE_SIZE),
+                    config_id: filter.config_id,
+                    name: filter.name.clone(),
+                    ..DataQuery::default()
+                };
+                let (data, next) = self
+                    .database
+                    .get_data(&query, Some(self.cursor))
+                    .map_err(|_| "Could not read stored data messages".to_string())?;
+                let data = data.into_iter().map(|data| Message::Data(Box::new(data)));
+                (data.collect::<Vec<_>>(), next)
+            }
+        };
+        match next {
+            Some(next) => self.cursor = next,
+            None => self.done = true,
+        }
+        self.page.extend(
+            messages
+                .into_iter()
+                .filter(|message| filter.matches(message)),
+        );
+        Ok(())
+    }
+}
+
+// Returns the stored alarm or data message that comes next in time
+fn next_stored(
+    alarms: &mut StoredPages,
+    data: &mut StoredPages,
+) -> Result<Option<Message>, String> {
+    let alarm_time = alarms.peek()?.map(Message::time);
+    let data_time = data.peek()?.map(Message::time);
+    let pages = match (alarm_time, data_time) {
+        (Some(alarm_time), Some(data_time)) if data_time < alarm_time => data,
+        (Some(_), _) => alarms,
+        (None, Some(_)) => data,
+        (None, None) => return Ok(None),
+    };
+    Ok(pages.page.pop_front())
+}
+
+/// # Stream live alarms and data
+///
+/// Sends netspot messages as Server-Sent Events once they are stored. Alarms are sent as `alarm`
+/// events and data messages as `data` events. The same filters as in the statistics endpoints
+/// can be used to select messages.
+///
+/// Alarms and data messages have the event id `<type>-<time>_<id>`, where the id is the id of the
+/// stored message. When the `Last-Event-ID` header is given, the stored messages after that event
+/// are first read from the database in time order, so that a reconnecting client does not miss
+/// messages. Messages of the other type sharing the time of that event are sent again. Invalid
+/// event ids give 400 Bad Request. If the client cannot keep up with the messages, a `lagged`
+/// event with the number of skipped messages is sent. The client can then reconnect to read the
+/// skipped messages from the database.
+#[openapi(tag = "Statistics")]
+#[get("/netspots/stream?<filter..>")]
+pub async fn message_stream(
+    state: &State<NetspotControlState>,
+    filter: MessageFilter,
+    last_event_id: LastEventId,
+    mut shutdown: Shutdown,
+) -> MessageEvents {
+    // Subscribing before reading the database, so that no message is missed in between
+    let mut stored_rx = state.database.subscribe_stored();
+    let mut messages_rx = state.subscribe_messages();
+    let metrics = state.metrics.clone();
+    let mut resume = last_event_id.0.map(|position| {
+        (
+            StoredPages::new(&state.database, &filter, MessageType::Alarm, position),
+            StoredPages::new(&state.database, &filter, MessageType::Data, position),
+        )
+    });
+
+    let events = stream! {
+        // Messages stored while reading the database can be both read and in the channel
+        let mut sent: HashSet<MessageKey> = HashSet::new();
+        if let Some((alarms, data)) = &mut resume {
+            loop {
+                match next_stored(alarms, data) {
+                    Ok(Some(message)) => {
+                        sent.extend(message_key(&message));
+                        yield message_event(&message);
+                    }
+                    Ok(None) => break,
+                    Err(err) => {
+                        eprintln!("Could not resume message stream: {err}");
+                        return;
+                    }
+                }
+            }
+        }
+        loop {
+            // Messages already in the channels are sent before ending at shutdown
+            tokio::select! {
+                biased;
+                result = stored_rx.recv() => match result {
+                    Ok(message) => {
+                        if let Some((msg_type, id)) = message_key(&message) {
+                            let repeated = sent.remove(&(msg_type, id));
+                            // Stored ids only grow, so the smaller ids cannot come any more
+                            sent.retain(|key| key.0 != msg_type || key.1 > id);
+                            if repeated {
+                                continue;
+                            }
+                        }
+                        if filter.matches(&message) {
+                            yield message_event(&message);
+                        }
+                    }
+                    Err(RecvError::Lagged(count)) => {
+                        metrics.record_lag("stream", count);
+                        yield Event::data(count.to_string()).event("lagged");
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                // Incidents and detections are sent as they come
+                result = messages_rx.recv() => match result {
+                    Ok(message @ (Message::Incident(_) | Message::Detection(_))) => {
+                        if filter.matches(&message) {
+                            yield message_event(&message);
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(count)) => {
+                        metrics.record_lag("stream", count);
+                        yield Event::data(count.to_string()).event("lagged");
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                _ = &mut shutdown => break,
+            }
+        }
+    };
+    EventStream::from(Box::pin(events) as Pin<Box<dyn Stream<Item = Event> + Send>>)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StreamPosition;
+    use crate::structures::statistics::MessageType;
+    use crate::tests_common::{wait_alarms, TestSetup};
+    use rocket::http::{Header, Status};
+
+    #[test]
+    fn stream_position() {
+        let position = "alarm-1000_5".parse::<StreamPosition>().unwrap();
+        assert_eq!(position.msg_type, MessageType::Alarm);
+        assert_eq!((position.cursor.time, position.cursor.id), (1000, 5));
+        let position = "data-1000_5".parse::<StreamPosition>().unwrap();
+        assert_eq!(position.msg_type, MessageType::Data);
+        for invalid in ["", "1000", "1000_5", "alarm-1000", "incident-1000_5"] {
+            assert!(invalid.parse::<StreamPosition>().is_err(), "{invalid}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream() {
+        let setup = TestSetup::new().await;
+        let client = &setup.client;
+
+        // Storing two alarms before opening the stream
+        for name in ["First", "Second"] {
+            let response = client
+                .post("/v1/netspots/test/alarm")
+                .body(format!(r#"{{"name": "{name}"}}"#))
+                .dispatch()
+                .await;
+            assert_eq!(response.status(), Status::Created);
+        }
+        let stored = wait_alarms(client, "/v1/netspots/alarms", 2).await;
+
+        // Invalid event ids are rejected
+        for invalid in ["foo", "0", "incident-0_0"] {
+            let response = client
+                .get("/v1/netspots/stream")
+                .header(Header::new("Last-Event-ID", invalid))
+                .dispatch()
+                .await;
+            assert_eq!(response.status(), Status::BadRequest);
+        }
+
+        // Resuming after the first alarm should return the second alarm and the live one
+        let first = format!("alarm-{}_{}", stored[0].time, stored[0].id.unwrap());
+        let stream = client
+            .get("/v1/netspots/stream?type=alarm")
+            .header(Header::new("Last-Event-ID", first))
+            .dispatch()
+            .await;
+        assert_eq!(stream.status(), Status::Ok);
+        let response = client
+            .post("/v1/netspots/test/alarm")
+            .body(r#"{"name": "Live"}"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Created);
+
+        // Ending the stream by shutting down the server once the live alarm is stored
+        wait_alarms(client, "/v1/netspots/alarms", 3).await;
+        client.rocket().shutdown().notify();
+        let body = stream.into_string().await.expect("Stream body");
+        assert_eq!(body.matches("event:alarm").count(), 2);
+        assert!(!body.contains("First"));
+        assert!(body.find("Second") < body.find("Live"));
+
+        // Live alarms have the ids of the stored alarms
+        let second = format!("id:alarm-{}_{}", stored[1].time, stored[1].id.unwrap());
+        assert!(body.contains(&second));
+        assert_eq!(body.matches("id:alarm-").count(), 2);
+
+        setup.cleanup().await;
+    }
+}
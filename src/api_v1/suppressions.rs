@@ -0,0 +1,202 @@
+use crate::api_v1::rules::{self, ErrorResponse, ManagedRule};
+use crate::state::suppressions::validate_rule;
+use crate::state::NetspotControlState;
+use crate::structures::rules::RuleList;
+use crate::structures::suppressions::{SuppressionRule, SuppressionRules};
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::{delete, get, post, put, State};
+use rocket_okapi::openapi;
+
+impl ManagedRule for SuppressionRule {
+    fn validate(&self) -> Result<(), String> {
+        validate_rule(self)
+    }
+
+    fn use_rules(state: &NetspotControlState, rules: SuppressionRules) {
+        state.suppressions.update(rules);
+    }
+}
+
+/// # List suppression rules
+///
+/// Suppression rules silence alarms during maintenance windows. Suppressed alarms are stored with
+/// the `suppressed` flag and the id of the matching rule, but they are not sent to the webhooks,
+/// the DHT or the incidents.
+#[openapi(tag = "Suppressions")]
+#[get("/suppressions")]
+pub async fn suppressions_list(
+    state: &State<NetspotControlState>,
+) -> Result<Json<RuleList>, Status> {
+    rules::list::<SuppressionRule>(state)
+}
+
+/// # Create a new suppression rule
+///
+/// The rule applies to alarms received after it is created. A rule with a `schedule` needs the
+/// `duration` of the windows, and invalid rules are rejected with 422 Unprocessable Entity and
+/// the reason.
+#[openapi(tag = "Suppressions")]
+#[post("/suppressions", data = "<new_rule>")]
+pub async fn suppression_add(
+    state: &State<NetspotControlState>,
+    new_rule: Json<SuppressionRule>,
+) -> Result<Status, ErrorResponse> {
+    rules::add(state, &*new_rule)
+}
+
+/// # Get suppression rule
+///
+/// Get suppression rule by ID
+#[openapi(tag = "Suppressions")]
+#[get("/suppressions/<id>")]
+pub async fn suppression_get(
+    state: &State<NetspotControlState>,
+    id: Result<i32, &str>,
+) -> Result<Option<Json<SuppressionRule>>, Status> {
+    rules::get(state, id)
+}
+
+/// # Update suppression rule
+///
+/// Update suppression rule by ID. The rule is checked the same way as when it is created.
+#[openapi(tag = "Suppressions")]
+#[put("/suppressions/<id>", data = "<rule>")]
+pub async fn suppression_put(
+    state: &State<NetspotControlState>,
+    id: Result<i32, &str>,
+    rule: Json<SuppressionRule>,
+) -> Result<(), ErrorResponse> {
+    rules::put(state, id, &*rule)
+}
+
+/// # Delete suppression rule
+///
+/// Delete suppression rule by ID. Alarms that were suppressed by the rule keep its id.
+#[openapi(tag = "Suppressions")]
+#[delete("/suppressions/<id>")]
+pub async fn suppression_delete(
+    state: &State<NetspotControlState>,
+    id: Result<i32, &str>,
+) -> Result<(), Status> {
+    rules::delete::<SuppressionRule>(state, id)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::structures::rules::RuleList;
+    use crate::structures::statistics::{AlarmMessages, Stat};
+    use crate::structures::suppressions::SuppressionRule;
+    use crate::tests_common::TestSetup;
+    use rocket::http::Status;
+    use rocket::local::asynchronous::Client;
+
+    async fn send_alarm(client: &Client, stat: &str) {
+        let response = client
+            .post("/v1/netspots/test/alarm")
+            .body(format!(r#"{{"stat": "{stat}"}}"#))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Created);
+    }
+
+    async fn get_alarms(client: &Client, uri: &str) -> AlarmMessages {
+        let response = client.get(uri).dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        response.into_json::<AlarmMessages>().await.unwrap()
+    }
+
+    // This test does the following:
+    //
+    // 1. POST   /v1/suppressions      : Expecting 422 for a schedule without a duration
+    // 2. POST   /v1/suppressions      : Adding a rule for TRAFFIC alarms
+    // 3. GET    /v1/suppressions      : Checking that the rule was added
+    // 4. POST   /v1/netspots/test/alarm : Sending TRAFFIC and R_SYN alarms
+    // 5. GET    /v1/netspots/alarms   : Only the TRAFFIC alarm should be suppressed
+    // 6. PUT    /v1/suppressions/1    : Making the rule expire
+    // 7. GET    /v1/suppressions/1    : Checking that the rule changed
+    // 8. POST   /v1/netspots/test/alarm : TRAFFIC alarm should not be suppressed anymore
+    // 9. DELETE /v1/suppressions/1    : Deleting the rule, and again expecting 404 Not Found
+    #[tokio::test]
+    async fn test_suppressions() {
+        let setup = TestSetup::new().await;
+        let client = &setup.client;
+
+        // 1. POST   /v1/suppressions      : Expecting 422 for a schedule without a duration
+        let response = client
+            .post("/v1/suppressions")
+            .body(r#"{"name": "Backup", "schedule": "0 2 * * *"}"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        assert_eq!(
+            response.into_string().await.unwrap(),
+            "Schedule requires a duration"
+        );
+
+        // 2. POST   /v1/suppressions      : Adding a rule for TRAFFIC alarms
+        let response = client
+            .post("/v1/suppressions")
+            .body(r#"{"name": "Maintenance", "stats": ["TRAFFIC"]}"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Created);
+
+        // 3. GET    /v1/suppressions      : Checking that the rule was added
+        let response = client.get("/v1/suppressions").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let rules = response.into_json::<RuleList>().await.unwrap();
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].id, 1);
+        assert_eq!(rules[0].name, "Maintenance");
+
+        // 4. POST   /v1/netspots/test/alarm : Sending TRAFFIC and R_SYN alarms
+        send_alarm(client, "TRAFFIC").await;
+        send_alarm(client, "R_SYN").await;
+        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+
+        // 5. GET    /v1/netspots/alarms   : Only the TRAFFIC alarm should be suppressed
+        let alarms = get_alarms(client, "/v1/netspots/alarms?suppressed=true").await;
+        assert_eq!(alarms.len(), 1);
+        assert_eq!(alarms[0].stat, Stat::Traffic);
+        assert_eq!(alarms[0].suppression_rule_id, Some(1));
+        let alarms = get_alarms(client, "/v1/netspots/alarms?suppressed=false").await;
+        assert_eq!(alarms.len(), 1);
+        assert_eq!(alarms[0].stat, Stat::RSyn);
+        assert_eq!(alarms[0].suppression_rule_id, None);
+
+        // 6. PUT    /v1/suppressions/1    : Making the rule expire
+        let response = client
+            .put("/v1/suppressions/1")
+            .body(r#"{"name": "Maintenance", "stats": ["TRAFFIC"], "expires": 1}"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+
+        // 7. GET    /v1/suppressions/1    : Checking that the rule changed
+        let response = client.get("/v1/suppressions/1").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let rule = response.into_json::<SuppressionRule>().await.unwrap();
+        assert_eq!(rule.expires, Some(1));
+
+        // 8. POST   /v1/netspots/test/alarm : TRAFFIC alarm should not be suppressed anymore
+        send_alarm(client, "TRAFFIC").await;
+        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+        let alarms = get_alarms(client, "/v1/netspots/alarms?stat=TRAFFIC").await;
+        assert_eq!(alarms.len(), 2);
+        assert!(alarms[0].suppressed);
+        assert!(!alarms[1].suppressed);
+
+        // 9. DELETE /v1/suppressions/1    : Deleting the rule, and again expecting 404 Not Found
+        let response = client.delete("/v1/suppressions/1").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let response = client.delete("/v1/suppressions/1").dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+        let response = client.get("/v1/suppressions/1").dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+        let response = client.get("/v1/suppressions/foo").dispatch().await;
+        assert_eq!(response.status(), Status::BadRequest);
+
+        setup.cleanup().await;
+    }
+}
@@ -1,5 +1,5 @@
 use crate::state::NetspotControlState;
-use crate::structures::statistics::{AlertStatus, Stat};
+use crate::structures::statistics::{AlarmMessage, AlertStatus, Message, MessageType, Stat};
 use rocket::http::Status;
 use rocket::post;
 use rocket::serde::json::Json;
@@ -7,6 +7,7 @@ use rocket::State;
 use rocket_okapi::okapi::schemars;
 use rocket_okapi::openapi;
 use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 #[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
 pub struct TestAlarmMessage {
@@ -54,6 +55,34 @@ impl Default for TestAlarmMessage {
     }
 }
 
+impl TestAlarmMessage {
+    // Completes the test alarm to a full alarm message
+    pub fn into_message(self) -> Message {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos() as i64;
+        Message::Alarm(Box::new(AlarmMessage {
+            id: None,
+            time,
+            name: self.name,
+            config_id: None,
+            job_id: None,
+            series: "TEST ALARM".to_string(),
+            stat: self.stat,
+            status: self.status,
+            value: self.value,
+            probability: self.probability,
+            code: 1,
+            severity: None,
+            suppressed: false,
+            suppression_rule_id: None,
+            review: None,
+            msg_type: MessageType::Alarm,
+        }))
+    }
+}
+
 /// # Send test alarm
 ///
 /// This endpoint allows developers to send test alarm messages.
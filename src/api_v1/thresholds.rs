@@ -0,0 +1,189 @@
+use crate::api_v1::rules::{self, ErrorResponse, ManagedRule};
+use crate::state::thresholds::validate_rule;
+use crate::state::NetspotControlState;
+use crate::structures::rules::RuleList;
+use crate::structures::thresholds::{ThresholdRule, ThresholdRules};
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::{delete, get, post, put, State};
+use rocket_okapi::openapi;
+
+impl ManagedRule for ThresholdRule {
+    fn validate(&self) -> Result<(), String> {
+        validate_rule(self)
+    }
+
+    fn use_rules(state: &NetspotControlState, rules: ThresholdRules) {
+        state.thresholds.update(rules);
+    }
+}
+
+/// # List threshold rules
+///
+/// Threshold rules send alarms when a stat in the data messages has been above or below a fixed
+/// value for the hold time. The alarms have the code 100 and the series `THRESHOLD <id>`, and
+/// they are handled like the alarms from netspot.
+#[openapi(tag = "Thresholds")]
+#[get("/thresholds")]
+pub async fn thresholds_list(state: &State<NetspotControlState>) -> Result<Json<RuleList>, Status> {
+    rules::list::<ThresholdRule>(state)
+}
+
+/// # Create a new threshold rule
+///
+/// The rule is checked on data messages received after it is created. The `conditions` can
+/// compare any value of the data messages, like `TRAFFIC_UP`, and invalid rules are rejected with
+/// 422 Unprocessable Entity and the reason.
+#[openapi(tag = "Thresholds")]
+#[post("/thresholds", data = "<new_rule>")]
+pub async fn threshold_add(
+    state: &State<NetspotControlState>,
+    new_rule: Json<ThresholdRule>,
+) -> Result<Status, ErrorResponse> {
+    rules::add(state, &*new_rule)
+}
+
+/// # Get threshold rule
+///
+/// Get threshold rule by ID
+#[openapi(tag = "Thresholds")]
+#[get("/thresholds/<id>")]
+pub async fn threshold_get(
+    state: &State<NetspotControlState>,
+    id: Result<i32, &str>,
+) -> Result<Option<Json<ThresholdRule>>, Status> {
+    rules::get(state, id)
+}
+
+/// # Update threshold rule
+///
+/// Update threshold rule by ID. The rule is checked the same way as when it is created, and
+/// changing any rule starts the hold times of all rules over.
+#[openapi(tag = "Thresholds")]
+#[put("/thresholds/<id>", data = "<rule>")]
+pub async fn threshold_put(
+    state: &State<NetspotControlState>,
+    id: Result<i32, &str>,
+    rule: Json<ThresholdRule>,
+) -> Result<(), ErrorResponse> {
+    rules::put(state, id, &*rule)
+}
+
+/// # Delete threshold rule
+///
+/// Delete threshold rule by ID
+#[openapi(tag = "Thresholds")]
+#[delete("/thresholds/<id>")]
+pub async fn threshold_delete(
+    state: &State<NetspotControlState>,
+    id: Result<i32, &str>,
+) -> Result<(), Status> {
+    rules::delete::<ThresholdRule>(state, id)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::structures::rules::RuleList;
+    use crate::structures::statistics::Stat;
+    use crate::structures::thresholds::{ThresholdCondition, ThresholdRule};
+    use crate::tests_common::TestSetup;
+    use rocket::http::Status;
+
+    // This test does the following:
+    //
+    // 1. POST   /v1/thresholds    : Adding a rule
+    // 2. GET    /v1/thresholds    : Checking that the rule was added
+    // 3. PUT    /v1/thresholds/1  : Updating the rule
+    // 4. GET    /v1/thresholds/1  : Checking that the rule changed
+    // 5. DELETE /v1/thresholds/1  : Deleting the rule
+    // 6. GET    /v1/thresholds    : Checking that the rule was deleted
+    // 7. Invalid requests         : Expecting 404 Not Found, 400 Bad Request and 422 for
+    //                               unknown stats and fields and too long hold times
+    #[tokio::test]
+    async fn test_thresholds() {
+        let setup = TestSetup::new().await;
+        let client = &setup.client;
+
+        // 1. POST   /v1/thresholds    : Adding a rule
+        let response = client
+            .post("/v1/thresholds")
+            .body(r#"{"name": "SYN flood", "stat": "R_SYN", "value": 0.8, "hold": 10}"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Created);
+
+        // 2. GET    /v1/thresholds    : Checking that the rule was added
+        let response = client.get("/v1/thresholds").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let rules = response.into_json::<RuleList>().await.unwrap();
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].id, 1);
+        assert_eq!(rules[0].name, "SYN flood");
+
+        // 3. PUT    /v1/thresholds/1  : Updating the rule
+        let response = client
+            .put("/v1/thresholds/1")
+            .body(
+                r#"{"name": "Cameras idle", "configurations": [1], "stat": "TRAFFIC",
+                    "condition": "below", "value": 1.0, "hold": 300}"#,
+            )
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+
+        // 4. GET    /v1/thresholds/1  : Checking that the rule changed
+        let response = client.get("/v1/thresholds/1").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let rule = response.into_json::<ThresholdRule>().await.unwrap();
+        assert_eq!(rule.stat, Stat::Traffic);
+        assert_eq!(rule.condition, ThresholdCondition::Below);
+        assert_eq!(rule.configurations, vec![1]);
+        assert_eq!(rule.hold, 300);
+
+        // 5. DELETE /v1/thresholds/1  : Deleting the rule
+        let response = client.delete("/v1/thresholds/1").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+
+        // 6. GET    /v1/thresholds    : Checking that the rule was deleted
+        let response = client.get("/v1/thresholds").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let rules = response.into_json::<RuleList>().await.unwrap();
+        assert!(rules.is_empty());
+
+        // 7. Invalid requests         : Expecting 404 Not Found, 400 Bad Request and 422 for
+        //                               unknown stats and fields and too long hold times
+        let response = client.get("/v1/thresholds/1").dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+        let response = client.delete("/v1/thresholds/1").dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+        let response = client.get("/v1/thresholds/foo").dispatch().await;
+        assert_eq!(response.status(), Status::BadRequest);
+        let response = client
+            .post("/v1/thresholds")
+            .body(r#"{"name": "Unknown", "stat": "R_FOO", "value": 1.0}"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        let response = client
+            .post("/v1/thresholds")
+            .body(
+                r#"{"name": "Unknown", "stat": "R_SYN", "value": 1.0,
+                    "conditions": [{"field": "R_FOO", "value": 1.0}]}"#,
+            )
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        assert_eq!(response.into_string().await.unwrap(), "Unknown field R_FOO");
+        let response = client
+            .post("/v1/thresholds")
+            .body(
+                r#"{"name": "Forever", "stat": "R_SYN", "value": 1.0,
+                    "hold": 18446744073709551615}"#,
+            )
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+
+        setup.cleanup().await;
+    }
+}
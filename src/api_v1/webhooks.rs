@@ -1,11 +1,27 @@
+use crate::api_v1::testing::TestAlarmMessage;
 use crate::state::database::DatabaseError;
+use crate::state::webhooks::validate_webhook;
 use crate::state::NetspotControlState;
-use crate::structures::webhooks::{Webhook, WebhookList};
+use crate::structures::webhooks::{
+    Webhook, WebhookDeliveries, WebhookDelivery, WebhookDetails, WebhookList, WebhookStatus,
+};
 use rocket::http::Status;
+use rocket::response::status;
 use rocket::serde::json::Json;
 use rocket::{delete, get, post, put, State};
 use rocket_okapi::openapi;
 
+// Errors have the reason as plain text when the webhook itself is invalid
+type ErrorResponse = status::Custom<String>;
+
+fn error_status(status: Status) -> ErrorResponse {
+    status::Custom(status, String::new())
+}
+
+fn validate(hook: &Webhook) -> Result<(), ErrorResponse> {
+    validate_webhook(hook).map_err(|err| status::Custom(Status::UnprocessableEntity, err))
+}
+
 fn update_webhooks(state: &State<NetspotControlState>) {
     match state.database.get_webhooks() {
         Ok(webhooks) => {
@@ -19,32 +35,37 @@ fn update_webhooks(state: &State<NetspotControlState>) {
 
 /// # Create a new webhook
 ///
-/// Let a user post a new webhook configuration
+/// Let a user post a new webhook configuration. The body template and the content type are
+/// checked first, and invalid ones are rejected with 422 Unprocessable Entity and the reason.
 #[openapi(tag = "Webhooks")]
 #[post("/netspots/webhook", data = "<new_hook>")]
 pub async fn webhook_add(
     state: &State<NetspotControlState>,
     new_hook: Json<Webhook>,
-) -> Result<Status, Status> {
+) -> Result<Status, ErrorResponse> {
+    let mut new_hook = new_hook.into_inner();
+    new_hook.secret = new_hook.secret.filter(|secret| !secret.is_empty());
+    validate(&new_hook)?;
     if state.database.add_webhook(&new_hook).is_ok() {
         update_webhooks(state);
         return Ok(Status::Created);
     }
-    Err(Status::BadRequest)
+    Err(error_status(Status::BadRequest))
 }
 
 /// # Get webhook configuration
 ///
-/// Get webhook configuration by ID
+/// Get webhook configuration by ID. The secret is not returned, `has_secret` tells whether the
+/// requests are signed.
 #[openapi(tag = "Webhooks")]
 #[get("/netspots/webhook/<id>")]
 pub async fn webhook_get(
     state: &State<NetspotControlState>,
     id: Result<i32, &str>,
-) -> Result<Option<Json<Webhook>>, Status> {
+) -> Result<Option<Json<WebhookDetails>>, Status> {
     match id {
         Ok(id) => match state.database.get_webhook(id) {
-            Some(hook) => Ok(Some(Json(hook))),
+            Some(hook) => Ok(Some(Json(WebhookDetails::from(hook)))),
             None => Ok(None),
         },
         Err(_) => Err(Status::BadRequest),
@@ -53,25 +74,36 @@ pub async fn webhook_get(
 
 /// # Update webhook configuration
 ///
-/// Update webhook configuration by ID
+/// Update webhook configuration by ID. The configuration is checked the same way as when a
+/// webhook is created. The current secret is kept when the update has no secret, and an empty
+/// secret removes it.
 #[openapi(tag = "Webhooks")]
 #[put("/netspots/webhook/<id>", data = "<hook>")]
 pub async fn webhook_put(
     state: &State<NetspotControlState>,
     id: Result<i32, &str>,
     hook: Json<Webhook>,
-) -> Result<(), Status> {
+) -> Result<(), ErrorResponse> {
     if let Ok(id) = id {
+        let mut hook = hook.into_inner();
+        hook.secret = match hook.secret {
+            Some(secret) => Some(secret).filter(|secret| !secret.is_empty()),
+            None => state
+                .database
+                .get_webhook(id)
+                .and_then(|stored| stored.secret),
+        };
+        validate(&hook)?;
         return match state.database.set_webhook(id, &hook) {
             Ok(_) => {
                 update_webhooks(state);
                 Ok(())
             }
-            Err(DatabaseError::NotFound) => Err(Status::NotFound),
-            Err(_) => Err(Status::InternalServerError),
+            Err(DatabaseError::NotFound) => Err(error_status(Status::NotFound)),
+            Err(_) => Err(error_status(Status::InternalServerError)),
         };
     }
-    Err(Status::BadRequest)
+    Err(error_status(Status::BadRequest))
 }
 
 /// # Delete webhook configuration
@@ -96,6 +128,77 @@ pub async fn webhook_delete(
     Err(Status::BadRequest)
 }
 
+/// # Get webhook delivery status
+///
+/// Messages are queued for each webhook and failed deliveries are retried with growing delays
+/// until the retry policy of the webhook gives up on them. Returns the number of queued messages,
+/// the number of messages that were given up as dead letters, and the results of the latest
+/// delivery attempts.
+#[openapi(tag = "Webhooks")]
+#[get("/netspots/webhook/<id>/status")]
+pub async fn webhook_status(
+    state: &State<NetspotControlState>,
+    id: Result<i32, &str>,
+) -> Result<Option<Json<WebhookStatus>>, Status> {
+    let id = id.map_err(|_| Status::BadRequest)?;
+    if state.database.get_webhook(id).is_none() {
+        return Ok(None);
+    }
+    match state.webhooks.status(id, &state.database) {
+        Ok(status) => Ok(Some(Json(status))),
+        Err(err) => {
+            eprintln!("Could not get webhook status: {err}");
+            Err(Status::InternalServerError)
+        }
+    }
+}
+
+/// # Get webhook delivery log
+///
+/// Lists the latest delivery attempts of the webhook, newest first. Each attempt has the HTTP
+/// status and the beginning of the body the host responded with, how long the request took, and
+/// the error when the delivery failed. The log is kept in memory for the 50 latest attempts.
+#[openapi(tag = "Webhooks")]
+#[get("/netspots/webhook/<id>/deliveries")]
+pub async fn webhook_deliveries(
+    state: &State<NetspotControlState>,
+    id: Result<i32, &str>,
+) -> Result<Option<Json<WebhookDeliveries>>, Status> {
+    let id = id.map_err(|_| Status::BadRequest)?;
+    if state.database.get_webhook(id).is_none() {
+        return Ok(None);
+    }
+    Ok(Some(Json(state.webhooks.deliveries(id))))
+}
+
+/// # Send test alarm to a webhook
+///
+/// Sends a test alarm only to this webhook and returns how the host responded. The alarm is made
+/// the same way as with the test alarm endpoint, and it is sent right away instead of being
+/// queued, so it is not retried when it fails. The attempt is also recorded in the delivery log.
+#[openapi(tag = "Webhooks")]
+#[post("/netspots/webhook/<id>/test", data = "<message>")]
+pub async fn webhook_test(
+    state: &State<NetspotControlState>,
+    id: Result<i32, &str>,
+    message: Option<Json<TestAlarmMessage>>,
+) -> Result<Option<Json<WebhookDelivery>>, Status> {
+    let id = id.map_err(|_| Status::BadRequest)?;
+    let webhook = match state.database.get_webhook(id) {
+        Some(webhook) => webhook,
+        None => return Ok(None),
+    };
+    let test_alarm = match message {
+        None => TestAlarmMessage::default(),
+        Some(json) => json.into_inner(),
+    };
+    let delivery = state
+        .webhooks
+        .send_test(id, &webhook, &test_alarm.into_message())
+        .await;
+    Ok(Some(Json(delivery)))
+}
+
 /// # List installed webhooks
 ///
 /// Lists installed webhooks by their id and names.
@@ -113,11 +216,21 @@ pub async fn webhooks_list(
 
 #[cfg(test)]
 mod tests {
+    use crate::state::NetspotControlState;
+    use crate::structures::statistics::{AlarmMessage, AlertStatus};
     use crate::structures::webhooks::{
-        Webhook, WebhookHeaders, WebhookList, WebhookRequestMethod, WebhookStatsType,
+        Webhook, WebhookDeliveries, WebhookDelivery, WebhookDetails, WebhookFilter, WebhookHeaders,
+        WebhookList, WebhookRequestMethod, WebhookRetryPolicy, WebhookStatsType, WebhookStatus,
+        WebhookTls,
     };
     use crate::tests_common::TestSetup;
+    use netspot_control::signature;
     use rocket::http::Status;
+    use rocket::local::asynchronous::Client;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
 
     // This test does the following:
     //
@@ -142,9 +255,19 @@ mod tests {
         let mut webhook = Webhook {
             name: "Test".to_string(),
             address: "http://127.0.0.1:9020/alarms".to_string(),
+            enabled: true,
             method: WebhookRequestMethod::Post,
             headers,
             stats_type: WebhookStatsType::Alarms,
+            configurations: vec![],
+            retry: WebhookRetryPolicy::default(),
+            secret: None,
+            filter: WebhookFilter::default(),
+            template: None,
+            content_type: None,
+            batch: None,
+            timeout_ms: None,
+            tls: WebhookTls::default(),
         };
         let response = client
             .post(webhook_uri)
@@ -172,6 +295,9 @@ mod tests {
         webhook.method = WebhookRequestMethod::Put;
         webhook.name = "Test webhook".to_string();
         webhook.stats_type = WebhookStatsType::Both;
+        webhook.configurations = vec![1];
+        webhook.filter.statuses = vec![AlertStatus::UpAlert];
+        webhook.filter.max_probability = Some(0.01);
         let response = client
             .put(webhook_1_uri)
             .body(serde_json::to_string(&webhook).unwrap())
@@ -280,4 +406,550 @@ mod tests {
 
         setup.cleanup().await;
     }
+
+    // Received requests as text
+    type Requests = Arc<Mutex<Vec<String>>>;
+
+    // Minimal HTTP server answering every request with the given response
+    async fn webhook_receiver(
+        response: &'static str,
+    ) -> (String, Requests, tokio::task::JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("Listener");
+        let address = format!("http://{}/hook", listener.local_addr().unwrap());
+        let requests = Requests::default();
+        let received = requests.clone();
+        let handle = tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                // Reading the request headers and the body before answering
+                let mut request = Vec::new();
+                let mut buffer = [0; 4096];
+                loop {
+                    match stream.read(&mut buffer).await {
+                        Ok(0) | Err(_) => break,
+                        Ok(count) => request.extend_from_slice(&buffer[..count]),
+                    }
+                    let text = String::from_utf8_lossy(&request);
+                    if let Some(end) = text.find("\r\n\r\n") {
+                        let length = text[..end]
+                            .lines()
+                            .filter_map(|line| line.split_once(':'))
+                            .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+                            .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+                            .unwrap_or(0);
+                        if request.len() >= end + 4 + length {
+                            break;
+                        }
+                    }
+                }
+                let request = String::from_utf8_lossy(&request).into_owned();
+                received.lock().unwrap().push(request);
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        (address, requests, handle)
+    }
+
+    const NO_CONTENT: &str = "HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n";
+
+    // Polls the webhook status until the condition holds
+    async fn wait_status(client: &Client, uri: &str, done: fn(&WebhookStatus) -> bool) {
+        for _ in 0..100 {
+            let response = client.get(uri).dispatch().await;
+            assert_eq!(response.status(), Status::Ok);
+            let status = response.into_json::<WebhookStatus>().await.unwrap();
+            if done(&status) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("Webhook status {uri} did not reach the expected state");
+    }
+
+    // This test does the following:
+    //
+    // 1. POST /v1/netspots/webhook          : Adding a webhook without a listening host
+    // 2. POST /v1/netspots/webhook          : Adding a webhook with a listening host
+    // 3. POST /v1/netspots/test/alarm       : Sending a message to both webhooks
+    // 4. GET  /v1/netspots/webhook/1/status : Message should be a dead letter after one attempt
+    // 5. GET  /v1/netspots/webhook/2/status : Message should be delivered
+    // 6. GET  /v1/netspots/webhook/3/status : Expecting 404 Not Found
+    #[tokio::test]
+    async fn test_webhook_delivery() {
+        let setup = TestSetup::new().await;
+        let client = &setup.client;
+        let (address, _, receiver) = webhook_receiver(NO_CONTENT).await;
+
+        // 1. POST /v1/netspots/webhook          : Adding a webhook without a listening host
+        let closed_port = std::net::TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .expect("Free port")
+            .port();
+        let json = format!(
+            r#"{{"name":"closed","address":"http://127.0.0.1:{closed_port}/",
+                "retry":{{"max_attempts":1}}}}"#
+        );
+        let response = client
+            .post("/v1/netspots/webhook")
+            .body(json)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Created);
+
+        // 2. POST /v1/netspots/webhook          : Adding a webhook with a listening host
+        let json = format!(r#"{{"name":"open","address":"{address}"}}"#);
+        let response = client
+            .post("/v1/netspots/webhook")
+            .body(json)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Created);
+
+        // 3. POST /v1/netspots/test/alarm       : Sending a message to both webhooks
+        let response = client
+            .post("/v1/netspots/test/alarm")
+            .body(r#"{"name": "Test"}"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Created);
+
+        // 4. GET  /v1/netspots/webhook/1/status : Message should be a dead letter after one attempt
+        wait_status(client, "/v1/netspots/webhook/1/status", |status| {
+            status.queued == 0 && status.dead_letters == 1
+        })
+        .await;
+        let response = client.get("/v1/netspots/webhook/1/status").dispatch().await;
+        let status = response.into_json::<WebhookStatus>().await.unwrap();
+        assert!(status.last_success.is_none());
+        assert!(status.last_error.is_some());
+
+        // 5. GET  /v1/netspots/webhook/2/status : Message should be delivered
+        wait_status(client, "/v1/netspots/webhook/2/status", |status| {
+            status.queued == 0 && status.last_success.is_some()
+        })
+        .await;
+        let response = client.get("/v1/netspots/webhook/2/status").dispatch().await;
+        let status = response.into_json::<WebhookStatus>().await.unwrap();
+        assert_eq!(status.dead_letters, 0);
+        assert!(status.last_error.is_none());
+
+        // 6. GET  /v1/netspots/webhook/3/status : Expecting 404 Not Found
+        let response = client.get("/v1/netspots/webhook/3/status").dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+
+        receiver.abort();
+        setup.cleanup().await;
+    }
+
+    // This test does the following:
+    //
+    // 1. POST /v1/netspots/webhook              : Adding a webhook with a failing host
+    // 2. POST /v1/netspots/webhook/1/test       : Expecting the response of the host
+    // 3. POST /v1/netspots/webhook/1/test       : With custom alarm, expecting the same response
+    // 4. GET  /v1/netspots/webhook/1/deliveries : Both attempts should be in the log
+    // 5. POST /v1/netspots/webhook/2/test       : Expecting 404 Not Found
+    // 6. GET  /v1/netspots/webhook/2/deliveries : Expecting 404 Not Found
+    // 7. GET  /v1/netspots/webhook/foo/deliveries : Expecting 400 Bad Request
+    #[tokio::test]
+    async fn test_webhook_test() {
+        let setup = TestSetup::new().await;
+        let client = &setup.client;
+        let failure = concat!(
+            "HTTP/1.1 500 Internal Server Error\r\n",
+            "Content-Length: 4\r\n",
+            "Connection: close\r\n\r\n",
+            "Oops"
+        );
+        let (address, _, receiver) = webhook_receiver(failure).await;
+
+        // 1. POST /v1/netspots/webhook              : Adding a webhook with a failing host
+        let json = format!(r#"{{"name":"failing","address":"{address}"}}"#);
+        let response = client
+            .post("/v1/netspots/webhook")
+            .body(json)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Created);
+
+        // 2. POST /v1/netspots/webhook/1/test       : Expecting the response of the host
+        let response = client.post("/v1/netspots/webhook/1/test").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let delivery = response.into_json::<WebhookDelivery>().await.unwrap();
+        assert!(delivery.test);
+        assert_eq!(delivery.status, Some(500));
+        assert_eq!(delivery.response.as_deref(), Some("Oops"));
+        assert!(delivery.error.is_some());
+
+        // 3. POST /v1/netspots/webhook/1/test       : With custom alarm, expecting the same response
+        let response = client
+            .post("/v1/netspots/webhook/1/test")
+            .body(r#"{"name": "Custom"}"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let delivery = response.into_json::<WebhookDelivery>().await.unwrap();
+        assert_eq!(delivery.status, Some(500));
+
+        // 4. GET  /v1/netspots/webhook/1/deliveries : Both attempts should be in the log
+        let response = client
+            .get("/v1/netspots/webhook/1/deliveries")
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let deliveries = response.into_json::<WebhookDeliveries>().await.unwrap();
+        assert_eq!(deliveries.len(), 2);
+        assert_eq!(deliveries[0], delivery);
+        assert!(deliveries[1].time <= deliveries[0].time);
+
+        // Test messages are not queued
+        let response = client.get("/v1/netspots/webhook/1/status").dispatch().await;
+        let status = response.into_json::<WebhookStatus>().await.unwrap();
+        assert_eq!(status.queued, 0);
+        assert!(status.last_error.is_some());
+
+        // 5. POST /v1/netspots/webhook/2/test       : Expecting 404 Not Found
+        let response = client.post("/v1/netspots/webhook/2/test").dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+
+        // 6. GET  /v1/netspots/webhook/2/deliveries : Expecting 404 Not Found
+        let response = client
+            .get("/v1/netspots/webhook/2/deliveries")
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::NotFound);
+
+        // 7. GET  /v1/netspots/webhook/foo/deliveries : Expecting 400 Bad Request
+        let response = client
+            .get("/v1/netspots/webhook/foo/deliveries")
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::BadRequest);
+
+        receiver.abort();
+        setup.cleanup().await;
+    }
+
+    // This test does the following:
+    //
+    // 1. POST /v1/netspots/webhook        : Adding a webhook with a secret
+    // 2. POST /v1/netspots/webhook/1/test : Sending a test alarm to the webhook
+    // 3. Checks the signature headers of the received request
+    // 4. GET  /v1/netspots/webhook/1      : Secret is not returned
+    // 5. PUT  /v1/netspots/webhook/1      : Update without a secret keeps it
+    // 6. PUT  /v1/netspots/webhook/1      : Empty secret removes it
+    #[tokio::test]
+    async fn test_webhook_signature() {
+        let setup = TestSetup::new().await;
+        let client = &setup.client;
+        let (address, requests, receiver) = webhook_receiver(NO_CONTENT).await;
+
+        // 1. POST /v1/netspots/webhook        : Adding a webhook with a secret
+        let json = format!(r#"{{"name":"signed","address":"{address}","secret":"s3cr3t"}}"#);
+        let response = client
+            .post("/v1/netspots/webhook")
+            .body(json)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Created);
+
+        // 2. POST /v1/netspots/webhook/1/test : Sending a test alarm to the webhook
+        let response = client.post("/v1/netspots/webhook/1/test").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let delivery = response.into_json::<WebhookDelivery>().await.unwrap();
+        assert_eq!(delivery.status, Some(204));
+
+        // 3. Checks the signature headers of the received request
+        let request = requests.lock().unwrap().pop().expect("Received request");
+        let (head, body) = request.split_once("\r\n\r\n").expect("Complete request");
+        let header = |name: &str| {
+            head.lines()
+                .filter_map(|line| line.split_once(':'))
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.trim().to_string())
+                .expect("Header")
+        };
+        let timestamp = header(signature::TIMESTAMP_HEADER);
+        let signature = header(signature::SIGNATURE_HEADER);
+        let now = timestamp.parse::<i64>().unwrap();
+        let secret = b"s3cr3t";
+        let tolerance = signature::DEFAULT_TOLERANCE;
+        let body = body.as_bytes();
+        let result = signature::verify(secret, &timestamp, &signature, body, now, tolerance);
+        assert_eq!(result, Ok(()));
+        let result = signature::verify(b"wrong", &timestamp, &signature, body, now, tolerance);
+        assert_eq!(result, Err(signature::SignatureError::Mismatch));
+
+        // 4. GET  /v1/netspots/webhook/1      : Secret is not returned
+        let response = client.get("/v1/netspots/webhook/1").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let text = response.into_string().await.unwrap();
+        assert!(!text.contains("s3cr3t"), "{text}");
+        let details = serde_json::from_str::<WebhookDetails>(&text).unwrap();
+        assert!(details.has_secret);
+        assert_eq!(details.webhook.secret, None);
+
+        // 5. PUT  /v1/netspots/webhook/1      : Update without a secret keeps it
+        let json = format!(r#"{{"name":"renamed","address":"{address}"}}"#);
+        let response = client
+            .put("/v1/netspots/webhook/1")
+            .body(json)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let response = client.get("/v1/netspots/webhook/1").dispatch().await;
+        let details = response.into_json::<WebhookDetails>().await.unwrap();
+        assert_eq!(details.webhook.name, "renamed");
+        assert!(details.has_secret);
+
+        // 6. PUT  /v1/netspots/webhook/1      : Empty secret removes it
+        let json = format!(r#"{{"name":"renamed","address":"{address}","secret":""}}"#);
+        let response = client
+            .put("/v1/netspots/webhook/1")
+            .body(json)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let response = client.get("/v1/netspots/webhook/1").dispatch().await;
+        let details = response.into_json::<WebhookDetails>().await.unwrap();
+        assert!(!details.has_secret);
+
+        receiver.abort();
+        setup.cleanup().await;
+    }
+
+    // This test does the following:
+    //
+    // 1. POST /v1/netspots/webhook        : With invalid template, expecting 422 with the reason
+    // 2. POST /v1/netspots/webhook        : With invalid content type, expecting 422
+    // 3. POST /v1/netspots/webhook        : Adding a webhook with a template
+    // 4. PUT  /v1/netspots/webhook/1      : With invalid template, expecting 422
+    // 5. POST /v1/netspots/webhook/1/test : Sending a test alarm to the webhook
+    // 6. Checks the rendered body and the content type of the received request
+    #[tokio::test]
+    async fn test_webhook_template() {
+        let setup = TestSetup::new().await;
+        let client = &setup.client;
+        let (address, requests, receiver) = webhook_receiver(NO_CONTENT).await;
+
+        // 1. POST /v1/netspots/webhook        : With invalid template, expecting 422 with the reason
+        let json = format!(r#"{{"name":"chat","address":"{address}","template":"{{{{ name "}}"#);
+        let response = client
+            .post("/v1/netspots/webhook")
+            .body(json)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        let reason = response.into_string().await.unwrap();
+        assert!(reason.starts_with("Template error: "), "{reason}");
+
+        // 2. POST /v1/netspots/webhook        : With invalid content type, expecting 422
+        let json = format!(r#"{{"name":"chat","address":"{address}","content_type":"text"}}"#);
+        let response = client
+            .post("/v1/netspots/webhook")
+            .body(json)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+
+        // 3. POST /v1/netspots/webhook        : Adding a webhook with a template
+        let template = r#"{\"text\": {{ (name ~ \": \" ~ stat ~ \" \" ~ status) | tojson }}}"#;
+        let json = format!(
+            r#"{{"name":"chat","address":"{address}","template":"{template}",
+                "content_type":"application/vnd.chat+json"}}"#
+        );
+        let response = client
+            .post("/v1/netspots/webhook")
+            .body(json)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Created);
+
+        // 4. PUT  /v1/netspots/webhook/1      : With invalid template, expecting 422
+        let json = format!(
+            r#"{{"name":"chat","address":"{address}","template":"{{{{ name | nope }}}}"}}"#
+        );
+        let response = client
+            .put("/v1/netspots/webhook/1")
+            .body(json)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+
+        // 5. POST /v1/netspots/webhook/1/test : Sending a test alarm to the webhook
+        let response = client.post("/v1/netspots/webhook/1/test").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let delivery = response.into_json::<WebhookDelivery>().await.unwrap();
+        assert_eq!(delivery.status, Some(204));
+
+        // 6. Checks the rendered body and the content type of the received request
+        let request = requests.lock().unwrap().pop().expect("Received request");
+        let (head, body) = request.split_once("\r\n\r\n").expect("Complete request");
+        assert!(head
+            .to_ascii_lowercase()
+            .contains("content-type: application/vnd.chat+json"));
+        assert_eq!(body, r#"{"text": "Test alarm: R_SYN UP_ALERT"}"#);
+
+        receiver.abort();
+        setup.cleanup().await;
+    }
+
+    // This test does the following:
+    //
+    // 1. POST /v1/netspots/webhook          : With a template and a batch, expecting 422
+    // 2. POST /v1/netspots/webhook          : Adding a webhook sending three messages at a time
+    // 3. POST /v1/netspots/test/alarm       : Sending two alarms
+    // 4. GET  /v1/netspots/webhook/1/status : Alarms should wait for a full batch
+    // 5. POST /v1/netspots/test/alarm       : Sending the third alarm
+    // 6. GET  /v1/netspots/webhook/1/status : Batch should be delivered
+    // 7. Checks that the alarms were received as a JSON array in one request
+    #[tokio::test]
+    async fn test_webhook_batch() {
+        let setup = TestSetup::new().await;
+        let client = &setup.client;
+        let (address, requests, receiver) = webhook_receiver(NO_CONTENT).await;
+
+        // 1. POST /v1/netspots/webhook          : With a template and a batch, expecting 422
+        let json = format!(r#"{{"name":"log","address":"{address}","template":"x","batch":{{}}}}"#);
+        let response = client
+            .post("/v1/netspots/webhook")
+            .body(json)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+
+        // 2. POST /v1/netspots/webhook          : Adding a webhook sending three messages at a time
+        let json = format!(
+            r#"{{"name":"log","address":"{address}",
+                "batch":{{"max_messages":3,"max_delay":3600}}}}"#
+        );
+        let response = client
+            .post("/v1/netspots/webhook")
+            .body(json)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Created);
+
+        // 3. POST /v1/netspots/test/alarm       : Sending two alarms
+        for name in ["First", "Second"] {
+            let response = client
+                .post("/v1/netspots/test/alarm")
+                .body(format!(r#"{{"name": "{name}"}}"#))
+                .dispatch()
+                .await;
+            assert_eq!(response.status(), Status::Created);
+        }
+
+        // 4. GET  /v1/netspots/webhook/1/status : Alarms should wait for a full batch
+        wait_status(client, "/v1/netspots/webhook/1/status", |status| {
+            status.queued == 2
+        })
+        .await;
+        let state = client.rocket().state::<NetspotControlState>().unwrap();
+        let almost_delay = SystemTime::now() + Duration::from_secs(3590);
+        let almost_delay = almost_delay.duration_since(UNIX_EPOCH).unwrap().as_nanos() as i64;
+        assert_eq!(state.database.get_due_webhooks(almost_delay), Ok(vec![]));
+        assert!(requests.lock().unwrap().is_empty());
+
+        // 5. POST /v1/netspots/test/alarm       : Sending the third alarm
+        let response = client
+            .post("/v1/netspots/test/alarm")
+            .body(r#"{"name": "Third"}"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Created);
+
+        // 6. GET  /v1/netspots/webhook/1/status : Batch should be delivered
+        wait_status(client, "/v1/netspots/webhook/1/status", |status| {
+            status.queued == 0 && status.last_success.is_some()
+        })
+        .await;
+
+        // 7. Checks that the alarms were received as a JSON array in one request
+        let request = {
+            let requests = requests.lock().unwrap();
+            assert_eq!(requests.len(), 1);
+            requests[0].clone()
+        };
+        let (_, body) = request.split_once("\r\n\r\n").expect("Complete request");
+        let alarms = serde_json::from_str::<Vec<AlarmMessage>>(body).expect("JSON array");
+        let names: Vec<&str> = alarms.iter().map(|alarm| alarm.name.as_str()).collect();
+        assert_eq!(names, ["First", "Second", "Third"]);
+
+        receiver.abort();
+        setup.cleanup().await;
+    }
+
+    // This test does the following:
+    //
+    // 1. POST /v1/netspots/webhook          : With an unreadable CA file, expecting 422
+    // 2. POST /v1/netspots/webhook          : Adding a disabled webhook
+    // 3. POST /v1/netspots/test/alarm       : Sending an alarm
+    // 4. GET  /v1/netspots/webhook/1/status : Nothing should be queued or sent
+    // 5. POST /v1/netspots/webhook          : Adding a webhook to a host that never responds
+    // 6. POST /v1/netspots/webhook/2/test   : Expecting the request to time out
+    #[tokio::test]
+    async fn test_webhook_options() {
+        let setup = TestSetup::new().await;
+        let client = &setup.client;
+        let (address, requests, receiver) = webhook_receiver(NO_CONTENT).await;
+
+        // 1. POST /v1/netspots/webhook          : With an unreadable CA file, expecting 422
+        let json = format!(
+            r#"{{"name":"lab","address":"{address}","tls":{{"ca_file":"/nonexistent.pem"}}}}"#
+        );
+        let response = client
+            .post("/v1/netspots/webhook")
+            .body(json)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        let reason = response.into_string().await.unwrap();
+        assert!(
+            reason.starts_with("Could not read /nonexistent.pem"),
+            "{reason}"
+        );
+
+        // 2. POST /v1/netspots/webhook          : Adding a disabled webhook
+        let json = format!(r#"{{"name":"paused","address":"{address}","enabled":false}}"#);
+        let response = client
+            .post("/v1/netspots/webhook")
+            .body(json)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Created);
+
+        // 3. POST /v1/netspots/test/alarm       : Sending an alarm
+        let response = client.post("/v1/netspots/test/alarm").dispatch().await;
+        assert_eq!(response.status(), Status::Created);
+
+        // 4. GET  /v1/netspots/webhook/1/status : Nothing should be queued or sent
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        let response = client.get("/v1/netspots/webhook/1/status").dispatch().await;
+        let status = response.into_json::<WebhookStatus>().await.unwrap();
+        assert_eq!(status, WebhookStatus::default());
+        assert!(requests.lock().unwrap().is_empty());
+
+        // 5. POST /v1/netspots/webhook          : Adding a webhook to a host that never responds
+        let silent = TcpListener::bind("127.0.0.1:0").await.expect("Listener");
+        let silent_address = silent.local_addr().unwrap();
+        let json =
+            format!(r#"{{"name":"slow","address":"http://{silent_address}/","timeout_ms":200}}"#);
+        let response = client
+            .post("/v1/netspots/webhook")
+            .body(json)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Created);
+
+        // 6. POST /v1/netspots/webhook/2/test   : Expecting the request to time out
+        let response = client.post("/v1/netspots/webhook/2/test").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let delivery = response.into_json::<WebhookDelivery>().await.unwrap();
+        assert_eq!(delivery.status, None);
+        assert!(delivery.error.is_some());
+        assert!(delivery.latency_ms < 5000, "{}", delivery.latency_ms);
+
+        drop(silent);
+        receiver.abort();
+        setup.cleanup().await;
+    }
 }
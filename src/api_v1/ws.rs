@@ -0,0 +1,281 @@
+use crate::state::NetspotControlState;
+use crate::structures::statistics::{Message, MessageFilter};
+use crate::structures::websocket::{SocketEvent, SocketRequest};
+use rocket::futures::{SinkExt, StreamExt};
+use rocket::{get, Shutdown, State};
+use rocket_okapi::openapi;
+use rocket_ws::{Channel, WebSocket};
+use std::collections::BTreeMap;
+use tokio::sync::broadcast::error::RecvError;
+
+// Subscription id is mapped to its filter
+type Subscriptions = BTreeMap<String, MessageFilter>;
+
+fn handle_request(subscriptions: &mut Subscriptions, text: &str) -> SocketEvent {
+    match serde_json::from_str::<SocketRequest>(text) {
+        Ok(SocketRequest::Subscribe { id, filter }) => {
+            subscriptions.insert(id.clone(), filter);
+            SocketEvent::Subscribed { id }
+        }
+        Ok(SocketRequest::Unsubscribe { id }) => match subscriptions.remove(&id) {
+            Some(_) => SocketEvent::Unsubscribed { id },
+            None => SocketEvent::Error {
+                error: format!("No subscription with id {id}"),
+            },
+        },
+        Err(err) => SocketEvent::Error {
+            error: format!("Invalid request: {err}"),
+        },
+    }
+}
+
+// Returns an event for the message when any of the subscriptions matches it
+fn message_event(subscriptions: &Subscriptions, message: Message) -> Option<SocketEvent> {
+    let matching: Vec<String> = subscriptions
+        .iter()
+        .filter(|(_, filter)| filter.matches(&message))
+        .map(|(id, _)| id.clone())
+        .collect();
+    if matching.is_empty() {
+        return None;
+    }
+    Some(SocketEvent::Message {
+        subscriptions: matching,
+        message,
+    })
+}
+
+/// # Live messages and process statuses over WebSocket
+///
+/// Opens a WebSocket where the client subscribes to live netspot messages. Requests and events
+/// are JSON text messages. A subscription is created with
+/// `{"action": "subscribe", "id": "<id>", "filter": {...}}`, where the filter has the same fields
+/// as the stream endpoint query, and removed with `{"action": "unsubscribe", "id": "<id>"}`.
+/// Each matching message is sent once as a `message` event listing all subscriptions it matched.
+///
+/// The status of every netspot process is sent as a `status` event when the socket is opened
+/// and again whenever it changes, so that the process statuses do not need to be polled.
+#[openapi(tag = "Statistics")]
+#[get("/ws")]
+pub fn websocket(
+    ws: WebSocket,
+    state: &State<NetspotControlState>,
+    mut shutdown: Shutdown,
+) -> Channel<'_> {
+    // Subscribing before the statuses are read, so that no change is missed in between
+    let mut messages_rx = state.subscribe_messages();
+    let mut status_rx = state.netspots.subscribe_status();
+
+    ws.channel(move |mut stream| {
+        Box::pin(async move {
+            for status in state.netspots.status_all().await {
+                let event = SocketEvent::Status { status };
+                stream
+                    .send(rocket_ws::Message::text(event.to_json()))
+                    .await?;
+            }
+
+            let mut subscriptions = Subscriptions::new();
+            loop {
+                let event = tokio::select! {
+                    request = stream.next() => match request {
+                        Some(Ok(rocket_ws::Message::Text(text))) => {
+                            handle_request(&mut subscriptions, &text)
+                        }
+                        Some(Ok(rocket_ws::Message::Binary(_))) => SocketEvent::Error {
+                            error: "Requests must be text messages".to_string(),
+                        },
+                        Some(Ok(rocket_ws::Message::Close(_))) | None => break,
+                        Some(Ok(_)) => continue, // Ping and pong are answered automatically
+                        Some(Err(err)) => return Err(err),
+                    },
+                    result = messages_rx.recv() => match result {
+                        Ok(message) => match message_event(&subscriptions, message) {
+                            Some(event) => event,
+                            None => continue,
+                        },
+                        Err(RecvError::Lagged(count)) => {
+                            state.metrics.record_lag("websocket", count);
+                            if subscriptions.is_empty() {
+                                continue;
+                            }
+                            SocketEvent::Lagged { count }
+                        }
+                        Err(RecvError::Closed) => break,
+                    },
+                    result = status_rx.recv() => match result {
+                        Ok(status) => SocketEvent::Status { status },
+                        Err(RecvError::Lagged(_)) => {
+                            // Some changes were missed, sending all current statuses instead
+                            for status in state.netspots.status_all().await {
+                                let event = SocketEvent::Status { status };
+                                stream.send(rocket_ws::Message::text(event.to_json())).await?;
+                            }
+                            continue;
+                        }
+                        Err(RecvError::Closed) => break,
+                    },
+                    _ = &mut shutdown => {
+                        stream.close(None).await?;
+                        break;
+                    }
+                };
+                stream
+                    .send(rocket_ws::Message::text(event.to_json()))
+                    .await?;
+            }
+            Ok(())
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::build_rocket;
+    use crate::state::NetspotControlState;
+    use rocket::futures::{SinkExt, StreamExt};
+    use std::net::TcpListener;
+    use std::path::PathBuf;
+    use std::time::Duration;
+    use tempfile::TempDir;
+    use tokio_tungstenite::tungstenite::Message;
+
+    type Socket = tokio_tungstenite::WebSocketStream<
+        tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+    >;
+
+    // Reads events until one with the given type is found
+    async fn next_event(socket: &mut Socket, event: &str) -> serde_json::Value {
+        loop {
+            let message = tokio::time::timeout(Duration::from_secs(5), socket.next())
+                .await
+                .expect("Event before timeout")
+                .expect("Open socket")
+                .expect("Valid message");
+            let value: serde_json::Value =
+                serde_json::from_str(message.to_text().expect("Text message")).expect("JSON");
+            if value["event"] == event {
+                return value;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_websocket() {
+        // WebSocket upgrade needs a real server, the local client cannot be used for it
+        let test_dir = TempDir::new().expect("temporary directory");
+        let mut test_db = PathBuf::from(test_dir.path());
+        test_db.push("test.db");
+        let state = NetspotControlState::new_customized(None, test_dir.path(), &test_db)
+            .await
+            .expect("Valid state object");
+        let port = TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .expect("Free port")
+            .port();
+        let figment = rocket::Config::figment()
+            .merge(("port", port))
+            .merge(("log_level", "off"));
+        let rocket = build_rocket(state)
+            .configure(figment)
+            .ignite()
+            .await
+            .expect("valid rocket");
+        let shutdown = rocket.shutdown();
+        let server = tokio::spawn(rocket.launch());
+        let base_url = format!("127.0.0.1:{port}/v1");
+
+        // Connecting once the server is up
+        let mut socket = None;
+        for _ in 0..50 {
+            match tokio_tungstenite::connect_async(format!("ws://{base_url}/ws")).await {
+                Ok((stream, _)) => {
+                    socket = Some(stream);
+                    break;
+                }
+                Err(_) => tokio::time::sleep(Duration::from_millis(100)).await,
+            }
+        }
+        let mut socket = socket.expect("WebSocket connection");
+
+        // Status of the default configuration is sent first
+        let event = next_event(&mut socket, "status").await;
+        assert_eq!(event["status"]["id"], 1);
+
+        // Invalid requests are answered with an error
+        socket.send(Message::text("{}")).await.unwrap();
+        next_event(&mut socket, "error").await;
+
+        // Two subscriptions with different filters
+        let requests = [
+            r#"{"action": "subscribe", "id": "alarms", "filter": {"type": "alarm"}}"#,
+            r#"{"action": "subscribe", "id": "data", "filter": {"type": "data"}}"#,
+            r#"{"action": "subscribe", "id": "tests", "filter": {"name": "Live"}}"#,
+        ];
+        for request in requests {
+            socket.send(Message::text(request)).await.unwrap();
+            next_event(&mut socket, "subscribed").await;
+        }
+
+        // Test alarm should match the alarm and the name subscriptions
+        let client = reqwest::Client::new();
+        let response = client
+            .post(format!("http://{base_url}/netspots/test/alarm"))
+            .body(r#"{"name": "Live"}"#)
+            .send()
+            .await
+            .expect("Test alarm sent");
+        assert_eq!(response.status(), reqwest::StatusCode::CREATED);
+        let event = next_event(&mut socket, "message").await;
+        assert_eq!(
+            event["subscriptions"],
+            serde_json::json!(["alarms", "tests"])
+        );
+        assert_eq!(event["message"]["name"], "Live");
+        assert_eq!(event["message"]["type"], "alarm");
+
+        // Unsubscribing an unknown id fails
+        let request = r#"{"action": "unsubscribe", "id": "foo"}"#;
+        socket.send(Message::text(request)).await.unwrap();
+        next_event(&mut socket, "error").await;
+        let request = r#"{"action": "unsubscribe", "id": "tests"}"#;
+        socket.send(Message::text(request)).await.unwrap();
+        next_event(&mut socket, "unsubscribed").await;
+
+        // Adding a disabled configuration sends its status
+        let response = client
+            .post(format!("http://{base_url}/netspot"))
+            .body(
+                r#"{
+	"configuration": {
+		"name": "Test",
+		"device": "any",
+		"promiscuous": true,
+		"enabled": false
+	},
+	"stats": {
+		"perf": {
+			"enabled": true
+		}
+	}
+}"#,
+            )
+            .send()
+            .await
+            .expect("Configuration sent");
+        assert_eq!(response.status(), reqwest::StatusCode::CREATED);
+        let event = next_event(&mut socket, "status").await;
+        assert_eq!(event["status"]["id"], 2);
+        assert_eq!(event["status"]["status"], "disabled");
+
+        // Shutting down the server closes the socket
+        shutdown.notify();
+        let rocket = server.await.unwrap().expect("Server stopped");
+        while let Some(Ok(message)) = socket.next().await {
+            assert!(!message.is_text());
+        }
+        if let Some(state) = rocket.state::<NetspotControlState>() {
+            state.shutdown().await;
+        }
+    }
+}
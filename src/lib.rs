@@ -0,0 +1,5 @@
+//! Parts of netspot_control that other services can reuse
+//!
+//! The server itself is the `netspot_control` binary.
+
+pub mod signature;
@@ -1,7 +1,11 @@
+use crate::state::database::enum_from_text;
+use crate::state::dht::DhtOptions;
 use crate::state::NetspotControlState;
 use std::path::{Path, PathBuf};
 
-use clap::Parser;
+use crate::structures::settings::RetentionSettings;
+use crate::structures::statistics::{Severity, MAX_SECONDS};
+use clap::{Args, Parser};
 use dotenvy::dotenv;
 use rocket::fs::{relative, FileServer};
 use rocket_okapi::rapidoc::{make_rapidoc, GeneralConfig, HideShowConfig, RapiDocConfig};
@@ -54,6 +58,81 @@ struct Cli {
     /// The API URL is most likely http://localhost:3000/pub
     #[arg(long, value_name = "API URL")]
     dht: Option<String>,
+
+    /// Send incidents to the DHT instead of every alarm
+    #[arg(long, requires = "dht")]
+    dht_incidents: bool,
+
+    /// Send only alarms, incidents and detections with at least <SEVERITY> to the DHT
+    ///
+    /// The severity is one of info, low, medium, high and critical
+    #[arg(long, requires = "dht", value_name = "SEVERITY", value_parser = severity_parser)]
+    dht_min_severity: Option<Severity>,
+
+    #[command(flatten)]
+    retention: RetentionArgs,
+}
+
+/// Message retention options
+///
+/// These override the retention settings stored in the database while the server is running.
+/// A zero value removes the limit. Ages must fit in message times, which are nanoseconds.
+#[derive(Debug, Args)]
+struct RetentionArgs {
+    /// Remove alarms older than <SECONDS>
+    #[arg(long, env = "ALARMS_MAX_AGE", value_name = "SECONDS", value_parser = max_age_parser())]
+    alarms_max_age: Option<u64>,
+
+    /// Keep only <ROWS> newest alarms
+    #[arg(long, env = "ALARMS_MAX_ROWS", value_name = "ROWS", value_parser = max_rows_parser())]
+    alarms_max_rows: Option<u64>,
+
+    /// Remove data messages older than <SECONDS>
+    #[arg(long, env = "DATA_MAX_AGE", value_name = "SECONDS", value_parser = max_age_parser())]
+    data_max_age: Option<u64>,
+
+    /// Keep only <ROWS> newest data messages
+    #[arg(long, env = "DATA_MAX_ROWS", value_name = "ROWS", value_parser = max_rows_parser())]
+    data_max_rows: Option<u64>,
+
+    /// Remove oldest messages when the database grows larger than <BYTES>
+    #[arg(long, env = "MAX_DATABASE_SIZE", value_name = "BYTES")]
+    max_database_size: Option<u64>,
+}
+
+fn severity_parser(value: &str) -> Result<Severity, String> {
+    enum_from_text(value.to_string()).map_err(|_| format!("Invalid severity: {value}"))
+}
+
+fn max_age_parser() -> clap::builder::RangedU64ValueParser {
+    clap::value_parser!(u64).range(..=MAX_SECONDS)
+}
+
+fn max_rows_parser() -> clap::builder::RangedU64ValueParser {
+    clap::value_parser!(u64).range(..=i64::MAX as u64)
+}
+
+impl RetentionArgs {
+    fn apply(&self, retention: &mut RetentionSettings) {
+        fn limit(value: u64) -> Option<u64> {
+            (value != 0).then_some(value)
+        }
+        if let Some(value) = self.alarms_max_age {
+            retention.alarms.max_age = limit(value);
+        }
+        if let Some(value) = self.alarms_max_rows {
+            retention.alarms.max_rows = limit(value);
+        }
+        if let Some(value) = self.data_max_age {
+            retention.data.max_age = limit(value);
+        }
+        if let Some(value) = self.data_max_rows {
+            retention.data.max_rows = limit(value);
+        }
+        if let Some(value) = self.max_database_size {
+            retention.max_database_size = limit(value);
+        }
+    }
 }
 
 /// Entry Point for the Server Program
@@ -70,12 +149,17 @@ async fn main() {
     }
 
     // Creating State object for the server
+    let dht = cli.dht.map(|api_url| DhtOptions {
+        api_url,
+        incidents: cli.dht_incidents,
+        min_severity: cli.dht_min_severity,
+    });
     let state = if cli.db_path.is_none() && cli.runtime_path.is_none() {
-        NetspotControlState::new(cli.dht).await
+        NetspotControlState::new(dht).await
     } else {
         let runtime_path = cli.runtime_path.unwrap_or(PathBuf::from("/tmp"));
         let db_path = cli.db_path.unwrap_or(Path::join(&runtime_path, "test.db"));
-        NetspotControlState::new_customized(cli.dht, &runtime_path, &db_path).await
+        NetspotControlState::new_customized(dht, &runtime_path, &db_path).await
     };
     let state = match state {
         Ok(state) => state,
@@ -85,6 +169,11 @@ async fn main() {
         }
     };
 
+    // Command line retention options override stored settings
+    let mut retention = state.database.get_settings().retention;
+    cli.retention.apply(&mut retention);
+    state.database.use_retention(retention);
+
     // Launch server
     let mut shutdown_handle: Option<JoinHandle<()>> = None;
     let launch_result = match cli.seconds {
@@ -165,6 +254,8 @@ fn build_rocket(state: NetspotControlState) -> rocket::Rocket<rocket::Build> {
         .mount("/", FileServer::from(relative!("static")))
         // Mount APIv1
         .mount("/v1/", api_v1::routes())
+        // Prometheus expects metrics at the root
+        .mount("/", rocket::routes![api_v1::metrics::metrics])
         // API documentation from the design
         // Using the openapi.json from the static/design folder
         .mount("/design/rapidoc/", make_rapidoc(&rapidoc_config))
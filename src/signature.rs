@@ -0,0 +1,187 @@
+//! Signing webhook requests
+//!
+//! When a webhook has a secret, every request carries two headers:
+//!
+//! * `X-Netspot-Timestamp` is the time of sending as seconds since Unix Epoch.
+//!
+//! * `X-Netspot-Signature` is `sha256=` followed by the hex encoded HMAC-SHA256 of the timestamp,
+//!   a period and the request body, using the webhook secret as the key.
+//!
+//! Receivers should compute the same HMAC and compare it, and reject requests where the
+//! timestamp is further than a few minutes from their own clock. Without the time check, a
+//! captured request could be sent again later. [`verify`] does both.
+//!
+//! ```
+//! use netspot_control::signature::{sign, verify, DEFAULT_TOLERANCE};
+//!
+//! let body = br#"{"type":"alarm"}"#;
+//! let signature = sign(b"secret", 1669629600, body);
+//! assert!(verify(b"secret", "1669629600", &signature, body, 1669629630, DEFAULT_TOLERANCE).is_ok());
+//! ```
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::fmt;
+use std::fmt::Write;
+
+type HmacSha256 = Hmac<Sha256>;
+
+// Header names are case-insensitive, lowercase is what HTTP libraries expect for static names
+pub const TIMESTAMP_HEADER: &str = "x-netspot-timestamp";
+pub const SIGNATURE_HEADER: &str = "x-netspot-signature";
+
+/// Accepted difference in seconds between the timestamp and the clock of the receiver
+pub const DEFAULT_TOLERANCE: u64 = 5 * 60;
+
+const SIGNATURE_PREFIX: &str = "sha256=";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SignatureError {
+    /// Timestamp header is not a number
+    InvalidTimestamp,
+    /// Timestamp is outside of the accepted time window
+    Expired,
+    /// Signature header is not in the `sha256=<hex>` format
+    InvalidSignature,
+    /// Signature does not match the body
+    Mismatch,
+}
+
+impl fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            SignatureError::InvalidTimestamp => "invalid timestamp",
+            SignatureError::Expired => "timestamp outside of the accepted time window",
+            SignatureError::InvalidSignature => "invalid signature format",
+            SignatureError::Mismatch => "signature does not match",
+        };
+        f.write_str(text)
+    }
+}
+
+impl std::error::Error for SignatureError {}
+
+fn signed_mac(secret: &[u8], timestamp: &str, body: &[u8]) -> HmacSha256 {
+    // HMAC accepts keys of any length
+    let mut mac = HmacSha256::new_from_slice(secret).expect("Any key length");
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    mac
+}
+
+/// Returns the signature header value for the body sent at the given time
+pub fn sign(secret: &[u8], timestamp: i64, body: &[u8]) -> String {
+    let digest = signed_mac(secret, &timestamp.to_string(), body)
+        .finalize()
+        .into_bytes();
+    let mut signature = String::from(SIGNATURE_PREFIX);
+    for byte in digest {
+        let _ = write!(signature, "{byte:02x}");
+    }
+    signature
+}
+
+/// Checks the timestamp and signature headers of a received request
+///
+/// The `now` and `tolerance` are in seconds. The signature is compared in constant time.
+pub fn verify(
+    secret: &[u8],
+    timestamp: &str,
+    signature: &str,
+    body: &[u8],
+    now: i64,
+    tolerance: u64,
+) -> Result<(), SignatureError> {
+    let time = timestamp
+        .trim()
+        .parse::<i64>()
+        .map_err(|_| SignatureError::InvalidTimestamp)?;
+    if now.abs_diff(time) > tolerance {
+        return Err(SignatureError::Expired);
+    }
+    let expected = signature
+        .trim()
+        .strip_prefix(SIGNATURE_PREFIX)
+        .and_then(decode_hex)
+        .ok_or(SignatureError::InvalidSignature)?;
+    signed_mac(secret, timestamp.trim(), body)
+        .verify_slice(&expected)
+        .map_err(|_| SignatureError::Mismatch)
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    text.as_bytes()
+        .chunks(2)
+        .map(|pair| match pair {
+            [high, low] => Some((hex_value(*high)? << 4) | hex_value(*low)?),
+            _ => None,
+        })
+        .collect()
+}
+
+fn hex_value(digit: u8) -> Option<u8> {
+    (digit as char).to_digit(16).map(|value| value as u8)
+}
+
+// Unit tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_signature() {
+        // Same as HMAC-SHA256 of "1.{}" with "key" computed by other tools
+        let signature = sign(b"key", 1, b"{}");
+        let expected = "sha256=1ba6b8171186efc613e8bcc0cbdab2748f24984d7c5a84faa2637afa0e40d224";
+        assert_eq!(signature, expected);
+    }
+
+    #[test]
+    fn verification() {
+        let body = b"{\"type\":\"alarm\"}";
+        let signature = sign(b"secret", 1000, body);
+        assert_eq!(
+            verify(b"secret", "1000", &signature, body, 1000, 300),
+            Ok(())
+        );
+        assert_eq!(
+            verify(b"secret", "1000", &signature, body, 1300, 300),
+            Ok(())
+        );
+        assert_eq!(
+            verify(b"secret", "1000", &signature, body, 700, 300),
+            Ok(())
+        );
+        assert_eq!(
+            verify(b"secret", "1000", &signature, body, 1301, 300),
+            Err(SignatureError::Expired)
+        );
+        assert_eq!(
+            verify(b"secret", "1001", &signature, body, 1000, 300),
+            Err(SignatureError::Mismatch)
+        );
+        assert_eq!(
+            verify(b"other", "1000", &signature, body, 1000, 300),
+            Err(SignatureError::Mismatch)
+        );
+        assert_eq!(
+            verify(b"secret", "1000", &signature, b"{}", 1000, 300),
+            Err(SignatureError::Mismatch)
+        );
+        assert_eq!(
+            verify(b"secret", "soon", &signature, body, 1000, 300),
+            Err(SignatureError::InvalidTimestamp)
+        );
+        assert_eq!(
+            verify(b"secret", "1000", &signature[7..], body, 1000, 300),
+            Err(SignatureError::InvalidSignature)
+        );
+        assert_eq!(
+            verify(b"secret", "1000", "sha256=zz", body, 1000, 300),
+            Err(SignatureError::InvalidSignature)
+        );
+    }
+}
@@ -1,21 +1,40 @@
+pub mod correlations;
 pub mod database;
 pub mod dht;
+pub mod incidents;
 pub mod logger;
+pub mod messages;
+pub mod metrics;
 pub mod netspots;
+pub mod pcaps;
+pub mod severity;
+pub mod suppressions;
+pub mod thresholds;
 pub mod webhooks;
 
 use crate::state::webhooks::WebhookManager;
 use crate::structures::statistics::Message;
 
-use crate::state::dht::dht_message_sender;
+use crate::state::correlations::CorrelationManager;
+use crate::state::dht::{dht_message_sender, DhtOptions};
+use crate::state::incidents::incident_engine;
 use crate::state::logger::message_printer;
+use crate::state::messages::MessageBus;
+use crate::state::metrics::{metrics_collector, Metrics, SharedMetrics};
+use crate::state::pcaps::PcapStore;
+use crate::state::severity::SeverityScorer;
+use crate::state::suppressions::Suppressor;
+use crate::state::thresholds::ThresholdManager;
 use crate::tasks::RunChecker;
 use database::Database;
-use netspots::NetspotManager;
+use netspots::{MessageSender, NetspotManager};
 use std::path::Path;
 use std::{env, fs};
 use tokio::sync::{broadcast, watch};
 
+// Messages kept for the broadcast receivers that fall behind
+const MESSAGE_CAPACITY: usize = 1024;
+
 // Netspot Control State
 //--------------------------------------------------------------------------------------------------
 
@@ -23,13 +42,21 @@ pub struct NetspotControlState {
     pub netspots: NetspotManager,
     pub database: Database,
     pub webhooks: WebhookManager,
+    pub suppressions: Suppressor,
+    pub thresholds: ThresholdManager,
+    pub correlations: CorrelationManager,
+    pub pcaps: PcapStore,
+    pub metrics: SharedMetrics,
+
+    /// Live messages from netspot processes
+    messages_tx: MessageBus,
 
     /// Signaling worker tasks to stop when shutdown is called
     run_tx: watch::Sender<bool>,
 }
 
 impl NetspotControlState {
-    pub async fn new(dht: Option<String>) -> Result<NetspotControlState, String> {
+    pub async fn new(dht: Option<DhtOptions>) -> Result<NetspotControlState, String> {
         // Get database path from environment
         let database_path = match env::var("DB_FILE_PATH") {
             Ok(path) => path,
@@ -43,7 +70,7 @@ impl NetspotControlState {
     }
 
     pub async fn new_customized(
-        dht: Option<String>,
+        dht: Option<DhtOptions>,
         runtime_path: &Path,
         database_path: &Path,
     ) -> Result<NetspotControlState, String> {
@@ -53,7 +80,7 @@ impl NetspotControlState {
         }
 
         // Ensure that database path exists
-        match database_path.parent() {
+        let pcaps = match database_path.parent() {
             None => {
                 return Err(format!("Invalid database path: {database_path:?}"));
             }
@@ -61,14 +88,27 @@ impl NetspotControlState {
                 if let Err(err) = fs::create_dir_all(path) {
                     return Err(format!("Could not create database path: {}", err));
                 }
+                // Uploaded capture files are kept with the database
+                PcapStore::new(&path.join("pcaps"))?
             }
-        }
+        };
 
         // Create channel for letting worker threads to know when to stop
         let (run_tx, _) = watch::channel(true);
 
-        // Create channels for broadcasting data and alarm messages
-        let (messages_tx, _) = broadcast::channel::<Message>(16);
+        // Create channels for broadcasting data and alarm messages. The database writer and the
+        // webhooks have their own queues, so that they do not miss messages when they fall behind.
+        let mut messages_tx = MessageBus::new(MESSAGE_CAPACITY);
+        let database_rx = messages_tx.queue();
+        let webhooks_rx = messages_tx.queue();
+
+        // Metrics are collected from the messages and from the worker tasks
+        let metrics = Metrics::new();
+        tokio::spawn(metrics_collector(
+            metrics.clone(),
+            metrics.receiver("metrics", messages_tx.subscribe()),
+            RunChecker::new(run_tx.subscribe()),
+        ));
 
         // Check if SHOW_NETSPOT_MESSAGES environment variable is set
         if let Ok(value) = env::var("SHOW_NETSPOT_MESSAGES") {
@@ -76,19 +116,25 @@ impl NetspotControlState {
                 if value != 0 {
                     // Printing received messages to stdout
                     tokio::spawn(message_printer(
-                        messages_tx.subscribe(),
+                        metrics.receiver("printer", messages_tx.subscribe()),
                         RunChecker::new(run_tx.subscribe()),
                     ));
                 }
             }
         };
 
+        // Check if NETSPOT_LOG_FILES environment variable is set
+        let log_files = match env::var("NETSPOT_LOG_FILES") {
+            Ok(value) => matches!(value.parse::<i32>(), Ok(value) if value != 0),
+            Err(_) => false,
+        };
+
         // Sending messages to DHT REST API
-        if let Some(api_url) = dht {
+        if let Some(options) = dht {
             tokio::spawn(dht_message_sender(
-                api_url,
+                options,
                 get_ip_addresses()?,
-                messages_tx.subscribe(),
+                metrics.receiver("dht", messages_tx.subscribe()),
                 RunChecker::new(run_tx.subscribe()),
             ));
         }
@@ -96,14 +142,48 @@ impl NetspotControlState {
         // Database has worker task for writing messages to the database.
         let database = Database::new(
             database_path.to_str().ok_or("Invalid DB path")?,
-            messages_tx.subscribe(),
+            metrics.queue_receiver("database", database_rx),
             RunChecker::new(run_tx.subscribe()),
         )?;
 
-        // Webhook manager has worker task for sending messages.
+        // Incident engine groups the alarms and sends the incidents back to the other receivers
+        tokio::spawn(incident_engine(
+            database.clone(),
+            messages_tx.clone(),
+            metrics.receiver("incidents", messages_tx.subscribe()),
+            RunChecker::new(run_tx.subscribe()),
+        ));
+
+        // Webhook manager has worker tasks for queueing and sending messages.
         let webhooks = WebhookManager::new(
             database.get_webhooks()?,
-            messages_tx.subscribe(),
+            database.clone(),
+            metrics.queue_receiver("webhooks", webhooks_rx),
+            metrics.clone(),
+            RunChecker::new(run_tx.subscribe()),
+        )?;
+
+        // Alarms are given their severity and checked against the suppression rules as they are
+        // received
+        let scorer = SeverityScorer::new(database.clone());
+        let suppressions = Suppressor::new(database.get_rules()?);
+
+        // Threshold rules send their alarms to the same channel with the netspot alarms
+        let thresholds = ThresholdManager::new(
+            database.get_rules()?,
+            scorer.clone(),
+            suppressions.clone(),
+            messages_tx.clone(),
+            metrics.receiver("thresholds", messages_tx.subscribe()),
+            RunChecker::new(run_tx.subscribe()),
+        );
+
+        // Correlation rules combine the alarms and send the detections back to the other receivers
+        let correlations = CorrelationManager::new(
+            database.get_rules()?,
+            database.clone(),
+            messages_tx.clone(),
+            metrics.receiver("correlations", messages_tx.subscribe()),
             RunChecker::new(run_tx.subscribe()),
         );
 
@@ -111,7 +191,13 @@ impl NetspotControlState {
         let netspots = NetspotManager::new(
             runtime_path,
             database.get_configurations()?,
-            messages_tx,
+            log_files,
+            MessageSender {
+                message_tx: messages_tx.clone(),
+                database: database.clone(),
+                scorer,
+                suppressor: suppressions.clone(),
+            },
             RunChecker::new(run_tx.subscribe()),
         )
         .await?;
@@ -125,15 +211,26 @@ impl NetspotControlState {
             database,
             netspots,
             webhooks,
+            suppressions,
+            thresholds,
+            correlations,
+            pcaps,
+            metrics,
+            messages_tx,
             run_tx,
         })
     }
 
+    pub fn subscribe_messages(&self) -> broadcast::Receiver<Message> {
+        self.messages_tx.subscribe()
+    }
+
     pub async fn shutdown(&self) {
         println!("NetspotControlState shutdown requested...");
 
         // Request all netspot processes to stop
         self.netspots.stop_all().await;
+        self.netspots.stop_replays().await;
 
         // Send signal to stop workers and wait them to stop
         if self.run_tx.send(false).is_ok() {
@@ -0,0 +1,267 @@
+use crate::state::database::Database;
+use crate::state::messages::MessageBus;
+use crate::state::metrics::MessageReceiver;
+use crate::structures::correlations::{CorrelationRule, CorrelationRules, Detection};
+use crate::structures::statistics::{seconds_as_nanos, AlarmMessage, Message, MessageType, Stat};
+use crate::tasks::RunChecker;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+// Alarms are combined for each rule and configuration, or only for each rule when the rule
+// combines alarms across configurations
+type GroupKey = (i32, Option<i32>);
+
+// Alarm waiting for the rest of the stats of its rule
+struct SeenAlarm {
+    time: i64,
+    config_id: Option<i32>,
+    stat: Stat,
+}
+
+#[derive(Default)]
+struct Correlator {
+    rules: Vec<(i32, CorrelationRule)>,
+    groups: HashMap<GroupKey, Vec<SeenAlarm>>,
+}
+
+impl Correlator {
+    // Changed rules start from a clean state
+    fn update(&mut self, rules: CorrelationRules) {
+        let mut rules: Vec<(i32, CorrelationRule)> = rules.into_iter().collect();
+        rules.sort_by_key(|(id, _)| *id);
+        self.rules = rules;
+        self.groups.clear();
+    }
+
+    fn correlate(&mut self, alarm: &AlarmMessage) -> Vec<Detection> {
+        let mut detections = Vec::new();
+        for (id, rule) in &self.rules {
+            if !rule.accepts(alarm) {
+                continue;
+            }
+            let group = match rule.across_configurations {
+                true => None,
+                false => alarm.config_id,
+            };
+            let seen = self.groups.entry((*id, group)).or_default();
+            let window = seconds_as_nanos(rule.window).unwrap_or(i64::MAX);
+            seen.retain(|seen| alarm.time.saturating_sub(seen.time) <= window);
+            seen.push(SeenAlarm {
+                time: alarm.time,
+                config_id: alarm.config_id,
+                stat: alarm.stat.clone(),
+            });
+            let complete = rule
+                .stats
+                .iter()
+                .all(|stat| seen.iter().any(|seen| seen.stat == *stat));
+            if !complete {
+                continue;
+            }
+            let mut configurations: Vec<i32> =
+                seen.iter().filter_map(|seen| seen.config_id).collect();
+            configurations.sort_unstable();
+            configurations.dedup();
+            detections.push(Detection {
+                id: 0,
+                time: alarm.time,
+                first_seen: seen
+                    .iter()
+                    .map(|seen| seen.time)
+                    .min()
+                    .unwrap_or(alarm.time),
+                name: rule.name.clone(),
+                rule_id: *id,
+                severity: rule.severity,
+                config_id: match configurations[..] {
+                    [config_id] => Some(config_id),
+                    _ => None,
+                },
+                configurations,
+                stats: rule.stats.clone(),
+                msg_type: MessageType::Detection,
+            });
+            seen.clear();
+        }
+        detections
+    }
+}
+
+// Combines the alarms with the correlation rules. Detections are stored in the database, and sent
+// to the other receivers as messages.
+pub struct CorrelationManager {
+    correlator: Arc<Mutex<Correlator>>,
+}
+
+impl CorrelationManager {
+    pub fn new(
+        rules: CorrelationRules,
+        database: Database,
+        messages_tx: MessageBus,
+        message_rx: MessageReceiver,
+        run_checker: RunChecker,
+    ) -> CorrelationManager {
+        let correlator = Arc::new(Mutex::new(Correlator::default()));
+        correlator.lock().unwrap().update(rules);
+        tokio::spawn(correlation_task(
+            correlator.clone(),
+            database,
+            messages_tx,
+            message_rx,
+            run_checker,
+        ));
+        CorrelationManager { correlator }
+    }
+
+    pub fn update(&self, rules: CorrelationRules) {
+        self.correlator.lock().unwrap().update(rules);
+    }
+}
+
+pub fn validate_rule(rule: &CorrelationRule) -> Result<(), String> {
+    if rule.stats.len() < 2 {
+        return Err("Rule requires at least two stats".to_string());
+    }
+    for (index, stat) in rule.stats.iter().enumerate() {
+        if rule.stats[..index].contains(stat) {
+            return Err("Stats must not repeat".to_string());
+        }
+    }
+    if rule.window == 0 {
+        return Err("Window must be at least one second".to_string());
+    }
+    Ok(())
+}
+
+async fn correlation_task(
+    correlator: Arc<Mutex<Correlator>>,
+    database: Database,
+    messages_tx: MessageBus,
+    mut message_rx: MessageReceiver,
+    mut run_checker: RunChecker,
+) {
+    println!("Correlation rules started.");
+    while run_checker.keep_running() {
+        tokio::select! {
+            Ok(message) = message_rx.recv() => {
+                match message {
+                    Message::Alarm(alarm) if !alarm.suppressed => {
+                        let detections = correlator.lock().unwrap().correlate(&alarm);
+                        for mut detection in detections {
+                            match database.add_detection(&detection) {
+                                Ok(id) => detection.id = id,
+                                Err(err) => {
+                                    eprintln!("Could not store detection: {err}");
+                                    continue;
+                                }
+                            }
+                            let _ = messages_tx.send(Message::Detection(Box::new(detection)));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            _ = run_checker.shutdown_recv() => {},
+        }
+    }
+    println!("Correlation rules stopped.");
+}
+
+// Unit tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structures::statistics::Severity;
+
+    const SECOND: i64 = 1_000_000_000;
+
+    fn scan_rule(across_configurations: bool) -> CorrelationRule {
+        CorrelationRule {
+            name: "Possible SYN scan".to_string(),
+            stats: vec![Stat::RSyn, Stat::RDstSrcPort, Stat::Traffic],
+            window: 10,
+            severity: Severity::High,
+            across_configurations,
+            ..CorrelationRule::default()
+        }
+    }
+
+    fn correlate(
+        correlator: &mut Correlator,
+        time: i64,
+        config_id: i32,
+        stat: Stat,
+    ) -> Vec<Detection> {
+        let alarm = AlarmMessage {
+            time: time * SECOND,
+            config_id: Some(config_id),
+            stat,
+            ..AlarmMessage::default()
+        };
+        correlator.correlate(&alarm)
+    }
+
+    #[test]
+    fn correlating() {
+        let mut correlator = Correlator::default();
+        correlator.update(CorrelationRules::from([(2, scan_rule(false))]));
+        let correlator = &mut correlator;
+
+        // Every stat is needed within the window
+        assert!(correlate(correlator, 0, 1, Stat::RSyn).is_empty());
+        assert!(correlate(correlator, 5, 1, Stat::RSyn).is_empty());
+        assert!(correlate(correlator, 6, 2, Stat::RDstSrcPort).is_empty());
+        assert!(correlate(correlator, 11, 1, Stat::RDstSrcPort).is_empty());
+        let detections = correlate(correlator, 12, 1, Stat::Traffic);
+        assert_eq!(detections.len(), 1);
+        assert_eq!(detections[0].rule_id, 2);
+        assert_eq!(detections[0].name, "Possible SYN scan");
+        assert_eq!(detections[0].severity, Severity::High);
+        assert_eq!(detections[0].first_seen, 5 * SECOND);
+        assert_eq!(detections[0].time, 12 * SECOND);
+        assert_eq!(detections[0].config_id, Some(1));
+        assert_eq!(detections[0].configurations, vec![1]);
+        assert_eq!(detections[0].msg_type, MessageType::Detection);
+
+        // Next detection needs a new set of alarms
+        assert!(correlate(correlator, 13, 1, Stat::Traffic).is_empty());
+
+        // Other configurations are combined separately
+        assert!(correlate(correlator, 14, 2, Stat::RSyn).is_empty());
+        assert_eq!(correlate(correlator, 15, 2, Stat::Traffic).len(), 1);
+    }
+
+    #[test]
+    fn correlating_across_configurations() {
+        let mut correlator = Correlator::default();
+        correlator.update(CorrelationRules::from([(1, scan_rule(true))]));
+        assert!(correlate(&mut correlator, 0, 1, Stat::RSyn).is_empty());
+        assert!(correlate(&mut correlator, 1, 2, Stat::RDstSrcPort).is_empty());
+        let detections = correlate(&mut correlator, 2, 1, Stat::Traffic);
+        assert_eq!(detections.len(), 1);
+        assert_eq!(detections[0].config_id, None);
+        assert_eq!(detections[0].configurations, vec![1, 2]);
+
+        // Updating the rules forgets the earlier alarms
+        assert!(correlate(&mut correlator, 3, 1, Stat::RSyn).is_empty());
+        assert!(correlate(&mut correlator, 3, 1, Stat::RDstSrcPort).is_empty());
+        correlator.update(CorrelationRules::from([(1, scan_rule(true))]));
+        assert!(correlate(&mut correlator, 4, 1, Stat::Traffic).is_empty());
+    }
+
+    #[test]
+    fn validation() {
+        let rule = |stats: Vec<Stat>, window: u64| CorrelationRule {
+            name: "Rule".to_string(),
+            stats,
+            window,
+            ..CorrelationRule::default()
+        };
+        assert!(validate_rule(&rule(vec![Stat::RSyn, Stat::Traffic], 60)).is_ok());
+        assert!(validate_rule(&rule(vec![Stat::RSyn], 60)).is_err());
+        assert!(validate_rule(&rule(vec![Stat::RSyn, Stat::RSyn], 60)).is_err());
+        assert!(validate_rule(&rule(vec![Stat::RSyn, Stat::Traffic], 0)).is_err());
+    }
+}
@@ -1,41 +1,136 @@
 mod models;
 mod schema;
 
-use crate::state::database::models::{NewAlarms, NewConfiguration, NewData, NewWebhook};
+use crate::state::database::models::{
+    Alarm, AlarmReviewChanges, Data, DatabaseSize, DetectionRow, IncidentRow, NewAlarm,
+    NewConfiguration, NewData, NewDetection, NewIncident, NewOutboxMessage, NewPcap, NewReplay,
+    NewSettings, NewWebhook, PcapRow, ReplayRow, RuleRow,
+};
+use crate::state::metrics::MessageReceiver;
 use crate::structures::configuration::{NetspotConfig, NetspotConfigMap};
+use crate::structures::correlations::{CorrelationRule, Detection, DetectionQuery, Detections};
+use crate::structures::incidents::{
+    Incident, IncidentQuery, IncidentSettings, IncidentState, Incidents,
+};
+use crate::structures::replays::{Pcap, Pcaps, Replay, ReplayStatus, Replays};
+use crate::structures::rules::{RuleItem, RuleList};
+use crate::structures::settings::{
+    CleanupResult, RetentionPolicy, RetentionSettings, RetentionStatus, Settings,
+};
 use crate::structures::statistics::{
-    AlarmMessage, AlarmMessages, DataMessage, DataMessages, Message,
+    seconds_as_nanos, AlarmMessage, AlarmMessages, AlarmQuery, AlarmReviewUpdate, DataMessage,
+    DataMessages, DataQuery, Message, MessageCursor, Severity,
 };
 
 use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Integer, Text};
 use diesel::sqlite::Sqlite;
 use diesel::{Connection, SqliteConnection};
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 
+use crate::structures::severity::SeveritySettings;
+use crate::structures::suppressions::SuppressionRule;
+use crate::structures::thresholds::ThresholdRule;
 use crate::structures::webhooks::{Webhook, WebhookItem, WebhookList, Webhooks};
 use crate::tasks::RunChecker;
 use std::collections::HashMap;
 use std::error::Error;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::{Duration, SystemTime};
-use tokio::sync::broadcast;
+use tokio::sync::{broadcast, mpsc};
+
+const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
+
+// Settings are stored in a single row with this id
+const SETTINGS_ID: i32 = 1;
+
+// How often old messages are removed
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
+
+// Number of oldest messages removed at a time when the database is too large
+const SHRINK_BATCH_ROWS: i64 = 1000;
+
+// Retention applies only to the live messages. Messages from the replays are kept until their
+// replay job is removed.
+const LIVE_MESSAGES: &str = "job_id IS NULL";
 
 pub enum DatabaseError {
     NotFound,
+    // Row is still in use, and the reason tells by what
+    Conflict(String),
     Unexpected(String),
 }
 
+// Rules are stored as JSON in tables that have only the id and the config columns. Therefore,
+// the same queries are used for all of them.
+pub trait JsonRule: Serialize + DeserializeOwned {
+    const TABLE: &'static str;
+    // Rule type in the error messages
+    const KIND: &'static str;
+
+    fn name(&self) -> &str;
+}
+
+impl JsonRule for CorrelationRule {
+    const TABLE: &'static str = "correlation_rules";
+    const KIND: &'static str = "correlation rule";
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl JsonRule for SuppressionRule {
+    const TABLE: &'static str = "suppression_rules";
+    const KIND: &'static str = "suppression rule";
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl JsonRule for ThresholdRule {
+    const TABLE: &'static str = "threshold_rules";
+    const KIND: &'static str = "threshold rule";
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
 // TODO: Check if RwLock could be used here
 type DbConnection = Arc<Mutex<SqliteConnection>>;
 
+// Retention policy and the last cleanup result are shared with the database writer task
+type SharedRetention = Arc<RwLock<RetentionSettings>>;
+type SharedCleanupResult = Arc<Mutex<Option<CleanupResult>>>;
+
+// Incident settings are read by the incident engine
+type SharedIncidentSettings = Arc<RwLock<IncidentSettings>>;
+
+// Severity settings are read for every received alarm
+type SharedSeveritySettings = Arc<RwLock<SeveritySettings>>;
+
+// Queued webhook messages are handled by the webhook manager
+pub use models::{enum_from_text, enum_to_text, OutboxMessage};
+
+#[derive(Clone)]
 pub struct Database {
     db_connection: DbConnection,
+    stored_tx: broadcast::Sender<Message>,
+    replay_tx: mpsc::UnboundedSender<Message>,
+    last_cleanup: SharedCleanupResult,
+    retention: SharedRetention,
+    incident_settings: SharedIncidentSettings,
+    severity_settings: SharedSeveritySettings,
 }
 
 impl Database {
     pub fn new(
         database_url: &str,
-        messages_rx: broadcast::Receiver<Message>,
+        messages_rx: MessageReceiver,
         run_checker: RunChecker,
     ) -> Result<Database, String> {
         // Get database connection
@@ -49,18 +144,44 @@ impl Database {
             return Err(format!("Could not run migrations: {}", err));
         }
 
+        // Stored settings are used until changed
+        let settings = Database::load_settings(&mut connection)?;
+
         // Create shared database connection object
         let db_connection = Arc::new(Mutex::new(connection));
+        let last_cleanup = Arc::new(Mutex::new(None));
+        let retention = Arc::new(RwLock::new(settings.retention));
+        let incident_settings = Arc::new(RwLock::new(settings.incidents));
+        let severity_settings = Arc::new(RwLock::new(settings.severity));
+        let (stored_tx, _) = broadcast::channel(16);
+        let (replay_tx, replay_rx) = mpsc::unbounded_channel();
 
         // Start task for writing incoming messages to the database
         tokio::spawn(database_writer(
             db_connection.clone(),
+            retention.clone(),
+            last_cleanup.clone(),
             messages_rx,
+            replay_rx,
+            stored_tx.clone(),
             run_checker,
         ));
 
         // Return complete database
-        Ok(Database { db_connection })
+        Ok(Database {
+            db_connection,
+            stored_tx,
+            replay_tx,
+            last_cleanup,
+            retention,
+            incident_settings,
+            severity_settings,
+        })
+    }
+
+    // Live alarms and data messages are sent again with their ids once they are stored
+    pub fn subscribe_stored(&self) -> broadcast::Receiver<Message> {
+        self.stored_tx.subscribe()
     }
 
     pub fn add_configuration(&self, new_config: &NetspotConfig) -> Result<(), String> {
@@ -82,6 +203,23 @@ impl Database {
         }
     }
 
+    pub fn add_rule<T: JsonRule>(&self, new_rule: &T) -> Result<(), String> {
+        match serde_json::to_string(new_rule) {
+            Ok(rule_config) => {
+                let mut connection = self.db_connection.lock().unwrap();
+                match diesel::sql_query(format!("INSERT INTO {} (config) VALUES (?)", T::TABLE))
+                    .bind::<Text, _>(&rule_config)
+                    .execute(&mut *connection)
+                {
+                    Ok(1) => Ok(()),
+                    Err(err) => Err(err.to_string()),
+                    Ok(rows) => Err(format!("Unexpected row write count: {}", rows)),
+                }
+            }
+            Err(err) => Err(format!("Could not convert {} to JSON: {}", T::KIND, err)),
+        }
+    }
+
     pub fn add_webhook(&self, new_webhook: &Webhook) -> Result<(), String> {
         match serde_json::to_string(&new_webhook) {
             Ok(webhook_config) => {
@@ -102,6 +240,110 @@ impl Database {
         }
     }
 
+    // Stores a new incident and returns its id
+    pub fn add_incident(&self, incident: &Incident) -> Result<i32, String> {
+        let mut connection = self.db_connection.lock().unwrap();
+        diesel::insert_into(schema::incidents::dsl::incidents)
+            .values(NewIncident::from(incident))
+            .execute(&mut *connection)
+            .and_then(|_| last_insert_rowid(&mut connection))
+            .map_err(|err| err.to_string())
+    }
+
+    // Stores a new detection and returns its id
+    pub fn add_detection(&self, detection: &Detection) -> Result<i32, String> {
+        let new_detection = NewDetection::try_from(detection).map_err(|err| err.to_string())?;
+        let mut connection = self.db_connection.lock().unwrap();
+        diesel::insert_into(schema::detections::dsl::detections)
+            .values(new_detection)
+            .execute(&mut *connection)
+            .and_then(|_| last_insert_rowid(&mut connection))
+            .map_err(|err| err.to_string())
+    }
+
+    // Stores the details of an uploaded capture file and returns its id
+    pub fn add_pcap(&self, pcap: &Pcap) -> Result<i32, String> {
+        let mut connection = self.db_connection.lock().unwrap();
+        diesel::insert_into(schema::pcaps::dsl::pcaps)
+            .values(NewPcap::from(pcap))
+            .execute(&mut *connection)
+            .and_then(|_| last_insert_rowid(&mut connection))
+            .map_err(|err| err.to_string())
+    }
+
+    // Stores a new replay job and returns its id
+    pub fn add_replay(&self, replay: &Replay) -> Result<i32, String> {
+        let mut connection = self.db_connection.lock().unwrap();
+        diesel::insert_into(schema::replays::dsl::replays)
+            .values(NewReplay::from(replay))
+            .execute(&mut *connection)
+            .and_then(|_| last_insert_rowid(&mut connection))
+            .map_err(|err| err.to_string())
+    }
+
+    // Queues a message from a replay for the database writer task, which receives the live
+    // messages from the broadcast channel. Replayed messages are not sent to the live receivers.
+    pub fn add_message(&self, message: Message) {
+        let _ = self.replay_tx.send(message);
+    }
+
+    // Queues the payloads for delivery to their webhooks. Each payload is sent at the earliest at
+    // the given time.
+    pub fn add_webhook_messages(
+        &self,
+        payloads: &[(i32, String, i64)],
+        now: i64,
+    ) -> Result<(), String> {
+        let new_messages: Vec<NewOutboxMessage> = payloads
+            .iter()
+            .map(|(webhook_id, payload, next_attempt)| NewOutboxMessage {
+                webhook_id: *webhook_id,
+                created: now,
+                payload,
+                next_attempt: *next_attempt,
+            })
+            .collect();
+        let mut connection = self.db_connection.lock().unwrap();
+        diesel::insert_into(schema::webhook_outbox::dsl::webhook_outbox)
+            .values(&new_messages)
+            .execute(&mut *connection)
+            .map(|_| ())
+            .map_err(|err| err.to_string())
+    }
+
+    // Returns the number of stored alarms and data messages
+    pub fn count_messages(&self) -> Result<(i64, i64), String> {
+        let mut connection = self.db_connection.lock().unwrap();
+        let alarms = schema::alarms::dsl::alarms
+            .count()
+            .get_result::<i64>(&mut *connection)
+            .map_err(|err| err.to_string())?;
+        let data = schema::data::dsl::data
+            .count()
+            .get_result::<i64>(&mut *connection)
+            .map_err(|err| err.to_string())?;
+        Ok((alarms, data))
+    }
+
+    // Returns the number of queued messages and dead letters for the webhook
+    pub fn count_webhook_messages(&self, webhook_id: i32) -> Result<(i64, i64), String> {
+        use schema::webhook_outbox::dsl;
+        let mut connection = self.db_connection.lock().unwrap();
+        let counts = dsl::webhook_outbox
+            .filter(dsl::webhook_id.eq(webhook_id))
+            .group_by(dsl::dead)
+            .select((dsl::dead, diesel::dsl::count_star()))
+            .load::<(bool, i64)>(&mut *connection)
+            .map_err(|err| err.to_string())?;
+        let count = |dead: bool| {
+            counts
+                .iter()
+                .find(|(is_dead, _)| *is_dead == dead)
+                .map_or(0, |(_, count)| *count)
+        };
+        Ok((count(false), count(true)))
+    }
+
     pub fn delete_configuration(&self, with_id: i32) -> Result<(), DatabaseError> {
         let mut connection = self.db_connection.lock().unwrap();
         match diesel::delete(
@@ -120,8 +362,118 @@ impl Database {
         }
     }
 
+    // Removes dead letters created before the given time
+    pub fn delete_dead_letters(&self, before: i64) -> Result<usize, String> {
+        use schema::webhook_outbox::dsl;
+        let mut connection = self.db_connection.lock().unwrap();
+        diesel::delete(
+            dsl::webhook_outbox
+                .filter(dsl::dead.eq(true))
+                .filter(dsl::created.lt(before)),
+        )
+        .execute(&mut *connection)
+        .map_err(|err| err.to_string())
+    }
+
+    // Capture file is kept while a configuration or a running replay uses it
+    pub fn delete_pcap(&self, with_id: i32) -> Result<(), DatabaseError> {
+        let mut connection = self.db_connection.lock().unwrap();
+        let result = connection.transaction(|connection| {
+            let running = schema::replays::dsl::replays
+                .filter(schema::replays::pcap_id.eq(with_id))
+                .filter(schema::replays::status.eq(models::enum_to_text(&ReplayStatus::Running)))
+                .count()
+                .get_result::<i64>(connection)?;
+            if running > 0 {
+                return Ok(Err("Capture file is being replayed".to_string()));
+            }
+            let configurations = schema::configurations::dsl::configurations
+                .load::<models::Configuration>(connection)?;
+            for configuration in configurations {
+                if let Ok(config) = serde_json::from_str::<NetspotConfig>(&configuration.config) {
+                    if config.configuration.pcap == Some(with_id) {
+                        return Ok(Err(format!(
+                            "Capture file is used by configuration {}",
+                            configuration.id
+                        )));
+                    }
+                }
+            }
+            diesel::delete(schema::pcaps::dsl::pcaps.filter(schema::pcaps::id.eq(with_id)))
+                .execute(connection)
+                .map(Ok)
+        });
+        match result {
+            Ok(Err(reason)) => Err(DatabaseError::Conflict(reason)),
+            Ok(Ok(0)) => Err(DatabaseError::NotFound),
+            Ok(Ok(1)) => Ok(()),
+            Err(err) => Err(DatabaseError::Unexpected(err.to_string())),
+            Ok(Ok(rows)) => Err(DatabaseError::Unexpected(format!(
+                "Unexpected row delete count: {}",
+                rows
+            ))),
+        }
+    }
+
+    // Removes the replay job together with its alarms and data messages
+    pub fn delete_replay(&self, with_id: i32) -> Result<(), DatabaseError> {
+        let mut connection = self.db_connection.lock().unwrap();
+        let result = connection.transaction(|connection| {
+            let running = schema::replays::dsl::replays
+                .filter(schema::replays::id.eq(with_id))
+                .filter(schema::replays::status.eq(models::enum_to_text(&ReplayStatus::Running)))
+                .count()
+                .get_result::<i64>(connection)?;
+            if running > 0 {
+                return Ok(Err("Replay is running".to_string()));
+            }
+            diesel::delete(schema::alarms::dsl::alarms.filter(schema::alarms::job_id.eq(with_id)))
+                .execute(connection)?;
+            diesel::delete(schema::data::dsl::data.filter(schema::data::job_id.eq(with_id)))
+                .execute(connection)?;
+            diesel::delete(schema::replays::dsl::replays.filter(schema::replays::id.eq(with_id)))
+                .execute(connection)
+                .map(Ok)
+        });
+        match result {
+            Ok(Err(reason)) => Err(DatabaseError::Conflict(reason)),
+            Ok(Ok(0)) => Err(DatabaseError::NotFound),
+            Ok(Ok(1)) => Ok(()),
+            Err(err) => Err(DatabaseError::Unexpected(err.to_string())),
+            Ok(Ok(rows)) => Err(DatabaseError::Unexpected(format!(
+                "Unexpected row delete count: {}",
+                rows
+            ))),
+        }
+    }
+
+    pub fn delete_rule<T: JsonRule>(&self, with_id: i32) -> Result<(), DatabaseError> {
+        let mut connection = self.db_connection.lock().unwrap();
+        match diesel::sql_query(format!("DELETE FROM {} WHERE id = ?", T::TABLE))
+            .bind::<Integer, _>(with_id)
+            .execute(&mut *connection)
+        {
+            Ok(0) => Err(DatabaseError::NotFound),
+            Ok(1) => Ok(()),
+            Err(err) => Err(DatabaseError::Unexpected(err.to_string())),
+            Ok(rows) => Err(DatabaseError::Unexpected(format!(
+                "Unexpected row delete count: {}",
+                rows
+            ))),
+        }
+    }
+
     pub fn delete_webhook(&self, with_id: i32) -> Result<(), DatabaseError> {
         let mut connection = self.db_connection.lock().unwrap();
+        // Queued messages are useless without the webhook
+        if let Err(err) = diesel::delete(
+            schema::webhook_outbox::dsl::webhook_outbox
+                .filter(schema::webhook_outbox::webhook_id.eq(with_id)),
+        )
+        .execute(&mut *connection)
+        {
+            return Err(DatabaseError::Unexpected(err.to_string()));
+        }
         match diesel::delete(
             schema::webhooks::dsl::webhooks.filter(schema::webhooks::id.eq(with_id)),
         )
@@ -137,33 +489,151 @@ impl Database {
         }
     }
 
+    pub fn delete_webhook_messages(&self, with_ids: &[i32]) -> Result<(), String> {
+        use schema::webhook_outbox::dsl;
+        let mut connection = self.db_connection.lock().unwrap();
+        diesel::delete(dsl::webhook_outbox.filter(dsl::id.eq_any(with_ids)))
+            .execute(&mut *connection)
+            .map(|_| ())
+            .map_err(|err| err.to_string())
+    }
+
+    // Makes the new messages of the webhook due now, for sending a full batch without waiting
+    pub fn expedite_webhook_messages(&self, webhook_id: i32, now: i64) -> Result<(), String> {
+        use schema::webhook_outbox::dsl;
+        let mut connection = self.db_connection.lock().unwrap();
+        diesel::update(
+            dsl::webhook_outbox
+                .filter(dsl::webhook_id.eq(webhook_id))
+                .filter(dsl::dead.eq(false))
+                .filter(dsl::attempts.eq(0))
+                .filter(dsl::next_attempt.gt(now)),
+        )
+        .set(dsl::next_attempt.eq(now))
+        .execute(&mut *connection)
+        .map(|_| ())
+        .map_err(|err| err.to_string())
+    }
+
+    // Records a failed delivery. The message is tried again at the given time, or kept as a dead
+    // letter when there is no retry time.
+    pub fn fail_webhook_message(
+        &self,
+        with_id: i32,
+        attempts: i32,
+        retry_at: Option<i64>,
+        error: &str,
+    ) -> Result<(), String> {
+        use schema::webhook_outbox::dsl;
+        let mut connection = self.db_connection.lock().unwrap();
+        let target = dsl::webhook_outbox.filter(dsl::id.eq(with_id));
+        let result = match retry_at {
+            Some(next_attempt) => diesel::update(target)
+                .set((
+                    dsl::attempts.eq(attempts),
+                    dsl::next_attempt.eq(next_attempt),
+                    dsl::last_error.eq(error),
+                ))
+                .execute(&mut *connection),
+            None => diesel::update(target)
+                .set((
+                    dsl::attempts.eq(attempts),
+                    dsl::last_error.eq(error),
+                    dsl::dead.eq(true),
+                ))
+                .execute(&mut *connection),
+        };
+        result.map(|_| ()).map_err(|err| err.to_string())
+    }
+
     pub fn get_alarms(
         &self,
-        time: Option<i64>,
-        last: Option<i32>,
-    ) -> Result<AlarmMessages, DatabaseError> {
+        alarm_query: &AlarmQuery,
+        cursor: Option<MessageCursor>,
+    ) -> Result<(AlarmMessages, Option<MessageCursor>), DatabaseError> {
         let mut query = schema::alarms::dsl::alarms
-            .select(schema::alarms::message)
+            .select(Alarm::as_select())
             .into_boxed();
-        if let Some(time) = time {
+        if let Some(time) = alarm_query.time {
             query = query.filter(schema::alarms::time.gt(time));
         }
-        if let Some(last) = last {
-            query = query.order(schema::alarms::time.desc()).limit(last.into())
+        if let Some(from) = alarm_query.from {
+            query = query.filter(schema::alarms::time.ge(from));
+        }
+        if let Some(to) = alarm_query.to {
+            query = query.filter(schema::alarms::time.lt(to));
+        }
+        if let Some(config_id) = alarm_query.config_id {
+            query = query.filter(schema::alarms::config_id.eq(config_id));
+        }
+        // Messages from the replays are read only with their job id
+        match alarm_query.job_id {
+            Some(job_id) => query = query.filter(schema::alarms::job_id.eq(job_id)),
+            None => query = query.filter(schema::alarms::job_id.is_null()),
+        }
+        if let Some(name) = &alarm_query.name {
+            query = query.filter(schema::alarms::name.eq(name));
+        }
+        if let Some(stat) = &alarm_query.stat {
+            query = query.filter(schema::alarms::stat.eq(models::enum_to_text(stat)));
+        }
+        if let Some(status) = &alarm_query.status {
+            query = query.filter(schema::alarms::status.eq(models::enum_to_text(status)));
+        }
+        if let Some(min_probability) = alarm_query.min_probability {
+            query = query.filter(schema::alarms::probability.ge(min_probability));
+        }
+        if let Some(label) = &alarm_query.label {
+            query = query.filter(schema::alarms::label.eq(models::enum_to_text(label)));
+        }
+        match alarm_query.reviewed {
+            Some(true) => query = query.filter(schema::alarms::review_time.is_not_null()),
+            Some(false) => query = query.filter(schema::alarms::review_time.is_null()),
+            None => {}
         }
+        if let Some(user) = &alarm_query.reviewed_by {
+            query = query.filter(schema::alarms::review_user.eq(user));
+        }
+        if let Some(suppressed) = alarm_query.suppressed {
+            query = query.filter(schema::alarms::suppressed.eq(suppressed));
+        }
+        if let Some(min_severity) = alarm_query.min_severity {
+            query = query.filter(schema::alarms::severity.eq_any(severity_texts(min_severity)));
+        }
+        if let Some(cursor) = cursor {
+            query = query.filter(
+                schema::alarms::time.gt(cursor.time).or(schema::alarms::time
+                    .eq(cursor.time)
+                    .and(schema::alarms::id.gt(cursor.id))),
+            );
+        }
+        query = match (alarm_query.limit, alarm_query.last) {
+            (Some(limit), _) => query
+                .order((schema::alarms::time.asc(), schema::alarms::id.asc()))
+                .limit(i64::from(limit) + 1),
+            (None, Some(last)) => query
+                .order((schema::alarms::time.desc(), schema::alarms::id.desc()))
+                .limit(last.into()),
+            (None, None) => query.order((schema::alarms::time.asc(), schema::alarms::id.asc())),
+        };
         let mut connection = self.db_connection.lock().unwrap();
-        match query.load::<String>(&mut *connection) {
-            Ok(rows) => {
+        match query.load::<Alarm>(&mut *connection) {
+            Ok(mut rows) => {
+                let next = split_page(&mut rows, alarm_query.limit, |row| MessageCursor {
+                    time: row.time,
+                    id: row.id,
+                });
                 let mut results = AlarmMessages::new();
                 for row in rows {
-                    if let Ok(message) = serde_json::from_str::<AlarmMessage>(&row) {
-                        results.push(message);
+                    match AlarmMessage::try_from(row) {
+                        Ok(message) => results.push(message),
+                        Err(err) => eprintln!("Skipping invalid alarm row: {}", err),
                     }
                 }
-                if last.is_some() {
+                if alarm_query.limit.is_none() && alarm_query.last.is_some() {
                     results.reverse();
                 }
-                Ok(results)
+                Ok((results, next))
             }
             Err(err) => Err(DatabaseError::Unexpected(err.to_string())),
         }
@@ -171,31 +641,60 @@ impl Database {
 
     pub fn get_data(
         &self,
-        time: Option<i64>,
-        last: Option<i32>,
-    ) -> Result<DataMessages, DatabaseError> {
+        data_query: &DataQuery,
+        cursor: Option<MessageCursor>,
+    ) -> Result<(DataMessages, Option<MessageCursor>), DatabaseError> {
         let mut query = schema::data::dsl::data
-            .select(schema::data::message)
+            .select(Data::as_select())
             .into_boxed();
-        if let Some(time) = time {
+        if let Some(time) = data_query.time {
             query = query.filter(schema::data::time.gt(time));
         }
-        if let Some(last) = last {
-            query = query.order(schema::data::time.desc()).limit(last.into())
+        if let Some(from) = data_query.from {
+            query = query.filter(schema::data::time.ge(from));
+        }
+        if let Some(to) = data_query.to {
+            query = query.filter(schema::data::time.lt(to));
         }
+        if let Some(config_id) = data_query.config_id {
+            query = query.filter(schema::data::config_id.eq(config_id));
+        }
+        // Messages from the replays are read only with their job id
+        match data_query.job_id {
+            Some(job_id) => query = query.filter(schema::data::job_id.eq(job_id)),
+            None => query = query.filter(schema::data::job_id.is_null()),
+        }
+        if let Some(name) = &data_query.name {
+            query = query.filter(schema::data::name.eq(name));
+        }
+        if let Some(cursor) = cursor {
+            query = query.filter(
+                schema::data::time.gt(cursor.time).or(schema::data::time
+                    .eq(cursor.time)
+                    .and(schema::data::id.gt(cursor.id))),
+            );
+        }
+        query = match (data_query.limit, data_query.last) {
+            (Some(limit), _) => query
+                .order((schema::data::time.asc(), schema::data::id.asc()))
+                .limit(i64::from(limit) + 1),
+            (None, Some(last)) => query
+                .order((schema::data::time.desc(), schema::data::id.desc()))
+                .limit(last.into()),
+            (None, None) => query.order((schema::data::time.asc(), schema::data::id.asc())),
+        };
         let mut connection = self.db_connection.lock().unwrap();
-        match query.load::<String>(&mut *connection) {
-            Ok(rows) => {
-                let mut results = DataMessages::new();
-                for row in rows {
-                    if let Ok(message) = serde_json::from_str::<DataMessage>(&row) {
-                        results.push(message);
-                    }
-                }
-                if last.is_some() {
+        match query.load::<Data>(&mut *connection) {
+            Ok(mut rows) => {
+                let next = split_page(&mut rows, data_query.limit, |row| MessageCursor {
+                    time: row.time,
+                    id: row.id,
+                });
+                let mut results: DataMessages = rows.into_iter().map(DataMessage::from).collect();
+                if data_query.limit.is_none() && data_query.last.is_some() {
                     results.reverse();
                 }
-                Ok(results)
+                Ok((results, next))
             }
             Err(err) => Err(DatabaseError::Unexpected(err.to_string())),
         }
@@ -208,7 +707,7 @@ impl Database {
             .load::<models::Configuration>(&mut *connection)
         {
             Ok(results) => {
-                if let Some(result) = results.get(0) {
+                if let Some(result) = results.first() {
                     return serde_json::from_str(&result.config).ok();
                 }
             }
@@ -243,6 +742,259 @@ impl Database {
         }
     }
 
+    pub fn get_detection(&self, with_id: i32) -> Option<Detection> {
+        let mut connection = self.db_connection.lock().unwrap();
+        match schema::detections::dsl::detections
+            .filter(schema::detections::id.eq(with_id))
+            .select(DetectionRow::as_select())
+            .first::<DetectionRow>(&mut *connection)
+            .optional()
+        {
+            Ok(Some(row)) => match Detection::try_from(row) {
+                Ok(detection) => return Some(detection),
+                Err(err) => eprintln!("Invalid detection row: {}", err),
+            },
+            Ok(None) => {}
+            Err(err) => eprintln!("Query failed: {}", err),
+        }
+        None
+    }
+
+    // Returns detections ordered by time
+    pub fn get_detections(&self, detection_query: &DetectionQuery) -> Result<Detections, String> {
+        use schema::detections::dsl;
+        let mut query = dsl::detections
+            .select(DetectionRow::as_select())
+            .into_boxed();
+        if let Some(from) = detection_query.from {
+            query = query.filter(dsl::time.ge(from));
+        }
+        if let Some(to) = detection_query.to {
+            query = query.filter(dsl::time.lt(to));
+        }
+        if let Some(rule_id) = detection_query.rule_id {
+            query = query.filter(dsl::rule_id.eq(rule_id));
+        }
+        if let Some(config_id) = detection_query.config_id {
+            query = query.filter(dsl::config_id.eq(config_id));
+        }
+        if let Some(min_severity) = detection_query.min_severity {
+            query = query.filter(dsl::severity.eq_any(severity_texts(min_severity)));
+        }
+        query = match detection_query.last {
+            Some(last) => query
+                .order((dsl::time.desc(), dsl::id.desc()))
+                .limit(last.into()),
+            None => query.order((dsl::time.asc(), dsl::id.asc())),
+        };
+        let mut connection = self.db_connection.lock().unwrap();
+        let rows = query
+            .load::<DetectionRow>(&mut *connection)
+            .map_err(|err| err.to_string())?;
+        let mut detections = Detections::new();
+        for row in rows {
+            match Detection::try_from(row) {
+                Ok(detection) => detections.push(detection),
+                Err(err) => eprintln!("Skipping invalid detection row: {}", err),
+            }
+        }
+        if detection_query.last.is_some() {
+            detections.reverse();
+        }
+        Ok(detections)
+    }
+
+    pub fn get_incident(&self, with_id: i32) -> Option<Incident> {
+        let mut connection = self.db_connection.lock().unwrap();
+        match schema::incidents::dsl::incidents
+            .filter(schema::incidents::id.eq(with_id))
+            .select(IncidentRow::as_select())
+            .first::<IncidentRow>(&mut *connection)
+            .optional()
+        {
+            Ok(Some(row)) => match Incident::try_from(row) {
+                Ok(incident) => return Some(incident),
+                Err(err) => eprintln!("Invalid incident row: {}", err),
+            },
+            Ok(None) => {}
+            Err(err) => eprintln!("Query failed: {}", err),
+        }
+        None
+    }
+
+    pub fn get_incident_settings(&self) -> IncidentSettings {
+        self.incident_settings.read().unwrap().clone()
+    }
+
+    // Returns incidents ordered by their first alarm
+    pub fn get_incidents(&self, incident_query: &IncidentQuery) -> Result<Incidents, String> {
+        use schema::incidents::dsl;
+        let mut query = dsl::incidents.select(IncidentRow::as_select()).into_boxed();
+        match incident_query.state {
+            Some(IncidentState::Open) => query = query.filter(dsl::closed.is_null()),
+            Some(IncidentState::Closed) => query = query.filter(dsl::closed.is_not_null()),
+            None => {}
+        }
+        if let Some(from) = incident_query.from {
+            query = query.filter(dsl::last_seen.ge(from));
+        }
+        if let Some(to) = incident_query.to {
+            query = query.filter(dsl::first_seen.lt(to));
+        }
+        if let Some(config_id) = incident_query.config_id {
+            query = query.filter(dsl::config_id.eq(config_id));
+        }
+        if let Some(stat) = &incident_query.stat {
+            query = query.filter(dsl::stat.eq(models::enum_to_text(stat)));
+        }
+        if let Some(status) = &incident_query.status {
+            query = query.filter(dsl::status.eq(models::enum_to_text(status)));
+        }
+        query = match incident_query.last {
+            Some(last) => query
+                .order((dsl::first_seen.desc(), dsl::id.desc()))
+                .limit(last.into()),
+            None => query.order((dsl::first_seen.asc(), dsl::id.asc())),
+        };
+        let mut connection = self.db_connection.lock().unwrap();
+        let rows = query
+            .load::<IncidentRow>(&mut *connection)
+            .map_err(|err| err.to_string())?;
+        let mut incidents = Incidents::new();
+        for row in rows {
+            match Incident::try_from(row) {
+                Ok(incident) => incidents.push(incident),
+                Err(err) => eprintln!("Skipping invalid incident row: {}", err),
+            }
+        }
+        if incident_query.last.is_some() {
+            incidents.reverse();
+        }
+        Ok(incidents)
+    }
+
+    pub fn get_pcap(&self, with_id: i32) -> Option<Pcap> {
+        let mut connection = self.db_connection.lock().unwrap();
+        match schema::pcaps::dsl::pcaps
+            .filter(schema::pcaps::id.eq(with_id))
+            .select(PcapRow::as_select())
+            .first::<PcapRow>(&mut *connection)
+            .optional()
+        {
+            Ok(row) => return row.map(Pcap::from),
+            Err(err) => eprintln!("Query failed: {}", err),
+        }
+        None
+    }
+
+    pub fn get_pcaps(&self) -> Result<Pcaps, String> {
+        let mut connection = self.db_connection.lock().unwrap();
+        schema::pcaps::dsl::pcaps
+            .select(PcapRow::as_select())
+            .order(schema::pcaps::id.asc())
+            .load::<PcapRow>(&mut *connection)
+            .map(|rows| rows.into_iter().map(Pcap::from).collect())
+            .map_err(|err| format!("Query failed: {}", err))
+    }
+
+    pub fn get_replay(&self, with_id: i32) -> Option<Replay> {
+        let mut connection = self.db_connection.lock().unwrap();
+        match schema::replays::dsl::replays
+            .filter(schema::replays::id.eq(with_id))
+            .select(ReplayRow::as_select())
+            .first::<ReplayRow>(&mut *connection)
+            .optional()
+        {
+            Ok(Some(row)) => match Replay::try_from(row) {
+                Ok(replay) => return Some(replay),
+                Err(err) => eprintln!("Invalid replay row: {}", err),
+            },
+            Ok(None) => {}
+            Err(err) => eprintln!("Query failed: {}", err),
+        }
+        None
+    }
+
+    pub fn get_replays(&self) -> Result<Replays, String> {
+        let mut connection = self.db_connection.lock().unwrap();
+        let rows = schema::replays::dsl::replays
+            .select(ReplayRow::as_select())
+            .order(schema::replays::id.asc())
+            .load::<ReplayRow>(&mut *connection)
+            .map_err(|err| format!("Query failed: {}", err))?;
+        let mut replays = Replays::new();
+        for row in rows {
+            match Replay::try_from(row) {
+                Ok(replay) => replays.push(replay),
+                Err(err) => eprintln!("Skipping invalid replay row: {}", err),
+            }
+        }
+        Ok(replays)
+    }
+
+    pub fn get_retention_status(&self) -> RetentionStatus {
+        RetentionStatus {
+            policy: self.retention.read().unwrap().clone(),
+            last_cleanup: self.last_cleanup.lock().unwrap().clone(),
+        }
+    }
+
+    pub fn get_rule<T: JsonRule>(&self, with_id: i32) -> Option<T> {
+        let mut connection = self.db_connection.lock().unwrap();
+        match diesel::sql_query(format!("SELECT id, config FROM {} WHERE id = ?", T::TABLE))
+            .bind::<Integer, _>(with_id)
+            .load::<RuleRow>(&mut *connection)
+        {
+            Ok(results) => {
+                if let Some(result) = results.first() {
+                    return serde_json::from_str(&result.config).ok();
+                }
+            }
+            Err(err) => eprintln!("Query failed: {}", err),
+        }
+        None
+    }
+
+    pub fn get_rules<T: JsonRule>(&self) -> Result<HashMap<i32, T>, String> {
+        let mut connection = self.db_connection.lock().unwrap();
+        match diesel::sql_query(format!("SELECT id, config FROM {}", T::TABLE))
+            .load::<RuleRow>(&mut *connection)
+        {
+            Ok(results) => {
+                let mut rules = HashMap::new();
+                for result in results {
+                    match serde_json::from_str::<T>(&result.config) {
+                        Ok(rule) => {
+                            rules.insert(result.id, rule);
+                        }
+                        Err(err) => {
+                            return Err(format!(
+                                "Parsing {} {} failed: {}",
+                                T::KIND,
+                                result.id,
+                                err
+                            ));
+                        }
+                    }
+                }
+                Ok(rules)
+            }
+            Err(err) => Err(format!("Query failed: {}", err)),
+        }
+    }
+
+    pub fn get_settings(&self) -> Settings {
+        Settings {
+            retention: self.retention.read().unwrap().clone(),
+            incidents: self.get_incident_settings(),
+            severity: self.get_severity_settings(),
+        }
+    }
+
+    pub fn get_severity_settings(&self) -> SeveritySettings {
+        self.severity_settings.read().unwrap().clone()
+    }
+
     pub fn get_webhook(&self, with_id: i32) -> Option<Webhook> {
         let mut connection = self.db_connection.lock().unwrap();
         match schema::webhooks::dsl::webhooks
@@ -250,7 +1002,7 @@ impl Database {
             .load::<models::Configuration>(&mut *connection)
         {
             Ok(results) => {
-                if let Some(result) = results.get(0) {
+                if let Some(result) = results.first() {
                     return serde_json::from_str(&result.config).ok();
                 }
             }
@@ -259,6 +1011,39 @@ impl Database {
         None
     }
 
+    // Returns the webhooks that have queued messages due for delivery
+    pub fn get_due_webhooks(&self, now: i64) -> Result<Vec<i32>, String> {
+        use schema::webhook_outbox::dsl;
+        let mut connection = self.db_connection.lock().unwrap();
+        dsl::webhook_outbox
+            .filter(dsl::dead.eq(false))
+            .filter(dsl::next_attempt.le(now))
+            .select(dsl::webhook_id)
+            .distinct()
+            .load::<i32>(&mut *connection)
+            .map_err(|err| err.to_string())
+    }
+
+    // Returns queued messages of the webhook that are due for delivery, oldest first
+    pub fn get_due_webhook_messages(
+        &self,
+        webhook_id: i32,
+        now: i64,
+        limit: i64,
+    ) -> Result<Vec<OutboxMessage>, String> {
+        use schema::webhook_outbox::dsl;
+        let mut connection = self.db_connection.lock().unwrap();
+        dsl::webhook_outbox
+            .filter(dsl::webhook_id.eq(webhook_id))
+            .filter(dsl::dead.eq(false))
+            .filter(dsl::next_attempt.le(now))
+            .order(dsl::id)
+            .limit(limit)
+            .select((dsl::id, dsl::created, dsl::payload, dsl::attempts))
+            .load::<OutboxMessage>(&mut *connection)
+            .map_err(|err| err.to_string())
+    }
+
     pub fn get_webhooks(&self) -> Result<Webhooks, String> {
         let mut connection = self.db_connection.lock().unwrap();
         match schema::webhooks::dsl::webhooks.load::<models::Configuration>(&mut *connection) {
@@ -283,6 +1068,19 @@ impl Database {
         }
     }
 
+    pub fn list_rules<T: JsonRule>(&self) -> Result<RuleList, String> {
+        let mut rules = self
+            .get_rules::<T>()?
+            .into_iter()
+            .map(|(id, rule)| RuleItem {
+                id,
+                name: rule.name().to_string(),
+            })
+            .collect::<RuleList>();
+        rules.sort_by_key(|rule| rule.id);
+        Ok(rules)
+    }
+
     pub fn list_webhooks(&self) -> Result<WebhookList, String> {
         let mut connection = self.db_connection.lock().unwrap();
         match schema::webhooks::dsl::webhooks.load::<models::Configuration>(&mut *connection) {
@@ -310,6 +1108,31 @@ impl Database {
         }
     }
 
+    // Updates the review of the alarm and returns the updated alarm
+    pub fn set_alarm_review(
+        &self,
+        with_id: i32,
+        update: &AlarmReviewUpdate,
+        time: i64,
+    ) -> Result<AlarmMessage, DatabaseError> {
+        let mut connection = self.db_connection.lock().unwrap();
+        match diesel::update(schema::alarms::dsl::alarms)
+            .filter(schema::alarms::id.eq(with_id))
+            .set(AlarmReviewChanges::new(update, time))
+            .execute(&mut *connection)
+        {
+            Ok(0) => return Err(DatabaseError::NotFound),
+            Ok(_) => {}
+            Err(err) => return Err(DatabaseError::Unexpected(err.to_string())),
+        }
+        let row = schema::alarms::dsl::alarms
+            .filter(schema::alarms::id.eq(with_id))
+            .select(Alarm::as_select())
+            .first::<Alarm>(&mut *connection)
+            .map_err(|err| DatabaseError::Unexpected(err.to_string()))?;
+        AlarmMessage::try_from(row).map_err(|err| DatabaseError::Unexpected(err.to_string()))
+    }
+
     pub fn set_configuration(
         &self,
         with_id: i32,
@@ -341,6 +1164,94 @@ impl Database {
         }
     }
 
+    // Replays that were running when the server stopped have failed
+    pub fn interrupt_replays(&self, now: i64) -> Result<(), String> {
+        use schema::replays::dsl;
+        let mut connection = self.db_connection.lock().unwrap();
+        diesel::update(dsl::replays)
+            .filter(dsl::status.eq(models::enum_to_text(&ReplayStatus::Running)))
+            .set((
+                dsl::status.eq(models::enum_to_text(&ReplayStatus::Failed)),
+                dsl::finished.eq(now),
+                dsl::error.eq("Interrupted by server restart"),
+            ))
+            .execute(&mut *connection)
+            .map(|_| ())
+            .map_err(|err| err.to_string())
+    }
+
+    // Stores the status and the progress of the replay
+    pub fn set_replay(&self, replay: &Replay) -> Result<(), String> {
+        let mut connection = self.db_connection.lock().unwrap();
+        diesel::update(schema::replays::dsl::replays)
+            .filter(schema::replays::id.eq(replay.id))
+            .set(NewReplay::from(replay))
+            .execute(&mut *connection)
+            .map(|_| ())
+            .map_err(|err| err.to_string())
+    }
+
+    pub fn set_rule<T: JsonRule>(&self, with_id: i32, new_rule: &T) -> Result<(), DatabaseError> {
+        match serde_json::to_string(new_rule) {
+            Ok(rule_config) => {
+                let mut connection = self.db_connection.lock().unwrap();
+                match diesel::sql_query(format!("UPDATE {} SET config = ? WHERE id = ?", T::TABLE))
+                    .bind::<Text, _>(&rule_config)
+                    .bind::<Integer, _>(with_id)
+                    .execute(&mut *connection)
+                {
+                    Ok(0) => Err(DatabaseError::NotFound),
+                    Ok(1) => Ok(()),
+                    Err(err) => Err(DatabaseError::Unexpected(err.to_string())),
+                    Ok(rows) => Err(DatabaseError::Unexpected(format!(
+                        "Unexpected row update count: {}",
+                        rows
+                    ))),
+                }
+            }
+            Err(err) => Err(DatabaseError::Unexpected(format!(
+                "Could not convert {} to JSON: {}",
+                T::KIND,
+                err
+            ))),
+        }
+    }
+
+    pub fn set_settings(&self, settings: &Settings) -> Result<(), String> {
+        let config = match serde_json::to_string(settings) {
+            Ok(config) => config,
+            Err(err) => return Err(format!("Could not convert Settings to JSON: {}", err)),
+        };
+        let new_settings = NewSettings {
+            id: SETTINGS_ID,
+            config: &config,
+        };
+        let mut connection = self.db_connection.lock().unwrap();
+        match diesel::replace_into(schema::settings::dsl::settings)
+            .values(new_settings)
+            .execute(&mut *connection)
+        {
+            Ok(_) => {
+                self.use_retention(settings.retention.clone());
+                *self.incident_settings.write().unwrap() = settings.incidents.clone();
+                *self.severity_settings.write().unwrap() = settings.severity.clone();
+                Ok(())
+            }
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    // Stores the changed counters and the closing time of the incident
+    pub fn set_incident(&self, incident: &Incident) -> Result<(), String> {
+        let mut connection = self.db_connection.lock().unwrap();
+        diesel::update(schema::incidents::dsl::incidents)
+            .filter(schema::incidents::id.eq(incident.id))
+            .set(NewIncident::from(incident))
+            .execute(&mut *connection)
+            .map(|_| ())
+            .map_err(|err| err.to_string())
+    }
+
     pub fn set_webhook(&self, with_id: i32, new_config: &Webhook) -> Result<(), DatabaseError> {
         match serde_json::to_string(&new_config) {
             Ok(config_json) => {
@@ -369,93 +1280,476 @@ impl Database {
         }
     }
 
+    // Changes the retention policy without storing it, used for command line overrides
+    pub fn use_retention(&self, retention: RetentionSettings) {
+        *self.retention.write().unwrap() = retention;
+    }
+
+    fn load_settings(connection: &mut SqliteConnection) -> Result<Settings, String> {
+        match schema::settings::dsl::settings
+            .filter(schema::settings::id.eq(SETTINGS_ID))
+            .select(schema::settings::config)
+            .first::<String>(connection)
+            .optional()
+        {
+            Ok(Some(config)) => serde_json::from_str::<Settings>(&config)
+                .map_err(|err| format!("Parsing settings failed: {}", err)),
+            Ok(None) => Ok(Settings::default()),
+            Err(err) => Err(format!("Query failed: {}", err)),
+        }
+    }
+
     fn run_migrations(
         connection: &mut impl MigrationHarness<Sqlite>,
     ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
-        const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
         connection.run_pending_migrations(MIGRATIONS)?;
         Ok(())
     }
 }
 
+// Severities are stored as text, so the minimum is given as the list of the accepted levels
+fn severity_texts(min_severity: Severity) -> Vec<String> {
+    min_severity
+        .and_above()
+        .iter()
+        .map(models::enum_to_text)
+        .collect()
+}
+
+// Pages are queried with one extra row to find out if there are more rows to read. The extra row
+// is removed here, and the cursor to the last row of the page is returned.
+fn split_page<T>(
+    rows: &mut Vec<T>,
+    limit: Option<i32>,
+    cursor: impl Fn(&T) -> MessageCursor,
+) -> Option<MessageCursor> {
+    let limit = usize::try_from(limit?).ok()?;
+    if rows.len() <= limit {
+        return None;
+    }
+    rows.truncate(limit);
+    rows.last().map(cursor)
+}
+
+// Id of the row inserted last with the connection
+fn last_insert_rowid(connection: &mut SqliteConnection) -> QueryResult<i32> {
+    diesel::select(diesel::dsl::sql::<diesel::sql_types::Integer>(
+        "last_insert_rowid()",
+    ))
+    .get_result::<i32>(connection)
+}
+
 async fn database_writer(
     db_connection: DbConnection,
-    mut message_rx: broadcast::Receiver<Message>,
+    retention: SharedRetention,
+    last_cleanup: SharedCleanupResult,
+    mut message_rx: MessageReceiver,
+    mut replay_rx: mpsc::UnboundedReceiver<Message>,
+    stored_tx: broadcast::Sender<Message>,
     mut run_checker: RunChecker,
 ) {
     println!("Database writer started.");
-    let mut cleanup_interval = tokio::time::interval(Duration::from_secs(60));
+    // First cleanup is delayed, so that command line overrides are in place before it
+    let mut cleanup_interval = tokio::time::interval_at(
+        tokio::time::Instant::now() + CLEANUP_INTERVAL,
+        CLEANUP_INTERVAL,
+    );
     while run_checker.keep_running() {
         tokio::select! {
-            Ok(message) = message_rx.recv() => write_message(message, &db_connection),
-            _ = cleanup_interval.tick() => cleanup_messages(&db_connection),
+            Ok(message) = message_rx.recv() => {
+                if let Some(message) = write_message(message, &db_connection) {
+                    let _ = stored_tx.send(message);
+                }
+            }
+            Some(message) = replay_rx.recv() => {
+                write_message(message, &db_connection);
+            }
+            _ = cleanup_interval.tick() => {
+                let policy = retention.read().unwrap().clone();
+                let result = cleanup_messages(&db_connection, &policy);
+                *last_cleanup.lock().unwrap() = Some(result);
+            }
             _ = run_checker.shutdown_recv() => {},
         }
     }
     println!("Database writer stopped.");
 }
 
-fn write_message(message: Message, db_connection: &DbConnection) {
-    if let Ok(json) = message.to_json() {
-        match message {
-            Message::Alarm(message) => {
-                write_alarms(db_connection, message.time, &json);
-            }
-            Message::Data(message) => {
-                write_data(db_connection, message.time, &json);
+// Cleanup
+//--------------------------------------------------------------------------------------------------
+
+fn cleanup_messages(db_connection: &DbConnection, retention: &RetentionSettings) -> CleanupResult {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos() as i64;
+    let mut result = CleanupResult {
+        time: now,
+        ..CleanupResult::default()
+    };
+
+    let mut connection = db_connection.lock().unwrap();
+    let (alarms, data) = (&retention.alarms, &retention.data);
+    let detections = retention.detections.as_ref().unwrap_or(alarms);
+    let cleanup = cleanup_table(&mut connection, "alarms", LIVE_MESSAGES, alarms, now)
+        .map(|rows| result.alarms_removed = rows)
+        .and_then(|_| cleanup_incidents(&mut connection, alarms, now))
+        .and_then(|_| cleanup_table(&mut connection, "detections", "TRUE", detections, now))
+        .map(|rows| result.detections_removed = rows)
+        .and_then(|_| cleanup_table(&mut connection, "data", LIVE_MESSAGES, data, now))
+        .map(|rows| result.data_removed = rows)
+        .and_then(|_| match retention.max_database_size {
+            Some(max_size) => shrink_database(&mut connection, max_size, &mut result),
+            None => Ok(()),
+        })
+        .and_then(|_| database_size(&mut connection));
+    match cleanup {
+        Ok(size) => result.database_size = Some(size),
+        Err(err) => {
+            eprintln!("cleanup_messages error: {}", err);
+            result.error = Some(err.to_string());
+        }
+    }
+
+    if result.alarms_removed > 0 || result.data_removed > 0 {
+        println!(
+            "{} alarms message(s) and {} data message(s) removed.",
+            result.alarms_removed, result.data_removed
+        );
+    }
+    if result.webhook_messages_removed > 0 {
+        println!(
+            "{} webhook message(s) removed to keep the database small enough.",
+            result.webhook_messages_removed
+        );
+    }
+    result
+}
+
+// Message tables share the id and time columns, which is all the cleanup needs. Therefore, the
+// same queries are used for all of them, limited to the rows matching the condition.
+fn cleanup_table(
+    connection: &mut SqliteConnection,
+    table: &str,
+    condition: &str,
+    policy: &RetentionPolicy,
+    now: i64,
+) -> QueryResult<u64> {
+    let mut removed = 0;
+    if let Some(max_age) = policy.max_age {
+        removed += diesel::sql_query(format!(
+            "DELETE FROM {table} WHERE {condition} AND time < ?"
+        ))
+        .bind::<BigInt, _>(oldest_kept(now, max_age))
+        .execute(connection)?;
+    }
+    if let Some(max_rows) = policy.max_rows {
+        removed += diesel::sql_query(format!(
+            "DELETE FROM {table} WHERE id IN \
+             (SELECT id FROM {table} WHERE {condition} \
+              ORDER BY time DESC, id DESC LIMIT -1 OFFSET ?)"
+        ))
+        .bind::<BigInt, _>(i64::try_from(max_rows).unwrap_or(i64::MAX))
+        .execute(connection)?;
+    }
+    Ok(removed as u64)
+}
+
+// Closed incidents are kept as long as the alarms
+fn cleanup_incidents(
+    connection: &mut SqliteConnection,
+    policy: &RetentionPolicy,
+    now: i64,
+) -> QueryResult<()> {
+    if let Some(max_age) = policy.max_age {
+        diesel::delete(
+            schema::incidents::dsl::incidents
+                .filter(schema::incidents::closed.lt(oldest_kept(now, max_age))),
+        )
+        .execute(connection)?;
+    }
+    Ok(())
+}
+
+// Time of the oldest message that is kept. Nothing is too old when the age does not fit.
+fn oldest_kept(now: i64, max_age: u64) -> i64 {
+    match seconds_as_nanos(max_age) {
+        Some(max_age) => now.saturating_sub(max_age),
+        None => i64::MIN,
+    }
+}
+
+// Removes the oldest messages until the database is small enough. Webhook dead letters go first,
+// then data messages, which are far more numerous than alarms. Messages still waiting for webhook
+// delivery are removed last.
+fn shrink_database(
+    connection: &mut SqliteConnection,
+    max_size: u64,
+    result: &mut CleanupResult,
+) -> QueryResult<()> {
+    let mut dead_letters_removed = 0;
+    for (table, condition, time, removed) in [
+        (
+            "webhook_outbox",
+            "dead",
+            "created",
+            &mut dead_letters_removed,
+        ),
+        ("data", LIVE_MESSAGES, "time", &mut result.data_removed),
+        ("alarms", LIVE_MESSAGES, "time", &mut result.alarms_removed),
+        (
+            "webhook_outbox",
+            "NOT dead",
+            "created",
+            &mut result.webhook_messages_removed,
+        ),
+    ] {
+        while database_size(connection)? > max_size {
+            let rows = diesel::sql_query(format!(
+                "DELETE FROM {table} WHERE id IN \
+                 (SELECT id FROM {table} WHERE {condition} \
+                  ORDER BY {time} ASC, id ASC LIMIT ?)"
+            ))
+            .bind::<BigInt, _>(SHRINK_BATCH_ROWS)
+            .execute(connection)?;
+            if rows == 0 {
+                break;
             }
+            *removed += rows as u64;
         }
     }
+    result.webhook_messages_removed += dead_letters_removed;
+    Ok(())
 }
 
-fn cleanup_messages(db_connection: &DbConnection) {
-    match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
-        Ok(duration) => {
-            // We remove all results that are older than one hour
-            let older_than = (duration - Duration::from_secs(60 * 60)).as_nanos() as i64;
-            println!("Cleaning messages older than {older_than}.");
-            let mut connection = db_connection.lock().unwrap();
-            match diesel::delete(schema::alarms::dsl::alarms)
-                .filter(schema::alarms::time.lt(older_than))
-                .execute(&mut *connection)
-            {
-                Ok(rows) => println!("{rows} alarms message(s) removed."),
-                Err(err) => eprintln!("cleanup_messages error: {}", err),
-            };
-            match diesel::delete(schema::data::dsl::data)
-                .filter(schema::data::time.lt(older_than))
-                .execute(&mut *connection)
-            {
-                Ok(rows) => println!("{rows} data message(s) removed."),
-                Err(err) => eprintln!("cleanup_messages error: {}", err),
-            };
+// Bytes used by the database, not counting free pages left behind by removed rows
+fn database_size(connection: &mut SqliteConnection) -> QueryResult<u64> {
+    diesel::sql_query(
+        "SELECT (page_count - freelist_count) * page_size AS size \
+         FROM pragma_page_count(), pragma_freelist_count(), pragma_page_size()",
+    )
+    .get_result::<DatabaseSize>(connection)
+    .map(|result| result.size as u64)
+}
+
+// Message writing
+//--------------------------------------------------------------------------------------------------
+
+// Returns the stored alarm or data message with its id
+fn write_message(message: Message, db_connection: &DbConnection) -> Option<Message> {
+    match message {
+        Message::Alarm(mut message) => {
+            message.id = Some(write_alarms(db_connection, &message)?);
+            Some(Message::Alarm(message))
         }
-        Err(err) => {
-            eprintln!("Unexpected error: {}", err);
+        Message::Data(mut message) => {
+            message.id = Some(write_data(db_connection, &message)?);
+            Some(Message::Data(message))
         }
+        // Incidents are stored by the incident engine, which keeps them up to date
+        Message::Incident(_) => None,
+        // Detections are stored by the correlation rules, so that they have their ids when sent
+        Message::Detection(_) => None,
     }
 }
 
-fn write_alarms(db_connection: &DbConnection, time: i64, message: &str) {
-    let new_alarms = NewAlarms { time, message };
+fn write_alarms(db_connection: &DbConnection, message: &AlarmMessage) -> Option<i32> {
+    let new_alarm = NewAlarm::from(message);
     let mut connection = db_connection.lock().unwrap();
     match diesel::insert_into(schema::alarms::dsl::alarms)
-        .values(new_alarms)
+        .values(new_alarm)
         .execute(&mut *connection)
+        .and_then(|_| last_insert_rowid(&mut connection))
     {
-        Ok(_) => (),
-        Err(err) => eprintln!("write_alarms error: {}", err),
-    };
+        Ok(id) => Some(id),
+        Err(err) => {
+            eprintln!("write_alarms error: {}", err);
+            None
+        }
+    }
 }
 
-fn write_data(db_connection: &DbConnection, time: i64, message: &str) {
-    let new_data = NewData { time, message };
+fn write_data(db_connection: &DbConnection, message: &DataMessage) -> Option<i32> {
+    let new_data = NewData::from(message);
     let mut connection = db_connection.lock().unwrap();
     match diesel::insert_into(schema::data::dsl::data)
         .values(new_data)
         .execute(&mut *connection)
+        .and_then(|_| last_insert_rowid(&mut connection))
     {
-        Ok(_) => (),
-        Err(err) => eprintln!("write_data error: {}", err),
-    };
+        Ok(id) => Some(id),
+        Err(err) => {
+            eprintln!("write_data error: {}", err);
+            None
+        }
+    }
+}
+
+// Unit tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::metrics::Metrics;
+    use crate::structures::statistics::{AlertStatus, Stat};
+    use tempfile::TempDir;
+    use tokio::sync::watch;
+
+    // Messages were stored as JSON before this migration moved them to columns
+    const STRUCTURED_MESSAGES: &str = "2022-11-21-083000_structured_messages";
+
+    // Database keeps its writer task running until the returned sender is dropped
+    fn open(url: &str) -> (Database, watch::Sender<bool>) {
+        let (run_tx, run_rx) = watch::channel(true);
+        let (_, message_rx) = broadcast::channel(1);
+        let messages_rx = Metrics::new().receiver("test", message_rx);
+        let database = Database::new(url, messages_rx, RunChecker::new(run_rx)).unwrap();
+        (database, run_tx)
+    }
+
+    #[tokio::test]
+    async fn structured_messages_migration() {
+        let test_dir = TempDir::new().expect("temporary directory");
+        let path = test_dir.path().join("old.db");
+        let url = path.to_str().unwrap();
+
+        // Making a database in the format before the migration, with rows in it
+        let mut connection = SqliteConnection::establish(url).unwrap();
+        let pending = connection.pending_migrations(MIGRATIONS).unwrap();
+        for migration in &pending {
+            if migration.name().to_string() == STRUCTURED_MESSAGES {
+                break;
+            }
+            connection.run_migration(migration.as_ref()).unwrap();
+        }
+        diesel::sql_query(concat!(
+            "INSERT INTO alarms(time, config_id, message) VALUES (1000, 1, '",
+            r#"{"type":"alarm","time":1000,"name":"Office","series":"eth0","#,
+            r#""stat":"R_SYN","status":"UP_ALERT","value":0.25,"probability":0.0001,"code":1}"#,
+            "')"
+        ))
+        .execute(&mut connection)
+        .unwrap();
+        diesel::sql_query(concat!(
+            "INSERT INTO data(time, config_id, message) VALUES (2000, NULL, '",
+            r#"{"type":"data","time":2000,"name":"Office","series":"any","#,
+            r#""PERF":1500.5,"R_SYN":0.25,"TRAFFIC_UP":42}"#,
+            "')"
+        ))
+        .execute(&mut connection)
+        .unwrap();
+        drop(connection);
+
+        // Opening the database runs the rest of the migrations
+        let (database, _run_tx) = open(url);
+
+        let (alarms, _) = database
+            .get_alarms(&AlarmQuery::default(), None)
+            .unwrap_or_else(|_| panic!("Could not read alarms"));
+        assert_eq!(alarms.len(), 1);
+        let alarm = &alarms[0];
+        assert_eq!(alarm.time, 1000);
+        assert_eq!(alarm.config_id, Some(1));
+        assert_eq!(alarm.name, "Office");
+        assert_eq!(alarm.series, "eth0");
+        assert_eq!(alarm.stat, Stat::RSyn);
+        assert_eq!(alarm.status, AlertStatus::UpAlert);
+        assert_eq!(alarm.value, 0.25);
+        assert_eq!(alarm.probability, 0.0001);
+        assert_eq!(alarm.code, 1);
+        assert_eq!(alarm.severity, None);
+        assert!(!alarm.suppressed);
+
+        let (data, _) = database
+            .get_data(&DataQuery::default(), None)
+            .unwrap_or_else(|_| panic!("Could not read data messages"));
+        assert_eq!(data.len(), 1);
+        let data = &data[0];
+        assert_eq!(data.time, 2000);
+        assert_eq!(data.config_id, None);
+        assert_eq!(data.series, "any");
+        assert_eq!(data.value(&Stat::Perf), Some(1500.5));
+        assert_eq!(data.value(&Stat::RSyn), Some(0.25));
+        assert_eq!(data.traffic_up, Some(42.0));
+        assert_eq!(data.value(&Stat::RAck), None);
+    }
+
+    #[tokio::test]
+    async fn shrink_webhook_outbox() {
+        let test_dir = TempDir::new().expect("temporary directory");
+        let path = test_dir.path().join("test.db");
+        let (database, _run_tx) = open(path.to_str().unwrap());
+
+        // Half of the queued webhook messages are dead letters
+        let payload = "x".repeat(1000);
+        let payloads: Vec<(i32, String, i64)> =
+            (0..2000).map(|_| (1, payload.clone(), 0)).collect();
+        database.add_webhook_messages(&payloads, 0).unwrap();
+        let mut connection = database.db_connection.lock().unwrap();
+        diesel::sql_query("UPDATE webhook_outbox SET dead = TRUE WHERE id <= 1000")
+            .execute(&mut *connection)
+            .unwrap();
+        let size = database_size(&mut connection).unwrap();
+        drop(connection);
+
+        // Dead letters are removed before the messages waiting for delivery
+        let retention = RetentionSettings {
+            alarms: RetentionPolicy::default(),
+            data: RetentionPolicy::default(),
+            detections: None,
+            max_database_size: Some(size * 2 / 3),
+        };
+        let result = cleanup_messages(&database.db_connection, &retention);
+        assert_eq!(result.error, None);
+        assert_eq!(result.webhook_messages_removed, 1000);
+        assert_eq!(database.count_webhook_messages(1), Ok((1000, 0)));
+
+        let retention = RetentionSettings {
+            max_database_size: Some(1),
+            ..retention
+        };
+        let result = cleanup_messages(&database.db_connection, &retention);
+        assert_eq!(result.webhook_messages_removed, 1000);
+        assert_eq!(database.count_webhook_messages(1), Ok((0, 0)));
+    }
+
+    #[tokio::test]
+    async fn detection_retention() {
+        let test_dir = TempDir::new().expect("temporary directory");
+        let path = test_dir.path().join("test.db");
+        let (database, _run_tx) = open(path.to_str().unwrap());
+        for time in [1000, 2000, 3000] {
+            let detection = Detection {
+                time,
+                stats: vec![Stat::RSyn, Stat::RAck],
+                ..Detection::default()
+            };
+            database.add_detection(&detection).unwrap();
+        }
+
+        // Detections have their own policy when given
+        let mut retention = RetentionSettings {
+            alarms: RetentionPolicy::default(),
+            data: RetentionPolicy::default(),
+            detections: Some(RetentionPolicy {
+                max_age: None,
+                max_rows: Some(2),
+            }),
+            max_database_size: None,
+        };
+        let result = cleanup_messages(&database.db_connection, &retention);
+        assert_eq!(result.error, None);
+        assert_eq!(result.detections_removed, 1);
+        assert_eq!(result.alarms_removed, 0);
+
+        // Otherwise, they follow the alarm policy
+        retention.detections = None;
+        retention.alarms.max_rows = Some(1);
+        let result = cleanup_messages(&database.db_connection, &retention);
+        assert_eq!(result.detections_removed, 1);
+        let detections = database.get_detections(&DetectionQuery::default()).unwrap();
+        assert_eq!(detections.len(), 1);
+        assert_eq!(detections[0].time, 3000);
+    }
 }
@@ -1,5 +1,14 @@
 use super::schema::*;
+use crate::structures::correlations::Detection;
+use crate::structures::incidents::{Incident, IncidentState};
+use crate::structures::replays::{Pcap, Replay};
+use crate::structures::statistics::{
+    AlarmMessage, AlarmReview, AlarmReviewUpdate, DataMessage, MessageType,
+};
 use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Integer, Text};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 
 #[derive(Debug, Queryable)]
 pub struct Configuration {
@@ -13,18 +22,313 @@ pub struct NewConfiguration<'a> {
     pub config: &'a str,
 }
 
-#[derive(Debug, Insertable, AsChangeset)]
+#[derive(Debug, Queryable, Selectable)]
 #[diesel(table_name = alarms)]
-pub struct NewAlarms<'a> {
+pub struct Alarm {
+    pub id: i32,
     pub time: i64,
-    pub message: &'a str,
+    pub config_id: Option<i32>,
+    pub name: String,
+    pub series: String,
+    pub stat: String,
+    pub status: String,
+    pub value: f64,
+    pub probability: f64,
+    pub code: i32,
+    pub label: Option<String>,
+    pub review_user: Option<String>,
+    pub review_time: Option<i64>,
+    pub note: Option<String>,
+    pub suppressed: bool,
+    pub suppression_rule_id: Option<i32>,
+    pub severity: Option<String>,
+    pub job_id: Option<i32>,
 }
 
-#[derive(Debug, Insertable, AsChangeset)]
+impl TryFrom<Alarm> for AlarmMessage {
+    type Error = serde_json::Error;
+
+    fn try_from(alarm: Alarm) -> Result<Self, Self::Error> {
+        // Reviewed alarms always have the review time
+        let review = match alarm.review_time {
+            Some(time) => Some(AlarmReview {
+                user: alarm.review_user.unwrap_or_default(),
+                time,
+                label: alarm.label.map(enum_from_text).transpose()?,
+                note: alarm.note,
+            }),
+            None => None,
+        };
+        Ok(AlarmMessage {
+            id: Some(alarm.id),
+            time: alarm.time,
+            name: alarm.name,
+            config_id: alarm.config_id,
+            job_id: alarm.job_id,
+            series: alarm.series,
+            stat: enum_from_text(alarm.stat)?,
+            status: enum_from_text(alarm.status)?,
+            value: alarm.value,
+            probability: alarm.probability,
+            code: alarm.code,
+            severity: alarm.severity.map(enum_from_text).transpose()?,
+            suppressed: alarm.suppressed,
+            suppression_rule_id: alarm.suppression_rule_id,
+            review,
+            msg_type: MessageType::Alarm,
+        })
+    }
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = alarms)]
+pub struct NewAlarm<'a> {
+    pub time: i64,
+    pub config_id: Option<i32>,
+    pub name: &'a str,
+    pub series: &'a str,
+    pub stat: String,
+    pub status: String,
+    pub value: f64,
+    pub probability: f64,
+    pub code: i32,
+    pub suppressed: bool,
+    pub suppression_rule_id: Option<i32>,
+    pub severity: Option<String>,
+    pub job_id: Option<i32>,
+}
+
+impl<'a> From<&'a AlarmMessage> for NewAlarm<'a> {
+    fn from(message: &'a AlarmMessage) -> Self {
+        NewAlarm {
+            time: message.time,
+            config_id: message.config_id,
+            name: &message.name,
+            series: &message.series,
+            stat: enum_to_text(&message.stat),
+            status: enum_to_text(&message.status),
+            value: message.value,
+            probability: message.probability,
+            code: message.code,
+            suppressed: message.suppressed,
+            suppression_rule_id: message.suppression_rule_id,
+            severity: message.severity.as_ref().map(enum_to_text),
+            job_id: message.job_id,
+        }
+    }
+}
+
+// Missing fields are left unchanged, and Some(None) sets the column to null
+#[derive(Debug, AsChangeset)]
+#[diesel(table_name = alarms)]
+pub struct AlarmReviewChanges<'a> {
+    pub label: Option<Option<String>>,
+    pub review_user: &'a str,
+    pub review_time: i64,
+    pub note: Option<Option<&'a str>>,
+}
+
+impl<'a> AlarmReviewChanges<'a> {
+    pub fn new(update: &'a AlarmReviewUpdate, time: i64) -> Self {
+        AlarmReviewChanges {
+            label: update
+                .label
+                .as_ref()
+                .map(|label| label.as_ref().map(enum_to_text)),
+            review_user: &update.user,
+            review_time: time,
+            note: update.note.as_ref().map(Option::as_deref),
+        }
+    }
+}
+
+#[derive(Debug, Queryable, Selectable)]
+#[diesel(table_name = data)]
+pub struct Data {
+    pub id: i32,
+    pub time: i64,
+    pub config_id: Option<i32>,
+    pub name: String,
+    pub series: String,
+    pub avg_pkt_size: Option<f64>,
+    pub avg_pkt_size_down: Option<f64>,
+    pub avg_pkt_size_up: Option<f64>,
+    pub perf: Option<f64>,
+    pub perf_down: Option<f64>,
+    pub perf_up: Option<f64>,
+    pub r_ack: Option<f64>,
+    pub r_ack_down: Option<f64>,
+    pub r_ack_up: Option<f64>,
+    pub r_arp: Option<f64>,
+    pub r_arp_down: Option<f64>,
+    pub r_arp_up: Option<f64>,
+    pub r_dst_src: Option<f64>,
+    pub r_dst_src_down: Option<f64>,
+    pub r_dst_src_up: Option<f64>,
+    pub r_dst_src_port: Option<f64>,
+    pub r_dst_src_port_down: Option<f64>,
+    pub r_dst_src_port_up: Option<f64>,
+    pub r_icmp: Option<f64>,
+    pub r_icmp_down: Option<f64>,
+    pub r_icmp_up: Option<f64>,
+    pub r_ip: Option<f64>,
+    pub r_ip_down: Option<f64>,
+    pub r_ip_up: Option<f64>,
+    pub r_syn: Option<f64>,
+    pub r_syn_down: Option<f64>,
+    pub r_syn_up: Option<f64>,
+    pub traffic: Option<f64>,
+    pub traffic_down: Option<f64>,
+    pub traffic_up: Option<f64>,
+    pub job_id: Option<i32>,
+}
+
+impl From<Data> for DataMessage {
+    fn from(data: Data) -> Self {
+        DataMessage {
+            id: Some(data.id),
+            time: data.time,
+            name: data.name,
+            config_id: data.config_id,
+            job_id: data.job_id,
+            series: data.series,
+            avg_pkt_size: data.avg_pkt_size,
+            avg_pkt_size_down: data.avg_pkt_size_down,
+            avg_pkt_size_up: data.avg_pkt_size_up,
+            perf: data.perf,
+            perf_down: data.perf_down,
+            perf_up: data.perf_up,
+            r_ack: data.r_ack,
+            r_ack_down: data.r_ack_down,
+            r_ack_up: data.r_ack_up,
+            r_arp: data.r_arp,
+            r_arp_down: data.r_arp_down,
+            r_arp_up: data.r_arp_up,
+            r_dst_src: data.r_dst_src,
+            r_dst_src_down: data.r_dst_src_down,
+            r_dst_src_up: data.r_dst_src_up,
+            r_dst_src_port: data.r_dst_src_port,
+            r_dst_src_port_down: data.r_dst_src_port_down,
+            r_dst_src_port_up: data.r_dst_src_port_up,
+            r_icmp: data.r_icmp,
+            r_icmp_down: data.r_icmp_down,
+            r_icmp_up: data.r_icmp_up,
+            r_ip: data.r_ip,
+            r_ip_down: data.r_ip_down,
+            r_ip_up: data.r_ip_up,
+            r_syn: data.r_syn,
+            r_syn_down: data.r_syn_down,
+            r_syn_up: data.r_syn_up,
+            traffic: data.traffic,
+            traffic_down: data.traffic_down,
+            traffic_up: data.traffic_up,
+            msg_type: MessageType::Data,
+        }
+    }
+}
+
+#[derive(Debug, Insertable)]
 #[diesel(table_name = data)]
 pub struct NewData<'a> {
     pub time: i64,
-    pub message: &'a str,
+    pub config_id: Option<i32>,
+    pub name: &'a str,
+    pub series: &'a str,
+    pub avg_pkt_size: Option<f64>,
+    pub avg_pkt_size_down: Option<f64>,
+    pub avg_pkt_size_up: Option<f64>,
+    pub perf: Option<f64>,
+    pub perf_down: Option<f64>,
+    pub perf_up: Option<f64>,
+    pub r_ack: Option<f64>,
+    pub r_ack_down: Option<f64>,
+    pub r_ack_up: Option<f64>,
+    pub r_arp: Option<f64>,
+    pub r_arp_down: Option<f64>,
+    pub r_arp_up: Option<f64>,
+    pub r_dst_src: Option<f64>,
+    pub r_dst_src_down: Option<f64>,
+    pub r_dst_src_up: Option<f64>,
+    pub r_dst_src_port: Option<f64>,
+    pub r_dst_src_port_down: Option<f64>,
+    pub r_dst_src_port_up: Option<f64>,
+    pub r_icmp: Option<f64>,
+    pub r_icmp_down: Option<f64>,
+    pub r_icmp_up: Option<f64>,
+    pub r_ip: Option<f64>,
+    pub r_ip_down: Option<f64>,
+    pub r_ip_up: Option<f64>,
+    pub r_syn: Option<f64>,
+    pub r_syn_down: Option<f64>,
+    pub r_syn_up: Option<f64>,
+    pub traffic: Option<f64>,
+    pub traffic_down: Option<f64>,
+    pub traffic_up: Option<f64>,
+    pub job_id: Option<i32>,
+}
+
+impl<'a> From<&'a DataMessage> for NewData<'a> {
+    fn from(message: &'a DataMessage) -> Self {
+        NewData {
+            time: message.time,
+            config_id: message.config_id,
+            name: &message.name,
+            series: &message.series,
+            avg_pkt_size: message.avg_pkt_size,
+            avg_pkt_size_down: message.avg_pkt_size_down,
+            avg_pkt_size_up: message.avg_pkt_size_up,
+            perf: message.perf,
+            perf_down: message.perf_down,
+            perf_up: message.perf_up,
+            r_ack: message.r_ack,
+            r_ack_down: message.r_ack_down,
+            r_ack_up: message.r_ack_up,
+            r_arp: message.r_arp,
+            r_arp_down: message.r_arp_down,
+            r_arp_up: message.r_arp_up,
+            r_dst_src: message.r_dst_src,
+            r_dst_src_down: message.r_dst_src_down,
+            r_dst_src_up: message.r_dst_src_up,
+            r_dst_src_port: message.r_dst_src_port,
+            r_dst_src_port_down: message.r_dst_src_port_down,
+            r_dst_src_port_up: message.r_dst_src_port_up,
+            r_icmp: message.r_icmp,
+            r_icmp_down: message.r_icmp_down,
+            r_icmp_up: message.r_icmp_up,
+            r_ip: message.r_ip,
+            r_ip_down: message.r_ip_down,
+            r_ip_up: message.r_ip_up,
+            r_syn: message.r_syn,
+            r_syn_down: message.r_syn_down,
+            r_syn_up: message.r_syn_up,
+            traffic: message.traffic,
+            traffic_down: message.traffic_down,
+            traffic_up: message.traffic_up,
+            job_id: message.job_id,
+        }
+    }
+}
+
+#[derive(Debug, Insertable, AsChangeset)]
+#[diesel(table_name = settings)]
+pub struct NewSettings<'a> {
+    pub id: i32,
+    pub config: &'a str,
+}
+
+#[derive(Debug, QueryableByName)]
+pub struct DatabaseSize {
+    #[diesel(sql_type = BigInt)]
+    pub size: i64,
+}
+
+// Rule tables are read with the same query
+#[derive(Debug, QueryableByName)]
+pub struct RuleRow {
+    #[diesel(sql_type = Integer)]
+    pub id: i32,
+    #[diesel(sql_type = Text)]
+    pub config: String,
 }
 
 #[derive(Debug, Insertable, AsChangeset)]
@@ -32,3 +336,344 @@ pub struct NewData<'a> {
 pub struct NewWebhook<'a> {
     pub config: &'a str,
 }
+
+// Queued message with the fields needed for sending it
+#[derive(Debug, Queryable)]
+pub struct OutboxMessage {
+    pub id: i32,
+    pub created: i64,
+    pub payload: String,
+    pub attempts: i32,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = webhook_outbox)]
+pub struct NewOutboxMessage<'a> {
+    pub webhook_id: i32,
+    pub created: i64,
+    pub payload: &'a str,
+    pub next_attempt: i64,
+}
+
+#[derive(Debug, Queryable, Selectable)]
+#[diesel(table_name = incidents)]
+pub struct IncidentRow {
+    pub id: i32,
+    pub config_id: Option<i32>,
+    pub name: String,
+    pub stat: String,
+    pub status: String,
+    pub first_seen: i64,
+    pub last_seen: i64,
+    pub closed: Option<i64>,
+    pub count: i32,
+    pub peak_value: f64,
+    pub min_probability: f64,
+    pub severity: String,
+    pub series: String,
+}
+
+impl TryFrom<IncidentRow> for Incident {
+    type Error = serde_json::Error;
+
+    fn try_from(row: IncidentRow) -> Result<Self, Self::Error> {
+        Ok(Incident {
+            id: row.id,
+            name: row.name,
+            config_id: row.config_id,
+            series: row.series,
+            stat: enum_from_text(row.stat)?,
+            status: enum_from_text(row.status)?,
+            state: match row.closed {
+                None => IncidentState::Open,
+                Some(_) => IncidentState::Closed,
+            },
+            first_seen: row.first_seen,
+            last_seen: row.last_seen,
+            closed: row.closed,
+            count: row.count,
+            peak_value: row.peak_value,
+            min_probability: row.min_probability,
+            severity: enum_from_text(row.severity)?,
+            msg_type: MessageType::Incident,
+        })
+    }
+}
+
+// Same columns are written when an incident is added and when it changes
+#[derive(Debug, Insertable, AsChangeset)]
+#[diesel(table_name = incidents, treat_none_as_null = true)]
+pub struct NewIncident<'a> {
+    pub config_id: Option<i32>,
+    pub name: &'a str,
+    pub stat: String,
+    pub status: String,
+    pub first_seen: i64,
+    pub last_seen: i64,
+    pub closed: Option<i64>,
+    pub count: i32,
+    pub peak_value: f64,
+    pub min_probability: f64,
+    pub severity: String,
+    pub series: &'a str,
+}
+
+impl<'a> From<&'a Incident> for NewIncident<'a> {
+    fn from(incident: &'a Incident) -> Self {
+        NewIncident {
+            config_id: incident.config_id,
+            name: &incident.name,
+            stat: enum_to_text(&incident.stat),
+            status: enum_to_text(&incident.status),
+            first_seen: incident.first_seen,
+            last_seen: incident.last_seen,
+            closed: incident.closed,
+            count: incident.count,
+            peak_value: incident.peak_value,
+            min_probability: incident.min_probability,
+            severity: enum_to_text(&incident.severity),
+            series: &incident.series,
+        }
+    }
+}
+
+#[derive(Debug, Queryable, Selectable)]
+#[diesel(table_name = detections)]
+pub struct DetectionRow {
+    pub id: i32,
+    pub time: i64,
+    pub first_seen: i64,
+    pub name: String,
+    pub rule_id: i32,
+    pub severity: String,
+    pub config_id: Option<i32>,
+    pub configurations: String,
+    pub stats: String,
+}
+
+impl TryFrom<DetectionRow> for Detection {
+    type Error = serde_json::Error;
+
+    fn try_from(row: DetectionRow) -> Result<Self, Self::Error> {
+        Ok(Detection {
+            id: row.id,
+            time: row.time,
+            first_seen: row.first_seen,
+            name: row.name,
+            rule_id: row.rule_id,
+            severity: enum_from_text(row.severity)?,
+            config_id: row.config_id,
+            configurations: serde_json::from_str(&row.configurations)?,
+            stats: serde_json::from_str(&row.stats)?,
+            msg_type: MessageType::Detection,
+        })
+    }
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = detections)]
+pub struct NewDetection<'a> {
+    pub time: i64,
+    pub first_seen: i64,
+    pub name: &'a str,
+    pub rule_id: i32,
+    pub severity: String,
+    pub config_id: Option<i32>,
+    pub configurations: String,
+    pub stats: String,
+}
+
+impl<'a> TryFrom<&'a Detection> for NewDetection<'a> {
+    type Error = serde_json::Error;
+
+    fn try_from(detection: &'a Detection) -> Result<Self, Self::Error> {
+        Ok(NewDetection {
+            time: detection.time,
+            first_seen: detection.first_seen,
+            name: &detection.name,
+            rule_id: detection.rule_id,
+            severity: enum_to_text(&detection.severity),
+            config_id: detection.config_id,
+            configurations: serde_json::to_string(&detection.configurations)?,
+            stats: serde_json::to_string(&detection.stats)?,
+        })
+    }
+}
+
+#[derive(Debug, Queryable, Selectable)]
+#[diesel(table_name = pcaps)]
+pub struct PcapRow {
+    pub id: i32,
+    pub name: String,
+    pub uploaded: i64,
+    pub size: i64,
+    pub packets: i64,
+    pub first_packet: Option<i64>,
+    pub last_packet: Option<i64>,
+}
+
+impl From<PcapRow> for Pcap {
+    fn from(row: PcapRow) -> Self {
+        Pcap {
+            id: row.id,
+            name: row.name,
+            uploaded: row.uploaded,
+            size: row.size,
+            packets: row.packets,
+            first_packet: row.first_packet,
+            last_packet: row.last_packet,
+        }
+    }
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = pcaps)]
+pub struct NewPcap<'a> {
+    pub name: &'a str,
+    pub uploaded: i64,
+    pub size: i64,
+    pub packets: i64,
+    pub first_packet: Option<i64>,
+    pub last_packet: Option<i64>,
+}
+
+impl<'a> From<&'a Pcap> for NewPcap<'a> {
+    fn from(pcap: &'a Pcap) -> Self {
+        NewPcap {
+            name: &pcap.name,
+            uploaded: pcap.uploaded,
+            size: pcap.size,
+            packets: pcap.packets,
+            first_packet: pcap.first_packet,
+            last_packet: pcap.last_packet,
+        }
+    }
+}
+
+// Status is stored as the same text it has in JSON messages
+#[derive(Debug, Queryable, Selectable)]
+#[diesel(table_name = replays)]
+pub struct ReplayRow {
+    pub id: i32,
+    pub config_id: i32,
+    pub pcap_id: i32,
+    pub status: String,
+    pub started: i64,
+    pub finished: Option<i64>,
+    pub progress: f64,
+    pub error: Option<String>,
+}
+
+impl TryFrom<ReplayRow> for Replay {
+    type Error = serde_json::Error;
+
+    fn try_from(row: ReplayRow) -> Result<Self, Self::Error> {
+        Ok(Replay {
+            id: row.id,
+            config_id: row.config_id,
+            pcap_id: row.pcap_id,
+            status: enum_from_text(row.status)?,
+            started: row.started,
+            finished: row.finished,
+            progress: row.progress,
+            error: row.error,
+        })
+    }
+}
+
+// Same columns are written when a replay is added and when it changes
+#[derive(Debug, Insertable, AsChangeset)]
+#[diesel(table_name = replays, treat_none_as_null = true)]
+pub struct NewReplay<'a> {
+    pub config_id: i32,
+    pub pcap_id: i32,
+    pub status: String,
+    pub started: i64,
+    pub finished: Option<i64>,
+    pub progress: f64,
+    pub error: Option<&'a str>,
+}
+
+impl<'a> From<&'a Replay> for NewReplay<'a> {
+    fn from(replay: &'a Replay) -> Self {
+        NewReplay {
+            config_id: replay.config_id,
+            pcap_id: replay.pcap_id,
+            status: enum_to_text(&replay.status),
+            started: replay.started,
+            finished: replay.finished,
+            progress: replay.progress,
+            error: replay.error.as_deref(),
+        }
+    }
+}
+
+// Stat and alert status are stored as the same text they have in JSON messages
+pub fn enum_to_text<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(text)) => text,
+        _ => String::new(),
+    }
+}
+
+pub fn enum_from_text<T: DeserializeOwned>(text: String) -> Result<T, serde_json::Error> {
+    serde_json::from_value(serde_json::Value::String(text))
+}
+
+// Unit tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structures::replays::ReplayStatus;
+    use crate::structures::statistics::{AlarmLabel, AlertStatus, Severity, Stat};
+    use std::fmt::Debug;
+
+    // Stored texts must not change, as the rows already in the databases use them
+    fn round_trip<T: Serialize + DeserializeOwned + PartialEq + Debug>(values: &[(T, &str)]) {
+        for (value, text) in values {
+            assert_eq!(enum_to_text(value), *text);
+            assert_eq!(enum_from_text::<T>(text.to_string()).unwrap(), *value);
+        }
+        assert!(enum_from_text::<T>("UNKNOWN".to_string()).is_err());
+    }
+
+    #[test]
+    fn enum_texts() {
+        round_trip(&[
+            (Stat::AvgPktSize, "AVG_PKT_SIZE"),
+            (Stat::Perf, "PERF"),
+            (Stat::RAck, "R_ACK"),
+            (Stat::RArp, "R_ARP"),
+            (Stat::RDstSrc, "R_DST_SRC"),
+            (Stat::RDstSrcPort, "R_DST_SRC_PORT"),
+            (Stat::RIcmp, "R_ICMP"),
+            (Stat::RIp, "R_IP"),
+            (Stat::RSyn, "R_SYN"),
+            (Stat::Traffic, "TRAFFIC"),
+        ]);
+        round_trip(&[
+            (AlertStatus::DownAlert, "DOWN_ALERT"),
+            (AlertStatus::UpAlert, "UP_ALERT"),
+        ]);
+        round_trip(&[
+            (Severity::Info, "info"),
+            (Severity::Low, "low"),
+            (Severity::Medium, "medium"),
+            (Severity::High, "high"),
+            (Severity::Critical, "critical"),
+        ]);
+        round_trip(&[
+            (AlarmLabel::Acknowledged, "acknowledged"),
+            (AlarmLabel::FalsePositive, "false_positive"),
+            (AlarmLabel::Investigated, "investigated"),
+        ]);
+        round_trip(&[
+            (ReplayStatus::Running, "running"),
+            (ReplayStatus::Completed, "completed"),
+            (ReplayStatus::Failed, "failed"),
+            (ReplayStatus::Cancelled, "cancelled"),
+        ]);
+    }
+}
@@ -4,7 +4,22 @@ diesel::table! {
     alarms (id) {
         id -> Integer,
         time -> BigInt,
-        message -> Text,
+        config_id -> Nullable<Integer>,
+        name -> Text,
+        series -> Text,
+        stat -> Text,
+        status -> Text,
+        value -> Double,
+        probability -> Double,
+        code -> Integer,
+        label -> Nullable<Text>,
+        review_user -> Nullable<Text>,
+        review_time -> Nullable<BigInt>,
+        note -> Nullable<Text>,
+        suppressed -> Bool,
+        suppression_rule_id -> Nullable<Integer>,
+        severity -> Nullable<Text>,
+        job_id -> Nullable<Integer>,
     }
 }
 
@@ -15,11 +30,129 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    correlation_rules (id) {
+        id -> Integer,
+        config -> Text,
+    }
+}
+
 diesel::table! {
     data (id) {
         id -> Integer,
         time -> BigInt,
-        message -> Text,
+        config_id -> Nullable<Integer>,
+        name -> Text,
+        series -> Text,
+        avg_pkt_size -> Nullable<Double>,
+        avg_pkt_size_down -> Nullable<Double>,
+        avg_pkt_size_up -> Nullable<Double>,
+        perf -> Nullable<Double>,
+        perf_down -> Nullable<Double>,
+        perf_up -> Nullable<Double>,
+        r_ack -> Nullable<Double>,
+        r_ack_down -> Nullable<Double>,
+        r_ack_up -> Nullable<Double>,
+        r_arp -> Nullable<Double>,
+        r_arp_down -> Nullable<Double>,
+        r_arp_up -> Nullable<Double>,
+        r_dst_src -> Nullable<Double>,
+        r_dst_src_down -> Nullable<Double>,
+        r_dst_src_up -> Nullable<Double>,
+        r_dst_src_port -> Nullable<Double>,
+        r_dst_src_port_down -> Nullable<Double>,
+        r_dst_src_port_up -> Nullable<Double>,
+        r_icmp -> Nullable<Double>,
+        r_icmp_down -> Nullable<Double>,
+        r_icmp_up -> Nullable<Double>,
+        r_ip -> Nullable<Double>,
+        r_ip_down -> Nullable<Double>,
+        r_ip_up -> Nullable<Double>,
+        r_syn -> Nullable<Double>,
+        r_syn_down -> Nullable<Double>,
+        r_syn_up -> Nullable<Double>,
+        traffic -> Nullable<Double>,
+        traffic_down -> Nullable<Double>,
+        traffic_up -> Nullable<Double>,
+        job_id -> Nullable<Integer>,
+    }
+}
+
+diesel::table! {
+    detections (id) {
+        id -> Integer,
+        time -> BigInt,
+        first_seen -> BigInt,
+        name -> Text,
+        rule_id -> Integer,
+        severity -> Text,
+        config_id -> Nullable<Integer>,
+        configurations -> Text,
+        stats -> Text,
+    }
+}
+
+diesel::table! {
+    incidents (id) {
+        id -> Integer,
+        config_id -> Nullable<Integer>,
+        name -> Text,
+        stat -> Text,
+        status -> Text,
+        first_seen -> BigInt,
+        last_seen -> BigInt,
+        closed -> Nullable<BigInt>,
+        count -> Integer,
+        peak_value -> Double,
+        min_probability -> Double,
+        severity -> Text,
+        series -> Text,
+    }
+}
+
+diesel::table! {
+    pcaps (id) {
+        id -> Integer,
+        name -> Text,
+        uploaded -> BigInt,
+        size -> BigInt,
+        packets -> BigInt,
+        first_packet -> Nullable<BigInt>,
+        last_packet -> Nullable<BigInt>,
+    }
+}
+
+diesel::table! {
+    replays (id) {
+        id -> Integer,
+        config_id -> Integer,
+        pcap_id -> Integer,
+        status -> Text,
+        started -> BigInt,
+        finished -> Nullable<BigInt>,
+        progress -> Double,
+        error -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    settings (id) {
+        id -> Integer,
+        config -> Text,
+    }
+}
+
+diesel::table! {
+    suppression_rules (id) {
+        id -> Integer,
+        config -> Text,
+    }
+}
+
+diesel::table! {
+    threshold_rules (id) {
+        id -> Integer,
+        config -> Text,
     }
 }
 
@@ -30,4 +163,31 @@ diesel::table! {
     }
 }
 
-diesel::allow_tables_to_appear_in_same_query!(alarms, configurations, data, webhooks,);
+diesel::table! {
+    webhook_outbox (id) {
+        id -> Integer,
+        webhook_id -> Integer,
+        created -> BigInt,
+        payload -> Text,
+        attempts -> Integer,
+        next_attempt -> BigInt,
+        last_error -> Nullable<Text>,
+        dead -> Bool,
+    }
+}
+
+diesel::allow_tables_to_appear_in_same_query!(
+    alarms,
+    configurations,
+    correlation_rules,
+    data,
+    detections,
+    incidents,
+    pcaps,
+    replays,
+    settings,
+    suppression_rules,
+    threshold_rules,
+    webhook_outbox,
+    webhooks,
+);
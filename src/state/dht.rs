@@ -1,37 +1,75 @@
+use crate::state::database::enum_to_text;
+use crate::state::metrics::MessageReceiver;
 use crate::structures::dht::{DhtMessage, RequestPostTopicUUID};
-use crate::structures::statistics::Message;
+use crate::structures::statistics::{Message, Severity};
 use crate::tasks::RunChecker;
-use tokio::sync::broadcast;
+
+pub struct DhtOptions {
+    pub api_url: String,
+    /// Incidents are sent instead of every alarm
+    pub incidents: bool,
+    /// Alarms, incidents and detections below this severity are not sent
+    pub min_severity: Option<Severity>,
+}
+
+impl DhtOptions {
+    fn accepts(&self, severity: Severity) -> bool {
+        self.min_severity
+            .iter()
+            .all(|min_severity| severity >= *min_severity)
+    }
+}
 
 pub async fn dht_message_sender(
-    api_url: String,
+    options: DhtOptions,
     ip_addresses: Vec<String>,
-    mut message_rx: broadcast::Receiver<Message>,
+    mut message_rx: MessageReceiver,
     mut run_checker: RunChecker,
 ) {
     println!("DHT message sender started.");
-    println!("  Using API URL: {api_url}");
+    println!("  Using API URL: {}", options.api_url);
+    if options.incidents {
+        println!("  Sending incidents instead of alarms");
+    }
+    if let Some(severity) = options.min_severity {
+        println!(
+            "  Sending alarms, incidents and detections with at least {} severity",
+            enum_to_text(&severity)
+        );
+    }
     while run_checker.keep_running() {
         tokio::select! {
-            Ok(message) = message_rx.recv() => handle_message(&api_url, &ip_addresses, message).await,
+            Ok(message) = message_rx.recv() => handle_message(&options, &ip_addresses, message).await,
             _ = run_checker.shutdown_recv() => {},
         }
     }
     println!("DHT message sender stopped.")
 }
 
-async fn handle_message(api_url: &str, ip_addresses: &[String], message: Message) {
-    match message {
-        Message::Alarm(message) => {
-            let dht_message = DhtMessage {
-                request_post_topic_uuid: RequestPostTopicUUID::new(ip_addresses, *message),
-            };
-            let json_message = serde_json::to_string(&dht_message);
-            if let Ok(message) = json_message {
-                tokio::spawn(send_message(api_url.to_string(), message));
-            }
+async fn handle_message(options: &DhtOptions, ip_addresses: &[String], message: Message) {
+    let request_post_topic_uuid = match message {
+        Message::Alarm(message)
+            if !options.incidents
+                && !message.suppressed
+                && options.accepts(message.severity.unwrap_or_default()) =>
+        {
+            RequestPostTopicUUID::new(ip_addresses, *message)
+        }
+        Message::Incident(incident) if options.incidents && options.accepts(incident.severity) => {
+            RequestPostTopicUUID::incident(ip_addresses, *incident)
+        }
+        // Detections are sent with either option
+        Message::Detection(detection) if options.accepts(detection.severity) => {
+            RequestPostTopicUUID::detection(ip_addresses, *detection)
         }
-        Message::Data(_) => (), // Skipping these for now
+        _ => return, // Skipping data messages for now, as well as suppressed and minor messages
+    };
+    let dht_message = DhtMessage {
+        request_post_topic_uuid,
+    };
+    let json_message = serde_json::to_string(&dht_message);
+    if let Ok(message) = json_message {
+        tokio::spawn(send_message(options.api_url.clone(), message));
     }
 }
 
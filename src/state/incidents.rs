@@ -0,0 +1,185 @@
+use crate::state::database::Database;
+use crate::state::messages::MessageBus;
+use crate::state::metrics::MessageReceiver;
+use crate::structures::incidents::{Incident, IncidentQuery, IncidentState};
+use crate::structures::statistics::{seconds_as_nanos, AlarmMessage, AlertStatus, Message, Stat};
+use crate::tasks::RunChecker;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Open incidents are checked this often for closing
+const CLOSE_INTERVAL: Duration = Duration::from_secs(1);
+
+// Alarms are grouped by configuration, name, series, stat and status. The name keeps apart the
+// test and threshold alarms, which have no configuration.
+type IncidentKey = (Option<i32>, String, String, Stat, AlertStatus);
+
+type OpenIncidents = HashMap<IncidentKey, Incident>;
+
+fn incident_key(alarm: &AlarmMessage) -> IncidentKey {
+    (
+        alarm.config_id,
+        alarm.name.clone(),
+        alarm.series.clone(),
+        alarm.stat.clone(),
+        alarm.status.clone(),
+    )
+}
+
+// Groups alarms into incidents. Incidents are stored in the database, and sent to the other
+// receivers as messages when they are opened and when they are closed.
+pub async fn incident_engine(
+    database: Database,
+    messages_tx: MessageBus,
+    mut message_rx: MessageReceiver,
+    mut run_checker: RunChecker,
+) {
+    println!("Incident engine started.");
+
+    // Incidents left open by the previous run are continued
+    let query = IncidentQuery {
+        state: Some(IncidentState::Open),
+        ..IncidentQuery::default()
+    };
+    let mut open = OpenIncidents::new();
+    match database.get_incidents(&query) {
+        Ok(incidents) => {
+            for incident in incidents {
+                let key = (
+                    incident.config_id,
+                    incident.name.clone(),
+                    incident.series.clone(),
+                    incident.stat.clone(),
+                    incident.status.clone(),
+                );
+                open.insert(key, incident);
+            }
+        }
+        Err(err) => eprintln!("Could not read open incidents: {err}"),
+    }
+
+    let mut interval = tokio::time::interval(CLOSE_INTERVAL);
+    while run_checker.keep_running() {
+        tokio::select! {
+            Ok(message) = message_rx.recv() => {
+                match message {
+                    Message::Alarm(alarm) if !alarm.suppressed => {
+                        alarm_handler(&alarm, &mut open, &database, &messages_tx);
+                    }
+                    _ => {}
+                }
+            }
+            _ = interval.tick() => {
+                let window = database.get_incident_settings().window;
+                for incident in close_expired(&mut open, now(), window) {
+                    if let Err(err) = database.set_incident(&incident) {
+                        eprintln!("Could not close incident({}): {err}", incident.id);
+                    }
+                    let _ = messages_tx.send(Message::Incident(Box::new(incident)));
+                }
+            }
+            _ = run_checker.shutdown_recv() => {},
+        }
+    }
+    println!("Incident engine stopped.");
+}
+
+fn alarm_handler(
+    alarm: &AlarmMessage,
+    open: &mut OpenIncidents,
+    database: &Database,
+    messages_tx: &MessageBus,
+) {
+    match open.entry(incident_key(alarm)) {
+        Entry::Occupied(mut entry) => {
+            let incident = entry.get_mut();
+            incident.add(alarm);
+            if let Err(err) = database.set_incident(incident) {
+                eprintln!("Could not update incident({}): {err}", incident.id);
+            }
+        }
+        Entry::Vacant(entry) => {
+            let mut incident = Incident::new(alarm);
+            match database.add_incident(&incident) {
+                Ok(id) => incident.id = id,
+                Err(err) => {
+                    eprintln!("Could not store incident: {err}");
+                    return;
+                }
+            }
+            let _ = messages_tx.send(Message::Incident(Box::new(incident.clone())));
+            entry.insert(incident);
+        }
+    }
+}
+
+// Closes and returns the incidents that have had no alarms within the window
+fn close_expired(open: &mut OpenIncidents, now: i64, window: u64) -> Vec<Incident> {
+    let window = seconds_as_nanos(window).unwrap_or(i64::MAX);
+    let expired: Vec<IncidentKey> = open
+        .iter()
+        .filter(|(_, incident)| now - incident.last_seen > window)
+        .map(|(key, _)| key.clone())
+        .collect();
+    expired
+        .into_iter()
+        .filter_map(|key| open.remove(&key))
+        .map(|mut incident| {
+            incident.close(now);
+            incident
+        })
+        .collect()
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos() as i64
+}
+
+// Unit tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn closing() {
+        let second = 1_000_000_000;
+        let alarm = |time: i64, stat: Stat| AlarmMessage {
+            time,
+            stat,
+            ..AlarmMessage::default()
+        };
+        let mut open = OpenIncidents::new();
+        for alarm in [alarm(0, Stat::RSyn), alarm(50 * second, Stat::Traffic)] {
+            open.insert(incident_key(&alarm), Incident::new(&alarm));
+        }
+
+        assert!(close_expired(&mut open, 60 * second, 60).is_empty());
+        let closed = close_expired(&mut open, 61 * second, 60);
+        assert_eq!(closed.len(), 1);
+        assert_eq!(closed[0].stat, Stat::RSyn);
+        assert_eq!(closed[0].state, IncidentState::Closed);
+        assert_eq!(closed[0].closed, Some(61 * second));
+        assert_eq!(open.len(), 1);
+    }
+
+    #[test]
+    fn keys() {
+        let alarm = |name: &str, series: &str| AlarmMessage {
+            name: name.to_string(),
+            series: series.to_string(),
+            ..AlarmMessage::default()
+        };
+
+        // Alarms without a configuration are kept apart by their name and series
+        let key = incident_key(&alarm("Test", "any"));
+        assert_eq!(key, incident_key(&alarm("Test", "any")));
+        assert_ne!(key, incident_key(&alarm("Threshold", "any")));
+        assert_ne!(key, incident_key(&alarm("Test", "eth0")));
+    }
+}
@@ -1,12 +1,9 @@
+use crate::state::metrics::MessageReceiver;
 use crate::structures::statistics::Message;
 use crate::tasks::RunChecker;
 use termion::{color, style};
-use tokio::sync::broadcast;
 
-pub async fn message_printer(
-    mut message_rx: broadcast::Receiver<Message>,
-    mut run_checker: RunChecker,
-) {
+pub async fn message_printer(mut message_rx: MessageReceiver, mut run_checker: RunChecker) {
     println!("Message printer started.");
     while run_checker.keep_running() {
         tokio::select! {
@@ -31,6 +28,22 @@ fn print_message(message: Message) {
             Message::Data(_) => {
                 println!("Data: {json}");
             }
+            Message::Incident(_) => {
+                println!(
+                    "{}Incident: {}{}",
+                    color::Fg(color::Red),
+                    json,
+                    style::Reset
+                );
+            }
+            Message::Detection(_) => {
+                println!(
+                    "{}Detection: {}{}",
+                    color::Fg(color::Magenta),
+                    json,
+                    style::Reset
+                );
+            }
         }
     }
 }
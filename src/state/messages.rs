@@ -0,0 +1,78 @@
+use crate::structures::statistics::Message;
+use tokio::sync::{broadcast, mpsc};
+
+// Live messages are broadcast to the receivers that may skip messages when they fall behind, and
+// queued for the receivers that must get every message, such as the database writer and the
+// webhook outbox. The queues are not limited, so a slow receiver delays its messages instead of
+// losing them.
+#[derive(Clone)]
+pub struct MessageBus {
+    broadcast_tx: broadcast::Sender<Message>,
+    queue_txs: Vec<mpsc::UnboundedSender<Message>>,
+}
+
+impl MessageBus {
+    pub fn new(capacity: usize) -> MessageBus {
+        let (broadcast_tx, _) = broadcast::channel(capacity);
+        MessageBus {
+            broadcast_tx,
+            queue_txs: Vec::new(),
+        }
+    }
+
+    // Queues are added before the bus is shared, so that they get all the messages
+    pub fn queue(&mut self) -> mpsc::UnboundedReceiver<Message> {
+        let (queue_tx, queue_rx) = mpsc::unbounded_channel();
+        self.queue_txs.push(queue_tx);
+        queue_rx
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Message> {
+        self.broadcast_tx.subscribe()
+    }
+
+    // Returns false if there was no receiver for the message
+    pub fn send(&self, message: Message) -> bool {
+        let mut received = false;
+        for queue_tx in &self.queue_txs {
+            received |= queue_tx.send(message.clone()).is_ok();
+        }
+        self.broadcast_tx.send(message).is_ok() || received
+    }
+}
+
+// Unit tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structures::statistics::AlarmMessage;
+    use tokio::sync::broadcast::error::TryRecvError;
+
+    #[test]
+    fn queues_keep_all_messages() {
+        let mut bus = MessageBus::new(2);
+        let mut queue_rx = bus.queue();
+        let mut message_rx = bus.subscribe();
+        for time in 0..5 {
+            assert!(bus.send(Message::Alarm(Box::new(AlarmMessage {
+                time,
+                ..AlarmMessage::default()
+            }))));
+        }
+
+        // Broadcast receiver misses the oldest messages, while the queue has them all
+        assert_eq!(message_rx.try_recv().err(), Some(TryRecvError::Lagged(3)));
+        for time in 0..5 {
+            match queue_rx.try_recv() {
+                Ok(Message::Alarm(alarm)) => assert_eq!(alarm.time, time),
+                _ => panic!("Alarm {time} was not queued"),
+            }
+        }
+
+        // Sending fails only without any receivers
+        drop((queue_rx, message_rx));
+        assert!(!bus.send(Message::Alarm(Box::default())));
+    }
+}
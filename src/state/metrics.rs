@@ -0,0 +1,317 @@
+use crate::state::database::enum_to_text;
+use crate::structures::statistics::{DataMessage, Message};
+use crate::tasks::RunChecker;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, mpsc};
+
+// Metrics are shared between the collector task, the message receivers and the API
+pub type SharedMetrics = Arc<Metrics>;
+
+// Latest data message is kept for each configuration, name and series
+type DataKey = (Option<i32>, String, String);
+
+// Metrics collected from the live messages and the worker tasks
+//--------------------------------------------------------------------------------------------------
+
+#[derive(Default)]
+pub struct Metrics {
+    alarms: Mutex<BTreeMap<(String, String), u64>>,
+    data: Mutex<BTreeMap<DataKey, DataMessage>>,
+    lagged: Mutex<BTreeMap<&'static str, u64>>,
+    webhook_failures: Mutex<BTreeMap<i32, u64>>,
+}
+
+impl Metrics {
+    pub fn new() -> SharedMetrics {
+        Arc::new(Metrics::default())
+    }
+
+    // Wraps the receiver so that messages it misses are counted under the given name
+    pub fn receiver(
+        self: &Arc<Self>,
+        name: &'static str,
+        message_rx: broadcast::Receiver<Message>,
+    ) -> MessageReceiver {
+        MessageReceiver {
+            message_rx: MessageSource::Broadcast(message_rx),
+            metrics: self.clone(),
+            name,
+        }
+    }
+
+    // Queued messages are never missed, but the receiver is used the same way
+    pub fn queue_receiver(
+        self: &Arc<Self>,
+        name: &'static str,
+        message_rx: mpsc::UnboundedReceiver<Message>,
+    ) -> MessageReceiver {
+        MessageReceiver {
+            message_rx: MessageSource::Queue(message_rx),
+            metrics: self.clone(),
+            name,
+        }
+    }
+
+    pub fn record_lag(&self, receiver: &'static str, count: u64) {
+        *self.lagged.lock().unwrap().entry(receiver).or_default() += count;
+    }
+
+    pub fn record_message(&self, message: &Message) {
+        match message {
+            Message::Alarm(alarm) => {
+                // Labels are the same text as the stat and status in JSON messages
+                let key = (enum_to_text(&alarm.stat), enum_to_text(&alarm.status));
+                *self.alarms.lock().unwrap().entry(key).or_default() += 1;
+            }
+            Message::Data(data) => {
+                let key = (data.config_id, data.name.clone(), data.series.clone());
+                self.data.lock().unwrap().insert(key, (**data).clone());
+            }
+            Message::Incident(_) | Message::Detection(_) => {}
+        }
+    }
+
+    // Stats of a removed configuration are no longer reported
+    pub fn remove_configuration(&self, config_id: i32) {
+        self.data
+            .lock()
+            .unwrap()
+            .retain(|(id, ..), _| *id != Some(config_id));
+    }
+
+    pub fn record_webhook_failure(&self, id: i32) {
+        *self.webhook_failures.lock().unwrap().entry(id).or_default() += 1;
+    }
+
+    pub fn write(&self, text: &mut MetricsText) {
+        text.family(
+            "netspot_stat",
+            "gauge",
+            "Latest value of each stat in the data messages",
+        );
+        for ((config_id, name, series), data) in self.data.lock().unwrap().iter() {
+            let config_id = config_id.map(|id| id.to_string()).unwrap_or_default();
+            for (stat, value) in data.stat_values() {
+                text.sample(
+                    "netspot_stat",
+                    &[
+                        ("config_id", &config_id),
+                        ("name", name),
+                        ("series", series),
+                        ("stat", stat),
+                    ],
+                    value,
+                );
+            }
+        }
+
+        text.family(
+            "netspot_alarms_total",
+            "counter",
+            "Alarms received since the server started",
+        );
+        for ((stat, status), count) in self.alarms.lock().unwrap().iter() {
+            text.sample(
+                "netspot_alarms_total",
+                &[("stat", stat), ("status", status)],
+                *count as f64,
+            );
+        }
+
+        text.family(
+            "netspot_control_messages_lagged_total",
+            "counter",
+            "Live messages skipped because a receiver could not keep up",
+        );
+        for (receiver, count) in self.lagged.lock().unwrap().iter() {
+            text.sample(
+                "netspot_control_messages_lagged_total",
+                &[("receiver", receiver)],
+                *count as f64,
+            );
+        }
+
+        text.family(
+            "netspot_control_webhook_failures_total",
+            "counter",
+            "Webhook requests that failed or were not answered with a success status",
+        );
+        for (id, count) in self.webhook_failures.lock().unwrap().iter() {
+            text.sample(
+                "netspot_control_webhook_failures_total",
+                &[("webhook", &id.to_string())],
+                *count as f64,
+            );
+        }
+    }
+}
+
+// Message receiver that counts the messages it misses
+//--------------------------------------------------------------------------------------------------
+
+enum MessageSource {
+    Broadcast(broadcast::Receiver<Message>),
+    Queue(mpsc::UnboundedReceiver<Message>),
+}
+
+pub struct MessageReceiver {
+    message_rx: MessageSource,
+    metrics: SharedMetrics,
+    name: &'static str,
+}
+
+impl MessageReceiver {
+    // Same as broadcast::Receiver::recv, except that lagging is recorded and skipped over
+    pub async fn recv(&mut self) -> Result<Message, RecvError> {
+        match &mut self.message_rx {
+            MessageSource::Broadcast(message_rx) => loop {
+                match message_rx.recv().await {
+                    Err(RecvError::Lagged(count)) => self.metrics.record_lag(self.name, count),
+                    result => return result,
+                }
+            },
+            MessageSource::Queue(message_rx) => message_rx.recv().await.ok_or(RecvError::Closed),
+        }
+    }
+}
+
+// Worker task
+//--------------------------------------------------------------------------------------------------
+
+pub async fn metrics_collector(
+    metrics: SharedMetrics,
+    mut message_rx: MessageReceiver,
+    mut run_checker: RunChecker,
+) {
+    println!("Metrics collector started.");
+    while run_checker.keep_running() {
+        tokio::select! {
+            Ok(message) = message_rx.recv() => metrics.record_message(&message),
+            _ = run_checker.shutdown_recv() => {},
+        }
+    }
+    println!("Metrics collector stopped.");
+}
+
+// Prometheus text format
+//--------------------------------------------------------------------------------------------------
+
+#[derive(Default)]
+pub struct MetricsText(String);
+
+impl MetricsText {
+    pub fn family(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.0, "# HELP {name} {help}");
+        let _ = writeln!(self.0, "# TYPE {name} {kind}");
+    }
+
+    pub fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: f64) {
+        self.0.push_str(name);
+        if !labels.is_empty() {
+            let labels: Vec<String> = labels
+                .iter()
+                .map(|(key, value)| format!("{key}=\"{}\"", escape_label(value)))
+                .collect();
+            let _ = write!(self.0, "{{{}}}", labels.join(","));
+        }
+        let _ = writeln!(self.0, " {}", format_value(value));
+    }
+
+    pub fn into_string(self) -> String {
+        self.0
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', "\\\"")
+        .replace('\n', r"\n")
+}
+
+fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+// Unit tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structures::statistics::{AlarmMessage, AlertStatus, Stat};
+
+    #[test]
+    fn text_format() {
+        let mut text = MetricsText::default();
+        text.family("test_metric", "gauge", "Test metric");
+        text.sample("test_metric", &[], 1.5);
+        text.sample("test_metric", &[("a", "x"), ("b", "\"y\"\n\\")], f64::NAN);
+        text.sample("test_metric", &[("a", "z")], f64::NEG_INFINITY);
+        let expected = concat!(
+            "# HELP test_metric Test metric\n",
+            "# TYPE test_metric gauge\n",
+            "test_metric 1.5\n",
+            "test_metric{a=\"x\",b=\"\\\"y\\\"\\n\\\\\"} NaN\n",
+            "test_metric{a=\"z\"} -Inf\n",
+        );
+        assert_eq!(text.into_string(), expected);
+    }
+
+    #[test]
+    fn recorded_metrics() {
+        let metrics = Metrics::new();
+        let alarm = AlarmMessage {
+            stat: Stat::RSyn,
+            status: AlertStatus::UpAlert,
+            ..AlarmMessage::default()
+        };
+        metrics.record_message(&Message::Alarm(Box::new(alarm.clone())));
+        metrics.record_message(&Message::Alarm(Box::new(alarm)));
+
+        // Only the latest data message is kept for each series
+        let mut data = DataMessage {
+            config_id: Some(1),
+            name: "Test".to_string(),
+            series: "any".to_string(),
+            r_syn: Some(0.5),
+            ..DataMessage::default()
+        };
+        metrics.record_message(&Message::Data(Box::new(data.clone())));
+        data.r_syn = Some(0.25);
+        data.r_syn_up = Some(0.75);
+        metrics.record_message(&Message::Data(Box::new(data)));
+
+        metrics.record_lag("database", 3);
+        metrics.record_lag("database", 2);
+        metrics.record_webhook_failure(4);
+
+        let mut text = MetricsText::default();
+        metrics.write(&mut text);
+        let text = text.into_string();
+        let labels = r#"config_id="1",name="Test",series="any""#;
+        assert!(text.contains(&format!("netspot_stat{{{labels},stat=\"R_SYN\"}} 0.25\n")));
+        assert!(text.contains(&format!(
+            "netspot_stat{{{labels},stat=\"R_SYN_UP\"}} 0.75\n"
+        )));
+        assert!(!text.contains("0.5\n"));
+        assert!(text.contains("netspot_alarms_total{stat=\"R_SYN\",status=\"UP_ALERT\"} 2\n"));
+        assert!(text.contains("netspot_control_messages_lagged_total{receiver=\"database\"} 5\n"));
+        assert!(text.contains("netspot_control_webhook_failures_total{webhook=\"4\"} 1\n"));
+
+        // Gauges of a deleted configuration are removed
+        metrics.remove_configuration(1);
+        let mut text = MetricsText::default();
+        metrics.write(&mut text);
+        assert!(!text.into_string().contains("netspot_stat{"));
+    }
+}
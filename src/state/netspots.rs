@@ -1,10 +1,21 @@
 mod net;
+mod output;
+mod replay;
+
+pub use net::MessageSender;
 
 use crate::api_v1::testing::TestAlarmMessage;
-use crate::state::netspots::net::SocketUse;
+use crate::state::netspots::net::{MessageSource, SharedListenerStatus, SocketUse};
+use crate::state::netspots::output::ProcessOutput;
+use crate::state::netspots::replay::{ReplayJob, SharedReplays};
 use crate::structures::configuration::{NetspotConfig, NetspotConfigMap};
-use crate::structures::statistics::{AlarmMessage, Message, MessageType};
-use crate::structures::status::{ProcessStatus, Status, Statuses};
+use crate::structures::logs::{LogLine, LogLines};
+use crate::structures::replays::{Pcap, Replay};
+use crate::structures::statistics::Message;
+use crate::structures::status::{
+    ListenerStatuses, ProcessAction, ProcessExit, ProcessStatus, ProcessUpdate, ProcessUpdates,
+    Status, Statuses,
+};
 use crate::tasks::RunChecker;
 
 use nix::sys::signal;
@@ -13,9 +24,11 @@ use nix::unistd::Pid;
 use rocket::warn;
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
+use std::os::unix::process::ExitStatusExt;
 use std::path::{Path, PathBuf};
-use std::sync::Mutex;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::process::{ExitStatus, Stdio};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use std::{fs, io};
 use tokio::process::{Child, Command};
 use tokio::sync::{broadcast, oneshot, RwLock};
@@ -24,59 +37,207 @@ use tokio::time;
 // Configuration id is mapped to process handler
 type Netspots = HashMap<i32, NetspotProcess>;
 
+// Processes are shared between the manager, the supervisor and the socket listeners
+type SharedNetspots = Arc<RwLock<Netspots>>;
+
+// Socket connections find the source of their messages from the netspots and the replays
+#[derive(Clone)]
+pub struct SharedProcesses {
+    netspots: SharedNetspots,
+    replays: SharedReplays,
+}
+
+// How often the supervisor checks netspot processes
+const SUPERVISOR_INTERVAL: Duration = Duration::from_secs(1);
+
+// Crashed processes are restarted after a delay that doubles on each consecutive crash
+const RESTART_BACKOFF_INITIAL: Duration = Duration::from_secs(1);
+const RESTART_BACKOFF_MAX: Duration = Duration::from_secs(60);
+
+// Consecutive crashes are forgotten once the process has been running this long
+const RESTART_STABLE_AFTER: Duration = Duration::from_secs(60);
+
+// Supervisor gives up after this many consecutive crashes
+const RESTART_LIMIT: u32 = 5;
+
 pub enum NetspotManagerError {
     NotFound,
 }
 
 pub struct NetspotManager {
     data_path: PathBuf,
-    message_tx: Mutex<broadcast::Sender<Message>>,
-    netspots_lock: RwLock<Netspots>,
+    listeners: Vec<SharedListenerStatus>,
+    log_files: bool,
+    netspots_lock: SharedNetspots,
+    replays_lock: SharedReplays,
+    sender: MessageSender,
+    status_tx: broadcast::Sender<Status>,
 }
 
 impl NetspotManager {
     pub async fn new(
         data_path: &Path,
         configurations: NetspotConfigMap,
-        message_tx: broadcast::Sender<Message>,
+        log_files: bool,
+        sender: MessageSender,
         run_checker: RunChecker,
     ) -> Result<NetspotManager, String> {
-        net::start_listener_task(
-            data_path,
-            SocketUse::Alarm,
-            message_tx.clone(),
-            run_checker.clone(),
-        )?;
-        net::start_listener_task(data_path, SocketUse::Data, message_tx.clone(), run_checker)?;
+        let netspots_lock = Arc::new(RwLock::new(Netspots::new()));
+        let replays_lock = SharedReplays::default();
+        let processes = SharedProcesses {
+            netspots: netspots_lock.clone(),
+            replays: replays_lock.clone(),
+        };
+        let listeners = vec![
+            net::start_listener_task(
+                data_path,
+                SocketUse::Alarm,
+                sender.clone(),
+                processes.clone(),
+                run_checker.clone(),
+            )?,
+            net::start_listener_task(
+                data_path,
+                SocketUse::Data,
+                sender.clone(),
+                processes,
+                run_checker.clone(),
+            )?,
+        ];
+        tokio::spawn(supervisor_task(netspots_lock.clone(), run_checker));
+
+        // Replays are not continued after a restart
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos() as i64;
+        sender.database.interrupt_replays(now)?;
+
+        let (status_tx, _) = broadcast::channel(16);
         let manager = NetspotManager {
             data_path: PathBuf::from(data_path),
-            message_tx: Mutex::new(message_tx),
-            netspots_lock: RwLock::new(Netspots::new()),
+            listeners,
+            log_files,
+            netspots_lock,
+            replays_lock,
+            sender,
+            status_tx,
         };
         manager.update_all(configurations).await?;
         Ok(manager)
     }
 
     pub fn send_test_alarm(&self, test_alarm: TestAlarmMessage) -> bool {
-        let time = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_nanos() as i64;
-        let full_alarm_message = AlarmMessage {
-            time,
-            name: test_alarm.name,
-            series: "TEST ALARM".to_string(),
-            stat: test_alarm.stat,
-            status: test_alarm.status,
-            value: test_alarm.value,
-            probability: test_alarm.probability,
-            code: 1,
-            msg_type: MessageType::Alarm,
+        let mut message = test_alarm.into_message();
+        if let Message::Alarm(alarm) = &mut message {
+            self.sender.scorer.apply(alarm);
+            self.sender.suppressor.apply(alarm);
+        }
+        self.sender.message_tx.send(message)
+    }
+
+    // Starts netspot for the capture file of the replay. The replay is stored with its status
+    // when it finishes.
+    pub async fn start_replay(
+        &self,
+        replay: Replay,
+        config: &NetspotConfig,
+        pcap: Pcap,
+        pcap_path: &Path,
+    ) -> Result<(), io::Error> {
+        let mut toml_file_path = self.data_path.clone();
+        toml_file_path.push(format!("replay_{}.toml", replay.id));
+        let data_path = self.data_path.to_str().expect("valid str");
+        let pcap_path = pcap_path.to_str().expect("valid str");
+        fs::write(
+            &toml_file_path,
+            config.make_replay_toml(data_path, pcap_path),
+        )?;
+        let process = match Command::new("netspot")
+            .args(["run", "-c", toml_file_path.to_str().expect("valid str")])
+            .kill_on_drop(true)
+            .spawn()
+        {
+            Ok(process) => process,
+            Err(err) => {
+                let _ = fs::remove_file(&toml_file_path);
+                return Err(err);
+            }
         };
-        let message_tx = self.message_tx.lock().unwrap();
-        message_tx
-            .send(Message::Alarm(Box::new(full_alarm_message)))
-            .is_ok()
+        self.run_replay(replay, pcap, toml_file_path, process).await;
+        Ok(())
+    }
+
+    // Follows the started netspot process of the replay until it exits or is stopped. The
+    // configuration file is removed after that.
+    pub async fn run_replay(
+        &self,
+        replay: Replay,
+        pcap: Pcap,
+        toml_file_path: PathBuf,
+        process: Child,
+    ) {
+        let mut replays = self.replays_lock.write().await;
+        let (job, replay_process) = ReplayJob::new(replay, pcap, toml_file_path, process.id());
+        replays.insert(job.replay.id, replay_process);
+        tokio::spawn(replay::replay_task(
+            job,
+            process,
+            self.sender.database.clone(),
+            self.replays_lock.clone(),
+        ));
+    }
+
+    // Stops the replay, which is then stored as cancelled. Returns false if it was not running.
+    pub async fn stop_replay(&self, id: i32) -> bool {
+        let mut replays = self.replays_lock.write().await;
+        match replays.get_mut(&id) {
+            Some(replay) => {
+                replay.stop(None);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Running replays are stored as cancelled when the server is shut down
+    pub async fn stop_replays(&self) {
+        let mut replays = self.replays_lock.write().await;
+        for replay in replays.values_mut() {
+            replay.stop(Some("Server was shut down".to_string()));
+        }
+    }
+
+    // Status of a process is sent every time it changes
+    pub fn subscribe_status(&self) -> broadcast::Receiver<Status> {
+        self.status_tx.subscribe()
+    }
+
+    pub fn listener_statuses(&self) -> ListenerStatuses {
+        self.listeners
+            .iter()
+            .map(|listener| listener.lock().unwrap().clone())
+            .collect()
+    }
+
+    pub async fn logs_by_id(&self, id: i32, count: usize) -> Result<LogLines, NetspotManagerError> {
+        let netspots = self.netspots_lock.read().await;
+        match netspots.get(&id) {
+            Some(process) => Ok(process.output.lines(count)),
+            None => Err(NetspotManagerError::NotFound),
+        }
+    }
+
+    pub async fn follow_logs_by_id(
+        &self,
+        id: i32,
+        count: usize,
+    ) -> Result<(LogLines, broadcast::Receiver<LogLine>), NetspotManagerError> {
+        let netspots = self.netspots_lock.read().await;
+        match netspots.get(&id) {
+            Some(process) => Ok(process.output.follow(count)),
+            None => Err(NetspotManagerError::NotFound),
+        }
     }
 
     pub async fn restart_all(&self) {
@@ -96,6 +257,7 @@ impl NetspotManager {
             if let Err(err) = process.start() {
                 warn!("Could not start process {}: {}", id, err.to_string());
             }
+            process.publish_status();
         }
     }
 
@@ -107,6 +269,7 @@ impl NetspotManager {
                 if let Err(err) = process.start() {
                     warn!("Could not start process {}: {}", id, err.to_string());
                 }
+                process.publish_status();
             }
         }
         self.status_by_id(id).await
@@ -135,6 +298,7 @@ impl NetspotManager {
             if let Err(err) = process.stop().await {
                 warn!("Error while stopping process {}: {}", id, err.to_string());
             }
+            process.publish_status();
         }
     }
 
@@ -146,98 +310,318 @@ impl NetspotManager {
                 if let Err(err) = process.stop().await {
                     warn!("Error while stopping process {}: {}", id, err.to_string());
                 }
+                process.publish_status();
             }
         }
         self.status_by_id(id).await
     }
 
-    pub async fn update_all(&self, configurations: NetspotConfigMap) -> Result<(), String> {
+    pub async fn update_all(
+        &self,
+        configurations: NetspotConfigMap,
+    ) -> Result<ProcessUpdates, String> {
         // Lock for writing
         let mut netspots = self.netspots_lock.write().await;
+        let mut updates = ProcessUpdates::new();
 
-        // Remove processes that are no longer in the database
-        netspots.retain(|id, _| configurations.contains_key(id));
+        // Stop and remove processes that are no longer in the database
+        let removed: Vec<i32> = netspots
+            .keys()
+            .filter(|id| !configurations.contains_key(id))
+            .copied()
+            .collect();
+        for id in removed {
+            if let Some(mut process) = netspots.remove(&id) {
+                let result = process.stop().await;
+                updates.push(process_update(id, result.map(|_| ProcessAction::Removed)));
+            }
+        }
 
         // Create and update entries
         for (id, config) in configurations {
-            match netspots.entry(id) {
+            let result = match netspots.entry(id) {
                 Entry::Occupied(entry) => {
-                    entry.into_mut().set_config(config);
+                    let process = entry.into_mut();
+                    let result = process.update_config(config).await;
+                    process.publish_status();
+                    result
                 }
                 Entry::Vacant(entry) => {
-                    entry.insert(NetspotProcess::from(&self.data_path, id, config));
+                    let process = entry.insert(NetspotProcess::from(
+                        &self.data_path,
+                        id,
+                        config,
+                        self.log_files,
+                        self.status_tx.clone(),
+                    ));
+                    process.publish_status();
+                    Ok(ProcessAction::Added)
                 }
             };
+            updates.push(process_update(id, result));
         }
 
-        Ok(())
+        updates.sort_by_key(|update| update.id);
+        Ok(updates)
+    }
+}
+
+fn process_update(id: i32, result: Result<ProcessAction, io::Error>) -> ProcessUpdate {
+    match result {
+        Ok(action) => ProcessUpdate {
+            id,
+            action,
+            error: None,
+        },
+        Err(err) => {
+            warn!("Could not apply configuration to process {}: {}", id, err);
+            ProcessUpdate {
+                id,
+                action: ProcessAction::Failed,
+                error: Some(err.to_string()),
+            }
+        }
     }
 }
 
+// Supervisor task
+//--------------------------------------------------------------------------------------------------
+
+async fn supervisor_task(netspots_lock: SharedNetspots, mut run_checker: RunChecker) {
+    println!("Netspot supervisor started.");
+    let mut interval = time::interval(SUPERVISOR_INTERVAL);
+    while run_checker.keep_running() {
+        tokio::select! {
+            _ = interval.tick() => {
+                // Restarts are spawned without the lock, so that the API is not blocked meanwhile
+                let launches: Vec<Launch> = {
+                    let mut netspots = netspots_lock.write().await;
+                    netspots
+                        .values_mut()
+                        .filter_map(|process| {
+                            let launch = process.supervise();
+                            process.publish_status();
+                            launch
+                        })
+                        .collect()
+                };
+                let results: Vec<(Launch, io::Result<Child>)> = launches
+                    .into_iter()
+                    .map(|launch| {
+                        let result = launch.spawn();
+                        (launch, result)
+                    })
+                    .collect();
+                let mut netspots = netspots_lock.write().await;
+                for (launch, result) in results {
+                    if let Some(process) = netspots.get_mut(&launch.id) {
+                        process.restarted(launch, result);
+                        process.publish_status();
+                    }
+                }
+            }
+            _ = run_checker.shutdown_recv() => {},
+        }
+    }
+    println!("Netspot supervisor stopped.");
+}
+
+// Finds the configuration or the replay of a running netspot process by its process id
+async fn source_by_pid(processes: &SharedProcesses, pid: u32) -> Option<MessageSource> {
+    let netspots = processes.netspots.read().await;
+    if let Some((id, _)) = netspots
+        .iter()
+        .find(|(_, process)| process.pid() == Some(pid))
+    {
+        return Some(MessageSource::Netspot { config_id: *id });
+    }
+    drop(netspots);
+    let replays = processes.replays.read().await;
+    replays
+        .iter()
+        .find(|(_, replay)| replay.pid == Some(pid))
+        .map(|(id, replay)| MessageSource::Replay {
+            config_id: replay.config_id,
+            job_id: *id,
+            latest: replay.latest.clone(),
+        })
+}
+
+// Netspot process
+//--------------------------------------------------------------------------------------------------
+
+// Everything needed to start a netspot process, so that it can be started without the lock
+struct Launch {
+    id: i32,
+    toml: String,
+    toml_file_path: String,
+}
+
+impl Launch {
+    fn spawn(&self) -> io::Result<Child> {
+        fs::write(&self.toml_file_path, &self.toml)?;
+        Command::new("netspot")
+            .args(["run", "-c", &self.toml_file_path])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+    }
+}
+
+// Delay before restarting a process that has crashed the given number of times in a row, or
+// None when the supervisor gives up
+fn restart_backoff(crashes: u32) -> Option<Duration> {
+    if crashes >= RESTART_LIMIT {
+        return None;
+    }
+    let exponent = crashes.max(1) - 1;
+    Some(
+        RESTART_BACKOFF_INITIAL
+            .saturating_mul(2u32.saturating_pow(exponent))
+            .min(RESTART_BACKOFF_MAX),
+    )
+}
+
+// Supervision state of the process, which is kept separately from the child handle
+#[derive(Debug)]
+enum Supervision {
+    None,             // Normal operation
+    Crashed(Instant), // Process exited unexpectedly and is restarted at the given instant
+    Restarting,       // Process was restarted automatically and is not yet stable
+    Failed,           // Too many consecutive crashes, waiting for a manual start
+}
+
 #[derive(Debug)]
 pub struct NetspotProcess {
     config: NetspotConfig,
+    crashes: u32,
     data_path: String,
     id: i32,
+    last_exit: Option<ProcessExit>,
+    output: ProcessOutput,
     process: Option<Child>,
+    published_status: Option<ProcessStatus>,
+    restarts: u32,
+    started_at: Option<Instant>,
+    status_tx: broadcast::Sender<Status>,
+    supervision: Supervision,
     toml_file_path: String,
 }
 
 impl NetspotProcess {
-    fn from(data_path: &Path, id: i32, config: NetspotConfig) -> NetspotProcess {
+    fn from(
+        data_path: &Path,
+        id: i32,
+        config: NetspotConfig,
+        log_files: bool,
+        status_tx: broadcast::Sender<Status>,
+    ) -> NetspotProcess {
         let mut toml_file_path = PathBuf::from(data_path);
         toml_file_path.push(format!("netspot_{id}.toml"));
         NetspotProcess {
             config,
+            crashes: 0,
             data_path: String::from(data_path.to_str().expect("valid str")),
             id,
+            last_exit: None,
+            output: ProcessOutput::new(data_path, id, log_files),
             process: None,
+            published_status: None,
+            restarts: 0,
+            started_at: None,
+            status_tx,
+            supervision: Supervision::None,
             toml_file_path: String::from(toml_file_path.to_str().expect("valid str")),
         }
     }
 
+    fn pid(&self) -> Option<u32> {
+        self.process.as_ref().and_then(|process| process.id())
+    }
+
     fn toml_file_path(&self) -> &str {
         &self.toml_file_path
     }
 
     fn process_status(&self) -> ProcessStatus {
-        match self.process {
-            None => {
-                if !self.config.configuration.enabled {
-                    ProcessStatus::Disabled
-                } else {
-                    ProcessStatus::Stopped
-                }
-            }
-            Some(_) => ProcessStatus::Running,
+        match (&self.process, &self.supervision) {
+            (Some(_), Supervision::Restarting) => ProcessStatus::Restarting,
+            (Some(_), _) => ProcessStatus::Running,
+            (None, _) if !self.config.configuration.is_live() => ProcessStatus::Disabled,
+            (None, Supervision::Crashed(_)) => ProcessStatus::Crashed,
+            (None, Supervision::Failed) => ProcessStatus::Failed,
+            (None, _) => ProcessStatus::Stopped,
         }
     }
 
-    fn set_config(&mut self, config: NetspotConfig) {
+    // Sends the status to subscribers when it has changed since it was last sent
+    fn publish_status(&mut self) {
+        let status = self.process_status();
+        if self.published_status != Some(status) {
+            self.published_status = Some(status);
+            // Sending fails only when there are no subscribers
+            let _ = self.status_tx.send(self.status());
+        }
+    }
+
+    // Takes the new configuration into use and starts, stops or restarts the process to match it
+    async fn update_config(&mut self, config: NetspotConfig) -> Result<ProcessAction, io::Error> {
+        let was_enabled = self.config.configuration.is_live();
+        let toml_changed =
+            self.config.make_toml(&self.data_path) != config.make_toml(&self.data_path);
         self.config = config;
+        let enabled = self.config.configuration.is_live();
+        let running = self.process.is_some();
+        match (was_enabled, enabled) {
+            (true, false) if running => {
+                self.stop().await?;
+                Ok(ProcessAction::Stopped)
+            }
+            (false, true) => {
+                self.start()?;
+                Ok(ProcessAction::Started)
+            }
+            (true, true) if running && toml_changed => {
+                self.stop().await?;
+                self.start()?;
+                Ok(ProcessAction::Restarted)
+            }
+            _ if toml_changed || was_enabled != enabled => Ok(ProcessAction::Updated),
+            _ => Ok(ProcessAction::Unchanged),
+        }
     }
 
-    fn start(&mut self) -> Result<(), io::Error> {
-        if self.process_status() == ProcessStatus::Running
-            || self.process_status() == ProcessStatus::Disabled
-        {
-            return Ok(());
+    fn launch(&self) -> Launch {
+        Launch {
+            id: self.id,
+            toml: self.config.make_toml(&self.data_path),
+            toml_file_path: self.toml_file_path.clone(),
         }
+    }
 
-        fs::write(&self.toml_file_path, self.config.make_toml(&self.data_path))?;
+    fn spawn(&mut self) -> Result<(), io::Error> {
+        let process = self.launch().spawn()?;
+        self.attach(process);
+        Ok(())
+    }
 
-        match Command::new("netspot")
-            .args(["run", "-c", &self.toml_file_path])
-            .kill_on_drop(true)
-            .spawn()
-        {
-            Ok(process) => {
-                self.process = Some(process);
-                println!("Netspot configuration {} started.", self.id);
-                Ok(())
-            }
-            Err(err) => Err(err),
+    fn attach(&mut self, mut process: Child) {
+        self.output.capture(&mut process);
+        self.process = Some(process);
+        self.started_at = Some(Instant::now());
+        println!("Netspot configuration {} started.", self.id);
+    }
+
+    fn start(&mut self) -> Result<(), io::Error> {
+        if self.process.is_some() || !self.config.configuration.is_live() {
+            return Ok(());
         }
+
+        // Manual start clears any crash history
+        self.crashes = 0;
+        self.supervision = Supervision::None;
+        self.spawn()
     }
 
     fn status(&self) -> Status {
@@ -245,11 +629,119 @@ impl NetspotProcess {
             id: self.id,
             name: self.config.configuration.name.clone(),
             status: self.process_status(),
+            restarts: self.restarts,
+            last_exit: self.last_exit.clone(),
         }
     }
 
+    // Called periodically by the supervisor task to reap exited processes. Returns the launch
+    // when a restart is due, and the supervisor reports back with restarted.
+    fn supervise(&mut self) -> Option<Launch> {
+        if let Some(process) = self.process.as_mut() {
+            match process.try_wait() {
+                Ok(None) => {
+                    // Still running, forgetting earlier crashes once the process is stable
+                    if let Some(started_at) = self.started_at {
+                        if self.crashes > 0 && started_at.elapsed() >= RESTART_STABLE_AFTER {
+                            self.crashes = 0;
+                            self.supervision = Supervision::None;
+                        }
+                    }
+                    return None;
+                }
+                Ok(Some(exit_status)) => {
+                    self.process = None;
+                    self.record_exit(exit_status);
+                    self.schedule_restart();
+                }
+                Err(err) => {
+                    eprintln!(
+                        "Could not check status of netspot configuration {}: {}",
+                        self.id, err
+                    );
+                    return None;
+                }
+            }
+        }
+
+        match self.supervision {
+            Supervision::Crashed(_) if !self.config.configuration.is_live() => {
+                self.supervision = Supervision::None;
+                None
+            }
+            Supervision::Crashed(restart_at) if Instant::now() >= restart_at => Some(self.launch()),
+            _ => None,
+        }
+    }
+
+    // Takes the restarted process into use, unless the process was started, stopped or
+    // reconfigured while it was being spawned. The discarded process is killed when dropped.
+    fn restarted(&mut self, launch: Launch, result: io::Result<Child>) {
+        let current = self.process.is_none()
+            && matches!(self.supervision, Supervision::Crashed(_))
+            && launch.toml == self.config.make_toml(&self.data_path);
+        match result {
+            Ok(_) if !current => {}
+            Ok(process) => {
+                self.attach(process);
+                self.restarts += 1;
+                self.supervision = Supervision::Restarting;
+            }
+            Err(err) => {
+                eprintln!(
+                    "Could not restart netspot configuration {}: {}",
+                    self.id, err
+                );
+                if current {
+                    self.schedule_restart();
+                }
+            }
+        }
+    }
+
+    fn record_exit(&mut self, exit_status: ExitStatus) {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos() as i64;
+        let exit = ProcessExit {
+            time,
+            code: exit_status.code(),
+            signal: exit_status.signal(),
+        };
+        eprintln!(
+            "Netspot configuration {} exited unexpectedly: {}",
+            self.id, exit_status
+        );
+        self.last_exit = Some(exit);
+    }
+
+    fn schedule_restart(&mut self) {
+        self.crashes += 1;
+        let backoff = match restart_backoff(self.crashes) {
+            Some(backoff) => backoff,
+            None => {
+                eprintln!(
+                    "Netspot configuration {} crashed {} times in a row. Giving up.",
+                    self.id, self.crashes
+                );
+                self.supervision = Supervision::Failed;
+                return;
+            }
+        };
+        println!(
+            "Restarting netspot configuration {} in {} second(s).",
+            self.id,
+            backoff.as_secs()
+        );
+        self.supervision = Supervision::Crashed(Instant::now() + backoff);
+    }
+
     async fn stop(&mut self) -> Result<(), io::Error> {
-        if self.process_status() != ProcessStatus::Running {
+        // Manual stop cancels any pending automatic restart
+        self.crashes = 0;
+        self.supervision = Supervision::None;
+        if self.process.is_none() {
             return Ok(());
         }
         if let Some(mut process) = self.process.take() {
@@ -283,3 +775,63 @@ impl NetspotProcess {
         Ok(())
     }
 }
+
+// Unit tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restart_backoffs() {
+        assert_eq!(restart_backoff(1), Some(Duration::from_secs(1)));
+        assert_eq!(restart_backoff(2), Some(Duration::from_secs(2)));
+        assert_eq!(restart_backoff(4), Some(Duration::from_secs(8)));
+        assert_eq!(restart_backoff(RESTART_LIMIT), None);
+        assert_eq!(restart_backoff(u32::MAX), None);
+        for crashes in 1..RESTART_LIMIT {
+            assert!(restart_backoff(crashes) <= Some(RESTART_BACKOFF_MAX));
+        }
+    }
+
+    #[tokio::test]
+    async fn give_up_after_restart_limit() {
+        let (status_tx, mut status_rx) = broadcast::channel(16);
+        let mut process = NetspotProcess::from(
+            Path::new("/tmp"),
+            1,
+            NetspotConfig::default(),
+            false,
+            status_tx,
+        );
+
+        // Each crash schedules a restart later than the previous one
+        let mut previous = Instant::now();
+        for _ in 1..RESTART_LIMIT {
+            process.schedule_restart();
+            let restart_at = match process.supervision {
+                Supervision::Crashed(restart_at) => restart_at,
+                ref supervision => panic!("Unexpected supervision: {supervision:?}"),
+            };
+            assert!(restart_at > previous);
+            previous = restart_at;
+            assert_eq!(process.process_status(), ProcessStatus::Crashed);
+        }
+
+        // Crash at the limit gives up, and the failure is published
+        process.schedule_restart();
+        assert!(matches!(process.supervision, Supervision::Failed));
+        assert!(process.supervise().is_none());
+        process.publish_status();
+        assert_eq!(
+            status_rx.recv().await.unwrap().status,
+            ProcessStatus::Failed
+        );
+
+        // Manual stop clears the crash history
+        process.stop().await.unwrap();
+        assert_eq!(process.crashes, 0);
+        assert!(matches!(process.supervision, Supervision::None));
+    }
+}
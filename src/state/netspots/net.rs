@@ -1,10 +1,24 @@
-use crate::structures::statistics::{AlarmMessage, DataMessage, Message};
+use crate::state::database::Database;
+use crate::state::messages::MessageBus;
+use crate::state::netspots::replay::SharedReplayTime;
+use crate::state::netspots::{source_by_pid, SharedProcesses};
+use crate::state::severity::SeverityScorer;
+use crate::state::suppressions::Suppressor;
+use crate::structures::statistics::{AlarmMessage, DataMessage, Message, MessageType};
+use crate::structures::status::{ConnectionStatus, ListenerStatus, MessageCounters};
 use crate::tasks::RunChecker;
 use std::os::unix::io::AsRawFd;
 use std::path::{Path, PathBuf};
-use tokio::io::{AsyncBufReadExt, BufReader};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncReadExt;
 use tokio::net::{UnixListener, UnixStream};
-use tokio::sync::broadcast;
+
+// Largest accepted JSON message. Netspot messages are well below one kilobyte.
+const MAX_FRAME_SIZE: usize = 64 * 1024;
+
+// Listener status is shared between the listener, its connections and the netspot manager
+pub type SharedListenerStatus = Arc<Mutex<ListenerStatus>>;
 
 // Socket use decides location for the Unix socket file
 #[derive(Copy, Clone)]
@@ -13,12 +27,60 @@ pub enum SocketUse {
     Data,
 }
 
+impl SocketUse {
+    fn name(&self) -> &'static str {
+        match self {
+            SocketUse::Alarm => "Alarm",
+            SocketUse::Data => "Data",
+        }
+    }
+}
+
+// Alarms are given their severity and checked against the suppression rules before they are sent.
+// Messages from the replays are stored instead of sending them.
+#[derive(Clone)]
+pub struct MessageSender {
+    pub message_tx: MessageBus,
+    pub database: Database,
+    pub scorer: SeverityScorer,
+    pub suppressor: Suppressor,
+}
+
+// Source of the messages received from a connection
+pub enum MessageSource {
+    Netspot {
+        config_id: i32,
+    },
+    Replay {
+        config_id: i32,
+        job_id: i32,
+        latest: SharedReplayTime,
+    },
+}
+
+impl MessageSource {
+    fn config_id(&self) -> i32 {
+        match self {
+            MessageSource::Netspot { config_id } => *config_id,
+            MessageSource::Replay { config_id, .. } => *config_id,
+        }
+    }
+
+    fn job_id(&self) -> Option<i32> {
+        match self {
+            MessageSource::Netspot { .. } => None,
+            MessageSource::Replay { job_id, .. } => Some(*job_id),
+        }
+    }
+}
+
 pub fn start_listener_task(
     data_path: &Path,
     socket_use: SocketUse,
-    message_tx: broadcast::Sender<Message>,
+    sender: MessageSender,
+    processes: SharedProcesses,
     run_checker: RunChecker,
-) -> Result<(), String> {
+) -> Result<SharedListenerStatus, String> {
     let mut socket_path = PathBuf::from(data_path);
     socket_path.push(match socket_use {
         SocketUse::Alarm => "netspot_alarm.socket",
@@ -39,36 +101,55 @@ pub fn start_listener_task(
         Err(err) => return Err(err.to_string()),
     };
 
-    let name = match socket_use {
-        SocketUse::Alarm => "Alarm",
-        SocketUse::Data => "Data",
-    };
+    let status = Arc::new(Mutex::new(ListenerStatus {
+        socket: match socket_use {
+            SocketUse::Alarm => MessageType::Alarm,
+            SocketUse::Data => MessageType::Data,
+        },
+        messages: MessageCounters::default(),
+        connections: Vec::new(),
+    }));
 
     // Start listener task
     tokio::spawn(listener_task(
         listener,
         socket_use,
-        message_tx,
-        name,
+        sender,
+        status.clone(),
+        processes,
         run_checker,
     ));
 
-    Ok(())
+    Ok(status)
 }
 
 async fn listener_task(
     listener: UnixListener,
     socket_use: SocketUse,
-    message_tx: broadcast::Sender<Message>,
-    name: &'static str,
+    sender: MessageSender,
+    status: SharedListenerStatus,
+    processes: SharedProcesses,
     mut run_checker: RunChecker,
 ) {
+    let name = socket_use.name();
     println!("{name} socket listener started.");
+    let mut next_connection_id = 1;
     while run_checker.keep_running() {
         tokio::select! {
             result = listener.accept() => {
                 match result {
-                    Ok((stream, _)) => { tokio::spawn(handle_connection(stream, socket_use, message_tx.clone(), name, run_checker.clone())); }
+                    Ok((stream, _)) => {
+                        tokio::spawn(handle_connection(
+                            stream,
+                            next_connection_id,
+                            socket_use,
+                            sender.clone(),
+                            status.clone(),
+                            processes.clone(),
+                            run_checker.clone(),
+                        ));
+                        next_connection_id += 1;
+                    }
                     Err(err) => {
                         eprintln!("Listener error: {}", err);
                         break;
@@ -82,24 +163,63 @@ async fn listener_task(
 }
 
 async fn handle_connection(
-    stream: UnixStream,
+    mut stream: UnixStream,
+    id: u64,
     socket_use: SocketUse,
-    message_tx: broadcast::Sender<Message>,
-    name: &'static str,
+    sender: MessageSender,
+    status: SharedListenerStatus,
+    processes: SharedProcesses,
     mut run_checker: RunChecker,
 ) {
+    let name = socket_use.name();
     let fd = stream.as_raw_fd();
     println!("{} connection in file descriptor {} connected.", name, fd);
-    let mut reader = BufReader::new(stream);
-    let mut buffer = Vec::new();
+
+    // Every netspot process opens its own connection, so the peer process id tells us which
+    // configuration or replay the messages belong to
+    let pid = match stream.peer_cred() {
+        Ok(credentials) => credentials.pid().map(|pid| pid as u32),
+        Err(err) => {
+            eprintln!("Could not read {name} connection credentials: {err}");
+            None
+        }
+    };
+    let mut source = None;
+
+    let connected = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos() as i64;
+    status.lock().unwrap().connections.push(ConnectionStatus {
+        id,
+        connected,
+        messages: MessageCounters::default(),
+    });
+
+    let mut framer = JsonFramer::default();
+    let mut buffer = [0u8; 4096];
     while run_checker.keep_running() {
         tokio::select! {
-            result = reader.read_until(b'}', &mut buffer) => {
+            result = stream.read(&mut buffer) => {
                 match result {
                     Ok(0) => break, // Disconnected
-                    Ok(_) => {
-                        parse_and_send(&socket_use, &buffer, &message_tx);
-                        buffer.clear();
+                    Ok(count) => {
+                        let frames = framer.push(&buffer[..count]);
+                        // The process is looked up lazily, as the connection may be accepted
+                        // before the manager has recorded the process it belongs to
+                        if let (None, Some(pid), false) = (&source, pid, frames.is_empty()) {
+                            source = source_by_pid(&processes, pid).await;
+                        }
+                        for frame in frames {
+                            let result = match frame {
+                                Frame::Object(json) => {
+                                    parse_and_send(&socket_use, &json, source.as_ref(), &sender)
+                                }
+                                Frame::Oversized => FrameResult::Oversized,
+                                Frame::Garbage => FrameResult::Malformed,
+                            };
+                            update_counters(&status, id, result);
+                        }
                     },
                     Err(err) => {
                         eprintln!("Unexpected error: {}.", err);
@@ -110,32 +230,262 @@ async fn handle_connection(
             _ = run_checker.shutdown_recv() => {},
         }
     }
+
+    status
+        .lock()
+        .unwrap()
+        .connections
+        .retain(|connection| connection.id != id);
     println!(
         "{} connection in file descriptor {} disconnected.",
         name, fd
     );
 }
 
+#[derive(Copy, Clone)]
+enum FrameResult {
+    Parsed,
+    Malformed,
+    Oversized,
+}
+
+fn update_counters(status: &SharedListenerStatus, id: u64, result: FrameResult) {
+    fn increment(counters: &mut MessageCounters, result: FrameResult) {
+        match result {
+            FrameResult::Parsed => counters.parsed += 1,
+            FrameResult::Malformed => counters.malformed += 1,
+            FrameResult::Oversized => counters.oversized += 1,
+        }
+    }
+
+    let mut status = status.lock().unwrap();
+    increment(&mut status.messages, result);
+    if let Some(connection) = status.connections.iter_mut().find(|c| c.id == id) {
+        increment(&mut connection.messages, result);
+    }
+}
+
 fn parse_and_send(
     socket_use: &SocketUse,
     json_bytes: &[u8],
-    message_tx: &broadcast::Sender<Message>,
-) {
-    // Try to convert u8 vector to str
-    if let Ok(json) = std::str::from_utf8(json_bytes) {
-        match socket_use {
-            SocketUse::Alarm => {
-                if let Ok(message) = serde_json::from_str::<AlarmMessage>(json) {
-                    let _ = message_tx.send(Message::Alarm(Box::new(message)));
+    source: Option<&MessageSource>,
+    sender: &MessageSender,
+) -> FrameResult {
+    let config_id = source.map(MessageSource::config_id);
+    let job_id = source.and_then(MessageSource::job_id);
+    let message = match socket_use {
+        SocketUse::Alarm => {
+            serde_json::from_slice::<AlarmMessage>(json_bytes).map(|mut message| {
+                message.config_id = config_id;
+                message.job_id = job_id;
+                sender.scorer.apply(&mut message);
+                sender.suppressor.apply(&mut message);
+                Message::Alarm(Box::new(message))
+            })
+        }
+        SocketUse::Data => serde_json::from_slice::<DataMessage>(json_bytes).map(|mut message| {
+            message.config_id = config_id;
+            message.job_id = job_id;
+            Message::Data(Box::new(message))
+        }),
+    };
+    match (message, source) {
+        (Ok(message), Some(MessageSource::Replay { latest, .. })) => {
+            {
+                let mut latest = latest.lock().unwrap();
+                *latest = (*latest).max(Some(message.time()));
+            }
+            sender.database.add_message(message);
+            FrameResult::Parsed
+        }
+        (Ok(message), _) => {
+            let _ = sender.message_tx.send(message);
+            FrameResult::Parsed
+        }
+        (Err(err), _) => {
+            let preview = String::from_utf8_lossy(&json_bytes[..json_bytes.len().min(100)]);
+            eprintln!("Warning: Received malformed message from netspot ({err}): {preview}");
+            FrameResult::Malformed
+        }
+    }
+}
+
+// JSON framing
+//--------------------------------------------------------------------------------------------------
+
+// Frames found from the byte stream
+#[derive(Debug, PartialEq, Eq)]
+enum Frame {
+    Object(Vec<u8>), // Complete JSON object, which may still fail to parse
+    Oversized,       // Object was larger than MAX_FRAME_SIZE and was discarded
+    Garbage,         // Bytes outside of any JSON object
+}
+
+// Splits a stream of concatenated or newline delimited JSON objects into separate objects.
+// Only nesting and strings are tracked here, the actual parsing is left for serde_json.
+#[derive(Default)]
+struct JsonFramer {
+    buffer: Vec<u8>,
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+    oversized: bool,
+    garbage: bool,
+}
+
+impl JsonFramer {
+    fn push(&mut self, bytes: &[u8]) -> Vec<Frame> {
+        let mut frames = Vec::new();
+        for &byte in bytes {
+            if self.depth == 0 {
+                self.push_outside(byte, &mut frames);
+                continue;
+            }
+
+            if self.oversized {
+                // Newline ends the discarded frame, so that we can recover from a truncated
+                // message without waiting for its braces to balance
+                if byte == b'\n' && !self.in_string {
+                    self.reset();
+                    frames.push(Frame::Oversized);
+                    continue;
+                }
+            } else {
+                self.buffer.push(byte);
+                if self.buffer.len() > MAX_FRAME_SIZE {
+                    self.oversized = true;
+                    self.buffer = Vec::new();
                 }
             }
-            SocketUse::Data => {
-                if let Ok(message) = serde_json::from_str::<DataMessage>(json) {
-                    let _ = message_tx.send(Message::Data(Box::new(message)));
+
+            if self.in_string {
+                if self.escaped {
+                    self.escaped = false;
+                } else if byte == b'\\' {
+                    self.escaped = true;
+                } else if byte == b'"' {
+                    self.in_string = false;
+                }
+                continue;
+            }
+
+            match byte {
+                b'"' => self.in_string = true,
+                b'{' | b'[' => self.depth += 1,
+                b'}' | b']' => {
+                    self.depth -= 1;
+                    if self.depth == 0 {
+                        if self.oversized {
+                            frames.push(Frame::Oversized);
+                        } else {
+                            frames.push(Frame::Object(std::mem::take(&mut self.buffer)));
+                        }
+                        self.reset();
+                    }
                 }
+                _ => {}
             }
         }
-    } else {
-        eprintln!("Warning: Received invalid UTF-8 from netspot");
+        frames
+    }
+
+    fn push_outside(&mut self, byte: u8, frames: &mut Vec<Frame>) {
+        match byte {
+            b'{' => {
+                if self.garbage {
+                    frames.push(Frame::Garbage);
+                    self.garbage = false;
+                }
+                self.depth = 1;
+                self.buffer.push(byte);
+            }
+            b'\n' => {
+                if self.garbage {
+                    frames.push(Frame::Garbage);
+                    self.garbage = false;
+                }
+            }
+            byte if byte.is_ascii_whitespace() => {}
+            _ => self.garbage = true,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.buffer.clear();
+        self.depth = 0;
+        self.in_string = false;
+        self.escaped = false;
+        self.oversized = false;
+    }
+}
+
+// Unit tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn object(json: &str) -> Frame {
+        Frame::Object(json.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn concatenated_and_newline_delimited() {
+        let mut framer = JsonFramer::default();
+        let frames = framer.push(b"{\"a\":1}{\"b\":2}\n{\"c\":3}\n");
+        assert_eq!(
+            frames,
+            vec![
+                object(r#"{"a":1}"#),
+                object(r#"{"b":2}"#),
+                object(r#"{"c":3}"#)
+            ]
+        );
+    }
+
+    #[test]
+    fn nested_objects_and_strings() {
+        let mut framer = JsonFramer::default();
+        let json = r#"{"name":"brace } and \" quote","nested":{"list":[{"x":"{"}]}}"#;
+        let frames = framer.push(json.as_bytes());
+        assert_eq!(frames, vec![object(json)]);
+    }
+
+    #[test]
+    fn split_over_multiple_reads() {
+        let mut framer = JsonFramer::default();
+        assert!(framer.push(br#"{"name":"te"#).is_empty());
+        assert!(framer.push(br#"st}"#).is_empty());
+        let frames = framer.push(br#""}"#);
+        assert_eq!(frames, vec![object(r#"{"name":"test}"}"#)]);
+    }
+
+    #[test]
+    fn garbage_between_objects() {
+        let mut framer = JsonFramer::default();
+        let frames = framer.push(b"not json\n{\"a\":1} trailing {\"b\":2}");
+        assert_eq!(
+            frames,
+            vec![
+                Frame::Garbage,
+                object(r#"{"a":1}"#),
+                Frame::Garbage,
+                object(r#"{"b":2}"#)
+            ]
+        );
+    }
+
+    #[test]
+    fn oversized_frames() {
+        let mut framer = JsonFramer::default();
+        let large = format!(r#"{{"value":"{}"}}"#, "x".repeat(MAX_FRAME_SIZE));
+        let frames = framer.push(large.as_bytes());
+        assert_eq!(frames, vec![Frame::Oversized]);
+
+        // Truncated oversized message is recovered from at the next line
+        let truncated = format!("{{\"value\":{}\n{{\"a\":1}}", "1".repeat(MAX_FRAME_SIZE));
+        let frames = framer.push(truncated.as_bytes());
+        assert_eq!(frames, vec![Frame::Oversized, object(r#"{"a":1}"#)]);
     }
 }
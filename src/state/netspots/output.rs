@@ -0,0 +1,202 @@
+use crate::structures::logs::{LogLine, LogLines, LogStream};
+use std::collections::VecDeque;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::Child;
+use tokio::sync::{broadcast, mpsc};
+
+// Number of output lines kept in memory for each netspot process
+const BUFFER_LINES: usize = 1000;
+
+// Log file is rotated when it grows larger than this
+const LOG_FILE_MAX_SIZE: u64 = 1024 * 1024;
+
+// Captured stdout and stderr of a netspot process. The same output is kept over restarts, so
+// that we can still see why the previous process crashed.
+#[derive(Clone, Debug)]
+pub struct ProcessOutput {
+    buffer: Arc<Mutex<VecDeque<LogLine>>>,
+    id: i32,
+    lines_tx: broadcast::Sender<LogLine>,
+    log_tx: Option<mpsc::UnboundedSender<LogLine>>,
+}
+
+impl ProcessOutput {
+    pub fn new(data_path: &Path, id: i32, log_to_file: bool) -> ProcessOutput {
+        // Log file is written by its own task, so that reading the output does not wait for it
+        let log_tx = if log_to_file {
+            let mut path = PathBuf::from(data_path);
+            path.push(format!("netspot_{id}.log"));
+            let (log_tx, log_rx) = mpsc::unbounded_channel();
+            tokio::spawn(log_file_writer(path, id, log_rx));
+            Some(log_tx)
+        } else {
+            None
+        };
+        let (lines_tx, _) = broadcast::channel(64);
+        ProcessOutput {
+            buffer: Arc::new(Mutex::new(VecDeque::with_capacity(BUFFER_LINES))),
+            id,
+            lines_tx,
+            log_tx,
+        }
+    }
+
+    // Takes stdout and stderr pipes from the child and starts reading them
+    pub fn capture(&self, process: &mut Child) {
+        if let Some(stdout) = process.stdout.take() {
+            tokio::spawn(read_output(stdout, LogStream::Stdout, self.clone()));
+        }
+        if let Some(stderr) = process.stderr.take() {
+            tokio::spawn(read_output(stderr, LogStream::Stderr, self.clone()));
+        }
+    }
+
+    // Returns the last `count` lines
+    pub fn lines(&self, count: usize) -> LogLines {
+        let buffer = self.buffer.lock().unwrap();
+        let skip = buffer.len().saturating_sub(count);
+        buffer.iter().skip(skip).cloned().collect()
+    }
+
+    // Returns the last `count` lines and receiver for the lines that come after them
+    pub fn follow(&self, count: usize) -> (LogLines, broadcast::Receiver<LogLine>) {
+        // Holding the buffer lock so that no line is missed or repeated between the two
+        let buffer = self.buffer.lock().unwrap();
+        let skip = buffer.len().saturating_sub(count);
+        let lines = buffer.iter().skip(skip).cloned().collect();
+        (lines, self.lines_tx.subscribe())
+    }
+
+    fn push(&self, line: LogLine) {
+        let mut buffer = self.buffer.lock().unwrap();
+        if let Some(log_tx) = &self.log_tx {
+            let _ = log_tx.send(line.clone());
+        }
+        if buffer.len() == BUFFER_LINES {
+            buffer.pop_front();
+        }
+        buffer.push_back(line.clone());
+        // Sending fails only when nobody is following, which is fine
+        let _ = self.lines_tx.send(line);
+    }
+}
+
+// Lines are read as bytes, because netspot may write invalid UTF-8, and the pipe must be kept
+// open so that netspot is not killed by writing to a closed pipe
+async fn read_output<R: AsyncRead + Unpin>(reader: R, stream: LogStream, output: ProcessOutput) {
+    let mut reader = BufReader::new(reader);
+    let mut bytes = Vec::new();
+    loop {
+        bytes.clear();
+        match reader.read_until(b'\n', &mut bytes).await {
+            Ok(0) => break, // Process closed the pipe
+            Ok(_) => {
+                let line = String::from_utf8_lossy(&bytes);
+                let line = line
+                    .strip_suffix('\n')
+                    .map(|line| line.strip_suffix('\r').unwrap_or(line))
+                    .unwrap_or(&line)
+                    .to_string();
+                // Keeping the output in the server log too, but now with the configuration id
+                match stream {
+                    LogStream::Stdout => println!("Netspot {}: {}", output.id, line),
+                    LogStream::Stderr => eprintln!("Netspot {}: {}", output.id, line),
+                }
+                let time = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_nanos() as i64;
+                output.push(LogLine {
+                    time,
+                    stream: stream.clone(),
+                    line,
+                });
+            }
+            Err(err) => {
+                eprintln!("Could not read netspot {} output: {}", output.id, err);
+                break;
+            }
+        }
+    }
+}
+
+// Writes the lines to the log file in the order they were read. Lines that arrive during a write
+// are written together with the next one.
+async fn log_file_writer(path: PathBuf, id: i32, mut log_rx: mpsc::UnboundedReceiver<LogLine>) {
+    while let Some(line) = log_rx.recv().await {
+        let mut lines = vec![line];
+        while let Ok(line) = log_rx.try_recv() {
+            lines.push(line);
+        }
+        let path = path.clone();
+        match tokio::task::spawn_blocking(move || append_to_file(&path, &lines)).await {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => eprintln!("Could not write netspot {id} log file: {err}"),
+            Err(err) => eprintln!("Could not write netspot {id} log file: {err}"),
+        }
+    }
+}
+
+fn append_to_file(path: &Path, lines: &[LogLine]) -> std::io::Result<()> {
+    // Keeping one rotated file next to the current one
+    if let Ok(metadata) = fs::metadata(path) {
+        if metadata.len() > LOG_FILE_MAX_SIZE {
+            let mut rotated = path.as_os_str().to_owned();
+            rotated.push(".1");
+            fs::rename(path, rotated)?;
+        }
+    }
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    let mut text = String::new();
+    for line in lines {
+        let stream = match line.stream {
+            LogStream::Stdout => "stdout",
+            LogStream::Stderr => "stderr",
+        };
+        text.push_str(&format!("{} {} {}\n", line.time, stream, line.line));
+    }
+    file.write_all(text.as_bytes())
+}
+
+// Unit tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn invalid_utf8() {
+        let test_dir = TempDir::new().expect("temporary directory");
+        let output = ProcessOutput::new(test_dir.path(), 1, true);
+
+        // Invalid bytes are replaced, and the lines after them are still read
+        let bytes: &[u8] = b"first \xff line\r\nsecond line\nlast";
+        read_output(bytes, LogStream::Stdout, output.clone()).await;
+        let lines: Vec<String> = output.lines(10).into_iter().map(|line| line.line).collect();
+        assert_eq!(lines, ["first \u{fffd} line", "second line", "last"]);
+
+        // Log file gets the same lines once the writer has caught up
+        let path = test_dir.path().join("netspot_1.log");
+        for _ in 0..100 {
+            let text = fs::read_to_string(&path).unwrap_or_default();
+            if text.lines().count() == 3 {
+                assert!(text.lines().all(|line| line.contains(" stdout ")));
+                assert!(text.ends_with(" stdout last\n"));
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("Log file did not get the lines");
+    }
+}
@@ -0,0 +1,297 @@
+use crate::state::database::Database;
+use crate::structures::replays::{Pcap, Replay, ReplayStatus};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::process::Child;
+use tokio::sync::{oneshot, RwLock};
+use tokio::time;
+
+// How often the progress of a running replay is stored
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+// Time of the newest message from the replay, updated by the socket connections
+pub type SharedReplayTime = Arc<Mutex<Option<i64>>>;
+
+// Job id is mapped to the running replay
+pub type SharedReplays = Arc<RwLock<HashMap<i32, ReplayProcess>>>;
+
+// Running replay, which the socket connections find by its process id
+pub struct ReplayProcess {
+    pub config_id: i32,
+    pub pid: Option<u32>,
+    pub latest: SharedReplayTime,
+    stop_tx: Option<oneshot::Sender<Option<String>>>,
+}
+
+impl ReplayProcess {
+    // Reason is stored as the error of the cancelled replay
+    pub fn stop(&mut self, reason: Option<String>) {
+        if let Some(stop_tx) = self.stop_tx.take() {
+            let _ = stop_tx.send(reason);
+        }
+    }
+}
+
+// Replay followed by the replay task
+pub struct ReplayJob {
+    pub replay: Replay,
+    pcap: Pcap,
+    toml_file_path: PathBuf,
+    latest: SharedReplayTime,
+    stop_rx: oneshot::Receiver<Option<String>>,
+}
+
+impl ReplayJob {
+    pub fn new(
+        replay: Replay,
+        pcap: Pcap,
+        toml_file_path: PathBuf,
+        pid: Option<u32>,
+    ) -> (ReplayJob, ReplayProcess) {
+        let latest = SharedReplayTime::default();
+        let (stop_tx, stop_rx) = oneshot::channel();
+        let process = ReplayProcess {
+            config_id: replay.config_id,
+            pid,
+            latest: latest.clone(),
+            stop_tx: Some(stop_tx),
+        };
+        let job = ReplayJob {
+            replay,
+            pcap,
+            toml_file_path,
+            latest,
+            stop_rx,
+        };
+        (job, process)
+    }
+
+    fn update_progress(&mut self) {
+        if let Some(time) = *self.latest.lock().unwrap() {
+            self.replay.progress = self.pcap.progress(time);
+        }
+    }
+
+    fn finish(&mut self, status: ReplayStatus, error: Option<String>) {
+        match status {
+            ReplayStatus::Completed => self.replay.progress = 1.0,
+            _ => self.update_progress(),
+        }
+        self.replay.status = status;
+        self.replay.finished = Some(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_nanos() as i64,
+        );
+        self.replay.error = error;
+    }
+}
+
+// Waits for netspot to go through the capture file, storing the progress on the way
+pub async fn replay_task(
+    mut job: ReplayJob,
+    mut process: Child,
+    database: Database,
+    replays_lock: SharedReplays,
+) {
+    let id = job.replay.id;
+    println!("Replay {id} started.");
+    let mut interval = time::interval(PROGRESS_INTERVAL);
+    let (status, error) = loop {
+        tokio::select! {
+            exit = process.wait() => {
+                break match exit {
+                    Ok(exit_status) if exit_status.success() => (ReplayStatus::Completed, None),
+                    Ok(exit_status) => (
+                        ReplayStatus::Failed,
+                        Some(format!("Netspot exited with {exit_status}")),
+                    ),
+                    Err(err) => (ReplayStatus::Failed, Some(err.to_string())),
+                };
+            }
+            reason = &mut job.stop_rx => {
+                let _ = process.kill().await;
+                break (ReplayStatus::Cancelled, reason.unwrap_or_default());
+            }
+            _ = interval.tick() => {
+                job.update_progress();
+                if let Err(err) = database.set_replay(&job.replay) {
+                    eprintln!("Could not store progress of replay {id}: {err}");
+                }
+            }
+        }
+    };
+    job.finish(status, error);
+    if let Err(err) = database.set_replay(&job.replay) {
+        eprintln!("Could not store replay {id}: {err}");
+    }
+    replays_lock.write().await.remove(&id);
+    if let Err(err) = fs::remove_file(&job.toml_file_path) {
+        eprintln!("Could not remove replay {id} configuration file: {err}");
+    }
+    println!("Replay {id} finished: {status:?}.");
+}
+
+// Unit tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::metrics::Metrics;
+    use crate::tasks::RunChecker;
+    use tempfile::TempDir;
+    use tokio::process::Command;
+    use tokio::sync::{broadcast, watch};
+
+    // Starts the replay task for the program, which stands in for netspot
+    async fn start(
+        database: &Database,
+        replays_lock: &SharedReplays,
+        test_dir: &TempDir,
+        program: &str,
+        args: &[&str],
+    ) -> (i32, PathBuf) {
+        let mut replay = Replay {
+            id: 0,
+            config_id: 2,
+            pcap_id: 3,
+            status: ReplayStatus::Running,
+            started: 0,
+            finished: None,
+            progress: 0.0,
+            error: None,
+        };
+        replay.id = database.add_replay(&replay).unwrap();
+        let pcap = Pcap {
+            first_packet: Some(1000),
+            last_packet: Some(2000),
+            ..Pcap::default()
+        };
+        let toml_file_path = test_dir.path().join(format!("replay_{}.toml", replay.id));
+        fs::write(&toml_file_path, "").unwrap();
+        let process = Command::new(program)
+            .args(args)
+            .kill_on_drop(true)
+            .spawn()
+            .unwrap();
+        let id = replay.id;
+        let (job, replay_process) = ReplayJob::new(replay, pcap, toml_file_path.clone(), None);
+        replays_lock.write().await.insert(id, replay_process);
+        tokio::spawn(replay_task(
+            job,
+            process,
+            database.clone(),
+            replays_lock.clone(),
+        ));
+        (id, toml_file_path)
+    }
+
+    // Polls the stored replay until the condition holds
+    async fn wait_replay(database: &Database, id: i32, condition: fn(&Replay) -> bool) -> Replay {
+        for _ in 0..100 {
+            if let Some(replay) = database.get_replay(id) {
+                if condition(&replay) {
+                    return replay;
+                }
+            }
+            time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("Replay {id} did not reach the expected state");
+    }
+
+    #[tokio::test]
+    async fn replay_statuses() {
+        let test_dir = TempDir::new().expect("temporary directory");
+        let url = test_dir.path().join("test.db");
+        let (run_tx, run_rx) = watch::channel(true);
+        let (_, message_rx) = broadcast::channel(1);
+        let database = Database::new(
+            url.to_str().unwrap(),
+            Metrics::new().receiver("test", message_rx),
+            RunChecker::new(run_rx),
+        )
+        .unwrap();
+        let replays_lock = SharedReplays::default();
+
+        // Running replay stores its progress until it is stopped
+        let (id, toml_file_path) =
+            start(&database, &replays_lock, &test_dir, "sleep", &["30"]).await;
+        *replays_lock.read().await[&id].latest.lock().unwrap() = Some(1500);
+        let replay = wait_replay(&database, id, |replay| replay.progress == 0.5).await;
+        assert_eq!(replay.status, ReplayStatus::Running);
+        assert_eq!(replay.finished, None);
+        replays_lock
+            .write()
+            .await
+            .get_mut(&id)
+            .unwrap()
+            .stop(Some("Stopped".to_string()));
+        let replay = wait_replay(&database, id, |replay| replay.finished.is_some()).await;
+        assert_eq!(replay.status, ReplayStatus::Cancelled);
+        assert_eq!(replay.progress, 0.5);
+        assert_eq!(replay.error, Some("Stopped".to_string()));
+        assert!(!replays_lock.read().await.contains_key(&id));
+        assert!(!toml_file_path.exists());
+
+        // Replay is completed when netspot exits successfully
+        let (id, toml_file_path) = start(&database, &replays_lock, &test_dir, "true", &[]).await;
+        let replay = wait_replay(&database, id, |replay| replay.finished.is_some()).await;
+        assert_eq!(replay.status, ReplayStatus::Completed);
+        assert_eq!(replay.progress, 1.0);
+        assert_eq!(replay.error, None);
+        assert!(!toml_file_path.exists());
+
+        // Replay fails when netspot exits with an error
+        let (id, _) = start(&database, &replays_lock, &test_dir, "false", &[]).await;
+        let replay = wait_replay(&database, id, |replay| replay.finished.is_some()).await;
+        assert_eq!(replay.status, ReplayStatus::Failed);
+        assert_eq!(replay.progress, 0.0);
+        assert!(replay
+            .error
+            .unwrap()
+            .starts_with("Netspot exited with exit status: 1"));
+        drop(run_tx);
+    }
+
+    #[test]
+    fn replay_progress() {
+        let replay = Replay {
+            id: 1,
+            config_id: 2,
+            pcap_id: 3,
+            status: ReplayStatus::Running,
+            started: 0,
+            finished: None,
+            progress: 0.0,
+            error: None,
+        };
+        let pcap = Pcap {
+            first_packet: Some(1000),
+            last_packet: Some(2000),
+            ..Pcap::default()
+        };
+        let (mut job, process) = ReplayJob::new(replay, pcap, PathBuf::new(), Some(10));
+        assert_eq!(process.config_id, 2);
+
+        // Progress follows the newest message of the replay
+        job.update_progress();
+        assert_eq!(job.replay.progress, 0.0);
+        *process.latest.lock().unwrap() = Some(1500);
+        job.update_progress();
+        assert_eq!(job.replay.progress, 0.5);
+
+        // Failed replay keeps its progress, and completed replay is done
+        job.finish(ReplayStatus::Failed, Some("Error".to_string()));
+        assert_eq!(job.replay.progress, 0.5);
+        assert_eq!(job.replay.status, ReplayStatus::Failed);
+        assert!(job.replay.finished.is_some());
+        job.finish(ReplayStatus::Completed, None);
+        assert_eq!(job.replay.progress, 1.0);
+        assert_eq!(job.replay.error, None);
+    }
+}
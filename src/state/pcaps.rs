@@ -0,0 +1,71 @@
+use crate::structures::replays::Pcap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::{fs, io};
+
+// Uploaded capture files are kept in their own directory next to the database, named by their ids
+#[derive(Clone)]
+pub struct PcapStore {
+    path: PathBuf,
+    next_upload: Arc<AtomicU64>,
+}
+
+impl PcapStore {
+    pub fn new(path: &Path) -> Result<PcapStore, String> {
+        if let Err(err) = fs::create_dir_all(path) {
+            return Err(format!("Could not create capture file path: {}", err));
+        }
+        Ok(PcapStore {
+            path: PathBuf::from(path),
+            next_upload: Arc::default(),
+        })
+    }
+
+    pub fn file_path(&self, id: i32) -> PathBuf {
+        let mut file_path = self.path.clone();
+        file_path.push(format!("{id}.pcap"));
+        file_path
+    }
+
+    // Uploads are received to a temporary file, which is kept only if it is a valid capture file
+    pub fn upload_path(&self) -> PathBuf {
+        let upload = self.next_upload.fetch_add(1, Ordering::Relaxed);
+        let mut upload_path = self.path.clone();
+        upload_path.push(format!("upload_{upload}.tmp"));
+        upload_path
+    }
+
+    pub fn keep(&self, upload_path: &Path, id: i32) -> io::Result<()> {
+        fs::rename(upload_path, self.file_path(id))
+    }
+
+    pub fn remove(&self, id: i32) -> io::Result<()> {
+        fs::remove_file(self.file_path(id))
+    }
+}
+
+// Reads the size, the number of packets and the time range of the packets from the capture file
+pub fn read_capture(path: &Path) -> Result<Pcap, String> {
+    let size = fs::metadata(path).map_err(|err| err.to_string())?.len() as i64;
+    let mut capture =
+        pcap::Capture::from_file(path).map_err(|err| format!("Not a capture file: {err}"))?;
+    let mut pcap = Pcap {
+        size,
+        ..Pcap::default()
+    };
+    loop {
+        match capture.next_packet() {
+            Ok(packet) => {
+                let ts = &packet.header.ts;
+                let time = ts.tv_sec * 1_000_000_000 + ts.tv_usec * 1_000;
+                pcap.packets += 1;
+                pcap.first_packet = Some(pcap.first_packet.map_or(time, |first| first.min(time)));
+                pcap.last_packet = Some(pcap.last_packet.map_or(time, |last| last.max(time)));
+            }
+            Err(pcap::Error::NoMorePackets) => break,
+            Err(err) => return Err(format!("Could not read capture file: {err}")),
+        }
+    }
+    Ok(pcap)
+}
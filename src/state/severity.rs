@@ -0,0 +1,91 @@
+use crate::state::database::Database;
+use crate::structures::severity::SeveritySettings;
+use crate::structures::statistics::{seconds_as_nanos, AlarmMessage, AlertStatus, Severity, Stat};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+// Repeats are counted for each configuration, stat and status
+type RepeatKey = (Option<i32>, Stat, AlertStatus);
+
+type RecentAlarms = HashMap<RepeatKey, Vec<i64>>;
+
+// Gives the alarms their severity before they are sent to the other receivers. Settings are read
+// from the database, so that changes are used from the next alarm on.
+#[derive(Clone)]
+pub struct SeverityScorer {
+    database: Database,
+    recent: Arc<Mutex<RecentAlarms>>,
+}
+
+impl SeverityScorer {
+    pub fn new(database: Database) -> SeverityScorer {
+        SeverityScorer {
+            database,
+            recent: Arc::default(),
+        }
+    }
+
+    pub fn apply(&self, alarm: &mut AlarmMessage) {
+        let settings = self.database.get_severity_settings();
+        let mut recent = self.recent.lock().unwrap();
+        alarm.severity = Some(score(&settings, &mut recent, alarm));
+    }
+}
+
+fn score(settings: &SeveritySettings, recent: &mut RecentAlarms, alarm: &AlarmMessage) -> Severity {
+    let repeats = match &settings.escalation {
+        Some(escalation) => {
+            let key = (alarm.config_id, alarm.stat.clone(), alarm.status.clone());
+            let times = recent.entry(key).or_default();
+            let window = seconds_as_nanos(escalation.window).unwrap_or(i64::MAX);
+            times.retain(|time| alarm.time.saturating_sub(*time) <= window);
+            let repeats = times.len();
+            times.push(alarm.time);
+            repeats
+        }
+        None => 0,
+    };
+    settings.severity(alarm, repeats)
+}
+
+// Unit tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structures::severity::SeverityEscalation;
+
+    #[test]
+    fn escalating() {
+        let settings = SeveritySettings {
+            bands: Vec::new(),
+            escalation: Some(SeverityEscalation {
+                window: 60,
+                repeats: 2,
+            }),
+            ..SeveritySettings::default()
+        };
+        let mut recent = RecentAlarms::new();
+        let mut severity = |time: i64, stat: Stat| {
+            let alarm = AlarmMessage {
+                time: time * 1_000_000_000,
+                config_id: Some(1),
+                stat,
+                ..AlarmMessage::default()
+            };
+            score(&settings, &mut recent, &alarm)
+        };
+
+        // Two repeats within the window raise one level
+        assert_eq!(severity(0, Stat::RSyn), Severity::Low);
+        assert_eq!(severity(10, Stat::RSyn), Severity::Low);
+        assert_eq!(severity(20, Stat::RSyn), Severity::Medium);
+        assert_eq!(severity(30, Stat::Traffic), Severity::Low);
+        assert_eq!(severity(40, Stat::RSyn), Severity::Medium);
+        assert_eq!(severity(50, Stat::RSyn), Severity::High);
+
+        // Older alarms are not repeats anymore
+        assert_eq!(severity(200, Stat::RSyn), Severity::Low);
+    }
+}
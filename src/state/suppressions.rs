@@ -0,0 +1,177 @@
+mod schedule;
+
+use crate::state::suppressions::schedule::Schedule;
+use crate::structures::statistics::AlarmMessage;
+use crate::structures::suppressions::{SuppressionRule, SuppressionRules};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+// Longer windows are rejected, because matching goes through each minute of the window
+const MAX_DURATION: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+// Rule with its schedule parsed
+struct ActiveRule {
+    id: i32,
+    rule: SuppressionRule,
+    schedule: Option<Schedule>,
+}
+
+impl ActiveRule {
+    fn matches(&self, alarm: &AlarmMessage) -> bool {
+        let rule = &self.rule;
+        if rule.expires.is_some_and(|expires| alarm.time >= expires) {
+            return false;
+        }
+        if !rule.configurations.is_empty()
+            && !alarm
+                .config_id
+                .is_some_and(|id| rule.configurations.contains(&id))
+        {
+            return false;
+        }
+        if !rule.stats.is_empty() && !rule.stats.contains(&alarm.stat) {
+            return false;
+        }
+        match (&self.schedule, rule.duration) {
+            (Some(schedule), Some(duration)) => {
+                schedule.in_window(alarm.time.div_euclid(1_000_000_000), duration)
+            }
+            _ => true,
+        }
+    }
+}
+
+// Marks the alarms matching the suppression rules before they are sent to the other receivers.
+// Rules are shared by the netspot listeners and the API.
+#[derive(Clone)]
+pub struct Suppressor {
+    rules: Arc<RwLock<Vec<ActiveRule>>>,
+}
+
+impl Suppressor {
+    pub fn new(rules: SuppressionRules) -> Suppressor {
+        let suppressor = Suppressor {
+            rules: Arc::default(),
+        };
+        suppressor.update(rules);
+        suppressor
+    }
+
+    // Rules are checked when they are created, so invalid rules are only skipped here
+    pub fn update(&self, rules: SuppressionRules) {
+        let mut active = Vec::new();
+        for (id, rule) in rules {
+            match parse_schedule(&rule) {
+                Ok(schedule) => active.push(ActiveRule { id, rule, schedule }),
+                Err(err) => eprintln!("Skipping suppression rule({id}): {err}"),
+            }
+        }
+        // The rule with the lowest id is used when several rules match
+        active.sort_by_key(|rule| rule.id);
+        *self.rules.write().unwrap() = active;
+    }
+
+    pub fn apply(&self, alarm: &mut AlarmMessage) {
+        let rules = self.rules.read().unwrap();
+        if let Some(rule) = rules.iter().find(|rule| rule.matches(alarm)) {
+            alarm.suppressed = true;
+            alarm.suppression_rule_id = Some(rule.id);
+        }
+    }
+}
+
+pub fn validate_rule(rule: &SuppressionRule) -> Result<(), String> {
+    parse_schedule(rule).map(|_| ())
+}
+
+fn parse_schedule(rule: &SuppressionRule) -> Result<Option<Schedule>, String> {
+    match (&rule.schedule, rule.duration) {
+        (Some(schedule), Some(duration)) => {
+            if duration == 0 || duration > MAX_DURATION.as_secs() {
+                return Err(format!(
+                    "Duration must be from 1 to {} seconds",
+                    MAX_DURATION.as_secs()
+                ));
+            }
+            schedule.parse::<Schedule>().map(Some)
+        }
+        (Some(_), None) => Err("Schedule requires a duration".to_string()),
+        (None, Some(_)) => Err("Duration requires a schedule".to_string()),
+        (None, None) => Ok(None),
+    }
+}
+
+// Unit tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structures::statistics::Stat;
+
+    // 2022-12-05 02:45:00 UTC as nanoseconds
+    const NIGHT: i64 = 1670208300 * 1_000_000_000;
+
+    #[test]
+    fn suppressing() {
+        let rules = SuppressionRules::from([
+            (
+                2,
+                SuppressionRule {
+                    name: "Backup".to_string(),
+                    configurations: vec![1],
+                    stats: vec![Stat::Traffic, Stat::Perf],
+                    schedule: Some("30 2 * * *".to_string()),
+                    duration: Some(3600),
+                    expires: None,
+                },
+            ),
+            (
+                3,
+                SuppressionRule {
+                    name: "Firmware update".to_string(),
+                    expires: Some(NIGHT),
+                    ..SuppressionRule::default()
+                },
+            ),
+        ]);
+        let suppressor = Suppressor::new(rules);
+        let suppressed = |time: i64, config_id: Option<i32>, stat: Stat| {
+            let mut alarm = AlarmMessage {
+                time,
+                config_id,
+                stat,
+                ..AlarmMessage::default()
+            };
+            suppressor.apply(&mut alarm);
+            assert_eq!(alarm.suppressed, alarm.suppression_rule_id.is_some());
+            alarm.suppression_rule_id
+        };
+
+        // Only the unlimited rule applies before it expires
+        let hour = 3600 * 1_000_000_000;
+        assert_eq!(suppressed(NIGHT - hour, Some(1), Stat::Traffic), Some(3));
+        assert_eq!(suppressed(NIGHT, Some(1), Stat::Traffic), Some(2));
+        assert_eq!(suppressed(NIGHT, Some(1), Stat::RSyn), None);
+        assert_eq!(suppressed(NIGHT, Some(2), Stat::Traffic), None);
+        assert_eq!(suppressed(NIGHT, None, Stat::Perf), None);
+        assert_eq!(suppressed(NIGHT + hour, Some(1), Stat::Perf), None);
+    }
+
+    #[test]
+    fn validation() {
+        let rule = |schedule: Option<&str>, duration: Option<u64>| SuppressionRule {
+            name: "Rule".to_string(),
+            schedule: schedule.map(String::from),
+            duration,
+            ..SuppressionRule::default()
+        };
+        assert!(validate_rule(&rule(None, None)).is_ok());
+        assert!(validate_rule(&rule(Some("0 3 * * 6"), Some(7200))).is_ok());
+        assert!(validate_rule(&rule(Some("0 3 * * 6"), None)).is_err());
+        assert!(validate_rule(&rule(None, Some(7200))).is_err());
+        assert!(validate_rule(&rule(Some("0 3 * * 6"), Some(0))).is_err());
+        assert!(validate_rule(&rule(Some("0 3 * * 6"), Some(8 * 24 * 3600))).is_err());
+        assert!(validate_rule(&rule(Some("0 25 * * *"), Some(60))).is_err());
+    }
+}
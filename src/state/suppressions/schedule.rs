@@ -0,0 +1,275 @@
+use std::str::FromStr;
+
+const MINUTE: i64 = 60;
+const DAY: i64 = 24 * 60 * MINUTE;
+
+// Cron expression with the fields minute, hour, day of month, month and day of week. Each field
+// is a comma separated list of `*`, single values and ranges, optionally with a step such as
+// `*/15` or `1-5/2`. Days of week are numbered from Sunday as 0, and 7 is also Sunday.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Schedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    // Like in cron, restricting both days and weekdays matches either of them. A field starting
+    // with `*`, such as `*/2`, does not count as restricted.
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl FromStr for Schedule {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = value.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(format!(
+                "Schedule must have five fields, found {}",
+                fields.len()
+            ));
+        };
+        let mut weekday_bits = parse_field(weekdays, 0, 7, "day of week")?;
+        if weekday_bits & (1 << 7) != 0 {
+            weekday_bits |= 1;
+        }
+        Ok(Schedule {
+            minutes: parse_field(minutes, 0, 59, "minute")?,
+            hours: parse_field(hours, 0, 23, "hour")?,
+            days: parse_field(days, 1, 31, "day of month")?,
+            months: parse_field(months, 1, 12, "month")?,
+            weekdays: weekday_bits,
+            any_day: days.starts_with('*'),
+            any_weekday: weekdays.starts_with('*'),
+        })
+    }
+}
+
+impl Schedule {
+    // Checks if a window of the given length started at or before the time, and is still open.
+    // Times are in seconds since Unix Epoch.
+    // The latest start is found day by day, so that the minutes of the window are not all checked.
+    pub fn in_window(&self, time: i64, duration: u64) -> bool {
+        let earliest = time - duration as i64;
+        let mut days = time.div_euclid(DAY);
+        let mut last_minute = time.rem_euclid(DAY) / MINUTE;
+        while days * DAY + last_minute * MINUTE > earliest {
+            if let Some(minute) = self.last_match(days, last_minute) {
+                return days * DAY + minute * MINUTE > earliest;
+            }
+            days -= 1;
+            last_minute = DAY / MINUTE - 1;
+        }
+        false
+    }
+
+    // Returns the latest matching minute of the day that is not after the given minute
+    fn last_match(&self, days: i64, last_minute: i64) -> Option<i64> {
+        if !self.day_matches(days) {
+            return None;
+        }
+        let last_hour = last_minute / 60;
+        let mut hours = self.hours & up_to(last_hour);
+        while let Some(hour) = highest(hours) {
+            // Only the last hour is cut short, so this goes through two hours at most
+            let minutes = if hour == last_hour {
+                self.minutes & up_to(last_minute % 60)
+            } else {
+                self.minutes
+            };
+            if let Some(minute) = highest(minutes) {
+                return Some(hour * 60 + minute);
+            }
+            hours &= !(1 << hour);
+        }
+        None
+    }
+
+    fn day_matches(&self, days: i64) -> bool {
+        let (month, day) = month_and_day(days);
+        let weekday = (days + 4).rem_euclid(7);
+        let day_matches = match (self.any_day, self.any_weekday) {
+            (false, false) => has(self.days, day) || has(self.weekdays, weekday),
+            _ => has(self.days, day) && has(self.weekdays, weekday),
+        };
+        day_matches && has(self.months, month)
+    }
+
+    // Checks if the minute starting at the time matches the schedule
+    #[cfg(test)]
+    fn matches(&self, time: i64) -> bool {
+        let seconds = time.rem_euclid(DAY);
+        self.day_matches(time.div_euclid(DAY))
+            && has(self.minutes, seconds / MINUTE % 60)
+            && has(self.hours, seconds / (60 * MINUTE))
+    }
+}
+
+fn has(bits: u64, value: i64) -> bool {
+    bits & (1 << value) != 0
+}
+
+// Bits of the values from zero to the given value
+fn up_to(value: i64) -> u64 {
+    (2 << value) - 1
+}
+
+fn highest(bits: u64) -> Option<i64> {
+    (bits != 0).then(|| 63 - bits.leading_zeros() as i64)
+}
+
+// Parses the field into bits of the allowed values
+fn parse_field(field: &str, min: u32, max: u32, name: &str) -> Result<u64, String> {
+    let invalid = || format!("Invalid {name} in schedule: {field}");
+    let mut bits = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().map_err(|_| invalid())?),
+            None => (part, 1),
+        };
+        let (first, last) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((first, last)) => (
+                first.parse::<u32>().map_err(|_| invalid())?,
+                last.parse::<u32>().map_err(|_| invalid())?,
+            ),
+            None => {
+                let value = range.parse::<u32>().map_err(|_| invalid())?;
+                // Like in cron, a single value with a step continues to the end of the range
+                (value, if part.contains('/') { max } else { value })
+            }
+        };
+        if step == 0 || first < min || last > max || first > last {
+            return Err(invalid());
+        }
+        for value in (first..=last).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+// Converts days since Unix Epoch to the month and the day of month in the Gregorian calendar
+fn month_and_day(days: i64) -> (i64, i64) {
+    let days = days + 719468;
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    };
+    (month, day)
+}
+
+// Unit tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2022-12-05 00:00:00 UTC was a Monday
+    const MONDAY: i64 = 1670198400;
+
+    #[test]
+    fn parsing() {
+        let schedule = "*/15 2,14 1-7 * 1-5".parse::<Schedule>().unwrap();
+        assert_eq!(schedule.minutes, 1 | 1 << 15 | 1 << 30 | 1 << 45);
+        assert_eq!(schedule.hours, 1 << 2 | 1 << 14);
+        assert_eq!(schedule.days, 0b1111_1110);
+        assert_eq!(schedule.weekdays, 0b11_1110);
+        assert!(!schedule.any_day && !schedule.any_weekday);
+
+        // Sunday can be both 0 and 7
+        let schedule = "0 0 * * 7".parse::<Schedule>().unwrap();
+        assert_eq!(schedule.weekdays, 1 | 1 << 7);
+
+        for invalid in [
+            "",
+            "* * * *",
+            "60 * * * *",
+            "* * 0 * *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+        ] {
+            assert!(invalid.parse::<Schedule>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn calendar() {
+        assert_eq!(month_and_day(0), (1, 1));
+        assert_eq!(month_and_day(MONDAY / DAY), (12, 5));
+        // 2024-02-29
+        assert_eq!(month_and_day(19782), (2, 29));
+        assert_eq!(month_and_day(-1), (12, 31));
+    }
+
+    #[test]
+    fn windows() {
+        // One hour window starting at 02:30 every night
+        let schedule = "30 2 * * *".parse::<Schedule>().unwrap();
+        let at = |hour: i64, minute: i64| MONDAY + hour * 3600 + minute * 60;
+        assert!(!schedule.in_window(at(2, 29), 3600));
+        assert!(schedule.in_window(at(2, 30), 3600));
+        assert!(schedule.in_window(at(3, 29) + 59, 3600));
+        assert!(!schedule.in_window(at(3, 30), 3600));
+
+        // Windows can continue past midnight
+        let schedule = "0 23 * * 1".parse::<Schedule>().unwrap();
+        assert!(schedule.in_window(MONDAY + DAY + 30 * 60, 7200));
+        assert!(!schedule.in_window(MONDAY + 2 * DAY + 30 * 60, 7200));
+
+        // Restricting both days and weekdays matches either of them
+        let schedule = "0 0 1 * 1".parse::<Schedule>().unwrap();
+        assert!(schedule.matches(MONDAY));
+        assert!(schedule.matches(MONDAY - 4 * DAY));
+        assert!(!schedule.matches(MONDAY + DAY));
+
+        // Field starting with `*` is not restricted, so both of them must match
+        let schedule = "0 0 */2 * 1".parse::<Schedule>().unwrap();
+        assert!(schedule.any_day && !schedule.any_weekday);
+        assert!(schedule.matches(MONDAY));
+        assert!(!schedule.matches(MONDAY + 2 * DAY));
+        assert!(!schedule.matches(MONDAY + 7 * DAY));
+        assert!(schedule.matches(MONDAY + 14 * DAY));
+    }
+
+    #[test]
+    fn latest_start() {
+        // Window is open if any minute within it matches, checked here minute by minute
+        let in_window = |schedule: &Schedule, time: i64, duration: i64| {
+            let mut start = time - time.rem_euclid(MINUTE);
+            while time - start < duration {
+                if schedule.matches(start) {
+                    return true;
+                }
+                start -= MINUTE;
+            }
+            false
+        };
+        for schedule in [
+            "*/20 1,13 * * *",
+            "59 23 */3 * 0",
+            "0 0 1 1 *",
+            "15-20 * 31 * 5",
+        ] {
+            let schedule = schedule.parse::<Schedule>().unwrap();
+            for duration in [60, 3600, 86400, 7 * 86400] {
+                for time in (MONDAY - 30 * DAY..MONDAY + 40 * DAY).step_by(12347) {
+                    assert_eq!(
+                        schedule.in_window(time, duration as u64),
+                        in_window(&schedule, time, duration),
+                        "{schedule:?} {time} {duration}"
+                    );
+                }
+            }
+        }
+    }
+}
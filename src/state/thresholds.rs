@@ -0,0 +1,314 @@
+use crate::state::messages::MessageBus;
+use crate::state::metrics::MessageReceiver;
+use crate::state::severity::SeverityScorer;
+use crate::state::suppressions::Suppressor;
+use crate::structures::statistics::{
+    seconds_as_nanos, AlarmMessage, DataMessage, Message, MessageType, MAX_SECONDS,
+};
+use crate::structures::thresholds::{ThresholdRule, ThresholdRules};
+use crate::tasks::RunChecker;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+// Alarms from threshold rules have this code, and the series tells the rule id
+pub const THRESHOLD_ALARM_CODE: i32 = 100;
+
+// Breaches are followed separately for each rule, configuration and series
+type BreachKey = (i32, Option<i32>, String);
+
+// Time when the condition started to hold, and whether the alarm has been sent
+struct Breach {
+    since: i64,
+    alarmed: bool,
+}
+
+#[derive(Default)]
+struct Evaluator {
+    rules: Vec<(i32, ThresholdRule)>,
+    breaches: HashMap<BreachKey, Breach>,
+}
+
+impl Evaluator {
+    // Changed rules start from a clean state
+    fn update(&mut self, rules: ThresholdRules) {
+        let mut rules: Vec<(i32, ThresholdRule)> = rules.into_iter().collect();
+        rules.sort_by_key(|(id, _)| *id);
+        self.rules = rules;
+        self.breaches.clear();
+    }
+
+    fn evaluate(&mut self, data: &DataMessage) -> Vec<AlarmMessage> {
+        let mut alarms = Vec::new();
+        for (id, rule) in &self.rules {
+            if !rule.accepts_config(data.config_id) {
+                continue;
+            }
+            let Some(value) = data.value(&rule.stat) else {
+                continue;
+            };
+            // Messages without all the values of the rule are skipped like the ones without the stat
+            let Some(conditions) = rule
+                .conditions
+                .iter()
+                .map(|condition| {
+                    data.field_value(&condition.field)
+                        .map(|field| condition.condition.holds(field, condition.value))
+                })
+                .collect::<Option<Vec<bool>>>()
+            else {
+                continue;
+            };
+            let key = (*id, data.config_id, data.series.clone());
+            if !rule.condition.holds(value, rule.value) || conditions.contains(&false) {
+                self.breaches.remove(&key);
+                continue;
+            }
+            let breach = self.breaches.entry(key).or_insert(Breach {
+                since: data.time,
+                alarmed: false,
+            });
+            let hold = seconds_as_nanos(rule.hold).unwrap_or(i64::MAX);
+            if !breach.alarmed && data.time.saturating_sub(breach.since) >= hold {
+                breach.alarmed = true;
+                alarms.push(AlarmMessage {
+                    time: data.time,
+                    name: data.name.clone(),
+                    config_id: data.config_id,
+                    series: format!("THRESHOLD {id}"),
+                    stat: rule.stat.clone(),
+                    status: rule.condition.status(),
+                    value,
+                    // The rule is certain about the value, unlike the statistical alarms
+                    probability: 0.0,
+                    code: THRESHOLD_ALARM_CODE,
+                    msg_type: MessageType::Alarm,
+                    ..AlarmMessage::default()
+                });
+            }
+        }
+        alarms
+    }
+}
+
+pub fn validate_rule(rule: &ThresholdRule) -> Result<(), String> {
+    if !rule.value.is_finite() {
+        return Err("Value must be a finite number".to_string());
+    }
+    for condition in &rule.conditions {
+        if !DataMessage::is_field(&condition.field) {
+            return Err(format!("Unknown field {}", condition.field));
+        }
+        if !condition.value.is_finite() {
+            return Err(format!(
+                "Value of {} must be a finite number",
+                condition.field
+            ));
+        }
+    }
+    if rule.hold > MAX_SECONDS {
+        return Err(format!("Hold must be at most {MAX_SECONDS} seconds"));
+    }
+    Ok(())
+}
+
+// Evaluates the threshold rules on the data messages, and sends the alarms to the same channel
+// with the netspot alarms
+pub struct ThresholdManager {
+    evaluator: Arc<Mutex<Evaluator>>,
+}
+
+impl ThresholdManager {
+    pub fn new(
+        rules: ThresholdRules,
+        scorer: SeverityScorer,
+        suppressor: Suppressor,
+        messages_tx: MessageBus,
+        message_rx: MessageReceiver,
+        run_checker: RunChecker,
+    ) -> ThresholdManager {
+        let evaluator = Arc::new(Mutex::new(Evaluator::default()));
+        evaluator.lock().unwrap().update(rules);
+        tokio::spawn(threshold_task(
+            evaluator.clone(),
+            scorer,
+            suppressor,
+            messages_tx,
+            message_rx,
+            run_checker,
+        ));
+        ThresholdManager { evaluator }
+    }
+
+    pub fn update(&self, rules: ThresholdRules) {
+        self.evaluator.lock().unwrap().update(rules);
+    }
+}
+
+async fn threshold_task(
+    evaluator: Arc<Mutex<Evaluator>>,
+    scorer: SeverityScorer,
+    suppressor: Suppressor,
+    messages_tx: MessageBus,
+    mut message_rx: MessageReceiver,
+    mut run_checker: RunChecker,
+) {
+    println!("Threshold rules started.");
+    while run_checker.keep_running() {
+        tokio::select! {
+            Ok(message) = message_rx.recv() => {
+                if let Message::Data(data) = message {
+                    let alarms = evaluator.lock().unwrap().evaluate(&data);
+                    for mut alarm in alarms {
+                        scorer.apply(&mut alarm);
+                        suppressor.apply(&mut alarm);
+                        let _ = messages_tx.send(Message::Alarm(Box::new(alarm)));
+                    }
+                }
+            }
+            _ = run_checker.shutdown_recv() => {},
+        }
+    }
+    println!("Threshold rules stopped.");
+}
+
+// Unit tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structures::statistics::{AlertStatus, Stat};
+    use crate::structures::thresholds::{FieldCondition, ThresholdCondition};
+
+    const SECOND: i64 = 1_000_000_000;
+
+    #[test]
+    fn evaluating() {
+        let mut evaluator = Evaluator::default();
+        evaluator.update(ThresholdRules::from([(
+            4,
+            ThresholdRule {
+                name: "SYN flood".to_string(),
+                configurations: vec![1],
+                stat: Stat::RSyn,
+                condition: ThresholdCondition::Above,
+                value: 0.8,
+                hold: 10,
+                ..ThresholdRule::default()
+            },
+        )]));
+        let mut data = |time: i64, config_id: Option<i32>, r_syn: Option<f64>| {
+            let data = DataMessage {
+                time: time * SECOND,
+                name: "Office".to_string(),
+                config_id,
+                series: "any".to_string(),
+                r_syn,
+                ..DataMessage::default()
+            };
+            evaluator.evaluate(&data)
+        };
+
+        // Condition has to hold for ten seconds, and the alarm is sent only once
+        assert!(data(0, Some(1), Some(0.9)).is_empty());
+        assert!(data(5, Some(1), None).is_empty());
+        assert!(data(9, Some(1), Some(0.95)).is_empty());
+        let alarms = data(10, Some(1), Some(0.85));
+        assert_eq!(alarms.len(), 1);
+        assert_eq!(alarms[0].time, 10 * SECOND);
+        assert_eq!(alarms[0].series, "THRESHOLD 4");
+        assert_eq!(alarms[0].code, THRESHOLD_ALARM_CODE);
+        assert_eq!(alarms[0].status, AlertStatus::UpAlert);
+        assert_eq!(alarms[0].value, 0.85);
+        assert!(data(20, Some(1), Some(0.9)).is_empty());
+
+        // Other configurations are not checked
+        assert!(data(0, Some(2), Some(0.9)).is_empty());
+        assert!(data(20, Some(2), Some(0.9)).is_empty());
+
+        // Returning below the threshold starts over
+        assert!(data(21, Some(1), Some(0.8)).is_empty());
+        assert!(data(22, Some(1), Some(0.9)).is_empty());
+        assert_eq!(data(32, Some(1), Some(0.9)).len(), 1);
+    }
+
+    #[test]
+    fn evaluating_conditions() {
+        let mut evaluator = Evaluator::default();
+        evaluator.update(ThresholdRules::from([(
+            1,
+            ThresholdRule {
+                name: "Upload flood".to_string(),
+                stat: Stat::RSyn,
+                value: 0.8,
+                conditions: vec![FieldCondition {
+                    field: "TRAFFIC_UP".to_string(),
+                    condition: ThresholdCondition::Above,
+                    value: 1000.0,
+                }],
+                ..ThresholdRule::default()
+            },
+        )]));
+        let mut data = |r_syn: f64, traffic_up: Option<f64>| {
+            let data = DataMessage {
+                series: "any".to_string(),
+                r_syn: Some(r_syn),
+                traffic_up,
+                ..DataMessage::default()
+            };
+            evaluator.evaluate(&data)
+        };
+
+        // All values have to cross their thresholds
+        assert!(data(0.9, Some(500.0)).is_empty());
+        assert!(data(0.5, Some(2000.0)).is_empty());
+        assert!(data(0.9, None).is_empty());
+        let alarms = data(0.9, Some(2000.0));
+        assert_eq!(alarms.len(), 1);
+        assert_eq!(alarms[0].stat, Stat::RSyn);
+        assert_eq!(alarms[0].value, 0.9);
+    }
+
+    #[test]
+    fn validation() {
+        let rule = ThresholdRule {
+            name: "Upload flood".to_string(),
+            value: 0.8,
+            conditions: vec![FieldCondition {
+                field: "TRAFFIC_UP".to_string(),
+                condition: ThresholdCondition::Above,
+                value: 1000.0,
+            }],
+            hold: MAX_SECONDS,
+            ..ThresholdRule::default()
+        };
+        assert_eq!(validate_rule(&rule), Ok(()));
+        for invalid in [
+            ThresholdRule {
+                value: f64::NAN,
+                ..rule.clone()
+            },
+            ThresholdRule {
+                hold: MAX_SECONDS + 1,
+                ..rule.clone()
+            },
+            ThresholdRule {
+                conditions: vec![FieldCondition {
+                    field: "TRAFFIC_SIDEWAYS".to_string(),
+                    ..FieldCondition::default()
+                }],
+                ..rule.clone()
+            },
+            ThresholdRule {
+                conditions: vec![FieldCondition {
+                    field: "R_SYN_UP".to_string(),
+                    value: f64::INFINITY,
+                    ..FieldCondition::default()
+                }],
+                ..rule.clone()
+            },
+        ] {
+            assert!(validate_rule(&invalid).is_err(), "{invalid:?}");
+        }
+    }
+}
@@ -1,88 +1,588 @@
-use crate::structures::statistics::Message;
-use crate::structures::webhooks::{Webhook, WebhookRequestMethod, WebhookStatsType, Webhooks};
+mod clients;
+mod template;
+
+use crate::state::database::{Database, OutboxMessage};
+use crate::state::metrics::{MessageReceiver, SharedMetrics};
+use crate::structures::statistics::{seconds_as_nanos, Message};
+use crate::structures::webhooks::{
+    Webhook, WebhookDeliveries, WebhookDelivery, WebhookError, WebhookRequestMethod, WebhookStatus,
+    Webhooks,
+};
 use crate::tasks::RunChecker;
+use clients::Clients;
+use netspot_control::signature;
+use rand::Rng;
 use reqwest::header;
-use std::sync::{Arc, RwLock};
-use tokio::sync::broadcast;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use template::Templates;
+use tokio::sync::{Notify, Semaphore};
+
+// Queued messages of a webhook are fetched this many at a time, or a full batch if it is larger
+const DELIVERY_BATCH_SIZE: i64 = 100;
+
+// Requests in flight at the same time across all webhooks
+const MAX_CONCURRENT_REQUESTS: usize = 8;
+
+// Only the beginning of the response body is kept in the delivery log
+const MAX_RESPONSE_LENGTH: usize = 1024;
+
+// Number of delivery attempts kept in the log of each webhook
+const DELIVERY_LOG_SIZE: usize = 50;
+
+// Delay before the first retry, doubled after each failed attempt up to the maximum delay
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(5 * 60);
+
+// Dead letters are kept for a week, so that there is time to find out what went wrong
+const DEAD_LETTER_RETENTION: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+const DEAD_LETTER_CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+// Templates are compiled when the webhooks are stored, and shared with the messages being rendered
+struct ActiveWebhooks {
+    webhooks: Webhooks,
+    templates: Arc<Templates>,
+}
+
+impl ActiveWebhooks {
+    fn new(webhooks: Webhooks) -> ActiveWebhooks {
+        ActiveWebhooks {
+            templates: Arc::new(Templates::new(&webhooks)),
+            webhooks,
+        }
+    }
+}
+
+type SharedWebhooks = Arc<RwLock<ActiveWebhooks>>;
+
+// Results of the latest delivery attempts are only kept in memory
+#[derive(Clone, Default)]
+struct DeliveryStatus {
+    last_success: Option<i64>,
+    last_error: Option<WebhookError>,
+    log: VecDeque<WebhookDelivery>,
+}
+
+impl DeliveryStatus {
+    fn record(&mut self, delivery: WebhookDelivery) {
+        match &delivery.error {
+            None => self.last_success = Some(delivery.time),
+            Some(error) => {
+                self.last_error = Some(WebhookError {
+                    time: delivery.time,
+                    error: error.clone(),
+                })
+            }
+        }
+        self.log.push_front(delivery);
+        self.log.truncate(DELIVERY_LOG_SIZE);
+    }
+}
+
+type SharedDeliveryStatuses = Arc<Mutex<BTreeMap<i32, DeliveryStatus>>>;
+
+fn record_delivery(delivery_statuses: &SharedDeliveryStatuses, id: i32, delivery: WebhookDelivery) {
+    delivery_statuses
+        .lock()
+        .unwrap()
+        .entry(id)
+        .or_default()
+        .record(delivery);
+}
 
 pub struct WebhookManager {
-    webhooks: Arc<RwLock<Webhooks>>,
+    webhooks: SharedWebhooks,
+    delivery_statuses: SharedDeliveryStatuses,
+    clients: Arc<Clients>,
 }
 
 impl WebhookManager {
     pub fn new(
         webhooks: Webhooks,
-        messages_rx: broadcast::Receiver<Message>,
+        database: Database,
+        messages_rx: MessageReceiver,
+        metrics: SharedMetrics,
         run_checker: RunChecker,
-    ) -> WebhookManager {
-        let webhooks = Arc::new(RwLock::new(webhooks));
-        tokio::spawn(webhook_sender_task(
+    ) -> Result<WebhookManager, String> {
+        let clients = Arc::new(Clients::new(MAX_CONCURRENT_REQUESTS)?);
+        let webhooks = Arc::new(RwLock::new(ActiveWebhooks::new(webhooks)));
+        let delivery_statuses = SharedDeliveryStatuses::default();
+        let queued = Arc::new(Notify::new());
+        tokio::spawn(webhook_queue_task(
             webhooks.clone(),
+            database.clone(),
+            delivery_statuses.clone(),
+            queued.clone(),
             messages_rx,
-            run_checker,
+            run_checker.clone(),
         ));
-        WebhookManager { webhooks }
+        let sender = Arc::new(Sender {
+            clients: clients.clone(),
+            database,
+            delivery_statuses: delivery_statuses.clone(),
+            metrics,
+            requests: Semaphore::new(MAX_CONCURRENT_REQUESTS),
+            busy: Mutex::default(),
+            queued,
+        });
+        tokio::spawn(webhook_delivery_task(webhooks.clone(), sender, run_checker));
+        Ok(WebhookManager {
+            webhooks,
+            delivery_statuses,
+            clients,
+        })
     }
 
     pub fn update(&self, webhooks: Webhooks) {
+        let webhooks = ActiveWebhooks::new(webhooks);
         if let Ok(mut webhooks_write_guard) = self.webhooks.write() {
+            self.delivery_statuses
+                .lock()
+                .unwrap()
+                .retain(|id, _| webhooks.webhooks.contains_key(id));
+            self.clients.clear();
             *webhooks_write_guard = webhooks;
         }
     }
+
+    pub fn status(&self, id: i32, database: &Database) -> Result<WebhookStatus, String> {
+        let (queued, dead_letters) = database.count_webhook_messages(id)?;
+        let delivery = self
+            .delivery_statuses
+            .lock()
+            .unwrap()
+            .get(&id)
+            .cloned()
+            .unwrap_or_default();
+        Ok(WebhookStatus {
+            queued,
+            dead_letters,
+            last_success: delivery.last_success,
+            last_error: delivery.last_error,
+        })
+    }
+
+    // Latest delivery attempts, newest first
+    pub fn deliveries(&self, id: i32) -> WebhookDeliveries {
+        match self.delivery_statuses.lock().unwrap().get(&id) {
+            Some(delivery) => delivery.log.iter().cloned().collect(),
+            None => vec![],
+        }
+    }
+
+    // Sends the message right away, bypassing the queue. The attempt is recorded in the log.
+    pub async fn send_test(
+        &self,
+        id: i32,
+        webhook: &Webhook,
+        message: &Message,
+    ) -> WebhookDelivery {
+        // Test alarms do not come from any configuration
+        let templates = self.webhooks.read().unwrap().templates.clone();
+        let mut delivery = match templates.render(id, webhook, message, None) {
+            Ok(payload) => match self.clients.get(&webhook.tls) {
+                Ok(client) => {
+                    let body = request_body(webhook, &[&payload]);
+                    send_message(&client, id, webhook, body).await
+                }
+                Err(error) => failed_delivery(error),
+            },
+            Err(error) => failed_delivery(error),
+        };
+        delivery.test = true;
+        record_delivery(&self.delivery_statuses, id, delivery.clone());
+        delivery
+    }
+}
+
+// Checks the webhook before it is saved
+pub fn validate_webhook(webhook: &Webhook) -> Result<(), String> {
+    template::validate(webhook)?;
+    if webhook.timeout_ms == Some(0) {
+        return Err("Timeout must be at least one millisecond".to_string());
+    }
+    if !webhook.tls.is_default() {
+        clients::build(&webhook.tls)?;
+    }
+    Ok(())
 }
 
-// Worker task
+// Delivery that failed before a request could be sent
+fn failed_delivery(error: String) -> WebhookDelivery {
+    WebhookDelivery {
+        time: now(),
+        test: false,
+        status: None,
+        latency_ms: 0,
+        response: None,
+        error: Some(error),
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos() as i64
+}
+
+// Data message sampling
+//--------------------------------------------------------------------------------------------------
+
+// Data messages are sampled separately for each webhook, configuration and series
+type SampleKey = (i32, Option<i32>, String, String);
+
+#[derive(Default)]
+struct DataSampler {
+    last_sent: HashMap<SampleKey, i64>,
+}
+
+impl DataSampler {
+    // Returns true when enough time has passed since the previous message sent to the webhook
+    fn accepts(&mut self, id: i32, webhook: &Webhook, message: &Message) -> bool {
+        let (interval, data) = match (webhook.filter.data_interval, message) {
+            (Some(interval), Message::Data(data)) => (interval, data),
+            _ => return true,
+        };
+        let key = (id, data.config_id, data.name.clone(), data.series.clone());
+        let interval = seconds_as_nanos(interval).unwrap_or(i64::MAX);
+        match self.last_sent.get(&key) {
+            Some(last) if data.time.saturating_sub(*last) < interval => false,
+            _ => {
+                self.last_sent.insert(key, data.time);
+                true
+            }
+        }
+    }
+
+    // Forgets webhooks that were removed or no longer sample data
+    fn retain(&mut self, webhooks: &Webhooks) {
+        self.last_sent.retain(|(id, ..), _| {
+            matches!(webhooks.get(id), Some(webhook) if webhook.filter.data_interval.is_some())
+        });
+    }
+}
+
+// Worker tasks
 //--------------------------------------------------------------------------------------------------
 
-async fn webhook_sender_task(
-    webhooks: Arc<RwLock<Webhooks>>,
-    mut message_rx: broadcast::Receiver<Message>,
+async fn webhook_queue_task(
+    webhooks: SharedWebhooks,
+    database: Database,
+    delivery_statuses: SharedDeliveryStatuses,
+    queued: Arc<Notify>,
+    mut message_rx: MessageReceiver,
     mut run_checker: RunChecker,
 ) {
-    println!("Webhook sender started.");
+    println!("Webhook queue started.");
+    let mut sampler = DataSampler::default();
+    let mut interval = tokio::time::interval(Duration::from_secs(60));
     while run_checker.keep_running() {
         tokio::select! {
-            Ok(message) = message_rx.recv() => message_handler(message, &webhooks, &run_checker),
+            Ok(message) = message_rx.recv() => {
+                message_handler(
+                    message,
+                    &webhooks,
+                    &mut sampler,
+                    &database,
+                    &delivery_statuses,
+                    &queued,
+                )
+            }
+            _ = interval.tick() => sampler.retain(&webhooks.read().unwrap().webhooks),
             _ = run_checker.shutdown_recv() => {},
         }
     }
+    println!("Webhook queue stopped.");
+}
+
+fn message_handler(
+    message: Message,
+    webhooks: &SharedWebhooks,
+    sampler: &mut DataSampler,
+    database: &Database,
+    delivery_statuses: &SharedDeliveryStatuses,
+    queued: &Notify,
+) {
+    // Lock is released before the configuration is read from the database
+    let (accepted, templates) = {
+        let active = webhooks.read().unwrap();
+        let accepted: Vec<(i32, Webhook)> = active
+            .webhooks
+            .iter()
+            .filter(|(id, webhook)| {
+                webhook.accepts(&message) && sampler.accepts(**id, webhook, &message)
+            })
+            .map(|(id, webhook)| (*id, webhook.clone()))
+            .collect();
+        (accepted, active.templates.clone())
+    };
+    if accepted.is_empty() {
+        return;
+    }
+
+    // Configuration is only needed by templates
+    let config = match message.config_id() {
+        Some(config_id)
+            if accepted
+                .iter()
+                .any(|(_, webhook)| webhook.template.is_some()) =>
+        {
+            database.get_configuration(config_id)
+        }
+        _ => None,
+    };
+
+    // Templates are rendered before queueing, so that the stored payload is what gets sent
+    let time = now();
+    let mut payloads = Vec::with_capacity(accepted.len());
+    for (id, webhook) in accepted {
+        match templates.render(id, &webhook, &message, config.as_ref()) {
+            Ok(payload) => {
+                // Batched messages wait for others until the batch is full or the delay has passed
+                let next_attempt = match &webhook.batch {
+                    Some(batch) => {
+                        time.saturating_add(seconds_as_nanos(batch.max_delay).unwrap_or(i64::MAX))
+                    }
+                    None => time,
+                };
+                payloads.push((id, payload, next_attempt));
+            }
+            Err(error) => {
+                eprintln!(
+                    "Warning: Could not make message for webhook({}) {}: {}",
+                    id, webhook.name, error
+                );
+                record_delivery(delivery_statuses, id, failed_delivery(error));
+            }
+        }
+    }
+    if payloads.is_empty() {
+        return;
+    }
+    match database.add_webhook_messages(&payloads, time) {
+        Ok(()) => queued.notify_one(),
+        Err(err) => eprintln!("Could not queue webhook messages: {err}"),
+    }
+}
+
+async fn webhook_delivery_task(
+    webhooks: SharedWebhooks,
+    sender: Arc<Sender>,
+    mut run_checker: RunChecker,
+) {
+    println!("Webhook sender started.");
+
+    // Retries and batches are due at their own time, so the queue is also checked every second
+    let mut interval = tokio::time::interval(Duration::from_secs(1));
+    let mut last_cleanup: Option<Instant> = None;
+    while run_checker.keep_running() {
+        tokio::select! {
+            _ = sender.queued.notified() => {},
+            _ = interval.tick() => {},
+            _ = run_checker.shutdown_recv() => continue,
+        }
+        let webhooks = webhooks.read().unwrap().webhooks.clone();
+        let time = now();
+
+        // Full batches are sent right away instead of waiting for the delay
+        for (id, webhook) in &webhooks {
+            let batch = match &webhook.batch {
+                Some(batch) if webhook.enabled && !sender.busy.lock().unwrap().contains(id) => {
+                    batch
+                }
+                _ => continue,
+            };
+            match sender.database.count_webhook_messages(*id) {
+                Ok((queued, _)) if queued >= batch.max_messages as i64 => {
+                    if let Err(err) = sender.database.expedite_webhook_messages(*id, time) {
+                        eprintln!("Could not update webhook messages: {err}");
+                    }
+                }
+                Ok(_) => {}
+                Err(err) => eprintln!("Could not count queued webhook messages: {err}"),
+            }
+        }
+
+        // Each webhook has at most one sender task, so a slow host only delays its own messages.
+        // Messages of disabled webhooks wait until the webhook is enabled again.
+        match sender.database.get_due_webhooks(time) {
+            Ok(ids) => {
+                for id in ids {
+                    let paused = matches!(webhooks.get(&id), Some(webhook) if !webhook.enabled);
+                    if !paused && sender.busy.lock().unwrap().insert(id) {
+                        let webhook = webhooks.get(&id).cloned();
+                        tokio::spawn(webhook_sender_task(sender.clone(), id, webhook));
+                    }
+                }
+            }
+            Err(err) => eprintln!("Could not get queued webhook messages: {err}"),
+        }
+
+        if !matches!(last_cleanup, Some(time) if time.elapsed() < DEAD_LETTER_CLEANUP_INTERVAL) {
+            let before = now() - DEAD_LETTER_RETENTION.as_nanos() as i64;
+            match sender.database.delete_dead_letters(before) {
+                Ok(0) => {}
+                Ok(count) => println!("Removed {count} old webhook dead letters."),
+                Err(err) => eprintln!("Could not remove old webhook dead letters: {err}"),
+            }
+            last_cleanup = Some(Instant::now());
+        }
+    }
     println!("Webhook sender stopped.");
 }
 
-fn message_handler(message: Message, webhooks: &Arc<RwLock<Webhooks>>, run_checker: &RunChecker) {
-    if let Ok(json) = message.to_json() {
-        let shared_message = Arc::new(json);
-        for (id, webhook) in &*webhooks.read().unwrap() {
-            let shared_webhook = Arc::new(webhook.clone());
-            match (&webhook.stats_type, &message) {
-                (WebhookStatsType::Both, _)
-                | (WebhookStatsType::Alarms, Message::Alarm(_))
-                | (WebhookStatsType::Data, Message::Data(_)) => {
-                    tokio::spawn(send_message(
-                        *id,
-                        shared_webhook.clone(),
-                        shared_message.clone(),
-                        run_checker.clone(),
-                    ));
+// Sends the due messages of one webhook in order
+async fn webhook_sender_task(sender: Arc<Sender>, id: i32, webhook: Option<Webhook>) {
+    let batch_size = match webhook.as_ref().and_then(|webhook| webhook.batch.as_ref()) {
+        Some(batch) => batch.max_messages as usize,
+        None => 1,
+    };
+    let limit = DELIVERY_BATCH_SIZE.max(batch_size as i64);
+    let messages = match sender.database.get_due_webhook_messages(id, now(), limit) {
+        Ok(messages) => messages,
+        Err(err) => {
+            eprintln!("Could not get queued webhook messages: {err}");
+            vec![]
+        }
+    };
+    match &webhook {
+        Some(webhook) => {
+            for batch in messages.chunks(batch_size) {
+                sender.deliver(id, webhook, batch).await;
+            }
+        }
+        None => {
+            // Webhook was removed before the messages could be sent
+            let ids: Vec<i32> = messages.iter().map(|message| message.id).collect();
+            if let Err(err) = sender.database.delete_webhook_messages(&ids) {
+                eprintln!("Could not remove webhook messages: {err}");
+            }
+        }
+    }
+    sender.busy.lock().unwrap().remove(&id);
+
+    // Rest of the due messages are sent by a new task
+    if messages.len() as i64 == limit {
+        sender.queued.notify_one();
+    }
+}
+
+// Shared by the delivery task and the sender tasks it starts
+struct Sender {
+    clients: Arc<Clients>,
+    database: Database,
+    delivery_statuses: SharedDeliveryStatuses,
+    metrics: SharedMetrics,
+    // Permits for requests in flight, so that slow hosts cannot tie up unlimited resources
+    requests: Semaphore,
+    // Webhooks that have a sender task running
+    busy: Mutex<HashSet<i32>>,
+    queued: Arc<Notify>,
+}
+
+impl Sender {
+    // Sends the messages in one request. Without batching there is only one message.
+    async fn deliver(&self, id: i32, webhook: &Webhook, messages: &[OutboxMessage]) {
+        let payloads: Vec<&str> = messages
+            .iter()
+            .map(|message| message.payload.as_str())
+            .collect();
+        let body = request_body(webhook, &payloads);
+        let delivery = match self.clients.get(&webhook.tls) {
+            Ok(client) => {
+                let _permit = self
+                    .requests
+                    .acquire()
+                    .await
+                    .expect("Semaphore is not closed");
+                send_message(&client, id, webhook, body).await
+            }
+            Err(error) => failed_delivery(error),
+        };
+        let time = delivery.time;
+        let error = delivery.error.clone();
+        record_delivery(&self.delivery_statuses, id, delivery);
+        let error = match error {
+            Some(error) => error,
+            None => {
+                let ids: Vec<i32> = messages.iter().map(|message| message.id).collect();
+                if let Err(err) = self.database.delete_webhook_messages(&ids) {
+                    eprintln!("Could not remove delivered webhook messages: {err}");
                 }
-                (_, _) => {} // Should not be send for this webhook
+                return;
+            }
+        };
+
+        self.metrics.record_webhook_failure(id);
+        eprintln!(
+            "Warning: Could not send message to webhook({}) {}: {}",
+            id, webhook.name, error
+        );
+        // Messages of a batch are retried together, so they share the retry time
+        let most_attempts = messages.iter().map(|message| message.attempts).max();
+        let retry_at = time + retry_delay(most_attempts.unwrap_or(0) + 1).as_nanos() as i64;
+        for message in messages {
+            let attempts = message.attempts + 1;
+            let age = Duration::from_nanos((time - message.created).max(0) as u64);
+            let retry_at = if attempts as u32 >= webhook.retry.max_attempts
+                || age >= Duration::from_secs(webhook.retry.max_age)
+            {
+                eprintln!(
+                    "Warning: Giving up on message({}) to webhook({}) {} after {} attempts",
+                    message.id, id, webhook.name, attempts
+                );
+                None
+            } else {
+                Some(retry_at)
+            };
+            if let Err(err) = self
+                .database
+                .fail_webhook_message(message.id, attempts, retry_at, &error)
+            {
+                eprintln!("Could not update webhook message: {err}");
             }
         }
     }
 }
 
+// Batches are sent as a JSON array of the messages
+fn request_body(webhook: &Webhook, payloads: &[&str]) -> String {
+    match webhook.batch {
+        Some(_) => format!("[{}]", payloads.join(",")),
+        None => payloads.concat(),
+    }
+}
+
+// Exponential backoff with jitter, so that the retries to a failing host are spread out
+fn retry_delay(attempts: i32) -> Duration {
+    let exponent = attempts.clamp(1, 32) as u32 - 1;
+    let delay = RETRY_DELAY
+        .saturating_mul(2u32.saturating_pow(exponent))
+        .min(MAX_RETRY_DELAY);
+    delay.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+}
+
 async fn send_message(
+    client: &reqwest::Client,
     id: i32,
-    webhook: Arc<Webhook>,
-    message: Arc<String>,
-    _: RunChecker, // Ensuring that message sending is done before application quits
-) {
+    webhook: &Webhook,
+    message: String,
+) -> WebhookDelivery {
     // Making headers for the request
     let mut headers = header::HeaderMap::new();
-    headers.insert(
-        header::CONTENT_TYPE,
-        header::HeaderValue::from_static("application/json"),
-    );
+    match header::HeaderValue::from_str(template::content_type(webhook)) {
+        Ok(content_type) => {
+            headers.insert(header::CONTENT_TYPE, content_type);
+        }
+        Err(_) => {
+            eprintln!(
+                "Warning: Invalid content type in webhook({}) {}",
+                id, webhook.name
+            );
+        }
+    }
     for (key, value) in &webhook.headers {
         let header_name = header::HeaderName::from_bytes(key.as_bytes());
         let header_value = header::HeaderValue::from_bytes(value.as_bytes());
@@ -99,33 +599,120 @@ async fn send_message(
         }
     }
 
+    // Signature is made when sending, so that retries have a fresh timestamp
+    if let Some(secret) = &webhook.secret {
+        let timestamp = now() / 1_000_000_000;
+        let signature = signature::sign(secret.as_bytes(), timestamp, message.as_bytes());
+        headers.insert(
+            signature::TIMESTAMP_HEADER,
+            header::HeaderValue::from(timestamp),
+        );
+        if let Ok(value) = header::HeaderValue::from_str(&signature) {
+            headers.insert(signature::SIGNATURE_HEADER, value);
+        }
+    }
+
     // Sending the request
-    let client = reqwest::Client::new();
-    let result = match webhook.method {
+    let mut delivery = WebhookDelivery {
+        time: now(),
+        test: false,
+        status: None,
+        latency_ms: 0,
+        response: None,
+        error: None,
+    };
+    let started = Instant::now();
+    let mut request = match webhook.method {
         WebhookRequestMethod::Get => client.get(&webhook.address),
         WebhookRequestMethod::Post => client.post(&webhook.address),
         WebhookRequestMethod::Put => client.put(&webhook.address),
+    };
+    if let Some(timeout) = webhook.timeout_ms {
+        request = request.timeout(Duration::from_millis(timeout));
     }
-    .headers(headers)
-    .body((*message).clone())
-    .send()
-    .await;
+    let result = request.headers(headers).body(message).send().await;
 
     // Checking the response
     match result {
         Ok(response) => {
-            if response.status() != 200 {
-                eprintln!(
-                    "Warning: Webhook({}) {} host responded: {:#?}",
-                    id, webhook.name, response
-                );
+            let status = response.status();
+            delivery.status = Some(status.as_u16());
+            delivery.response = read_response(response).await;
+            if !status.is_success() {
+                delivery.error = Some(format!("Host responded with {status}"));
             }
         }
-        Err(err) => {
-            eprintln!(
-                "Warning: Could not send message to webhook({}) {}: {}",
-                id, webhook.name, err
+        Err(err) => delivery.error = Some(err.to_string()),
+    }
+    delivery.latency_ms = started.elapsed().as_millis() as u64;
+    delivery
+}
+
+// Reads the beginning of the response body, ignoring the rest
+async fn read_response(mut response: reqwest::Response) -> Option<String> {
+    let mut body = Vec::new();
+    while body.len() < MAX_RESPONSE_LENGTH {
+        match response.chunk().await {
+            Ok(Some(chunk)) => body.extend_from_slice(&chunk),
+            _ => break,
+        }
+    }
+    body.truncate(MAX_RESPONSE_LENGTH);
+    if body.is_empty() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&body).into_owned())
+}
+
+// Unit tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structures::statistics::DataMessage;
+
+    #[test]
+    fn retry_delays() {
+        for attempts in 1..=20 {
+            let expected = (RETRY_DELAY * 2u32.pow(attempts as u32 - 1)).min(MAX_RETRY_DELAY);
+            let delay = retry_delay(attempts);
+            assert!(delay <= expected, "{attempts}: {delay:?} > {expected:?}");
+            assert!(
+                delay >= expected / 2,
+                "{attempts}: {delay:?} < {expected:?} / 2"
             );
         }
+        assert!(retry_delay(i32::MAX) <= MAX_RETRY_DELAY);
+    }
+
+    #[test]
+    fn data_sampling() {
+        let webhook: Webhook = serde_json::from_str(
+            r#"{"name":"test","address":"test","filter":{"data_interval":10}}"#,
+        )
+        .unwrap();
+        let second = 1_000_000_000;
+        let data = |time: i64, series: &str| {
+            Message::Data(Box::new(DataMessage {
+                time,
+                series: series.to_string(),
+                ..DataMessage::default()
+            }))
+        };
+        let mut sampler = DataSampler::default();
+        assert!(sampler.accepts(1, &webhook, &data(0, "any")));
+        assert!(!sampler.accepts(1, &webhook, &data(9 * second, "any")));
+        assert!(sampler.accepts(1, &webhook, &data(10 * second, "any")));
+        // Series and webhooks are sampled separately
+        assert!(sampler.accepts(1, &webhook, &data(11 * second, "eth0")));
+        assert!(sampler.accepts(2, &webhook, &data(11 * second, "any")));
+        // Alarms are never sampled
+        let alarm = Message::Alarm(Box::default());
+        assert!(sampler.accepts(1, &webhook, &alarm));
+
+        // Removed webhooks are forgotten
+        sampler.retain(&Webhooks::from([(1, webhook)]));
+        assert_eq!(sampler.last_sent.len(), 2);
     }
 }
@@ -0,0 +1,133 @@
+use crate::structures::webhooks::WebhookTls;
+use reqwest::{Certificate, Client, ClientBuilder, Identity};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+// Requests taking longer than this are failed attempts, unless the webhook has its own timeout
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Webhooks without TLS options share one client, so that connections to the same host are
+// reused. Other clients are made when first needed and shared by webhooks with the same options.
+pub struct Clients {
+    default: Client,
+    custom: Mutex<HashMap<WebhookTls, Client>>,
+}
+
+impl Clients {
+    pub fn new(max_idle_per_host: usize) -> Result<Clients, String> {
+        let default = builder()
+            .pool_max_idle_per_host(max_idle_per_host)
+            .build()
+            .map_err(|err| format!("Could not create client for webhooks: {err}"))?;
+        Ok(Clients {
+            default,
+            custom: Mutex::default(),
+        })
+    }
+
+    pub fn get(&self, tls: &WebhookTls) -> Result<Client, String> {
+        if tls.is_default() {
+            return Ok(self.default.clone());
+        }
+        let mut custom = self.custom.lock().unwrap();
+        if let Some(client) = custom.get(tls) {
+            return Ok(client.clone());
+        }
+        let client = build(tls)?;
+        custom.insert(tls.clone(), client.clone());
+        Ok(client)
+    }
+
+    // Forgets the clients with TLS options, so that changed certificate files are read again
+    pub fn clear(&self) {
+        self.custom.lock().unwrap().clear();
+    }
+}
+
+fn builder() -> ClientBuilder {
+    Client::builder().timeout(REQUEST_TIMEOUT)
+}
+
+// Makes a client with the TLS options, failing when the certificate files are not usable
+pub fn build(tls: &WebhookTls) -> Result<Client, String> {
+    let mut builder = builder()
+        .danger_accept_invalid_certs(tls.accept_invalid_certs)
+        .danger_accept_invalid_hostnames(tls.accept_invalid_certs);
+    if let Some(path) = &tls.ca_file {
+        let certificates = Certificate::from_pem_bundle(&read(path)?)
+            .map_err(|err| format!("Invalid CA certificates in {path}: {err}"))?;
+        if certificates.is_empty() {
+            return Err(format!("No CA certificates in {path}"));
+        }
+        for certificate in certificates {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+    match (&tls.client_cert_file, &tls.client_key_file) {
+        (Some(cert_path), Some(key_path)) => {
+            let identity = Identity::from_pkcs8_pem(&read(cert_path)?, &read(key_path)?)
+                .map_err(|err| format!("Invalid client certificate or key: {err}"))?;
+            builder = builder.identity(identity);
+        }
+        (None, None) => {}
+        _ => return Err("Client certificate and key must be given together".to_string()),
+    }
+    builder
+        .build()
+        .map_err(|err| format!("Could not create client for webhook: {err}"))
+}
+
+fn read(path: &str) -> Result<Vec<u8>, String> {
+    std::fs::read(path).map_err(|err| format!("Could not read {path}: {err}"))
+}
+
+// Unit tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tls_options() {
+        let clients = Clients::new(1).unwrap();
+        assert!(clients.get(&WebhookTls::default()).is_ok());
+
+        let lab = WebhookTls {
+            accept_invalid_certs: true,
+            ..WebhookTls::default()
+        };
+        assert!(clients.get(&lab).is_ok());
+        assert_eq!(clients.custom.lock().unwrap().len(), 1);
+        clients.clear();
+        assert!(clients.custom.lock().unwrap().is_empty());
+
+        let missing = WebhookTls {
+            ca_file: Some("/nonexistent/ca.pem".to_string()),
+            ..WebhookTls::default()
+        };
+        let error = clients.get(&missing).unwrap_err();
+        assert!(
+            error.starts_with("Could not read /nonexistent/ca.pem"),
+            "{error}"
+        );
+
+        let empty = tempfile::NamedTempFile::new().unwrap();
+        let empty_path = empty.path().to_str().unwrap().to_string();
+        let not_pem = WebhookTls {
+            ca_file: Some(empty_path.clone()),
+            ..WebhookTls::default()
+        };
+        assert!(build(&not_pem).is_err());
+
+        let without_key = WebhookTls {
+            client_cert_file: Some(empty_path),
+            ..WebhookTls::default()
+        };
+        assert_eq!(
+            build(&without_key).unwrap_err(),
+            "Client certificate and key must be given together"
+        );
+    }
+}
@@ -0,0 +1,234 @@
+use crate::structures::configuration::NetspotConfig;
+use crate::structures::correlations::Detection;
+use crate::structures::incidents::Incident;
+use crate::structures::statistics::{DataMessage, Message};
+use crate::structures::webhooks::{Webhook, Webhooks};
+use minijinja::Environment;
+use reqwest::header::HeaderValue;
+use serde_json::{json, Map, Value};
+
+// Content type of the raw JSON messages, also used for templates when not set
+const DEFAULT_CONTENT_TYPE: &str = "application/json";
+
+pub fn content_type(webhook: &Webhook) -> &str {
+    webhook
+        .content_type
+        .as_deref()
+        .unwrap_or(DEFAULT_CONTENT_TYPE)
+}
+
+// Template sees the message fields directly, and the whole message, the webhook and the
+// configuration as objects
+fn context(id: i32, webhook: &Webhook, message: &Message, config: Option<&NetspotConfig>) -> Value {
+    let message = match message {
+        Message::Alarm(alarm) => serde_json::to_value(alarm),
+        Message::Data(data) => serde_json::to_value(data),
+        Message::Incident(incident) => serde_json::to_value(incident),
+        Message::Detection(detection) => serde_json::to_value(detection),
+    }
+    .unwrap_or_default();
+    let mut context = match &message {
+        Value::Object(fields) => fields.clone(),
+        _ => Map::new(),
+    };
+    context.insert("message".to_string(), message);
+    context.insert(
+        "webhook".to_string(),
+        json!({"id": id, "name": webhook.name}),
+    );
+    let config = config.map(|config| {
+        let miner = &config.configuration;
+        json!({
+            "id": context.get("config_id"),
+            "name": miner.name,
+            "device": miner.device,
+            "promiscuous": miner.promiscuous,
+            "enabled": miner.enabled,
+        })
+    });
+    context.insert("config".to_string(), config.unwrap_or_default());
+    Value::Object(context)
+}
+
+// Templates of the stored webhooks, compiled once and named by the webhook id
+pub struct Templates(Environment<'static>);
+
+// Environment::default has no filters or tests, unlike Environment::new
+impl Default for Templates {
+    fn default() -> Templates {
+        Templates(Environment::new())
+    }
+}
+
+impl Templates {
+    pub fn new(webhooks: &Webhooks) -> Templates {
+        let mut templates = Templates::default();
+        for (id, webhook) in webhooks {
+            // Templates were checked when the webhooks were saved
+            if let Err(error) = templates.add(*id, webhook) {
+                eprintln!(
+                    "Warning: Invalid template in webhook({}) {}: {}",
+                    id, webhook.name, error
+                );
+            }
+        }
+        templates
+    }
+
+    fn add(&mut self, id: i32, webhook: &Webhook) -> Result<(), String> {
+        match &webhook.template {
+            None => Ok(()),
+            Some(template) => self
+                .0
+                .add_template_owned(id.to_string(), template.clone())
+                .map_err(|err| format!("Template error: {err:#}")),
+        }
+    }
+
+    // Returns the request body for the message, which is the message as JSON without a template
+    pub fn render(
+        &self,
+        id: i32,
+        webhook: &Webhook,
+        message: &Message,
+        config: Option<&NetspotConfig>,
+    ) -> Result<String, String> {
+        if webhook.template.is_none() {
+            return message.to_json().map_err(|err| err.to_string());
+        }
+        self.0
+            .get_template(&id.to_string())
+            .and_then(|template| template.render(context(id, webhook, message, config)))
+            .map_err(|err| format!("Template error: {err:#}"))
+    }
+}
+
+// Checks the template, the content type and the batch before the webhook is saved. The template
+// is tried with each message type, so that errors like unknown filters are found early.
+pub fn validate(webhook: &Webhook) -> Result<(), String> {
+    if let Some(content_type) = &webhook.content_type {
+        let essence = content_type.split(';').next().unwrap_or_default().trim();
+        let valid = HeaderValue::from_str(content_type).is_ok()
+            && matches!(essence.split_once('/'), Some((top, sub))
+                if !top.is_empty() && !sub.is_empty() && !sub.contains('/'));
+        if !valid {
+            return Err(format!("Invalid content type: {content_type}"));
+        }
+    }
+    if let Some(batch) = &webhook.batch {
+        if batch.max_messages == 0 {
+            return Err("Batch must have room for at least one message".to_string());
+        }
+        if webhook.template.is_some() {
+            return Err("Templates cannot be used with batches".to_string());
+        }
+    }
+    if webhook.template.is_some() {
+        let mut templates = Templates::default();
+        templates.add(0, webhook)?;
+        let messages = [
+            Message::Alarm(Box::default()),
+            Message::Data(Box::<DataMessage>::default()),
+            Message::Incident(Box::<Incident>::default()),
+            Message::Detection(Box::<Detection>::default()),
+        ];
+        for message in &messages {
+            templates.render(0, webhook, message, None)?;
+        }
+    }
+    Ok(())
+}
+
+// Unit tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structures::statistics::{AlarmMessage, AlertStatus, Stat};
+
+    fn webhook(template: &str) -> Webhook {
+        let mut webhook: Webhook =
+            serde_json::from_str(r#"{"name":"Pager","address":"test"}"#).unwrap();
+        webhook.template = Some(template.to_string());
+        webhook
+    }
+
+    fn render(
+        id: i32,
+        webhook: &Webhook,
+        message: &Message,
+        config: Option<&NetspotConfig>,
+    ) -> Result<String, String> {
+        let templates = Templates::new(&Webhooks::from([(id, webhook.clone())]));
+        templates.render(id, webhook, message, config)
+    }
+
+    fn alarm() -> Message {
+        Message::Alarm(Box::new(AlarmMessage {
+            name: "Office \"LAN\"".to_string(),
+            config_id: Some(2),
+            stat: Stat::RSyn,
+            status: AlertStatus::UpAlert,
+            probability: 0.001,
+            ..AlarmMessage::default()
+        }))
+    }
+
+    #[test]
+    fn without_template() {
+        let mut webhook = webhook("");
+        webhook.template = None;
+        let message = alarm();
+        assert_eq!(
+            render(1, &webhook, &message, None),
+            Ok(message.to_json().unwrap())
+        );
+        assert_eq!(content_type(&webhook), "application/json");
+    }
+
+    #[test]
+    fn template_fields() {
+        let config: NetspotConfig =
+            serde_json::from_str(r#"{"configuration":{"name":"Office","device":"eth0"}}"#).unwrap();
+        let template = concat!(
+            "{{ stat }} {{ status }} {{ probability }} {{ message.type }} ",
+            "{{ webhook.id }} {{ webhook.name }} {{ config.id }} {{ config.device }} ",
+            r#"{"text": {{ name | tojson }}}"#,
+        );
+        let body = render(3, &webhook(template), &alarm(), Some(&config)).unwrap();
+        let expected = r#"R_SYN UP_ALERT 0.001 alarm 3 Pager 2 eth0 {"text": "Office \"LAN\""}"#;
+        assert_eq!(body, expected);
+
+        // Configuration is missing for test alarms
+        let body = render(3, &webhook("{{ config.name or 'test' }}"), &alarm(), None).unwrap();
+        assert_eq!(body, "test");
+    }
+
+    #[test]
+    fn validation() {
+        assert_eq!(
+            validate(&webhook("{% if stat %}{{ stat }}{% endif %}")),
+            Ok(())
+        );
+        let error = validate(&webhook("{% if stat %}")).unwrap_err();
+        assert!(error.starts_with("Template error: "), "{error}");
+        let error = validate(&webhook("{{ stat | no_such_filter }}")).unwrap_err();
+        assert!(error.contains("no_such_filter"), "{error}");
+
+        let mut hook = webhook("text");
+        hook.content_type = Some("text/plain; charset=utf-8".to_string());
+        assert_eq!(validate(&hook), Ok(()));
+        hook.content_type = Some("plain".to_string());
+        assert_eq!(
+            validate(&hook),
+            Err("Invalid content type: plain".to_string())
+        );
+
+        hook.content_type = None;
+        hook.batch = Some(Default::default());
+        assert!(validate(&hook).is_err());
+        hook.template = None;
+        assert_eq!(validate(&hook), Ok(()));
+    }
+}
@@ -1,5 +1,15 @@
 pub mod configuration;
+pub mod correlations;
 pub mod dht;
+pub mod incidents;
+pub mod logs;
+pub mod replays;
+pub mod rules;
+pub mod settings;
+pub mod severity;
 pub mod statistics;
 pub mod status;
+pub mod suppressions;
+pub mod thresholds;
 pub mod webhooks;
+pub mod websocket;
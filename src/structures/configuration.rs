@@ -89,6 +89,26 @@ agent_name = "{agent_name}"
     }
 
     pub fn make_toml(&self, data_path: &str) -> String {
+        self.make_toml_for(
+            data_path,
+            &self.configuration.device,
+            self.configuration.promiscuous,
+            &self.make_influxdb1_toml(),
+        )
+    }
+
+    // Replays read the capture file as the device, and their results are not sent to InfluxDB
+    pub fn make_replay_toml(&self, data_path: &str, pcap_path: &str) -> String {
+        self.make_toml_for(data_path, pcap_path, false, "")
+    }
+
+    fn make_toml_for(
+        &self,
+        data_path: &str,
+        device: &str,
+        promiscuous: bool,
+        influxdb1: &str,
+    ) -> String {
         format!(
             r#"[miner]
 device = "{device}"
@@ -115,11 +135,11 @@ alert = {alert}
 bounded = {bounded}
 max_excess = {max_excess}
 {spot_overrides}"#,
-            device = self.configuration.device,
-            promiscuous = self.configuration.promiscuous,
+            device = device,
+            promiscuous = promiscuous,
             analyzer = self.stats.make_analyzer_toml(),
             tag = self.configuration.name,
-            influxdb1 = self.make_influxdb1_toml(),
+            influxdb1 = influxdb1,
             depth = self.spot.depth,
             q = self.spot.q,
             n_init = self.spot.n_init,
@@ -365,5 +385,12 @@ bounded = true
 max_excess = 200
 "#;
         assert_eq!(config.make_toml("/tmp"), expected);
+
+        // Replay reads the capture file and leaves out the InfluxDB exporter
+        let replay_toml = config.make_replay_toml("/tmp", "/data/pcaps/1.pcap");
+        assert!(replay_toml
+            .starts_with("[miner]\ndevice = \"/data/pcaps/1.pcap\"\npromiscuous = false\n"));
+        assert!(!replay_toml.contains("[exporter.influxdb]"));
+        assert!(replay_toml.contains("tag = \"InfluxDB test\""));
     }
 }
@@ -14,6 +14,17 @@ pub struct MinerConfig {
     pub promiscuous: bool,
     #[serde(default = "miner_default_enabled")]
     pub enabled: bool,
+    /// Uploaded capture file that is replayed instead of capturing the device. Configurations
+    /// with a capture file are run only by replay jobs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pcap: Option<i32>,
+}
+
+impl MinerConfig {
+    // Netspot process is kept running for the enabled configurations without a capture file
+    pub fn is_live(&self) -> bool {
+        self.enabled && self.pcap.is_none()
+    }
 }
 
 // Default values
@@ -46,6 +57,8 @@ mod tests {
         assert_eq!("any", config.device);
         assert!(config.promiscuous);
         assert!(config.enabled);
+        assert_eq!(None, config.pcap);
+        assert!(config.is_live());
     }
 
     #[test]
@@ -61,6 +74,15 @@ mod tests {
         assert!(!config.enabled);
     }
 
+    #[test]
+    fn pcap_config() {
+        // Configurations with a capture file are not run live
+        let config: MinerConfig = serde_json::from_str(r#"{"name":"test","pcap":2}"#).unwrap();
+        assert_eq!(Some(2), config.pcap);
+        assert!(config.enabled);
+        assert!(!config.is_live());
+    }
+
     #[test]
     fn missing_name() {
         // Following should fail as the required name is missing
@@ -0,0 +1,172 @@
+use crate::structures::statistics::{AlarmMessage, AlertStatus, MessageType, Severity, Stat};
+use rocket::FromForm;
+use rocket_okapi::okapi::schemars;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// Correlation rules
+//--------------------------------------------------------------------------------------------------
+
+/// Combines alarms for several stats into a named detection. The detection is sent when there has
+/// been an alarm for every stat of the rule within the window. After that, the rule starts to wait
+/// for a new set of alarms.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+pub struct CorrelationRule {
+    pub name: String,
+    /// Stats that must all have alarms, at least two
+    pub stats: Vec<Stat>,
+    /// Only alarms from these configuration ids are combined, empty for all
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub configurations: Vec<i32>,
+    /// Only alarms with this status are combined
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<AlertStatus>,
+    /// Seconds from the first alarm to the last one
+    #[serde(default = "CorrelationRule::default_window")]
+    pub window: u64,
+    #[serde(default)]
+    pub severity: Severity,
+    /// Alarms from different configurations are combined, instead of each configuration separately
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub across_configurations: bool,
+}
+
+impl CorrelationRule {
+    fn default_window() -> u64 {
+        60
+    }
+
+    pub fn accepts(&self, alarm: &AlarmMessage) -> bool {
+        if !self.stats.contains(&alarm.stat) {
+            return false;
+        }
+        if self
+            .status
+            .as_ref()
+            .is_some_and(|status| *status != alarm.status)
+        {
+            return false;
+        }
+        if self.configurations.is_empty() {
+            return true;
+        }
+        match alarm.config_id {
+            Some(config_id) => self.configurations.contains(&config_id),
+            None => false,
+        }
+    }
+}
+
+// Container for correlation rules
+pub type CorrelationRules = HashMap<i32, CorrelationRule>;
+
+// Detection
+//--------------------------------------------------------------------------------------------------
+
+/// Alarms of a correlation rule seen together
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize, schemars::JsonSchema)]
+pub struct Detection {
+    pub id: i32,
+    /// Time of the alarm that completed the detection as nanoseconds since Unix Epoch
+    pub time: i64,
+    /// Time of the first alarm as nanoseconds since Unix Epoch
+    pub first_seen: i64,
+    /// Name of the rule
+    pub name: String,
+    pub rule_id: i32,
+    pub severity: Severity,
+    /// Configuration that produced all the alarms, missing when there were several or none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config_id: Option<i32>,
+    /// Configurations that produced the alarms
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub configurations: Vec<i32>,
+    pub stats: Vec<Stat>,
+    #[serde(rename = "type")]
+    pub msg_type: MessageType,
+}
+
+pub type Detections = Vec<Detection>;
+
+/// Query parameters for reading detections
+#[derive(Clone, Debug, Default, FromForm, schemars::JsonSchema)]
+pub struct DetectionQuery {
+    /// Only detections from this time onwards
+    pub from: Option<i64>,
+    /// Only detections before this time
+    pub to: Option<i64>,
+    /// Only detections of this rule
+    pub rule_id: Option<i32>,
+    /// Only detections from this configuration id alone
+    pub config_id: Option<i32>,
+    /// Only detections with at least this severity
+    pub min_severity: Option<Severity>,
+    /// Only this many newest detections
+    pub last: Option<i32>,
+}
+
+// Unit tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn correlation_rule() {
+        let json = r#"{"name": "Possible SYN scan", "stats": ["R_SYN", "R_DST_SRC_PORT"],
+            "configurations": [2], "status": "UP_ALERT", "severity": "high"}"#;
+        let rule = serde_json::from_str::<CorrelationRule>(json).unwrap();
+        assert_eq!(rule.window, 60);
+        assert_eq!(rule.severity, Severity::High);
+        assert!(!rule.across_configurations);
+        let alarm = |config_id: Option<i32>, stat: Stat, status: AlertStatus| AlarmMessage {
+            config_id,
+            stat,
+            status,
+            ..AlarmMessage::default()
+        };
+        assert!(rule.accepts(&alarm(Some(2), Stat::RSyn, AlertStatus::UpAlert)));
+        assert!(!rule.accepts(&alarm(Some(2), Stat::RSyn, AlertStatus::DownAlert)));
+        assert!(!rule.accepts(&alarm(Some(2), Stat::Traffic, AlertStatus::UpAlert)));
+        assert!(!rule.accepts(&alarm(Some(1), Stat::RSyn, AlertStatus::UpAlert)));
+        assert!(!rule.accepts(&alarm(None, Stat::RSyn, AlertStatus::UpAlert)));
+
+        let json = serde_json::to_string(&CorrelationRule {
+            name: "Scan".to_string(),
+            stats: vec![Stat::RSyn, Stat::Traffic],
+            window: 30,
+            across_configurations: true,
+            ..CorrelationRule::default()
+        })
+        .unwrap();
+        let expected = concat!(
+            r#"{"name":"Scan","stats":["R_SYN","TRAFFIC"],"window":30,"severity":"info","#,
+            r#""across_configurations":true}"#
+        );
+        assert_eq!(json, expected);
+    }
+
+    #[test]
+    fn detection_serialize() {
+        let detection = Detection {
+            id: 3,
+            time: 20,
+            first_seen: 10,
+            name: "Scan".to_string(),
+            rule_id: 1,
+            severity: Severity::Medium,
+            config_id: Some(2),
+            configurations: vec![2],
+            stats: vec![Stat::RSyn, Stat::Traffic],
+            msg_type: MessageType::Detection,
+        };
+        let json = serde_json::to_string(&detection).unwrap();
+        let expected = concat!(
+            r#"{"id":3,"time":20,"first_seen":10,"name":"Scan","rule_id":1,"#,
+            r#""severity":"medium","config_id":2,"configurations":[2],"#,
+            r#""stats":["R_SYN","TRAFFIC"],"type":"detection"}"#
+        );
+        assert_eq!(json, expected);
+    }
+}
@@ -1,3 +1,5 @@
+use crate::structures::correlations::Detection;
+use crate::structures::incidents::Incident;
 use crate::structures::statistics::AlarmMessage;
 use serde::Serialize;
 
@@ -23,6 +25,34 @@ impl RequestPostTopicUUID {
             value: Value::new(addresses, alarm),
         }
     }
+
+    pub fn incident(addresses: &[String], incident: Incident) -> Self {
+        RequestPostTopicUUID {
+            topic_name: "SIFIS:Netspot_Incident".to_string(),
+            topic_uuid: "Netspot_Incident".to_string(),
+            value: Value {
+                description: "Netspot Anomaly Incident".to_string(),
+                addresses: addresses.to_owned(),
+                alarm: None,
+                incident: Some(incident),
+                detection: None,
+            },
+        }
+    }
+
+    pub fn detection(addresses: &[String], detection: Detection) -> Self {
+        RequestPostTopicUUID {
+            topic_name: "SIFIS:Netspot_Detection".to_string(),
+            topic_uuid: "Netspot_Detection".to_string(),
+            value: Value {
+                description: "Netspot Correlated Detection".to_string(),
+                addresses: addresses.to_owned(),
+                alarm: None,
+                incident: None,
+                detection: Some(detection),
+            },
+        }
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -30,7 +60,12 @@ impl RequestPostTopicUUID {
 pub struct Value {
     pub description: String,
     pub addresses: Vec<String>,
-    pub alarm: AlarmMessage,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alarm: Option<AlarmMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub incident: Option<Incident>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detection: Option<Detection>,
 }
 
 impl Value {
@@ -38,7 +73,9 @@ impl Value {
         Value {
             description: "Netspot Anomaly Alarm".to_string(),
             addresses: addresses.to_owned(),
-            alarm,
+            alarm: Some(alarm),
+            incident: None,
+            detection: None,
         }
     }
 }
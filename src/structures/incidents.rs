@@ -0,0 +1,205 @@
+use crate::structures::statistics::{AlarmMessage, AlertStatus, MessageType, Severity, Stat};
+use rocket::{FromForm, FromFormField};
+use rocket_okapi::okapi::schemars;
+use serde::{Deserialize, Serialize};
+
+// Incident settings
+//--------------------------------------------------------------------------------------------------
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+pub struct IncidentSettings {
+    /// Alarms arriving within this many seconds from the previous alarm of the same configuration,
+    /// name, series, stat and status belong to the same incident. The incident is closed after a quiet window.
+    #[serde(default = "IncidentSettings::default_window")]
+    pub window: u64,
+}
+
+impl IncidentSettings {
+    fn default_window() -> u64 {
+        60
+    }
+}
+
+impl Default for IncidentSettings {
+    fn default() -> Self {
+        IncidentSettings {
+            window: IncidentSettings::default_window(),
+        }
+    }
+}
+
+// Incident
+//--------------------------------------------------------------------------------------------------
+
+#[derive(
+    Clone,
+    Debug,
+    Default,
+    Deserialize,
+    FromFormField,
+    PartialEq,
+    Eq,
+    Serialize,
+    schemars::JsonSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum IncidentState {
+    #[default]
+    #[field(value = "open")]
+    Open,
+    #[field(value = "closed")]
+    Closed,
+}
+
+/// Group of alarms with the same configuration, name, series, stat and status
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize, schemars::JsonSchema)]
+pub struct Incident {
+    pub id: i32,
+    pub name: String,
+    /// Configuration that produced the alarms, missing for test alarms
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config_id: Option<i32>,
+    pub series: String,
+    pub stat: Stat,
+    pub status: AlertStatus,
+    pub state: IncidentState,
+    /// Time of the first alarm as nanoseconds since Unix Epoch
+    pub first_seen: i64,
+    /// Time of the latest alarm as nanoseconds since Unix Epoch
+    pub last_seen: i64,
+    /// Time when the incident was closed as nanoseconds since Unix Epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub closed: Option<i64>,
+    /// Number of alarms in the incident
+    pub count: i32,
+    /// Highest value of upward alarms, or lowest value of downward alarms
+    pub peak_value: f64,
+    /// Probability of the most anomalous alarm
+    pub min_probability: f64,
+    /// Highest severity of the alarms, alarms without one count as info
+    #[serde(default)]
+    pub severity: Severity,
+    #[serde(rename = "type")]
+    pub msg_type: MessageType,
+}
+
+impl Incident {
+    // Starts a new open incident from the alarm. The id is given when the incident is stored.
+    pub fn new(alarm: &AlarmMessage) -> Self {
+        Incident {
+            id: 0,
+            name: alarm.name.clone(),
+            config_id: alarm.config_id,
+            series: alarm.series.clone(),
+            stat: alarm.stat.clone(),
+            status: alarm.status.clone(),
+            state: IncidentState::Open,
+            first_seen: alarm.time,
+            last_seen: alarm.time,
+            closed: None,
+            count: 1,
+            peak_value: alarm.value,
+            min_probability: alarm.probability,
+            severity: alarm.severity.unwrap_or_default(),
+            msg_type: MessageType::Incident,
+        }
+    }
+
+    pub fn add(&mut self, alarm: &AlarmMessage) {
+        self.last_seen = self.last_seen.max(alarm.time);
+        self.count += 1;
+        self.peak_value = match self.status {
+            AlertStatus::UpAlert => self.peak_value.max(alarm.value),
+            AlertStatus::DownAlert => self.peak_value.min(alarm.value),
+        };
+        self.min_probability = self.min_probability.min(alarm.probability);
+        self.severity = self.severity.max(alarm.severity.unwrap_or_default());
+    }
+
+    pub fn close(&mut self, time: i64) {
+        self.state = IncidentState::Closed;
+        self.closed = Some(time);
+    }
+}
+
+pub type Incidents = Vec<Incident>;
+
+/// Query parameters for reading incidents
+#[derive(Clone, Debug, Default, FromForm, schemars::JsonSchema)]
+pub struct IncidentQuery {
+    /// Only open or only closed incidents
+    pub state: Option<IncidentState>,
+    /// Only incidents with alarms from this time onwards
+    pub from: Option<i64>,
+    /// Only incidents that started before this time
+    pub to: Option<i64>,
+    /// Only incidents from this configuration id
+    pub config_id: Option<i32>,
+    pub stat: Option<Stat>,
+    pub status: Option<AlertStatus>,
+    /// Only this many newest incidents
+    pub last: Option<i32>,
+}
+
+// Unit tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grouping() {
+        let alarm = |time: i64, value: f64, probability: f64| AlarmMessage {
+            time,
+            name: "Office".to_string(),
+            config_id: Some(1),
+            series: "any".to_string(),
+            stat: Stat::RSyn,
+            status: AlertStatus::UpAlert,
+            value,
+            probability,
+            ..AlarmMessage::default()
+        };
+        let mut incident = Incident::new(&alarm(10, 0.5, 0.01));
+        incident.add(&AlarmMessage {
+            severity: Some(Severity::High),
+            ..alarm(30, 0.9, 0.02)
+        });
+        incident.add(&AlarmMessage {
+            severity: Some(Severity::Medium),
+            ..alarm(20, 0.7, 0.001)
+        });
+        assert_eq!(incident.first_seen, 10);
+        assert_eq!(incident.last_seen, 30);
+        assert_eq!(incident.count, 3);
+        assert_eq!(incident.peak_value, 0.9);
+        assert_eq!(incident.min_probability, 0.001);
+        assert_eq!(incident.severity, Severity::High);
+        assert_eq!(incident.state, IncidentState::Open);
+
+        incident.close(100);
+        let json = serde_json::to_string(&incident).unwrap();
+        let expected = concat!(
+            r#"{"id":0,"name":"Office","config_id":1,"series":"any","stat":"R_SYN","#,
+            r#""status":"UP_ALERT","#,
+            r#""state":"closed","first_seen":10,"last_seen":30,"closed":100,"count":3,"#,
+            r#""peak_value":0.9,"min_probability":0.001,"severity":"high","type":"incident"}"#
+        );
+        assert_eq!(json, expected);
+
+        // Peak of downward alarms is the lowest value
+        let mut down = alarm(10, 5.0, 0.01);
+        down.status = AlertStatus::DownAlert;
+        let mut incident = Incident::new(&down);
+        down.value = 2.0;
+        incident.add(&down);
+        assert_eq!(incident.peak_value, 2.0);
+    }
+
+    #[test]
+    fn settings() {
+        let settings = serde_json::from_str::<IncidentSettings>("{}").unwrap();
+        assert_eq!(settings.window, 60);
+    }
+}
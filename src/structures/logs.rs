@@ -0,0 +1,54 @@
+use rocket_okapi::okapi::schemars;
+use serde::{Deserialize, Serialize};
+
+// Output stream of the netspot process
+//--------------------------------------------------------------------------------------------------
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum LogStream {
+    Stdout,
+    Stderr,
+}
+
+// Single line of netspot output
+//--------------------------------------------------------------------------------------------------
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+pub struct LogLine {
+    /// Time when the line was received as nanoseconds since Unix Epoch
+    pub time: i64,
+    pub stream: LogStream,
+    pub line: String,
+}
+
+pub type LogLines = Vec<LogLine>;
+
+// Unit tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_streams() {
+        let streams = vec![LogStream::Stdout, LogStream::Stderr];
+        let json = serde_json::to_string(&streams).unwrap();
+        let expected = r#"["stdout","stderr"]"#;
+        assert_eq!(json, expected);
+    }
+
+    #[test]
+    fn log_line() {
+        let line = LogLine {
+            time: 1,
+            stream: LogStream::Stderr,
+            line: "Error: device not found".to_string(),
+        };
+        let json = serde_json::to_string(&line).unwrap();
+        let expected = r#"{"time":1,"stream":"stderr","line":"Error: device not found"}"#;
+        assert_eq!(json, expected);
+        assert_eq!(serde_json::from_str::<LogLine>(&json).unwrap(), line);
+    }
+}
@@ -0,0 +1,130 @@
+use rocket_okapi::okapi::schemars;
+use serde::{Deserialize, Serialize};
+
+// Capture files
+//--------------------------------------------------------------------------------------------------
+
+/// Uploaded capture file that configurations can replay instead of capturing a device
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+pub struct Pcap {
+    pub id: i32,
+    pub name: String,
+    /// Upload time as nanoseconds since Unix Epoch
+    pub uploaded: i64,
+    /// File size in bytes
+    pub size: i64,
+    pub packets: i64,
+    /// Time of the first packet as nanoseconds since Unix Epoch, missing when there are no packets
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_packet: Option<i64>,
+    /// Time of the last packet as nanoseconds since Unix Epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_packet: Option<i64>,
+}
+
+impl Pcap {
+    // Netspot gives the messages the time of the packets, so the progress is estimated from the
+    // time of the newest message within the time range of the packets
+    pub fn progress(&self, time: i64) -> f64 {
+        match (self.first_packet, self.last_packet) {
+            (Some(first), Some(last)) if last > first => {
+                ((time - first) as f64 / (last - first) as f64).clamp(0.0, 1.0)
+            }
+            (Some(first), Some(_)) if time >= first => 1.0,
+            _ => 0.0,
+        }
+    }
+}
+
+pub type Pcaps = Vec<Pcap>;
+
+// Replays
+//--------------------------------------------------------------------------------------------------
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplayStatus {
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+/// Replays the capture file of the configuration
+#[derive(Clone, Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct ReplayRequest {
+    pub config_id: i32,
+}
+
+/// Job running netspot over a capture file. Alarms and data messages of the job are stored with
+/// its id as `job_id`, and they are not sent to the live receivers.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize, schemars::JsonSchema)]
+pub struct Replay {
+    pub id: i32,
+    pub config_id: i32,
+    pub pcap_id: i32,
+    pub status: ReplayStatus,
+    /// Start time as nanoseconds since Unix Epoch
+    pub started: i64,
+    /// End time as nanoseconds since Unix Epoch, missing while the job is running
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished: Option<i64>,
+    /// Share of the capture file replayed, from 0 to 1
+    pub progress: f64,
+    /// Reason for a failed replay
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+pub type Replays = Vec<Replay>;
+
+// Unit tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pcap_progress() {
+        let pcap = Pcap {
+            first_packet: Some(1000),
+            last_packet: Some(2000),
+            ..Pcap::default()
+        };
+        assert_eq!(pcap.progress(500), 0.0);
+        assert_eq!(pcap.progress(1250), 0.25);
+        assert_eq!(pcap.progress(2000), 1.0);
+        assert_eq!(pcap.progress(3000), 1.0);
+
+        // Single packet is replayed at once, and an empty file has nothing to replay
+        let pcap = Pcap {
+            first_packet: Some(1000),
+            last_packet: Some(1000),
+            ..Pcap::default()
+        };
+        assert_eq!(pcap.progress(999), 0.0);
+        assert_eq!(pcap.progress(1000), 1.0);
+        assert_eq!(Pcap::default().progress(1000), 0.0);
+    }
+
+    #[test]
+    fn replay_serialize() {
+        let replay = Replay {
+            id: 2,
+            config_id: 3,
+            pcap_id: 1,
+            status: ReplayStatus::Running,
+            started: 10,
+            finished: None,
+            progress: 0.5,
+            error: None,
+        };
+        let json = serde_json::to_string(&replay).unwrap();
+        let expected = concat!(
+            r#"{"id":2,"config_id":3,"pcap_id":1,"status":"running","started":10,"#,
+            r#""progress":0.5}"#
+        );
+        assert_eq!(json, expected);
+    }
+}
@@ -0,0 +1,11 @@
+use rocket_okapi::okapi::schemars;
+use serde::{Deserialize, Serialize};
+
+/// Correlation, suppression and threshold rules are listed by their id and name
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+pub struct RuleItem {
+    pub id: i32,
+    pub name: String,
+}
+
+pub type RuleList = Vec<RuleItem>;
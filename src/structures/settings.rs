@@ -0,0 +1,221 @@
+use crate::structures::incidents::IncidentSettings;
+use crate::structures::severity::SeveritySettings;
+use crate::structures::statistics::MAX_SECONDS;
+use rocket_okapi::okapi::schemars;
+use serde::{Deserialize, Serialize};
+
+// Message retention
+//--------------------------------------------------------------------------------------------------
+
+/// Retention limits for one type of messages. Missing limits are not applied.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+pub struct RetentionPolicy {
+    /// Messages older than this many seconds are removed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_age: Option<u64>,
+    /// Only this many newest messages are kept
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_rows: Option<u64>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+pub struct RetentionSettings {
+    #[serde(default = "RetentionSettings::default_policy")]
+    pub alarms: RetentionPolicy,
+    #[serde(default = "RetentionSettings::default_policy")]
+    pub data: RetentionPolicy,
+    /// Detections are kept as long as the alarms they were made of, unless they have their own
+    /// policy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detections: Option<RetentionPolicy>,
+    /// Oldest messages are removed when the database grows larger than this many bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_database_size: Option<u64>,
+}
+
+impl RetentionPolicy {
+    // Limits are compared with message times and row counts, which are i64 in the database
+    pub fn validate(&self, messages: &str) -> Result<(), String> {
+        if matches!(self.max_age, Some(max_age) if max_age > MAX_SECONDS) {
+            return Err(format!(
+                "Maximum age of {messages} must be at most {MAX_SECONDS} seconds"
+            ));
+        }
+        if matches!(self.max_rows, Some(max_rows) if i64::try_from(max_rows).is_err()) {
+            return Err(format!(
+                "Maximum rows of {messages} must be at most {}",
+                i64::MAX
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl RetentionSettings {
+    // Messages are kept for one hour by default
+    fn default_policy() -> RetentionPolicy {
+        RetentionPolicy {
+            max_age: Some(60 * 60),
+            max_rows: None,
+        }
+    }
+}
+
+impl Default for RetentionSettings {
+    fn default() -> Self {
+        RetentionSettings {
+            alarms: RetentionSettings::default_policy(),
+            data: RetentionSettings::default_policy(),
+            detections: None,
+            max_database_size: None,
+        }
+    }
+}
+
+/// Result of the latest database cleanup
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+pub struct CleanupResult {
+    pub time: i64,
+    pub alarms_removed: u64,
+    pub data_removed: u64,
+    #[serde(default)]
+    pub detections_removed: u64,
+    /// Webhook messages and dead letters removed to keep the database small enough
+    #[serde(default)]
+    pub webhook_messages_removed: u64,
+    /// Bytes used by the database after the cleanup
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub database_size: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+pub struct RetentionStatus {
+    pub policy: RetentionSettings,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_cleanup: Option<CleanupResult>,
+}
+
+// Settings
+//--------------------------------------------------------------------------------------------------
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize, schemars::JsonSchema)]
+pub struct Settings {
+    #[serde(default)]
+    pub retention: RetentionSettings,
+    #[serde(default)]
+    pub incidents: IncidentSettings,
+    #[serde(default)]
+    pub severity: SeveritySettings,
+}
+
+impl Settings {
+    // Checks the settings before they are stored
+    pub fn validate(&self) -> Result<(), String> {
+        self.retention.alarms.validate("alarms")?;
+        self.retention.data.validate("data messages")?;
+        if let Some(detections) = &self.retention.detections {
+            detections.validate("detections")?;
+        }
+        if self.incidents.window > MAX_SECONDS {
+            return Err(format!(
+                "Incident window must be at most {MAX_SECONDS} seconds"
+            ));
+        }
+        self.severity.validate()
+    }
+}
+
+// Unit tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults() {
+        // Empty settings should keep both alarms and data for one hour
+        let settings = serde_json::from_str::<Settings>("{}").unwrap();
+        assert_eq!(settings, Settings::default());
+        let json = serde_json::to_string(&settings).unwrap();
+        let expected = concat!(
+            r#"{"retention":{"alarms":{"max_age":3600},"data":{"max_age":3600}},"#,
+            r#""incidents":{"window":60},"severity":{"base":"low","bands":["#,
+            r#"{"max_probability":0.001,"raise":1},{"max_probability":1e-6,"raise":2}]}}"#
+        );
+        assert_eq!(json, expected);
+    }
+
+    #[test]
+    fn retention() {
+        let json = r#"{
+    "alarms": {"max_age": 86400, "max_rows": 10000},
+    "data": {},
+    "max_database_size": 1048576
+}"#;
+        let retention = serde_json::from_str::<RetentionSettings>(json).unwrap();
+        let expected = RetentionSettings {
+            alarms: RetentionPolicy {
+                max_age: Some(86400),
+                max_rows: Some(10000),
+            },
+            data: RetentionPolicy {
+                max_age: None,
+                max_rows: None,
+            },
+            detections: None,
+            max_database_size: Some(1048576),
+        };
+        assert_eq!(retention, expected);
+    }
+
+    #[test]
+    fn validation() {
+        let mut settings = Settings::default();
+        assert_eq!(settings.validate(), Ok(()));
+        settings.retention.alarms.max_age = Some(MAX_SECONDS);
+        settings.retention.data.max_rows = Some(i64::MAX as u64);
+        assert_eq!(settings.validate(), Ok(()));
+
+        // Limits that would wrap around as message times or row counts are rejected
+        settings.retention.alarms.max_age = Some(u64::MAX);
+        assert!(settings.validate().is_err());
+        settings.retention.alarms.max_age = None;
+        settings.retention.data.max_rows = Some(u64::MAX);
+        assert!(settings.validate().is_err());
+        settings.retention.data.max_rows = None;
+        settings.retention.detections = Some(RetentionPolicy {
+            max_age: Some(u64::MAX),
+            max_rows: None,
+        });
+        assert!(settings.validate().is_err());
+        settings.retention.detections = None;
+        settings.incidents.window = MAX_SECONDS + 1;
+        assert!(settings.validate().is_err());
+    }
+
+    #[test]
+    fn retention_status() {
+        let status = RetentionStatus {
+            policy: RetentionSettings::default(),
+            last_cleanup: Some(CleanupResult {
+                time: 1,
+                alarms_removed: 2,
+                data_removed: 3,
+                detections_removed: 0,
+                webhook_messages_removed: 0,
+                database_size: Some(4096),
+                error: None,
+            }),
+        };
+        let json = serde_json::to_string(&status).unwrap();
+        let expected = concat!(
+            r#"{"policy":{"alarms":{"max_age":3600},"data":{"max_age":3600}},"#,
+            r#""last_cleanup":{"time":1,"alarms_removed":2,"data_removed":3,"#,
+            r#""detections_removed":0,"webhook_messages_removed":0,"database_size":4096}}"#
+        );
+        assert_eq!(json, expected);
+    }
+}
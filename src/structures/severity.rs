@@ -0,0 +1,182 @@
+use crate::structures::statistics::{AlarmMessage, Severity, Stat, MAX_SECONDS};
+use rocket_okapi::okapi::schemars;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Alarms with at most this probability are raised by the given number of levels
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize, schemars::JsonSchema)]
+pub struct SeverityBand {
+    pub max_probability: f64,
+    pub raise: usize,
+}
+
+/// Repeated alarms of the same configuration, stat and status raise the severity
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+pub struct SeverityEscalation {
+    /// Earlier alarms within this many seconds are counted as repeats
+    pub window: u64,
+    /// Severity is raised one level for each this many repeats
+    pub repeats: u32,
+}
+
+/// Severity of an alarm is the base severity of its stat, raised by the probability band and the
+/// number of repeats
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize, schemars::JsonSchema)]
+pub struct SeveritySettings {
+    /// Base severity of the stats that are not listed in `stats`
+    #[serde(default = "SeveritySettings::default_base")]
+    pub base: Severity,
+    /// Base severity of each stat
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub stats: HashMap<Stat, Severity>,
+    /// Probability bands, of which the band raising the most is used
+    #[serde(default = "SeveritySettings::default_bands")]
+    pub bands: Vec<SeverityBand>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub escalation: Option<SeverityEscalation>,
+}
+
+impl SeveritySettings {
+    fn default_base() -> Severity {
+        Severity::Low
+    }
+
+    fn default_bands() -> Vec<SeverityBand> {
+        vec![
+            SeverityBand {
+                max_probability: 1e-3,
+                raise: 1,
+            },
+            SeverityBand {
+                max_probability: 1e-6,
+                raise: 2,
+            },
+        ]
+    }
+
+    // Checks the settings before they are stored
+    pub fn validate(&self) -> Result<(), String> {
+        for band in &self.bands {
+            if !(0.0..=1.0).contains(&band.max_probability) {
+                return Err("Maximum probability of a band must be from 0 to 1".to_string());
+            }
+        }
+        if let Some(escalation) = &self.escalation {
+            if escalation.repeats == 0 {
+                return Err("Escalation repeats must be at least 1".to_string());
+            }
+            if escalation.window > MAX_SECONDS {
+                return Err(format!(
+                    "Escalation window must be at most {MAX_SECONDS} seconds"
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // Repeats are the earlier alarms counted within the escalation window
+    pub fn severity(&self, alarm: &AlarmMessage, repeats: usize) -> Severity {
+        let base = self.stats.get(&alarm.stat).copied().unwrap_or(self.base);
+        let band = self
+            .bands
+            .iter()
+            .filter(|band| alarm.probability <= band.max_probability)
+            .map(|band| band.raise)
+            .max()
+            .unwrap_or_default();
+        let escalation = match &self.escalation {
+            Some(escalation) => repeats
+                .checked_div(escalation.repeats as usize)
+                .unwrap_or_default(),
+            None => 0,
+        };
+        base.raise(band.saturating_add(escalation))
+    }
+}
+
+impl Default for SeveritySettings {
+    fn default() -> Self {
+        SeveritySettings {
+            base: SeveritySettings::default_base(),
+            stats: HashMap::new(),
+            bands: SeveritySettings::default_bands(),
+            escalation: None,
+        }
+    }
+}
+
+// Unit tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(
+        settings: &SeveritySettings,
+        stat: Stat,
+        probability: f64,
+        repeats: usize,
+    ) -> Severity {
+        let alarm = AlarmMessage {
+            stat,
+            probability,
+            ..AlarmMessage::default()
+        };
+        settings.severity(&alarm, repeats)
+    }
+
+    #[test]
+    fn scoring() {
+        // Probability bands raise the base severity
+        let settings = serde_json::from_str::<SeveritySettings>("{}").unwrap();
+        assert_eq!(settings, SeveritySettings::default());
+        assert_eq!(score(&settings, Stat::RSyn, 0.5, 0), Severity::Low);
+        assert_eq!(score(&settings, Stat::RSyn, 1e-4, 0), Severity::Medium);
+        assert_eq!(score(&settings, Stat::RSyn, 0.0, 0), Severity::High);
+        assert_eq!(score(&settings, Stat::RSyn, 0.5, 9), Severity::Low);
+
+        let json = r#"{"base": "info", "stats": {"R_SYN": "medium"},
+            "bands": [{"max_probability": 0.01, "raise": 1}],
+            "escalation": {"window": 300, "repeats": 2}}"#;
+        let settings = serde_json::from_str::<SeveritySettings>(json).unwrap();
+        assert_eq!(score(&settings, Stat::Traffic, 0.5, 0), Severity::Info);
+        assert_eq!(score(&settings, Stat::RSyn, 0.5, 0), Severity::Medium);
+        assert_eq!(score(&settings, Stat::RSyn, 0.001, 0), Severity::High);
+
+        // Every two repeats raise one level, up to critical
+        assert_eq!(score(&settings, Stat::Traffic, 0.5, 1), Severity::Info);
+        assert_eq!(score(&settings, Stat::Traffic, 0.5, 2), Severity::Low);
+        assert_eq!(score(&settings, Stat::RSyn, 0.001, 20), Severity::Critical);
+    }
+
+    #[test]
+    fn validation() {
+        let mut settings = SeveritySettings {
+            escalation: Some(SeverityEscalation {
+                window: MAX_SECONDS,
+                repeats: 1,
+            }),
+            ..SeveritySettings::default()
+        };
+        assert_eq!(settings.validate(), Ok(()));
+
+        // Probabilities outside 0 to 1, zero repeats and too long windows are rejected
+        settings.bands[0].max_probability = f64::NAN;
+        assert!(settings.validate().is_err());
+        settings.bands[0].max_probability = -0.5;
+        assert!(settings.validate().is_err());
+        settings.bands = vec![];
+        assert_eq!(settings.validate(), Ok(()));
+        settings.escalation = Some(SeverityEscalation {
+            window: 60,
+            repeats: 0,
+        });
+        assert!(settings.validate().is_err());
+        settings.escalation = Some(SeverityEscalation {
+            window: MAX_SECONDS + 1,
+            repeats: 1,
+        });
+        assert!(settings.validate().is_err());
+    }
+}
@@ -1,61 +1,254 @@
+use crate::structures::correlations::Detection;
+use crate::structures::incidents::Incident;
+use rocket::{FromForm, FromFormField};
 use rocket_okapi::okapi::schemars;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
 
-#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Deserialize,
+    FromFormField,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    schemars::JsonSchema,
+)]
 #[serde(rename_all = "lowercase")]
 pub enum MessageType {
     #[default]
+    #[field(value = "alarm")]
     Alarm,
+    #[field(value = "data")]
     Data,
+    #[field(value = "incident")]
+    Incident,
+    #[field(value = "detection")]
+    Detection,
 }
 
-#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+#[derive(
+    Clone,
+    Debug,
+    Default,
+    Deserialize,
+    FromFormField,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    schemars::JsonSchema,
+)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum Stat {
     #[default]
+    #[field(value = "AVG_PKT_SIZE")]
     AvgPktSize,
+    #[field(value = "PERF")]
     Perf,
+    #[field(value = "R_ACK")]
     RAck,
+    #[field(value = "R_ARP")]
     RArp,
+    #[field(value = "R_DST_SRC")]
     RDstSrc,
+    #[field(value = "R_DST_SRC_PORT")]
     RDstSrcPort,
+    #[field(value = "R_ICMP")]
     RIcmp,
+    #[field(value = "R_IP")]
     RIp,
+    #[field(value = "R_SYN")]
     RSyn,
+    #[field(value = "TRAFFIC")]
     Traffic,
 }
 
-#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+#[derive(
+    Clone,
+    Debug,
+    Default,
+    Deserialize,
+    FromFormField,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    schemars::JsonSchema,
+)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum AlertStatus {
     #[default]
+    #[field(value = "DOWN_ALERT")]
     DownAlert,
+    #[field(value = "UP_ALERT")]
     UpAlert,
 }
 
+/// Severity levels from the least to the most severe
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Deserialize,
+    FromFormField,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    schemars::JsonSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    #[default]
+    #[field(value = "info")]
+    Info,
+    #[field(value = "low")]
+    Low,
+    #[field(value = "medium")]
+    Medium,
+    #[field(value = "high")]
+    High,
+    #[field(value = "critical")]
+    Critical,
+}
+
+impl Severity {
+    const LEVELS: [Severity; 5] = [
+        Severity::Info,
+        Severity::Low,
+        Severity::Medium,
+        Severity::High,
+        Severity::Critical,
+    ];
+
+    // Raises the severity by the given number of levels, at most to critical
+    pub fn raise(self, levels: usize) -> Severity {
+        let index = (self as usize).saturating_add(levels);
+        Severity::LEVELS[index.min(Severity::LEVELS.len() - 1)]
+    }
+
+    // Levels from this severity to the most severe
+    pub fn and_above(self) -> Vec<Severity> {
+        Severity::LEVELS[self as usize..].to_vec()
+    }
+}
+
 #[derive(Clone, Debug, Default, Deserialize, Serialize, schemars::JsonSchema)]
 pub struct AlarmMessage {
+    /// Id of the stored alarm, missing until the alarm is stored
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<i32>,
     pub time: i64,
     pub name: String,
+    /// Configuration that produced the message, missing for test alarms
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config_id: Option<i32>,
+    /// Replay job that produced the message, missing for live messages
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job_id: Option<i32>,
     pub series: String,
     pub stat: Stat,
     pub status: AlertStatus,
     pub value: f64,
     pub probability: f64,
     pub code: i32,
+    /// Severity given by the severity settings when the alarm was received
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub severity: Option<Severity>,
+    /// Suppressed alarms are stored, but not sent to webhooks, the DHT or the incidents
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub suppressed: bool,
+    /// Suppression rule that matched the alarm
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suppression_rule_id: Option<i32>,
+    /// Review of the stored alarm by an operator
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub review: Option<AlarmReview>,
     #[serde(rename = "type")]
     pub msg_type: MessageType,
 }
 
 pub type AlarmMessages = Vec<AlarmMessage>;
 
+#[derive(
+    Clone, Debug, Deserialize, FromFormField, PartialEq, Eq, Serialize, schemars::JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum AlarmLabel {
+    #[field(value = "acknowledged")]
+    Acknowledged,
+    #[field(value = "false_positive")]
+    FalsePositive,
+    #[field(value = "investigated")]
+    Investigated,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize, schemars::JsonSchema)]
+pub struct AlarmReview {
+    /// Operator who last changed the review
+    pub user: String,
+    /// Time of the last change as nanoseconds since Unix Epoch
+    pub time: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<AlarmLabel>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+/// Changes to the review of an alarm. Missing label and note are left unchanged, and null
+/// removes them.
+#[derive(Clone, Debug, Deserialize, Serialize, schemars::JsonSchema)]
+pub struct AlarmReviewUpdate {
+    pub user: String,
+    #[serde(
+        default,
+        deserialize_with = "nullable",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub label: Option<Option<AlarmLabel>>,
+    #[serde(
+        default,
+        deserialize_with = "nullable",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub note: Option<Option<String>>,
+}
+
+// Null is read as Some(None), so that it can be told apart from a missing field
+fn nullable<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
 #[derive(Clone, Debug, Default, Deserialize, Serialize, schemars::JsonSchema)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub struct DataMessage {
+    /// Id of the stored message, missing until the message is stored
+    #[serde(rename = "id", default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<i32>,
     #[serde(rename = "time")]
     pub time: i64,
     #[serde(rename = "name")]
     pub name: String,
+    /// Configuration that produced the message
+    #[serde(rename = "config_id", default, skip_serializing_if = "Option::is_none")]
+    pub config_id: Option<i32>,
+    /// Replay job that produced the message, missing for live messages
+    #[serde(rename = "job_id", default, skip_serializing_if = "Option::is_none")]
+    pub job_id: Option<i32>,
     #[serde(rename = "series")]
     pub series: String,
     #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -122,23 +315,314 @@ pub struct DataMessage {
     pub msg_type: MessageType,
 }
 
+impl DataMessage {
+    /// Returns the value of the given stat, when the message has it
+    pub fn value(&self, stat: &Stat) -> Option<f64> {
+        match stat {
+            Stat::AvgPktSize => self.avg_pkt_size,
+            Stat::Perf => self.perf,
+            Stat::RAck => self.r_ack,
+            Stat::RArp => self.r_arp,
+            Stat::RDstSrc => self.r_dst_src,
+            Stat::RDstSrcPort => self.r_dst_src_port,
+            Stat::RIcmp => self.r_icmp,
+            Stat::RIp => self.r_ip,
+            Stat::RSyn => self.r_syn,
+            Stat::Traffic => self.traffic,
+        }
+    }
+
+    /// Returns all values in the message, named as in JSON
+    pub fn stat_values(&self) -> Vec<(&'static str, f64)> {
+        self.fields()
+            .into_iter()
+            .filter_map(|(name, value)| value.map(|value| (name, value)))
+            .collect()
+    }
+
+    /// Returns the value named as in JSON, like `TRAFFIC_UP`, when the message has it
+    pub fn field_value(&self, field: &str) -> Option<f64> {
+        self.fields()
+            .into_iter()
+            .find(|(name, _)| *name == field)
+            .and_then(|(_, value)| value)
+    }
+
+    /// Tells whether data messages have a value with the name
+    pub fn is_field(field: &str) -> bool {
+        DataMessage::default()
+            .fields()
+            .iter()
+            .any(|(name, _)| *name == field)
+    }
+
+    fn fields(&self) -> [(&'static str, Option<f64>); 30] {
+        [
+            ("AVG_PKT_SIZE", self.avg_pkt_size),
+            ("AVG_PKT_SIZE_DOWN", self.avg_pkt_size_down),
+            ("AVG_PKT_SIZE_UP", self.avg_pkt_size_up),
+            ("PERF", self.perf),
+            ("PERF_DOWN", self.perf_down),
+            ("PERF_UP", self.perf_up),
+            ("R_ACK", self.r_ack),
+            ("R_ACK_DOWN", self.r_ack_down),
+            ("R_ACK_UP", self.r_ack_up),
+            ("R_ARP", self.r_arp),
+            ("R_ARP_DOWN", self.r_arp_down),
+            ("R_ARP_UP", self.r_arp_up),
+            ("R_DST_SRC", self.r_dst_src),
+            ("R_DST_SRC_DOWN", self.r_dst_src_down),
+            ("R_DST_SRC_UP", self.r_dst_src_up),
+            ("R_DST_SRC_PORT", self.r_dst_src_port),
+            ("R_DST_SRC_PORT_DOWN", self.r_dst_src_port_down),
+            ("R_DST_SRC_PORT_UP", self.r_dst_src_port_up),
+            ("R_ICMP", self.r_icmp),
+            ("R_ICMP_DOWN", self.r_icmp_down),
+            ("R_ICMP_UP", self.r_icmp_up),
+            ("R_IP", self.r_ip),
+            ("R_IP_DOWN", self.r_ip_down),
+            ("R_IP_UP", self.r_ip_up),
+            ("R_SYN", self.r_syn),
+            ("R_SYN_DOWN", self.r_syn_down),
+            ("R_SYN_UP", self.r_syn_up),
+            ("TRAFFIC", self.traffic),
+            ("TRAFFIC_DOWN", self.traffic_down),
+            ("TRAFFIC_UP", self.traffic_up),
+        ]
+    }
+}
+
 pub type DataMessages = Vec<DataMessage>;
 
-#[derive(Clone, Debug)]
+// Messages are serialized as they are, the type field tells them apart
+#[derive(Clone, Debug, Serialize)]
+#[serde(untagged)]
 pub enum Message {
     Alarm(Box<AlarmMessage>),
     Data(Box<DataMessage>),
+    /// Sent when an incident is opened and when it is closed
+    Incident(Box<Incident>),
+    /// Sent when the alarms of a correlation rule have been seen together
+    Detection(Box<Detection>),
 }
 
 impl Message {
+    pub fn config_id(&self) -> Option<i32> {
+        match self {
+            Message::Alarm(value) => value.config_id,
+            Message::Data(value) => value.config_id,
+            Message::Incident(value) => value.config_id,
+            Message::Detection(value) => value.config_id,
+        }
+    }
+
+    pub fn time(&self) -> i64 {
+        match self {
+            Message::Alarm(value) => value.time,
+            Message::Data(value) => value.time,
+            Message::Incident(value) => value.closed.unwrap_or(value.first_seen),
+            Message::Detection(value) => value.time,
+        }
+    }
+
     pub fn to_json(&self) -> Result<String, serde_json::Error> {
         match self {
             Message::Alarm(value) => serde_json::to_string(value),
             Message::Data(value) => serde_json::to_string(value),
+            Message::Incident(value) => serde_json::to_string(value),
+            Message::Detection(value) => serde_json::to_string(value),
+        }
+    }
+
+    // Suppressed alarms are only stored and streamed
+    pub fn is_suppressed(&self) -> bool {
+        matches!(self, Message::Alarm(alarm) if alarm.suppressed)
+    }
+}
+
+// Filter for live messages
+//--------------------------------------------------------------------------------------------------
+
+/// Selects which live messages are delivered. Missing fields match every message.
+///
+/// Alarm status and probability only exist in alarms and incidents, so data messages never match
+/// when they are given. Data messages match a stat when they have a value for it. Incidents and
+/// detections are only sent to filters that ask for their type. Detections match a stat when the
+/// rule combines it, and they have no configuration name, status or probability.
+#[derive(
+    Clone, Debug, Default, Deserialize, FromForm, PartialEq, Serialize, schemars::JsonSchema,
+)]
+pub struct MessageFilter {
+    /// Only alarms, data messages, incidents or detections
+    #[field(name = "type")]
+    #[serde(default, rename = "type", skip_serializing_if = "Option::is_none")]
+    pub msg_type: Option<MessageType>,
+    /// Only messages from this configuration id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config_id: Option<i32>,
+    /// Only messages from configurations with this name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stat: Option<Stat>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<AlertStatus>,
+    /// Only alarms with at least this probability
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_probability: Option<f64>,
+}
+
+impl MessageFilter {
+    pub fn matches(&self, message: &Message) -> bool {
+        if self.config_id.is_some() && self.config_id != message.config_id() {
+            return false;
+        }
+        match message {
+            Message::Alarm(alarm) => {
+                matches!(self.msg_type, None | Some(MessageType::Alarm))
+                    && self.name.iter().all(|name| *name == alarm.name)
+                    && self.stat.iter().all(|stat| *stat == alarm.stat)
+                    && self.status.iter().all(|status| *status == alarm.status)
+                    && self
+                        .min_probability
+                        .iter()
+                        .all(|probability| alarm.probability >= *probability)
+            }
+            Message::Data(data) => {
+                matches!(self.msg_type, None | Some(MessageType::Data))
+                    && self.name.iter().all(|name| *name == data.name)
+                    && self.stat.iter().all(|stat| data.value(stat).is_some())
+                    && self.status.is_none()
+                    && self.min_probability.is_none()
+            }
+            Message::Incident(incident) => {
+                self.msg_type == Some(MessageType::Incident)
+                    && self.name.iter().all(|name| *name == incident.name)
+                    && self.stat.iter().all(|stat| *stat == incident.stat)
+                    && self.status.iter().all(|status| *status == incident.status)
+                    && self
+                        .min_probability
+                        .iter()
+                        .all(|probability| incident.min_probability >= *probability)
+            }
+            Message::Detection(detection) => {
+                self.msg_type == Some(MessageType::Detection)
+                    && self.name.is_none()
+                    && self.stat.iter().all(|stat| detection.stats.contains(stat))
+                    && self.status.is_none()
+                    && self.min_probability.is_none()
+            }
         }
     }
 }
 
+// Queries for stored messages
+//--------------------------------------------------------------------------------------------------
+
+/// Position after the last message of a page. Messages are ordered by time and then by their
+/// database id, so that messages sharing a timestamp are not skipped between pages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MessageCursor {
+    pub time: i64,
+    pub id: i32,
+}
+
+impl fmt::Display for MessageCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}_{}", self.time, self.id)
+    }
+}
+
+impl FromStr for MessageCursor {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (time, id) = value
+            .split_once('_')
+            .ok_or_else(|| format!("Invalid cursor: {value}"))?;
+        Ok(MessageCursor {
+            time: time
+                .parse()
+                .map_err(|_| format!("Invalid cursor: {value}"))?,
+            id: id.parse().map_err(|_| format!("Invalid cursor: {value}"))?,
+        })
+    }
+}
+
+/// Query parameters for reading stored alarms
+#[derive(Clone, Debug, Default, FromForm, schemars::JsonSchema)]
+pub struct AlarmQuery {
+    /// Only messages newer than this time
+    pub time: Option<i64>,
+    /// Only this many newest messages
+    pub last: Option<i32>,
+    /// Only messages from this time onwards
+    pub from: Option<i64>,
+    /// Only messages before this time
+    pub to: Option<i64>,
+    /// Only messages from this configuration id
+    pub config_id: Option<i32>,
+    /// Only messages from this replay job, instead of the live messages
+    pub job_id: Option<i32>,
+    /// Only messages from configurations with this name
+    pub name: Option<String>,
+    pub stat: Option<Stat>,
+    pub status: Option<AlertStatus>,
+    /// Only alarms with at least this probability
+    pub min_probability: Option<f64>,
+    /// Only alarms with this review label
+    pub label: Option<AlarmLabel>,
+    /// Only reviewed alarms, or only alarms without a review
+    pub reviewed: Option<bool>,
+    /// Only alarms last reviewed by this user
+    pub reviewed_by: Option<String>,
+    /// Only suppressed alarms, or only alarms that were not suppressed
+    pub suppressed: Option<bool>,
+    /// Only alarms with at least this severity
+    pub min_severity: Option<Severity>,
+    /// Continue from the `next` cursor of the previous page
+    pub cursor: Option<String>,
+    /// Page size
+    pub limit: Option<i32>,
+}
+
+/// Query parameters for reading stored data messages
+#[derive(Clone, Debug, Default, FromForm, schemars::JsonSchema)]
+pub struct DataQuery {
+    /// Only messages newer than this time
+    pub time: Option<i64>,
+    /// Only this many newest messages
+    pub last: Option<i32>,
+    /// Only messages from this time onwards
+    pub from: Option<i64>,
+    /// Only messages before this time
+    pub to: Option<i64>,
+    /// Only messages from this configuration id
+    pub config_id: Option<i32>,
+    /// Only messages from this replay job, instead of the live messages
+    pub job_id: Option<i32>,
+    /// Only messages from configurations with this name
+    pub name: Option<String>,
+    /// Continue from the `next` cursor of the previous page
+    pub cursor: Option<String>,
+    /// Page size
+    pub limit: Option<i32>,
+}
+
+// Message times
+//--------------------------------------------------------------------------------------------------
+
+/// Longest duration in seconds that fits in message times, which are nanoseconds in an i64
+pub const MAX_SECONDS: u64 = i64::MAX as u64 / 1_000_000_000;
+
+// Converts seconds to nanoseconds for comparing message times, or None when it does not fit
+pub fn seconds_as_nanos(seconds: u64) -> Option<i64> {
+    i64::try_from(Duration::from_secs(seconds).as_nanos()).ok()
+}
+
+// Unit tests
+//--------------------------------------------------------------------------------------------------
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -152,6 +636,22 @@ mod tests {
         assert_eq!(json, expected);
     }
 
+    #[test]
+    fn severities() {
+        let test = vec![Severity::Info, Severity::Medium, Severity::Critical];
+        let json = serde_json::to_string(&test).unwrap();
+        let expected = r#"["info","medium","critical"]"#;
+        assert_eq!(json, expected);
+        assert!(Severity::Low < Severity::High);
+        assert_eq!(Severity::default(), Severity::Info);
+        assert_eq!(Severity::Low.raise(2), Severity::High);
+        assert_eq!(Severity::High.raise(5), Severity::Critical);
+        assert_eq!(
+            Severity::High.and_above(),
+            [Severity::High, Severity::Critical]
+        );
+    }
+
     #[test]
     fn stats() {
         // These should be presented as a screaming snake case string
@@ -215,14 +715,21 @@ mod tests {
     #[test]
     fn alarm_message_serialize() {
         let alarm = AlarmMessage {
+            id: None,
             time: 1,
             name: "Example".to_string(),
+            config_id: None,
+            job_id: None,
             series: "Series".to_string(),
             stat: Stat::AvgPktSize,
             status: AlertStatus::DownAlert,
             value: 1.0,
             probability: 0.5,
             code: 1,
+            severity: Some(Severity::Medium),
+            suppressed: false,
+            suppression_rule_id: None,
+            review: None,
             msg_type: MessageType::Alarm,
         };
         let json = serde_json::to_string(&alarm).unwrap();
@@ -236,10 +743,30 @@ mod tests {
             r#""value":1.0,"#,
             r#""probability":0.5,"#,
             r#""code":1,"#,
+            r#""severity":"medium","#,
             r#""type":"alarm""#,
             r#"}"#
         );
         assert_eq!(json, expected);
+
+        // Stored alarms have an id and possibly a review
+        let alarm = AlarmMessage {
+            id: Some(7),
+            review: Some(AlarmReview {
+                user: "alice".to_string(),
+                time: 2,
+                label: Some(AlarmLabel::FalsePositive),
+                note: None,
+            }),
+            ..alarm
+        };
+        let json = serde_json::to_string(&alarm).unwrap();
+        assert!(json.starts_with(r#"{"id":7,"time":1,"#));
+        assert!(json.ends_with(concat!(
+            r#""code":1,"severity":"medium","#,
+            r#""review":{"user":"alice","time":2,"label":"false_positive"},"#,
+            r#""type":"alarm"}"#
+        )));
     }
 
     #[test]
@@ -348,19 +875,28 @@ mod tests {
     #[test]
     fn message_to_json() {
         let message = Message::Alarm(Box::new(AlarmMessage {
+            id: None,
             time: 1,
             name: "AlarmName".to_string(),
+            config_id: Some(5),
+            job_id: None,
             series: "AlarmSeries".to_string(),
             stat: Stat::AvgPktSize,
             status: AlertStatus::UpAlert,
             value: 2.0,
             probability: 3.0,
             code: 4,
+            severity: None,
+            suppressed: false,
+            suppression_rule_id: None,
+            review: None,
             msg_type: MessageType::Alarm,
         }));
+        assert_eq!(message.config_id(), Some(5));
         let json = message.to_json().unwrap();
         let expected = concat!(
-            r#"{"time":1,"name":"AlarmName","series":"AlarmSeries","stat":"AVG_PKT_SIZE","#,
+            r#"{"time":1,"name":"AlarmName","config_id":5,"series":"AlarmSeries","#,
+            r#""stat":"AVG_PKT_SIZE","#,
             r#""status":"UP_ALERT","value":2.0,"probability":3.0,"code":4,"type":"alarm"}"#
         );
         assert_eq!(json, expected);
@@ -375,5 +911,137 @@ mod tests {
         let json = message.to_json().unwrap();
         let expected = r#"{"time":1,"name":"DataName","series":"DataSeries","type":"data"}"#;
         assert_eq!(json, expected);
+        assert_eq!(message.config_id(), None);
+    }
+
+    #[test]
+    fn message_filter() {
+        let alarm = Message::Alarm(Box::new(AlarmMessage {
+            name: "Alarms".to_string(),
+            config_id: Some(1),
+            stat: Stat::RSyn,
+            status: AlertStatus::UpAlert,
+            probability: 0.5,
+            ..AlarmMessage::default()
+        }));
+        let data = Message::Data(Box::new(DataMessage {
+            name: "Data".to_string(),
+            config_id: Some(2),
+            r_syn: Some(1.0),
+            msg_type: MessageType::Data,
+            ..DataMessage::default()
+        }));
+
+        // Empty filter matches everything
+        let filter = MessageFilter::default();
+        assert!(filter.matches(&alarm));
+        assert!(filter.matches(&data));
+
+        let filter = MessageFilter {
+            msg_type: Some(MessageType::Data),
+            ..MessageFilter::default()
+        };
+        assert!(!filter.matches(&alarm));
+        assert!(filter.matches(&data));
+
+        let filter = MessageFilter {
+            config_id: Some(1),
+            ..MessageFilter::default()
+        };
+        assert!(filter.matches(&alarm));
+        assert!(!filter.matches(&data));
+
+        let filter = MessageFilter {
+            name: Some("Data".to_string()),
+            ..MessageFilter::default()
+        };
+        assert!(!filter.matches(&alarm));
+        assert!(filter.matches(&data));
+
+        let filter = MessageFilter {
+            stat: Some(Stat::RSyn),
+            ..MessageFilter::default()
+        };
+        assert!(filter.matches(&alarm));
+        assert!(filter.matches(&data));
+        let filter = MessageFilter {
+            stat: Some(Stat::Perf),
+            ..MessageFilter::default()
+        };
+        assert!(!filter.matches(&alarm));
+        assert!(!filter.matches(&data));
+
+        // Alarm only filters
+        let filter = MessageFilter {
+            status: Some(AlertStatus::UpAlert),
+            min_probability: Some(0.5),
+            ..MessageFilter::default()
+        };
+        assert!(filter.matches(&alarm));
+        assert!(!filter.matches(&data));
+        let filter = MessageFilter {
+            min_probability: Some(0.75),
+            ..MessageFilter::default()
+        };
+        assert!(!filter.matches(&alarm));
+
+        // Incidents are only matched when asked for
+        let incident = Message::Incident(Box::new(Incident {
+            config_id: Some(1),
+            stat: Stat::RSyn,
+            ..Incident::default()
+        }));
+        assert!(!MessageFilter::default().matches(&incident));
+        let filter = MessageFilter {
+            msg_type: Some(MessageType::Incident),
+            stat: Some(Stat::RSyn),
+            ..MessageFilter::default()
+        };
+        assert!(filter.matches(&incident));
+        assert!(!filter.matches(&alarm));
+
+        // Detections as well, and their stats are the ones combined by the rule
+        let detection = Message::Detection(Box::new(Detection {
+            config_id: Some(1),
+            stats: vec![Stat::RSyn, Stat::Traffic],
+            ..Detection::default()
+        }));
+        assert!(!MessageFilter::default().matches(&detection));
+        let filter = MessageFilter {
+            msg_type: Some(MessageType::Detection),
+            stat: Some(Stat::Traffic),
+            ..MessageFilter::default()
+        };
+        assert!(filter.matches(&detection));
+        assert!(!filter.matches(&incident));
+        let filter = MessageFilter {
+            msg_type: Some(MessageType::Detection),
+            status: Some(AlertStatus::UpAlert),
+            ..MessageFilter::default()
+        };
+        assert!(!filter.matches(&detection));
+    }
+
+    #[test]
+    fn message_cursor() {
+        let cursor = MessageCursor {
+            time: 1666074152545768954,
+            id: 42,
+        };
+        let token = cursor.to_string();
+        assert_eq!(token, "1666074152545768954_42");
+        assert_eq!(token.parse::<MessageCursor>(), Ok(cursor));
+        assert!("".parse::<MessageCursor>().is_err());
+        assert!("123".parse::<MessageCursor>().is_err());
+        assert!("abc_1".parse::<MessageCursor>().is_err());
+    }
+
+    #[test]
+    fn seconds_as_nanoseconds() {
+        assert_eq!(seconds_as_nanos(0), Some(0));
+        assert_eq!(seconds_as_nanos(60), Some(60_000_000_000));
+        assert!(seconds_as_nanos(MAX_SECONDS).is_some());
+        assert_eq!(seconds_as_nanos(MAX_SECONDS + 1), None);
+        assert_eq!(seconds_as_nanos(u64::MAX), None);
     }
 }
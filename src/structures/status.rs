@@ -1,25 +1,52 @@
+use crate::structures::statistics::MessageType;
 use rocket_okapi::okapi::schemars;
 use serde::{Deserialize, Serialize};
 
 // Process status
 //--------------------------------------------------------------------------------------------------
 
-#[derive(Debug, Deserialize, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Serialize, schemars::JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum ProcessStatus {
     Running,
     Stopped,
     Disabled,
+    Crashed,    // Exited unexpectedly, waiting for automatic restart
+    Restarting, // Restarted automatically, but not yet running long enough to be stable
+    Failed,     // Crashed too many times in a row, must be started manually
+}
+
+// Information about how the netspot process exited
+//--------------------------------------------------------------------------------------------------
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+pub struct ProcessExit {
+    /// Exit time as nanoseconds since Unix Epoch
+    pub time: i64,
+    /// Exit code when the process exited normally
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<i32>,
+    /// Signal number when the process was terminated by a signal
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signal: Option<i32>,
 }
 
 // Netspot status structure
 //--------------------------------------------------------------------------------------------------
 
-#[derive(Debug, Deserialize, Serialize, schemars::JsonSchema)]
+#[derive(Clone, Debug, Deserialize, Serialize, schemars::JsonSchema)]
 pub struct Status {
     pub id: i32,
     pub name: String,
     pub status: ProcessStatus,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub restarts: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_exit: Option<ProcessExit>,
+}
+
+fn is_zero(value: &u32) -> bool {
+    *value == 0
 }
 
 // Collection of statuses are simply vector of Status structures
@@ -27,6 +54,65 @@ pub struct Status {
 
 pub type Statuses = Vec<Status>;
 
+// Actions taken on netspot processes when configurations change
+//--------------------------------------------------------------------------------------------------
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ProcessAction {
+    Unchanged,
+    Updated,   // Configuration changed, but the process was not running
+    Started,   // Configuration was enabled
+    Stopped,   // Configuration was disabled
+    Restarted, // Running process was restarted to use the changed configuration
+    Added,
+    Removed,
+    Failed, // Starting or stopping the process failed
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+pub struct ProcessUpdate {
+    pub id: i32,
+    pub action: ProcessAction,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+pub type ProcessUpdates = Vec<ProcessUpdate>;
+
+// Message counters for the netspot socket listeners
+//--------------------------------------------------------------------------------------------------
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+pub struct MessageCounters {
+    /// Messages parsed successfully
+    pub parsed: u64,
+    /// Messages that were not valid JSON or did not match the expected message structure
+    pub malformed: u64,
+    /// Messages discarded for being larger than the maximum message size
+    pub oversized: u64,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+pub struct ConnectionStatus {
+    pub id: u64,
+    /// Connection time as nanoseconds since Unix Epoch
+    pub connected: i64,
+    pub messages: MessageCounters,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+pub struct ListenerStatus {
+    /// Which messages the listener socket receives
+    pub socket: MessageType,
+    /// Totals over all connections since the server started
+    pub messages: MessageCounters,
+    /// Currently open connections
+    pub connections: Vec<ConnectionStatus>,
+}
+
+pub type ListenerStatuses = Vec<ListenerStatus>;
+
 // Unit tests
 //--------------------------------------------------------------------------------------------------
 
@@ -49,6 +135,8 @@ mod tests {
             id: 1,
             name: "Test".to_string(),
             status: ProcessStatus::Running,
+            restarts: 0,
+            last_exit: None,
         };
         let json = serde_json::to_string(&status).unwrap();
         let expected = r#"{"id":1,"name":"Test","status":"running"}"#;
@@ -82,16 +170,22 @@ mod tests {
                 id: 1,
                 name: "Test".to_string(),
                 status: ProcessStatus::Running,
+                restarts: 0,
+                last_exit: None,
             },
             Status {
                 id: 2,
                 name: "Another test".to_string(),
                 status: ProcessStatus::Stopped,
+                restarts: 0,
+                last_exit: None,
             },
             Status {
                 id: 3,
                 name: "Yet another test".to_string(),
                 status: ProcessStatus::Disabled,
+                restarts: 0,
+                last_exit: None,
             },
         ];
         let json = serde_json::to_string(&statuses).unwrap();
@@ -102,4 +196,90 @@ mod tests {
         );
         assert_eq!(json, expected);
     }
+
+    #[test]
+    fn supervision_statuses() {
+        let statuses = vec![
+            ProcessStatus::Crashed,
+            ProcessStatus::Restarting,
+            ProcessStatus::Failed,
+        ];
+        let json = serde_json::to_string(&statuses).unwrap();
+        let expected = r#"["crashed","restarting","failed"]"#;
+        assert_eq!(json, expected);
+    }
+
+    #[test]
+    fn status_with_exit() {
+        let status = Status {
+            id: 1,
+            name: "Test".to_string(),
+            status: ProcessStatus::Crashed,
+            restarts: 2,
+            last_exit: Some(ProcessExit {
+                time: 3,
+                code: None,
+                signal: Some(9),
+            }),
+        };
+        let json = serde_json::to_string(&status).unwrap();
+        let expected = concat!(
+            r#"{"id":1,"name":"Test","status":"crashed","restarts":2,"#,
+            r#""last_exit":{"time":3,"signal":9}}"#
+        );
+        assert_eq!(json, expected);
+        let status = serde_json::from_str::<Status>(&json).unwrap();
+        assert_eq!(status.restarts, 2);
+        assert_eq!(status.last_exit.unwrap().signal, Some(9));
+    }
+
+    #[test]
+    fn process_updates() {
+        let updates = vec![
+            ProcessUpdate {
+                id: 1,
+                action: ProcessAction::Restarted,
+                error: None,
+            },
+            ProcessUpdate {
+                id: 2,
+                action: ProcessAction::Failed,
+                error: Some("No such file or directory".to_string()),
+            },
+        ];
+        let json = serde_json::to_string(&updates).unwrap();
+        let expected = concat!(
+            r#"[{"id":1,"action":"restarted"},"#,
+            r#"{"id":2,"action":"failed","error":"No such file or directory"}]"#
+        );
+        assert_eq!(json, expected);
+    }
+
+    #[test]
+    fn listener_status_serialize() {
+        let status = ListenerStatus {
+            socket: MessageType::Data,
+            messages: MessageCounters {
+                parsed: 3,
+                malformed: 1,
+                oversized: 0,
+            },
+            connections: vec![ConnectionStatus {
+                id: 1,
+                connected: 2,
+                messages: MessageCounters {
+                    parsed: 3,
+                    malformed: 1,
+                    oversized: 0,
+                },
+            }],
+        };
+        let json = serde_json::to_string(&status).unwrap();
+        let expected = concat!(
+            r#"{"socket":"data","messages":{"parsed":3,"malformed":1,"oversized":0},"#,
+            r#""connections":[{"id":1,"connected":2,"#,
+            r#""messages":{"parsed":3,"malformed":1,"oversized":0}}]}"#
+        );
+        assert_eq!(json, expected);
+    }
 }
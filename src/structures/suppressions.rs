@@ -0,0 +1,60 @@
+use crate::structures::statistics::Stat;
+use rocket_okapi::okapi::schemars;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Suppressed alarms are stored as usual, but they are not sent to the webhooks, the DHT or the
+/// incident engine. Alarms are suppressed when they match the configurations and the stats of
+/// the rule, and their time is within a window of the schedule.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+pub struct SuppressionRule {
+    pub name: String,
+    /// Only alarms from these configuration ids are suppressed, empty for all
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub configurations: Vec<i32>,
+    /// Only alarms of these stats are suppressed, empty for all
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stats: Vec<Stat>,
+    /// Start times of the windows as a cron expression in UTC, with the fields minute, hour, day
+    /// of month, month and day of week. Without a schedule, the rule is in effect all the time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<String>,
+    /// Length of each window in seconds, required with a schedule
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<u64>,
+    /// The rule has no effect after this time, as nanoseconds since Unix Epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<i64>,
+}
+
+// Container for suppression rules
+pub type SuppressionRules = HashMap<i32, SuppressionRule>;
+
+// Unit tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suppression_rule() {
+        let json = r#"{
+    "name": "Nightly backup",
+    "configurations": [1],
+    "stats": ["TRAFFIC", "PERF"],
+    "schedule": "30 2 * * *",
+    "duration": 3600
+}"#;
+        let rule = serde_json::from_str::<SuppressionRule>(json).unwrap();
+        assert_eq!(rule.configurations, vec![1]);
+        assert_eq!(rule.stats, vec![Stat::Traffic, Stat::Perf]);
+        assert_eq!(rule.schedule.as_deref(), Some("30 2 * * *"));
+        assert_eq!(rule.duration, Some(3600));
+        assert_eq!(rule.expires, None);
+
+        // Only the name is required
+        let rule = serde_json::from_str::<SuppressionRule>(r#"{"name": "All"}"#).unwrap();
+        assert_eq!(serde_json::to_string(&rule).unwrap(), r#"{"name":"All"}"#);
+    }
+}
@@ -0,0 +1,123 @@
+use crate::structures::statistics::{AlertStatus, Stat};
+use rocket_okapi::okapi::schemars;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ThresholdCondition {
+    #[default]
+    Above,
+    Below,
+}
+
+impl ThresholdCondition {
+    pub fn holds(&self, value: f64, threshold: f64) -> bool {
+        match self {
+            ThresholdCondition::Above => value > threshold,
+            ThresholdCondition::Below => value < threshold,
+        }
+    }
+
+    // Alarms for values above the threshold are upward alerts
+    pub fn status(&self) -> AlertStatus {
+        match self {
+            ThresholdCondition::Above => AlertStatus::UpAlert,
+            ThresholdCondition::Below => AlertStatus::DownAlert,
+        }
+    }
+}
+
+/// Comparison of any value in the data messages, named as in JSON like `TRAFFIC_UP`
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize, schemars::JsonSchema)]
+pub struct FieldCondition {
+    pub field: String,
+    #[serde(default)]
+    pub condition: ThresholdCondition,
+    pub value: f64,
+}
+
+/// Deterministic rule on the values of the data messages. An alarm is sent when the value of the
+/// stat has been above or below the threshold, together with the other `conditions`, for the hold
+/// time. The next alarm is sent after the values have returned within the thresholds and crossed
+/// them again.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize, schemars::JsonSchema)]
+pub struct ThresholdRule {
+    pub name: String,
+    /// Only data from these configuration ids is checked, empty for all
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub configurations: Vec<i32>,
+    pub stat: Stat,
+    #[serde(default)]
+    pub condition: ThresholdCondition,
+    pub value: f64,
+    /// Other values of the same data message that must cross their thresholds at the same time
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub conditions: Vec<FieldCondition>,
+    /// Seconds the condition must hold before the alarm is sent
+    #[serde(default)]
+    pub hold: u64,
+}
+
+impl ThresholdRule {
+    pub fn accepts_config(&self, config_id: Option<i32>) -> bool {
+        if self.configurations.is_empty() {
+            return true;
+        }
+        match config_id {
+            Some(config_id) => self.configurations.contains(&config_id),
+            None => false,
+        }
+    }
+}
+
+// Container for threshold rules
+pub type ThresholdRules = HashMap<i32, ThresholdRule>;
+
+// Unit tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn threshold_rule() {
+        let json = r#"{"name": "Cameras idle", "configurations": [2], "stat": "TRAFFIC",
+            "condition": "below", "value": 1.0, "hold": 300}"#;
+        let rule = serde_json::from_str::<ThresholdRule>(json).unwrap();
+        assert_eq!(rule.condition, ThresholdCondition::Below);
+        assert_eq!(rule.condition.status(), AlertStatus::DownAlert);
+        assert!(rule.condition.holds(0.5, rule.value));
+        assert!(!rule.condition.holds(1.0, rule.value));
+        assert!(rule.accepts_config(Some(2)));
+        assert!(!rule.accepts_config(None));
+        assert!(rule.conditions.is_empty());
+
+        // Conditions on other values of the message
+        let json = r#"{"name": "Upload flood", "stat": "R_SYN", "value": 0.8,
+            "conditions": [{"field": "TRAFFIC_UP", "value": 1000.0}]}"#;
+        let rule = serde_json::from_str::<ThresholdRule>(json).unwrap();
+        assert_eq!(
+            rule.conditions,
+            vec![FieldCondition {
+                field: "TRAFFIC_UP".to_string(),
+                condition: ThresholdCondition::Above,
+                value: 1000.0,
+            }]
+        );
+
+        // Values above the threshold without a hold time by default
+        let json = r#"{"name": "SYN flood", "stat": "R_SYN", "value": 0.8}"#;
+        let rule = serde_json::from_str::<ThresholdRule>(json).unwrap();
+        assert_eq!(rule.condition, ThresholdCondition::Above);
+        assert_eq!(rule.hold, 0);
+        assert!(rule.accepts_config(None));
+        let json = serde_json::to_string(&rule).unwrap();
+        let expected = concat!(
+            r#"{"name":"SYN flood","stat":"R_SYN","condition":"above","value":0.8,"#,
+            r#""hold":0}"#
+        );
+        assert_eq!(json, expected);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::structures::statistics::{AlertStatus, Message, Severity, Stat};
 use rocket_okapi::okapi::schemars;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -23,21 +24,283 @@ pub enum WebhookStatsType {
     #[default]
     Both, // Both alarms and data
     Data,   // Only data
+    Incidents, // Only incidents, when they are opened and closed
+    Detections, // Only detections of the correlation rules
+}
+
+/// Failed deliveries are retried with growing delays until either limit is reached. After that,
+/// the message is kept as a dead letter.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+pub struct WebhookRetryPolicy {
+    /// Delivery attempts before giving up
+    #[serde(default = "WebhookRetryPolicy::default_max_attempts")]
+    pub max_attempts: u32,
+    /// Seconds after which a message is no longer retried
+    #[serde(default = "WebhookRetryPolicy::default_max_age")]
+    pub max_age: u64,
+}
+
+impl WebhookRetryPolicy {
+    fn default_max_attempts() -> u32 {
+        10
+    }
+
+    fn default_max_age() -> u64 {
+        60 * 60
+    }
+
+    fn is_default(&self) -> bool {
+        *self == WebhookRetryPolicy::default()
+    }
+}
+
+impl Default for WebhookRetryPolicy {
+    fn default() -> Self {
+        WebhookRetryPolicy {
+            max_attempts: WebhookRetryPolicy::default_max_attempts(),
+            max_age: WebhookRetryPolicy::default_max_age(),
+        }
+    }
+}
+
+/// Selects which messages are sent to the webhook. Every given condition must match. Empty lists
+/// and missing values match everything.
+///
+/// Alarm status and probability only concern alarms, so they do not stop data messages or detections
+/// from being sent. Detections have no configuration name, so names do not stop them either, and
+/// they match the stats they combine.
+/// Severity concerns alarms, incidents and detections, so it does not stop data messages.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize, schemars::JsonSchema)]
+pub struct WebhookFilter {
+    /// Only messages from configurations with these names
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub names: Vec<String>,
+    /// Only alarms for these stats, and data messages that have a value for any of them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stats: Vec<Stat>,
+    /// Only alarms with these statuses
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub statuses: Vec<AlertStatus>,
+    /// Only alarms with at least this probability
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_probability: Option<f64>,
+    /// Only alarms with at most this probability. Lower probability means a more anomalous value.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_probability: Option<f64>,
+    /// Only alarms and detections with at least this severity
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_severity: Option<Severity>,
+    /// At most one data message per this many seconds is sent for each configuration and series
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_interval: Option<u64>,
+}
+
+impl WebhookFilter {
+    fn is_empty(&self) -> bool {
+        *self == WebhookFilter::default()
+    }
+
+    // Sampling interval is not checked here, because it depends on the earlier messages
+    pub fn matches(&self, message: &Message) -> bool {
+        match message {
+            Message::Alarm(alarm) => {
+                (self.names.is_empty() || self.names.contains(&alarm.name))
+                    && (self.stats.is_empty() || self.stats.contains(&alarm.stat))
+                    && (self.statuses.is_empty() || self.statuses.contains(&alarm.status))
+                    && self
+                        .min_probability
+                        .iter()
+                        .all(|probability| alarm.probability >= *probability)
+                    && self
+                        .max_probability
+                        .iter()
+                        .all(|probability| alarm.probability <= *probability)
+                    && self
+                        .min_severity
+                        .iter()
+                        .all(|severity| alarm.severity.unwrap_or_default() >= *severity)
+            }
+            Message::Data(data) => {
+                (self.names.is_empty() || self.names.contains(&data.name))
+                    && (self.stats.is_empty()
+                        || self.stats.iter().any(|stat| data.value(stat).is_some()))
+            }
+            Message::Incident(incident) => {
+                (self.names.is_empty() || self.names.contains(&incident.name))
+                    && (self.stats.is_empty() || self.stats.contains(&incident.stat))
+                    && (self.statuses.is_empty() || self.statuses.contains(&incident.status))
+                    && self
+                        .min_probability
+                        .iter()
+                        .all(|probability| incident.min_probability >= *probability)
+                    && self
+                        .max_probability
+                        .iter()
+                        .all(|probability| incident.min_probability <= *probability)
+                    && self
+                        .min_severity
+                        .iter()
+                        .all(|severity| incident.severity >= *severity)
+            }
+            Message::Detection(detection) => {
+                (self.stats.is_empty()
+                    || self.stats.iter().any(|stat| detection.stats.contains(stat)))
+                    && self
+                        .min_severity
+                        .iter()
+                        .all(|severity| detection.severity >= *severity)
+            }
+        }
+    }
+}
+
+/// Messages are collected and sent together as a JSON array. A batch is sent when it has the
+/// maximum number of messages, or when its oldest message has waited for the maximum delay.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+pub struct WebhookBatch {
+    /// Messages sent at most in one request
+    #[serde(default = "WebhookBatch::default_max_messages")]
+    pub max_messages: u32,
+    /// Seconds a message may wait for others before the batch is sent
+    #[serde(default = "WebhookBatch::default_max_delay")]
+    pub max_delay: u64,
+}
+
+impl WebhookBatch {
+    fn default_max_messages() -> u32 {
+        100
+    }
+
+    fn default_max_delay() -> u64 {
+        10
+    }
+}
+
+impl Default for WebhookBatch {
+    fn default() -> Self {
+        WebhookBatch {
+            max_messages: WebhookBatch::default_max_messages(),
+            max_delay: WebhookBatch::default_max_delay(),
+        }
+    }
+}
+
+/// Certificates are read from PEM files on the server. The client key must be in PKCS #8 format.
+#[derive(
+    Clone, Debug, Default, Deserialize, PartialEq, Eq, Hash, Serialize, schemars::JsonSchema,
+)]
+pub struct WebhookTls {
+    /// Additional root certificates for verifying the host
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ca_file: Option<String>,
+    /// Client certificate for hosts that require one, given with the key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_cert_file: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_key_file: Option<String>,
+    /// Skips verifying the certificate of the host, only for testing environments
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub accept_invalid_certs: bool,
+}
+
+impl WebhookTls {
+    pub fn is_default(&self) -> bool {
+        *self == WebhookTls::default()
+    }
 }
 
 // Webhook
 //--------------------------------------------------------------------------------------------------
 
-#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize, schemars::JsonSchema)]
 pub struct Webhook {
     pub name: String,
     pub address: String,
+    /// Disabled webhooks receive no new messages, and their queued messages wait
+    #[serde(default = "Webhook::default_enabled", skip_serializing_if = "is_true")]
+    pub enabled: bool,
     #[serde(default)]
     pub method: WebhookRequestMethod,
     #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub headers: WebhookHeaders,
     #[serde(default, rename = "type")]
     pub stats_type: WebhookStatsType,
+    /// Only messages from these configuration ids are sent, or all messages when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub configurations: Vec<i32>,
+    #[serde(default, skip_serializing_if = "WebhookRetryPolicy::is_default")]
+    pub retry: WebhookRetryPolicy,
+    /// Requests are signed with this key, see the signature headers in the README. The secret is
+    /// never returned. Updates without it keep the current secret, and an empty one removes it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    #[serde(default, skip_serializing_if = "WebhookFilter::is_empty")]
+    pub filter: WebhookFilter,
+    /// Template for the request body, the message is sent as JSON without one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+    /// Content type of the request body, application/json by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    /// Messages are sent together as a JSON array, up to `max_messages` in one request and after
+    /// waiting at most `max_delay` seconds. Messages are sent one by one when missing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub batch: Option<WebhookBatch>,
+    /// Milliseconds to wait for the response, ten seconds by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "WebhookTls::is_default")]
+    pub tls: WebhookTls,
+}
+
+impl Webhook {
+    fn default_enabled() -> bool {
+        true
+    }
+
+    // Whether the message should be sent, apart from the data sampling interval
+    pub fn accepts(&self, message: &Message) -> bool {
+        if !self.enabled || message.is_suppressed() {
+            return false;
+        }
+        let type_matches = matches!(
+            (&self.stats_type, message),
+            (WebhookStatsType::Both, Message::Alarm(_) | Message::Data(_))
+                | (WebhookStatsType::Alarms, Message::Alarm(_))
+                | (WebhookStatsType::Data, Message::Data(_))
+                | (WebhookStatsType::Incidents, Message::Incident(_))
+                | (WebhookStatsType::Detections, Message::Detection(_))
+        );
+        type_matches && self.accepts_config(message.config_id()) && self.filter.matches(message)
+    }
+
+    pub fn accepts_config(&self, config_id: Option<i32>) -> bool {
+        if self.configurations.is_empty() {
+            return true;
+        }
+        match config_id {
+            Some(config_id) => self.configurations.contains(&config_id),
+            None => false,
+        }
+    }
+}
+
+/// Webhook as returned from the API, with only a flag telling whether it has a secret
+#[derive(Debug, Deserialize, PartialEq, Serialize, schemars::JsonSchema)]
+pub struct WebhookDetails {
+    #[serde(flatten)]
+    pub webhook: Webhook,
+    pub has_secret: bool,
+}
+
+impl From<Webhook> for WebhookDetails {
+    fn from(mut webhook: Webhook) -> Self {
+        let has_secret = webhook.secret.take().is_some();
+        WebhookDetails {
+            webhook,
+            has_secret,
+        }
+    }
 }
 
 // Webhook listing
@@ -51,6 +314,62 @@ pub struct WebhookItem {
 
 pub type WebhookList = Vec<WebhookItem>;
 
+// Delivery status
+//--------------------------------------------------------------------------------------------------
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+pub struct WebhookError {
+    /// Time of the failed attempt as nanoseconds since Unix Epoch
+    pub time: i64,
+    pub error: String,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+pub struct WebhookStatus {
+    /// Messages waiting for delivery
+    pub queued: i64,
+    /// Messages that were not delivered within the retry policy
+    pub dead_letters: i64,
+    /// Time of the latest successful delivery as nanoseconds since Unix Epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_success: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<WebhookError>,
+}
+
+// Delivery log
+//--------------------------------------------------------------------------------------------------
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+pub struct WebhookDelivery {
+    /// Attempt time as nanoseconds since Unix Epoch
+    pub time: i64,
+    /// Sent from the test endpoint instead of the delivery queue
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub test: bool,
+    /// HTTP status code of the response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    /// Milliseconds from sending the request until the response or the error
+    pub latency_ms: u64,
+    /// Beginning of the response body
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<String>,
+    /// Reason why the delivery failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+fn is_true(value: &bool) -> bool {
+    *value
+}
+
+pub type WebhookDeliveries = Vec<WebhookDelivery>;
+
 // Container for webhook configurations
 //--------------------------------------------------------------------------------------------------
 
@@ -62,6 +381,9 @@ pub type Webhooks = HashMap<i32, Webhook>;
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::structures::correlations::Detection;
+    use crate::structures::incidents::Incident;
+    use crate::structures::statistics::{AlarmMessage, DataMessage};
 
     #[test]
     fn headers() {
@@ -93,10 +415,31 @@ mod tests {
             WebhookStatsType::Alarms,
             WebhookStatsType::Both,
             WebhookStatsType::Data,
+            WebhookStatsType::Incidents,
+            WebhookStatsType::Detections,
         ];
         let json = serde_json::to_string(&stats_types).unwrap();
-        let expected = r#"["alarms","both","data"]"#;
+        let expected = r#"["alarms","both","data","incidents","detections"]"#;
         assert_eq!(json, expected);
+
+        // Each type accepts only its own messages
+        let alarm = Message::Alarm(Box::default());
+        let data = Message::Data(Box::default());
+        let incident = Message::Incident(Box::default());
+        let detection = Message::Detection(Box::default());
+        let mut hook = serde_json::from_str::<Webhook>(r#"{"name":"a","address":"b"}"#).unwrap();
+        for (stats_type, accepted) in [
+            (WebhookStatsType::Alarms, [true, false, false, false]),
+            (WebhookStatsType::Both, [true, true, false, false]),
+            (WebhookStatsType::Data, [false, true, false, false]),
+            (WebhookStatsType::Incidents, [false, false, true, false]),
+            (WebhookStatsType::Detections, [false, false, false, true]),
+        ] {
+            hook.stats_type = stats_type;
+            for (message, accepted) in [&alarm, &data, &incident, &detection].iter().zip(accepted) {
+                assert_eq!(hook.accepts(message), accepted, "{:?}", hook.stats_type);
+            }
+        }
     }
 
     #[test]
@@ -104,9 +447,19 @@ mod tests {
         let hook = Webhook {
             name: "test".to_string(),
             address: "http://captain.hook/".to_string(),
+            enabled: true,
             method: WebhookRequestMethod::Post,
             headers: HashMap::from([("code".to_string(), "12345".to_string())]),
             stats_type: WebhookStatsType::Both,
+            configurations: vec![],
+            retry: WebhookRetryPolicy::default(),
+            secret: None,
+            filter: WebhookFilter::default(),
+            template: None,
+            content_type: None,
+            batch: None,
+            timeout_ms: None,
+            tls: WebhookTls::default(),
         };
         let json = serde_json::to_string(&hook).unwrap();
         let expected = concat!(
@@ -129,12 +482,22 @@ mod tests {
         let expected = Webhook {
             name: "Captain Hook".to_string(),
             address: "https://captain.hook/".to_string(),
+            enabled: true,
             method: WebhookRequestMethod::Get,
             headers: WebhookHeaders::from([
                 ("key".to_string(), "12345".to_string()),
                 ("gold".to_string(), "1991".to_string()),
             ]),
             stats_type: WebhookStatsType::Data,
+            configurations: vec![],
+            retry: WebhookRetryPolicy::default(),
+            secret: None,
+            filter: WebhookFilter::default(),
+            template: None,
+            content_type: None,
+            batch: None,
+            timeout_ms: None,
+            tls: WebhookTls::default(),
         };
         assert_eq!(hook, expected);
     }
@@ -190,9 +553,19 @@ mod tests {
         let expected = Webhook {
             name: "test".to_string(),
             address: "test".to_string(),
+            enabled: true,
             method: WebhookRequestMethod::Post,
             headers: Default::default(),
             stats_type: WebhookStatsType::Both,
+            configurations: vec![],
+            retry: WebhookRetryPolicy::default(),
+            secret: None,
+            filter: WebhookFilter::default(),
+            template: None,
+            content_type: None,
+            batch: None,
+            timeout_ms: None,
+            tls: WebhookTls::default(),
         };
         assert_eq!(hook, expected);
     }
@@ -202,13 +575,243 @@ mod tests {
         let hook = Webhook {
             name: "name".to_string(),
             address: "address".to_string(),
+            enabled: true,
             method: Default::default(),
             headers: Default::default(),
             stats_type: Default::default(),
+            configurations: vec![],
+            retry: WebhookRetryPolicy::default(),
+            secret: None,
+            filter: WebhookFilter::default(),
+            template: None,
+            content_type: None,
+            batch: None,
+            timeout_ms: None,
+            tls: WebhookTls::default(),
         };
         let json = serde_json::to_string(&hook).unwrap();
         let expected = r#"{"name":"name","address":"address","method":"POST","type":"both"}"#;
         // Note: expected JSON should not have headers field at all.
         assert_eq!(json, expected);
     }
+
+    #[test]
+    fn details_without_secret() {
+        let json = r#"{"name":"test","address":"test","secret":"s3cr3t"}"#;
+        let hook = serde_json::from_str::<Webhook>(json).unwrap();
+        let json = serde_json::to_string(&WebhookDetails::from(hook)).unwrap();
+        let expected = concat!(
+            r#"{"name":"test","address":"test","method":"POST","type":"both","#,
+            r#""has_secret":true}"#
+        );
+        assert_eq!(json, expected);
+    }
+
+    #[test]
+    fn configurations() {
+        let json = r#"{"name":"test","address":"test","configurations":[1,3]}"#;
+        let hook = serde_json::from_str::<Webhook>(json).unwrap();
+        assert_eq!(hook.configurations, vec![1, 3]);
+        assert!(hook.accepts_config(Some(1)));
+        assert!(!hook.accepts_config(Some(2)));
+        assert!(!hook.accepts_config(None));
+
+        // Webhooks without configurations accept every message
+        let json = r#"{"name":"test","address":"test"}"#;
+        let hook = serde_json::from_str::<Webhook>(json).unwrap();
+        assert!(hook.accepts_config(Some(2)));
+        assert!(hook.accepts_config(None));
+    }
+
+    #[test]
+    fn retry_policy() {
+        let json = r#"{"name":"test","address":"test","retry":{"max_attempts":3}}"#;
+        let hook = serde_json::from_str::<Webhook>(json).unwrap();
+        let expected = WebhookRetryPolicy {
+            max_attempts: 3,
+            max_age: 3600,
+        };
+        assert_eq!(hook.retry, expected);
+        let json = serde_json::to_string(&hook).unwrap();
+        let expected = concat!(
+            r#"{"name":"test","address":"test","method":"POST","type":"both","#,
+            r#""retry":{"max_attempts":3,"max_age":3600}}"#
+        );
+        assert_eq!(json, expected);
+    }
+
+    #[test]
+    fn status_serialize() {
+        let status = WebhookStatus {
+            queued: 2,
+            dead_letters: 1,
+            last_success: None,
+            last_error: Some(WebhookError {
+                time: 3,
+                error: "Host responded with 500 Internal Server Error".to_string(),
+            }),
+        };
+        let json = serde_json::to_string(&status).unwrap();
+        let expected = concat!(
+            r#"{"queued":2,"dead_letters":1,"#,
+            r#""last_error":{"time":3,"error":"Host responded with 500 Internal Server Error"}}"#
+        );
+        assert_eq!(json, expected);
+    }
+
+    #[test]
+    fn delivery_serialize() {
+        let deliveries = vec![
+            WebhookDelivery {
+                time: 2,
+                test: true,
+                status: Some(204),
+                latency_ms: 5,
+                response: None,
+                error: None,
+            },
+            WebhookDelivery {
+                time: 1,
+                test: false,
+                status: Some(500),
+                latency_ms: 12,
+                response: Some("Oops".to_string()),
+                error: Some("Host responded with 500 Internal Server Error".to_string()),
+            },
+        ];
+        let json = serde_json::to_string(&deliveries).unwrap();
+        let expected = concat!(
+            r#"[{"time":2,"test":true,"status":204,"latency_ms":5},"#,
+            r#"{"time":1,"status":500,"latency_ms":12,"response":"Oops","#,
+            r#""error":"Host responded with 500 Internal Server Error"}]"#
+        );
+        assert_eq!(json, expected);
+    }
+
+    #[test]
+    fn filter() {
+        let json = r#"{"name":"test","address":"test","type":"alarms","filter":{
+            "stats":["R_SYN","PERF"],"statuses":["UP_ALERT"],"max_probability":0.01}}"#;
+        let hook = serde_json::from_str::<Webhook>(json).unwrap();
+        let mut alarm = AlarmMessage {
+            stat: Stat::RSyn,
+            status: AlertStatus::UpAlert,
+            probability: 0.001,
+            ..AlarmMessage::default()
+        };
+        assert!(hook.accepts(&Message::Alarm(Box::new(alarm.clone()))));
+        alarm.probability = 0.5;
+        assert!(!hook.accepts(&Message::Alarm(Box::new(alarm.clone()))));
+        alarm.probability = 0.001;
+        alarm.status = AlertStatus::DownAlert;
+        assert!(!hook.accepts(&Message::Alarm(Box::new(alarm.clone()))));
+        alarm.status = AlertStatus::UpAlert;
+        alarm.suppressed = true;
+        assert!(!hook.accepts(&Message::Alarm(Box::new(alarm.clone()))));
+        alarm.suppressed = false;
+        alarm.stat = Stat::RAck;
+        assert!(!hook.accepts(&Message::Alarm(Box::new(alarm))));
+
+        // Alarms, incidents and detections below the minimum severity are not sent
+        let json = r#"{"name":"test","address":"test","type":"alarms","filter":{
+            "min_severity":"high"}}"#;
+        let mut hook = serde_json::from_str::<Webhook>(json).unwrap();
+        let mut alarm = AlarmMessage {
+            severity: Some(Severity::Critical),
+            ..AlarmMessage::default()
+        };
+        assert!(hook.accepts(&Message::Alarm(Box::new(alarm.clone()))));
+        alarm.severity = Some(Severity::Medium);
+        assert!(!hook.accepts(&Message::Alarm(Box::new(alarm))));
+        hook.stats_type = WebhookStatsType::Incidents;
+        let mut incident = Incident {
+            severity: Severity::High,
+            ..Incident::default()
+        };
+        assert!(hook.accepts(&Message::Incident(Box::new(incident.clone()))));
+        incident.severity = Severity::Info;
+        assert!(!hook.accepts(&Message::Incident(Box::new(incident))));
+        hook.stats_type = WebhookStatsType::Detections;
+        let mut detection = Detection {
+            severity: Severity::High,
+            ..Detection::default()
+        };
+        assert!(hook.accepts(&Message::Detection(Box::new(detection.clone()))));
+        detection.severity = Severity::Low;
+        assert!(!hook.accepts(&Message::Detection(Box::new(detection.clone()))));
+
+        // Names are configuration names, which detections do not have
+        hook.filter.names = vec!["Office".to_string()];
+        detection.severity = Severity::High;
+        assert!(hook.accepts(&Message::Detection(Box::new(detection))));
+
+        // Data messages need a value for any of the stats, alarm conditions do not apply
+        let json = r#"{"name":"test","address":"test","filter":{
+            "names":["Office"],"stats":["PERF"],"statuses":["UP_ALERT"]}}"#;
+        let hook = serde_json::from_str::<Webhook>(json).unwrap();
+        let mut data = DataMessage {
+            name: "Office".to_string(),
+            perf: Some(1.0),
+            ..DataMessage::default()
+        };
+        assert!(hook.accepts(&Message::Data(Box::new(data.clone()))));
+        data.perf = None;
+        assert!(!hook.accepts(&Message::Data(Box::new(data.clone()))));
+        data.perf = Some(1.0);
+        data.name = "Lab".to_string();
+        assert!(!hook.accepts(&Message::Data(Box::new(data))));
+
+        // Empty filter is not serialized
+        let json = r#"{"name":"test","address":"test","filter":{}}"#;
+        let hook = serde_json::from_str::<Webhook>(json).unwrap();
+        let json = serde_json::to_string(&hook).unwrap();
+        assert_eq!(
+            json,
+            r#"{"name":"test","address":"test","method":"POST","type":"both"}"#
+        );
+    }
+
+    #[test]
+    fn batch() {
+        let json = r#"{"name":"test","address":"test","batch":{"max_delay":60}}"#;
+        let hook = serde_json::from_str::<Webhook>(json).unwrap();
+        let expected = WebhookBatch {
+            max_messages: 100,
+            max_delay: 60,
+        };
+        assert_eq!(hook.batch, Some(expected));
+        let json = serde_json::to_string(&hook).unwrap();
+        assert!(json.ends_with(r#""batch":{"max_messages":100,"max_delay":60}}"#));
+    }
+
+    #[test]
+    fn connection_options() {
+        let json = r#"{"name":"test","address":"test"}"#;
+        let hook = serde_json::from_str::<Webhook>(json).unwrap();
+        assert!(hook.enabled);
+        assert_eq!(hook.timeout_ms, None);
+        assert!(hook.tls.is_default());
+
+        let json = concat!(
+            r#"{"name":"test","address":"test","enabled":false,"timeout_ms":500,"#,
+            r#""tls":{"ca_file":"/etc/ca.pem","accept_invalid_certs":true}}"#,
+        );
+        let hook = serde_json::from_str::<Webhook>(json).unwrap();
+        assert!(!hook.enabled);
+        assert_eq!(hook.timeout_ms, Some(500));
+        let expected = WebhookTls {
+            ca_file: Some("/etc/ca.pem".to_string()),
+            accept_invalid_certs: true,
+            ..WebhookTls::default()
+        };
+        assert_eq!(hook.tls, expected);
+        let json = serde_json::to_string(&hook).unwrap();
+        assert!(json.contains(r#""enabled":false"#));
+        let expected =
+            r#""timeout_ms":500,"tls":{"ca_file":"/etc/ca.pem","accept_invalid_certs":true}}"#;
+        assert!(json.ends_with(expected));
+
+        // Disabled webhooks accept nothing
+        assert!(!hook.accepts(&Message::Alarm(Box::default())));
+    }
 }
@@ -0,0 +1,139 @@
+use crate::structures::statistics::{Message, MessageFilter};
+use crate::structures::status::Status;
+use rocket_okapi::okapi::schemars;
+use serde::{Deserialize, Serialize};
+
+// Requests from the client
+//--------------------------------------------------------------------------------------------------
+
+/// Requests are sent by the client as JSON text messages
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize, schemars::JsonSchema)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum SocketRequest {
+    /// Starts sending live messages that match the filter. Subscribing again with the same id
+    /// replaces the earlier filter.
+    Subscribe {
+        id: String,
+        #[serde(default)]
+        filter: MessageFilter,
+    },
+    /// Stops sending messages for the subscription
+    Unsubscribe { id: String },
+}
+
+// Events to the client
+//--------------------------------------------------------------------------------------------------
+
+/// Events are sent by the server as JSON text messages
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+pub enum SocketEvent {
+    /// Subscription was created or its filter was replaced
+    Subscribed { id: String },
+    /// Subscription was removed
+    Unsubscribed { id: String },
+    /// Live message with the ids of all subscriptions that it matched
+    Message {
+        subscriptions: Vec<String>,
+        message: Message,
+    },
+    /// Status of a netspot process, sent for every process when the socket is opened and after
+    /// that every time the status changes
+    Status { status: Status },
+    /// Number of live messages skipped because the client could not keep up
+    Lagged { count: u64 },
+    /// Request could not be handled
+    Error { error: String },
+}
+
+impl SocketEvent {
+    pub fn to_json(&self) -> String {
+        // Events contain only strings, numbers and already valid messages
+        serde_json::to_string(self).expect("serializable event")
+    }
+}
+
+// Unit tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structures::statistics::{AlarmMessage, MessageType, Stat};
+    use crate::structures::status::ProcessStatus;
+
+    #[test]
+    fn requests() {
+        let request = serde_json::from_str::<SocketRequest>(
+            r#"{"action":"subscribe","id":"a","filter":{"type":"alarm","config_id":1}}"#,
+        )
+        .unwrap();
+        let expected = SocketRequest::Subscribe {
+            id: "a".to_string(),
+            filter: MessageFilter {
+                msg_type: Some(MessageType::Alarm),
+                config_id: Some(1),
+                ..MessageFilter::default()
+            },
+        };
+        assert_eq!(request, expected);
+
+        // Filter is optional
+        let request =
+            serde_json::from_str::<SocketRequest>(r#"{"action":"subscribe","id":"b"}"#).unwrap();
+        let expected = SocketRequest::Subscribe {
+            id: "b".to_string(),
+            filter: MessageFilter::default(),
+        };
+        assert_eq!(request, expected);
+
+        let request =
+            serde_json::from_str::<SocketRequest>(r#"{"action":"unsubscribe","id":"a"}"#).unwrap();
+        assert_eq!(
+            request,
+            SocketRequest::Unsubscribe {
+                id: "a".to_string()
+            }
+        );
+
+        // Unknown actions are rejected
+        assert!(serde_json::from_str::<SocketRequest>(r#"{"action":"start","id":"a"}"#).is_err());
+    }
+
+    #[test]
+    fn events() {
+        let event = SocketEvent::Subscribed {
+            id: "a".to_string(),
+        };
+        assert_eq!(event.to_json(), r#"{"event":"subscribed","id":"a"}"#);
+
+        let event = SocketEvent::Message {
+            subscriptions: vec!["a".to_string(), "b".to_string()],
+            message: Message::Alarm(Box::new(AlarmMessage {
+                time: 1,
+                name: "Test".to_string(),
+                stat: Stat::RSyn,
+                ..AlarmMessage::default()
+            })),
+        };
+        let json = event.to_json();
+        assert!(json.starts_with(r#"{"event":"message","subscriptions":["a","b"],"message":{"#));
+        assert!(json.contains(r#""name":"Test""#));
+        assert!(json.contains(r#""type":"alarm""#));
+
+        let event = SocketEvent::Status {
+            status: Status {
+                id: 1,
+                name: "Test".to_string(),
+                status: ProcessStatus::Running,
+                restarts: 0,
+                last_exit: None,
+            },
+        };
+        let expected = r#"{"event":"status","status":{"id":1,"name":"Test","status":"running"}}"#;
+        assert_eq!(event.to_json(), expected);
+
+        let event = SocketEvent::Lagged { count: 3 };
+        assert_eq!(event.to_json(), r#"{"event":"lagged","count":3}"#);
+    }
+}
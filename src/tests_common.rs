@@ -1,10 +1,12 @@
 use crate::build_rocket;
 use crate::state::NetspotControlState;
+use crate::structures::statistics::AlarmMessages;
 use crate::structures::status::{Status, Statuses};
 
 use rocket::local::asynchronous::Client;
 use std::collections::HashMap;
 use std::path::PathBuf;
+use std::time::Duration;
 use tempfile::TempDir;
 
 pub struct TestSetup {
@@ -47,3 +49,18 @@ pub fn statuses_to_hash_map(statuses: Statuses) -> HashMap<i32, Status> {
     }
     hash_map
 }
+
+// Polls the alarms until the expected number of them is stored
+pub async fn wait_alarms(client: &Client, uri: &str, count: usize) -> AlarmMessages {
+    for _ in 0..100 {
+        let response = client.get(uri).dispatch().await;
+        if response.status() == rocket::http::Status::Ok {
+            let messages = response.into_json::<AlarmMessages>().await.unwrap();
+            if messages.len() == count {
+                return messages;
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("Alarms {uri} did not reach {count} messages");
+}